@@ -0,0 +1,73 @@
+//! Allocation counting and the measurement loop shared by the benchmarks.
+//!
+//! A benchmark includes this with `mod common;`, which also installs the
+//! counting allocator for it. Heap allocations are counted as well as wall
+//! time, since they are stable across noisy machines.
+
+// Each benchmark uses only part of this
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::VM;
+use skyhetu::{Lexer, Parser, Value};
+
+/// Iterations of each benchmark loop
+pub const ITERATIONS: usize = 1_000_000;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A loop that adds `body` to `total` `ITERATIONS` times and ends with `total`
+pub fn summing_loop(body: &str) -> String {
+    format!(
+        "state i = 0\nstate total = 0\nwhile i < {} {{\n  total -> total + {}\n  i -> i + 1\n}}\ntotal",
+        ITERATIONS, body
+    )
+}
+
+/// Best wall time of 10 runs of `source` and the allocations made by one run,
+/// checking that each run evaluates to `expected`. The program is compiled
+/// without optimization, so literals in the loop aren't folded away, and
+/// causality retention is kept small so the log's growth doesn't swamp the
+/// measurement.
+pub fn measure(source: &str, expected: Value) -> (f64, usize) {
+    let mut best = f64::MAX;
+    let mut allocations = 0;
+    for _ in 0..10 {
+        let tokens = Lexer::new(source).tokenize().expect("lex failed");
+        let program = Parser::new(tokens).parse().expect("parse failed");
+        let mut vm = VM::new();
+        vm.causality.set_retention(Some(16));
+        let mut compiler = Compiler::new();
+        compiler.set_optimize(false);
+        let chunk = compiler.compile(&program, &mut vm.heap).expect("compile failed");
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let result = vm.run(chunk).expect("benchmark program failed");
+        let elapsed = start.elapsed().as_secs_f64();
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        assert_eq!(result, expected);
+        best = best.min(elapsed);
+    }
+    (best, allocations)
+}
@@ -0,0 +1,30 @@
+//! Measures reading and transitioning a global `state` in a hot loop.
+//!
+//! Each iteration reads `counter` twice and transitions it once, so the
+//! name lookups dominate once causality retention is kept small. Run with
+//! `cargo bench --bench global_access`.
+
+mod common;
+
+use common::{measure, ITERATIONS};
+use skyhetu::Value;
+
+fn main() {
+    let source = format!(
+        "state counter = 0\nwhile counter < {} {{\n  counter -> counter + 1\n}}\ncounter",
+        ITERATIONS
+    );
+
+    let (best, allocations) = measure(&source, Value::Number(ITERATIONS as f64));
+
+    println!(
+        "global_access: {} iterations in {:.1} ms, {:.1} ns per iteration",
+        ITERATIONS,
+        best * 1e3,
+        best * 1e9 / ITERATIONS as f64
+    );
+    println!(
+        "global_access: {:.2} allocations per iteration",
+        allocations as f64 / ITERATIONS as f64
+    );
+}
@@ -0,0 +1,45 @@
+//! Measures the cost of referencing and calling a built-in in a hot loop.
+//!
+//! Referencing a native copies its value onto the stack, which should not
+//! allocate; the loop that stores `len` in a local is compared with one that
+//! stores a number, so only the reference is counted. Calling it is measured
+//! the same way, against a loop adding a literal. Run with
+//! `cargo bench --bench native_calls`.
+
+mod common;
+
+use common::{measure, summing_loop, ITERATIONS};
+use skyhetu::Value;
+
+/// A loop that stores `value` in a local on every iteration
+fn storing_loop(value: &str) -> String {
+    format!(
+        "state i = 0\nwhile i < {} {{\n  let _held = {}\n  i -> i + 1\n}}\ni",
+        ITERATIONS, value
+    )
+}
+
+fn main() {
+    let expected = Value::Number(ITERATIONS as f64);
+    let (_, allocs_with_reference) = measure(&storing_loop("len"), expected.clone());
+    let (_, allocs_without_reference) = measure(&storing_loop("3"), expected);
+    let allocs_per_reference = (allocs_with_reference - allocs_without_reference) as f64 / ITERATIONS as f64;
+
+    let expected = Value::Number(3.0 * ITERATIONS as f64);
+    let (with_call, allocs_with) = measure(&summing_loop("len(\"abc\")"), expected.clone());
+    let (without_call, allocs_without) = measure(&summing_loop("3"), expected);
+    let per_call = (with_call - without_call) * 1e9 / ITERATIONS as f64;
+    let allocs_per_call = (allocs_with - allocs_without) as f64 / ITERATIONS as f64;
+
+    println!(
+        "native_calls: {} iterations, {:.1} ms with len(), {:.1} ms without",
+        ITERATIONS,
+        with_call * 1e3,
+        without_call * 1e3
+    );
+    println!("native_calls: {:.2} allocations per reference to a native", allocs_per_reference);
+    println!(
+        "native_calls: {:.1} ns and {:.2} allocations per native call",
+        per_call, allocs_per_call
+    );
+}
@@ -0,0 +1,47 @@
+//! Compares building a long string by concatenation with building it in a
+//! buffer.
+//!
+//! `s -> s + piece` copies the whole string on every step, so the loop is
+//! quadratic in the final length; `buf_push` appends in place. Causality
+//! retention is kept small so the concatenation loop doesn't also keep every
+//! intermediate string alive. Run with `cargo bench --bench string_building`.
+
+use std::time::Instant;
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::VM;
+use skyhetu::{Lexer, Parser, Value};
+
+const APPENDS: usize = 50_000;
+
+/// Best of five runs of `source`, in seconds
+fn best_time(source: &str) -> f64 {
+    let mut best = f64::MAX;
+    for _ in 0..5 {
+        let tokens = Lexer::new(source).tokenize().expect("lex failed");
+        let program = Parser::new(tokens).parse().expect("parse failed");
+        let mut vm = VM::new();
+        vm.causality.set_retention(Some(16));
+        let chunk = Compiler::new().compile(&program, &mut vm.heap).expect("compile failed");
+
+        let start = Instant::now();
+        let result = vm.run(chunk).expect("benchmark program failed");
+        best = best.min(start.elapsed().as_secs_f64());
+        assert_eq!(result, Value::Number((APPENDS * 8) as f64));
+    }
+    best
+}
+
+fn main() {
+    let concat = best_time(&format!(
+        "state s = \"\"\nfor i in range({}) {{\n  s -> s + \"abcdefgh\"\n}}\nlen(s)",
+        APPENDS
+    ));
+    let buffer = best_time(&format!(
+        "let b = buffer()\nfor i in range({}) {{\n  buf_push(b, \"abcdefgh\")\n}}\nlen(buf_str(b))",
+        APPENDS
+    ));
+
+    println!("string_building: {} appends by concatenation in {:.1} ms", APPENDS, concat * 1e3);
+    println!("string_building: {} appends to a buffer in {:.1} ms", APPENDS, buffer * 1e3);
+    println!("string_building: buffer is {:.1}x faster", concat / buffer);
+}
@@ -0,0 +1,30 @@
+//! Measures the cost of loading string literals in a hot loop.
+//!
+//! Each iteration concatenates two literals and takes the length of the
+//! result; the same loop with a number literal in place of the strings is
+//! subtracted so the loop and causality bookkeeping cancel out. Run with
+//! `cargo bench --bench string_literals`.
+
+mod common;
+
+use common::{measure, summing_loop, ITERATIONS};
+use skyhetu::Value;
+
+fn main() {
+    let expected = Value::Number(4.0 * ITERATIONS as f64);
+    let (with_strings, allocs_with) = measure(&summing_loop("len(\"hit\" + \"!\")"), expected.clone());
+    let (without_strings, allocs_without) = measure(&summing_loop("max(4, 0)"), expected);
+    let per_iteration = (with_strings - without_strings) * 1e9 / ITERATIONS as f64;
+    let allocs_per_iteration = (allocs_with - allocs_without) as f64 / ITERATIONS as f64;
+
+    println!(
+        "string_literals: {} iterations, {:.1} ms with literals, {:.1} ms without",
+        ITERATIONS,
+        with_strings * 1e3,
+        without_strings * 1e3
+    );
+    println!(
+        "string_literals: {:.1} ns and {:.2} allocations per two-literal concatenation",
+        per_iteration, allocs_per_iteration
+    );
+}
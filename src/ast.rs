@@ -58,10 +58,15 @@ pub enum Expr {
         span: Span,
     },
     
-    /// Lambda/anonymous function: |a, b| a + b
+    /// Anonymous function: fn(a, b) => a + b, or fn(a, b) { ... }.
+    /// An expression body is stored as a single return statement.
     Lambda {
         params: Vec<String>,
-        body: Box<Expr>,
+        /// Parameters declared `state`, a subset of `params`
+        state_params: Vec<String>,
+        /// Each parameter's `: type` annotation, if it has one
+        param_types: Vec<Option<String>>,
+        body: Vec<Stmt>,
         span: Span,
     },
 
@@ -72,6 +77,34 @@ pub enum Expr {
         span: Span,
     },
 
+    /// Array literal: [a, b, c]
+    Array {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+
+    /// Index access: arr[i]
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+
+    /// If-expression: if cond { a } else { b }, or cond ? a : b. Each branch
+    /// holds a single expression; without an else the value is nil.
+    If {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Option<Box<Expr>>,
+        span: Span,
+    },
+
+    /// Superclass method: super.name, bound to the current `this`
+    Super { method: String, span: Span },
+
+    /// Spread argument: f(...args). Only valid in call arguments
+    Spread { expr: Box<Expr>, span: Span },
+
     /// Property assignment: obj.prop = value
     Set {
         object: Box<Expr>,
@@ -79,6 +112,14 @@ pub enum Expr {
         value: Box<Expr>,
         span: Span,
     },
+
+    /// Element assignment: arr[i] = value
+    IndexSet {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        span: Span,
+    },
 }
 
 impl Expr {
@@ -96,11 +137,63 @@ impl Expr {
             Expr::Logical { span, .. } => *span,
             Expr::Lambda { span, .. } => *span,
             Expr::Get { span, .. } => *span,
+            Expr::If { span, .. } => *span,
+            Expr::Super { span, .. } => *span,
+            Expr::Spread { span, .. } => *span,
             Expr::Set { span, .. } => *span,
+            Expr::Array { span, .. } => *span,
+            Expr::Index { span, .. } => *span,
+            Expr::IndexSet { span, .. } => *span,
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    /// Source-like rendering, used to show conditions in diagnostics
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Number { value, .. } => {
+                if value.fract() == 0.0 && value.abs() < 1e15 {
+                    write!(f, "{}", *value as i64)
+                } else {
+                    write!(f, "{}", value)
+                }
+            }
+            Expr::String { value, .. } => write!(f, "{:?}", value),
+            Expr::Bool { value, .. } => write!(f, "{}", value),
+            Expr::Nil { .. } => write!(f, "nil"),
+            Expr::Ident { name, .. } => write!(f, "{}", name),
+            Expr::Binary { left, op, right, .. } => write!(f, "{} {} {}", left, op, right),
+            Expr::Unary { op, operand, .. } => write!(f, "{}{}", op, operand),
+            Expr::Call { callee, args, .. } => write!(f, "{}({})", callee, join(args)),
+            Expr::Grouping { expr, .. } => write!(f, "({})", expr),
+            Expr::Logical { left, op, right, .. } => write!(f, "{} {} {}", left, op, right),
+            Expr::Lambda { params, body, .. } => match body.as_slice() {
+                [Stmt::Return { value: Some(value), .. }] => write!(f, "fn({}) => {}", params.join(", "), value),
+                _ => write!(f, "fn({}) {{ ... }}", params.join(", ")),
+            },
+            Expr::Get { object, name, .. } => write!(f, "{}.{}", object, name),
+            Expr::Array { elements, .. } => write!(f, "[{}]", join(elements)),
+            Expr::Index { object, index, .. } => write!(f, "{}[{}]", object, index),
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                write!(f, "if {} {{ {} }}", condition, then_branch)?;
+                match else_branch {
+                    Some(else_branch) => write!(f, " else {{ {} }}", else_branch),
+                    None => Ok(()),
+                }
+            }
+            Expr::Super { method, .. } => write!(f, "super.{}", method),
+            Expr::Spread { expr, .. } => write!(f, "...{}", expr),
+            Expr::Set { object, name, value, .. } => write!(f, "{}.{} = {}", object, name, value),
+            Expr::IndexSet { object, index, value, .. } => write!(f, "{}[{}] = {}", object, index, value),
         }
     }
 }
 
+fn join(exprs: &[Expr]) -> String {
+    exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+}
+
 /// Binary operators
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOp {
@@ -142,6 +235,15 @@ pub enum UnaryOp {
     Not,  // !
 }
 
+impl std::fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+
 /// Logical operators
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogicalOp {
@@ -149,6 +251,15 @@ pub enum LogicalOp {
     Or,
 }
 
+impl std::fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogicalOp::And => write!(f, "and"),
+            LogicalOp::Or => write!(f, "or"),
+        }
+    }
+}
+
 /// Statement nodes
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -169,6 +280,13 @@ pub enum Stmt {
         span: Span,
     },
     
+    /// Compile-time constant: const X = expr
+    Const {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    
     /// State transition: x -> expr
     Transition {
         name: String,
@@ -176,6 +294,14 @@ pub enum Stmt {
         span: Span,
     },
     
+    /// Property transition: obj.field -> expr
+    PropertyTransition {
+        object: Expr,
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    
     /// Block: { stmt* }
     Block { stmts: Vec<Stmt>, span: Span },
     
@@ -187,6 +313,15 @@ pub enum Stmt {
         span: Span,
     },
     
+    /// Match statement: match subject { 1 | 2 => { } "a" => { } _ => { } }.
+    /// Each arm lists the values that select it; `default` is the `_` arm.
+    Match {
+        subject: Expr,
+        arms: Vec<(Vec<Expr>, Stmt)>,
+        default: Option<Box<Stmt>>,
+        span: Span,
+    },
+    
     /// While loop: while cond { }
     While {
         condition: Expr,
@@ -206,6 +341,10 @@ pub enum Stmt {
     Function {
         name: String,
         params: Vec<String>,
+        /// Parameters declared `state`, a subset of `params`
+        state_params: Vec<String>,
+        /// Each parameter's `: type` annotation, if it has one
+        param_types: Vec<Option<String>>,
         body: Vec<Stmt>,
         span: Span,
     },
@@ -219,9 +358,11 @@ pub enum Stmt {
     /// Continue statement  
     Continue { span: Span },
     
-    /// Class definition
+    /// Class definition: class Name : Parent { methods }
     Class {
         name: String,
+        /// The class inherited from, an identifier
+        superclass: Option<Expr>,
         methods: Vec<Stmt>,
         span: Span,
     },
@@ -240,6 +381,31 @@ pub enum Stmt {
     },
 }
 
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr { expr } => expr.span(),
+            Stmt::Let { span, .. } => *span,
+            Stmt::State { span, .. } => *span,
+            Stmt::Const { span, .. } => *span,
+            Stmt::Transition { span, .. } => *span,
+            Stmt::PropertyTransition { span, .. } => *span,
+            Stmt::Block { span, .. } => *span,
+            Stmt::If { span, .. } => *span,
+            Stmt::Match { span, .. } => *span,
+            Stmt::While { span, .. } => *span,
+            Stmt::For { span, .. } => *span,
+            Stmt::Function { span, .. } => *span,
+            Stmt::Return { span, .. } => *span,
+            Stmt::Break { span } => *span,
+            Stmt::Continue { span } => *span,
+            Stmt::Class { span, .. } => *span,
+            Stmt::Import { span, .. } => *span,
+            Stmt::Export { span, .. } => *span,
+        }
+    }
+}
+
 /// A complete program
 #[derive(Debug, Clone)]
 pub struct Program {
@@ -0,0 +1,52 @@
+//! Kinds of global bindings, shared by the compiler's redefinition warnings
+//! and the VM's redefinition notices
+
+use std::fmt;
+use crate::value::Value;
+
+/// What kind of declaration introduced a global binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Let,
+    State,
+    Function,
+    Class,
+    Const,
+    Native,
+}
+
+impl BindingKind {
+    /// Kind of a binding created by DefineGlobal, which serves let, fn and class
+    pub fn of_definition(value: &Value) -> Self {
+        match value {
+            Value::Closure(_) | Value::Function(_) => BindingKind::Function,
+            Value::Class(_) => BindingKind::Class,
+            _ => BindingKind::Let,
+        }
+    }
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingKind::Let => write!(f, "let"),
+            BindingKind::State => write!(f, "state"),
+            BindingKind::Function => write!(f, "fn"),
+            BindingKind::Class => write!(f, "class"),
+            BindingKind::Const => write!(f, "const"),
+            BindingKind::Native => write!(f, "native fn"),
+        }
+    }
+}
+
+/// Describe a global being redefined with a different kind.
+/// Changes to or from `state` are spelled out since they change what gets tracked.
+pub fn redefinition_message(name: &str, old: BindingKind, new: BindingKind) -> String {
+    if old == BindingKind::State {
+        format!("'{}' redefined from state to {}; its causality history ends here", name, new)
+    } else if new == BindingKind::State {
+        format!("'{}' redefined from {} to state; transitions of '{}' are tracked from here on", name, old, name)
+    } else {
+        format!("'{}' redefined from {} to {}", name, old, new)
+    }
+}
@@ -2,7 +2,17 @@
 //!
 //! A stack-based virtual machine with causality tracking.
 
-use std::fmt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::error::{ErrorKind, Result, SkyHetuError};
+use crate::value::{Function, Value};
+
+/// Magic bytes at the start of a compiled `.skyc` file
+pub const BYTECODE_MAGIC: &[u8; 4] = b"SKYC";
+
+/// Version of the `.skyc` format; bumped whenever opcodes or the layout change
+pub const BYTECODE_VERSION: u8 = 9;
 
 /// Opcodes for the VM
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,13 +33,18 @@ pub enum OpCode {
     GetGlobal,      // Get global variable
     SetGlobal,      // Set global variable
     DefineState,    // Define mutable state
+    DefineConst,    // Define compile-time constant (name index)
     GetLocal,       // Get local variable (stack offset)
     SetLocal,       // Set local variable
-    TransitionLocal, // Transition a local state variable
+    TransitionLocal, // Transition a local state variable (slot, name, causes)
+    InitLocalState, // Record the declaration of a local state (name index)
+    InitParamState, // Record the incoming value of a `state` parameter (slot, name)
+    CheckType,      // Check a parameter against its annotated type (slot, type name)
+    TransitionParam, // Transition a `state` parameter (slot, name, causes)
 
     
     // State transitions (causality tracked)
-    Transition,     // State transition: var -> value
+    Transition,     // State transition: var -> value (name, causes)
     
     // Arithmetic
     Add,
@@ -58,40 +73,80 @@ pub enum OpCode {
     
     // Functions
     Call,           // Call function (arg count)
+    CallSpread,     // Call with spread args (arg count, then a spread flag byte per arg)
+    TailCall,       // Call whose result is returned, reusing the caller's frame (arg count)
     Return,         // Return from function
     Closure,        // Create closure
     
     GetUpvalue,     // Get upvalue (index)
     SetUpvalue,     // Set upvalue (index)
-    TransitionUpvalue, // Transition upvalue (index, name)
+    TransitionUpvalue, // Transition upvalue (index, name, causes)
     CloseUpvalue,   // Close upvalue (hoist)
     
     // Built-ins
-    Print,          // Print (arg count)
+    Print,          // Print (u16 arg count)
     Why,            // Query causality
     Time,           // Get logical time
     
     // Loops
     Break,          // Break from loop
     Continue,       // Continue loop
+    IterStart,      // Turn the value on top of the stack into something `IterLen`/`IterGet` can walk
+    IterVersion,    // Push the version of the array on top of the stack (0 for other values)
+    CheckIteration, // Error if the array being iterated was resized (iterable slot, version slot)
+    IterLen,        // Push the number of elements to iterate (iterable slot)
+    IterGet,        // Push the element at an index (iterable slot, index slot)
     
     // Arrays
-    Array,          // Create array (element count)
+    Array,          // Create array (u16 element count)
     Index,          // Array indexing
+    IndexSet,       // Array element assignment
+    IndexSetState,  // Element assignment on a state array (name index)
+    CallState,      // push()/pop() on a state array (arg count, name index)
     
     // Classes and Instances
     Class,          // Create class (name index)
     Method,         // Define method (name index)
     GetProperty,    // Get property (name index)
     SetProperty,    // Set property (name index)
+    TransitionProperty, // Tracked field transition (name, causes)
+    WhyProperty,    // Query causality of an instance field (name index)
+    WhyParam,       // Query causality of a `state` parameter in this call (name index)
+    Inherit,        // Make the class on top of the stack a subclass of the one below it, popping it
+    GetSuper,       // Bind a superclass method to `this` (name index)
 
     // Misc
     Halt,           // Stop execution
 }
 
+impl OpCode {
+    /// Number of fixed operand bytes following this opcode.
+    /// `Closure` is additionally followed by an (is_local, index) byte pair per upvalue,
+    /// and `CallSpread` by one flag byte per argument.
+    pub fn operand_width(self) -> usize {
+        match self {
+            OpCode::Constant | OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal |
+            OpCode::DefineState | OpCode::DefineConst | OpCode::GetLocal | OpCode::SetLocal |
+            OpCode::IndexSetState |
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue | OpCode::Loop |
+            OpCode::Closure | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Why |
+            OpCode::InitLocalState | OpCode::Print | OpCode::Array |
+            OpCode::Class | OpCode::Method | OpCode::GetProperty | OpCode::SetProperty |
+            OpCode::WhyProperty | OpCode::WhyParam | OpCode::GetSuper | OpCode::IterLen => 2,
+            OpCode::Transition | OpCode::TransitionProperty | OpCode::CheckIteration |
+            OpCode::IterGet | OpCode::InitParamState | OpCode::CheckType => 4,
+            OpCode::TransitionLocal | OpCode::TransitionUpvalue | OpCode::TransitionParam => 6,
+            OpCode::Call | OpCode::CallSpread | OpCode::TailCall => 1,
+            OpCode::CallState => 3,
+            _ => 0,
+        }
+    }
+}
+
 impl From<u8> for OpCode {
     fn from(byte: u8) -> Self {
-        // Safety: we control all writes to bytecode
+        // Safety: callers only decode bytes that are compiler output or have
+        // passed `Chunk::validate`, which rejects anything past `Halt`
         unsafe { std::mem::transmute(byte) }
     }
 }
@@ -106,16 +161,79 @@ impl From<OpCode> for u8 {
 #[derive(Debug, Clone)]
 pub struct Chunk {
     /// The bytecode instructions
-    pub code: Vec<u8>,
+    pub(crate) code: Vec<u8>,
     
     /// Constant pool
-    pub constants: Vec<crate::value::Value>,
+    pub(crate) constants: Vec<crate::value::Value>,
+    
+    /// Pool index of each number, string, bool and nil constant, so repeats share an entry
+    constant_slots: HashMap<ConstantKey, u16>,
     
     /// Line numbers for each instruction (for error reporting)
-    pub lines: Vec<usize>,
+    pub(crate) lines: Vec<usize>,
     
     /// Variable names (for debugging and causality)
-    pub names: Vec<String>,
+    pub(crate) names: Vec<Rc<str>>,
+    
+    /// State variables read by each transition's new value, referenced by transition operands
+    pub(crate) causes: Vec<Vec<String>>,
+    
+    /// Code regions guarded by conditions, only filled in when compiling with debug info
+    pub(crate) guards: Vec<Guard>,
+    
+    /// Code regions compiled from an imported module, with the module's path
+    pub(crate) files: Vec<SourceFile>,
+    
+    /// Where each local variable is in scope, only filled in when compiling with debug info
+    pub(crate) locals: Vec<LocalName>,
+}
+
+/// A constant that compares by value. Numbers are keyed by their bits, so
+/// `0` and `-0` stay distinct and NaN can be pooled.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(Rc<str>),
+    Bool(bool),
+    Nil,
+}
+
+impl ConstantKey {
+    /// Functions are never merged: each has its own prototype
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Value::String(s) => Some(ConstantKey::String(Rc::clone(s))),
+            Value::Bool(b) => Some(ConstantKey::Bool(*b)),
+            Value::Nil => Some(ConstantKey::Nil),
+            _ => None,
+        }
+    }
+}
+
+/// A region of bytecode and the conditions that were true when it runs, outermost first
+#[derive(Debug, Clone)]
+pub(crate) struct Guard {
+    start: usize,
+    end: usize,
+    conditions: Vec<String>,
+}
+
+/// A region of bytecode compiled from another file than the chunk's own
+#[derive(Debug, Clone)]
+pub(crate) struct SourceFile {
+    start: usize,
+    end: usize,
+    path: PathBuf,
+}
+
+/// A region of bytecode in which the local in stack slot `slot` is `name`
+#[derive(Debug, Clone)]
+pub(crate) struct LocalName {
+    start: usize,
+    end: usize,
+    slot: usize,
+    name: String,
 }
 
 impl Chunk {
@@ -123,8 +241,13 @@ impl Chunk {
         Self {
             code: Vec::new(),
             constants: Vec::new(),
+            constant_slots: HashMap::new(),
             lines: Vec::new(),
             names: Vec::new(),
+            causes: Vec::new(),
+            guards: Vec::new(),
+            files: Vec::new(),
+            locals: Vec::new(),
         }
     }
     
@@ -146,20 +269,89 @@ impl Chunk {
         self.write_byte(value as u8, line);
     }
     
-    /// Add a constant and return its index
-    pub fn add_constant(&mut self, value: crate::value::Value) -> u16 {
+    /// Add a constant and return its index. A number, string, bool or nil
+    /// already in the pool is reused. `None` when the pool is full.
+    pub fn add_constant(&mut self, value: crate::value::Value) -> Option<u16> {
+        let key = ConstantKey::of(&value);
+        if let Some(idx) = key.as_ref().and_then(|k| self.constant_slots.get(k)) {
+            return Some(*idx);
+        }
+        let idx = u16::try_from(self.constants.len()).ok()?;
         self.constants.push(value);
-        (self.constants.len() - 1) as u16
+        if let Some(key) = key {
+            self.constant_slots.insert(key, idx);
+        }
+        Some(idx)
     }
     
-    /// Add a name and return its index
-    pub fn add_name(&mut self, name: String) -> u16 {
+    /// Add a name and return its index. `None` when the name table is full.
+    pub fn add_name(&mut self, name: String) -> Option<u16> {
         // Check if name already exists
-        if let Some(idx) = self.names.iter().position(|n| n == &name) {
+        if let Some(idx) = self.names.iter().position(|n| **n == *name) {
+            return Some(idx as u16);
+        }
+        let idx = u16::try_from(self.names.len()).ok()?;
+        self.names.push(name.into());
+        Some(idx)
+    }
+    
+    /// Add the state variables a transition read, returning the list's index
+    pub fn add_causes(&mut self, causes: Vec<String>) -> u16 {
+        if let Some(idx) = self.causes.iter().position(|c| c == &causes) {
             return idx as u16;
         }
-        self.names.push(name);
-        (self.names.len() - 1) as u16
+        self.causes.push(causes);
+        (self.causes.len() - 1) as u16
+    }
+    
+    /// State variables read by the transition whose causes operand is `idx`
+    pub fn causes(&self, idx: u16) -> &[String] {
+        &self.causes[idx as usize]
+    }
+    
+    /// Record that the code in `start..end` only runs while `conditions` hold
+    pub fn add_guard(&mut self, start: usize, end: usize, conditions: Vec<String>) {
+        self.guards.push(Guard { start, end, conditions });
+    }
+    
+    /// Conditions guarding the instruction at `offset`, outermost first; empty
+    /// when the chunk was compiled without debug info
+    pub fn guards_at(&self, offset: usize) -> &[String] {
+        self.guards
+            .iter()
+            .filter(|g| (g.start..g.end).contains(&offset))
+            .max_by_key(|g| g.conditions.len())
+            .map_or(&[], |g| &g.conditions)
+    }
+    
+    /// Record that the code in `start..end` was compiled from the file at `path`
+    pub fn add_file(&mut self, start: usize, end: usize, path: PathBuf) {
+        self.files.push(SourceFile { start, end, path });
+    }
+    
+    /// File the instruction at `offset` was compiled from, or `None` for the
+    /// program's own source. Nested imports are narrower, so the narrowest wins.
+    pub fn file_at(&self, offset: usize) -> Option<&Path> {
+        self.files
+            .iter()
+            .filter(|f| (f.start..f.end).contains(&offset))
+            .min_by_key(|f| f.end - f.start)
+            .map(|f| f.path.as_path())
+    }
+    
+    /// Record that the local in `slot` is called `name` in the code in `start..end`
+    pub fn add_local_name(&mut self, start: usize, end: usize, slot: usize, name: String) {
+        self.locals.push(LocalName { start, end, slot, name });
+    }
+    
+    /// Stack slot of the local `name` in scope at `offset`, innermost first;
+    /// `None` when the chunk was compiled without debug info
+    pub fn local_slot(&self, offset: usize, name: &str) -> Option<usize> {
+        self.locals
+            .iter()
+            .filter(|l| l.name == name && (l.start..l.end).contains(&offset))
+            .max_by_key(|l| l.slot)
+            .map(|l| l.slot)
     }
     
     /// Read a 16-bit value at offset
@@ -167,11 +359,64 @@ impl Chunk {
         ((self.code[offset] as u16) << 8) | (self.code[offset + 1] as u16)
     }
     
+    /// The bytecode instructions
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+    
+    /// The constant pool
+    pub fn constants(&self) -> &[crate::value::Value] {
+        &self.constants
+    }
+    
+    /// Names referenced by the bytecode
+    pub fn names(&self) -> &[Rc<str>] {
+        &self.names
+    }
+    
+    /// Source line of the byte at `offset`, or 0 if out of range
+    pub fn line(&self, offset: usize) -> usize {
+        self.lines.get(offset).copied().unwrap_or(0)
+    }
+    
+    /// Offset of the instruction following the one at `offset`.
+    /// The heap is needed to find how many upvalue pairs follow a `Closure`.
+    pub fn next_instruction(&self, offset: usize, heap: &crate::gc::Heap) -> usize {
+        let op = OpCode::from(self.code[offset]);
+        let mut next = offset + 1 + op.operand_width();
+        if op == OpCode::CallSpread {
+            next += self.code[offset + 1] as usize;
+        }
+        if op == OpCode::Closure {
+            let idx = self.read_u16(offset + 1);
+            if let crate::value::Value::Function(handle) = &self.constants[idx as usize] {
+                next += 2 * heap.get_function(*handle).map_or(0, |f| f.upvalue_count);
+            }
+        }
+        next
+    }
+    
+    /// Number of instructions in the chunk
+    pub fn instruction_count(&self, heap: &crate::gc::Heap) -> usize {
+        let mut count = 0;
+        let mut offset = 0;
+        while offset < self.code.len() {
+            offset = self.next_instruction(offset, heap);
+            count += 1;
+        }
+        count
+    }
+    
     /// Get current code length (for jump patching)
     pub fn len(&self) -> usize {
         self.code.len()
     }
     
+    /// Check whether any code has been written
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+    
     /// Patch a jump instruction at offset
     pub fn patch_jump(&mut self, offset: usize) {
         let jump = self.code.len() - offset - 2;
@@ -182,94 +427,708 @@ impl Chunk {
         self.code[offset + 1] = jump as u8;
     }
     
+    /// Where the instruction at `offset` jumps to, if it is a jump
+    pub fn jump_target(&self, offset: usize) -> Option<usize> {
+        let distance = || self.read_u16(offset + 1) as usize;
+        match OpCode::from(self.code[offset]) {
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue => Some(offset + 3 + distance()),
+            OpCode::Loop => Some(offset + 3 - distance()),
+            _ => None,
+        }
+    }
+    
+    /// Remove the instructions no path from the start of the chunk reaches,
+    /// such as code after a `return` and the implicit return after one, and
+    /// re-target the jumps that remain. Returns the number of bytes removed.
+    pub fn eliminate_dead_code(&mut self, heap: &crate::gc::Heap) -> usize {
+        let mut starts = Vec::new();
+        let mut offset = 0;
+        while offset < self.code.len() {
+            starts.push(offset);
+            offset = self.next_instruction(offset, heap);
+        }
+        
+        let mut reachable = vec![false; self.code.len()];
+        let mut pending = vec![0];
+        while let Some(offset) = pending.pop() {
+            if offset >= self.code.len() || reachable[offset] {
+                continue;
+            }
+            reachable[offset] = true;
+            pending.extend(self.jump_target(offset));
+            let op = OpCode::from(self.code[offset]);
+            if !matches!(op, OpCode::Jump | OpCode::Loop | OpCode::Return | OpCode::Halt) {
+                pending.push(self.next_instruction(offset, heap));
+            }
+        }
+        if starts.iter().all(|&start| reachable[start]) {
+            return 0;
+        }
+        
+        // New offset of every old byte: removed code maps to the next kept instruction
+        let mut relocated = vec![0; self.code.len() + 1];
+        let mut code = Vec::with_capacity(self.code.len());
+        let mut lines = Vec::with_capacity(self.lines.len());
+        let mut jumps = Vec::new();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(self.code.len());
+            relocated[start..end].fill(code.len());
+            if reachable[start] {
+                if let Some(target) = self.jump_target(start) {
+                    jumps.push((code.len(), target));
+                }
+                code.extend_from_slice(&self.code[start..end]);
+                lines.extend_from_slice(&self.lines[start..end]);
+            }
+        }
+        relocated[self.code.len()] = code.len();
+        
+        for (offset, target) in jumps {
+            let target = relocated[target];
+            let distance = if OpCode::from(code[offset]) == OpCode::Loop {
+                offset + 3 - target
+            } else {
+                target - (offset + 3)
+            };
+            code[offset + 1] = (distance >> 8) as u8;
+            code[offset + 2] = distance as u8;
+        }
+        for guard in &mut self.guards {
+            guard.start = relocated[guard.start];
+            guard.end = relocated[guard.end];
+        }
+        for file in &mut self.files {
+            file.start = relocated[file.start];
+            file.end = relocated[file.end];
+        }
+        for local in &mut self.locals {
+            local.start = relocated[local.start];
+            local.end = relocated[local.end];
+        }
+        
+        let removed = self.code.len() - code.len();
+        self.code = code;
+        self.lines = lines;
+        removed
+    }
+    
     /// Disassemble for debugging
-    pub fn disassemble(&self, name: &str) -> String {
+    /// Listing of this chunk's instructions. The heap is needed to name
+    /// function constants and to find the upvalue pairs following a `Closure`.
+    pub fn disassemble(&self, name: &str, heap: &crate::gc::Heap) -> String {
         let mut result = format!("== {} ==\n", name);
         let mut offset = 0;
         
         while offset < self.code.len() {
-            let (s, new_offset) = self.disassemble_instruction(offset);
-            result.push_str(&s);
+            result.push_str(&self.disassemble_instruction(offset, heap));
             result.push('\n');
-            offset = new_offset;
+            offset = self.next_instruction(offset, heap);
         }
         
         result
     }
     
-    fn disassemble_instruction(&self, offset: usize) -> (String, usize) {
+    /// Listing of this chunk followed by the chunk of every function in its
+    /// constant pool, recursively, each under the function's name
+    pub fn disassemble_all(&self, name: &str, heap: &crate::gc::Heap) -> String {
+        let mut result = self.disassemble(name, heap);
+        for constant in &self.constants {
+            if let Value::Function(handle) = constant {
+                if let Some(function) = heap.get_function(*handle) {
+                    result.push('\n');
+                    result.push_str(&function.chunk.disassemble_all(&format!("fn {}", function.name), heap));
+                }
+            }
+        }
+        result
+    }
+    
+    /// ` causes:[a, b]` for the causes operand at `offset`, or nothing if the list is empty
+    fn format_causes(&self, offset: usize) -> String {
+        let causes = self.causes(self.read_u16(offset));
+        if causes.is_empty() {
+            String::new()
+        } else {
+            format!(" causes:[{}]", causes.join(", "))
+        }
+    }
+    
+    fn disassemble_instruction(&self, offset: usize, heap: &crate::gc::Heap) -> String {
         let op = OpCode::from(self.code[offset]);
-        let line = self.lines.get(offset).copied().unwrap_or(0);
+        let line = self.line(offset);
         
-        let (instr, new_offset) = match op {
+        let instr = match op {
             OpCode::Constant => {
                 let idx = self.read_u16(offset + 1);
                 let val = &self.constants[idx as usize];
-                (format!("CONSTANT {:04} '{}'", idx, val), offset + 3)
+                format!("CONSTANT {:04} '{}'", idx, val)
             }
             OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal | 
-            OpCode::DefineState | OpCode::Transition |
-            OpCode::Class | OpCode::Method | OpCode::GetProperty | OpCode::SetProperty => {
+            OpCode::DefineState | OpCode::DefineConst | OpCode::InitLocalState |
+            OpCode::IndexSetState |
+            OpCode::Class | OpCode::Method | OpCode::GetProperty | OpCode::SetProperty |
+            OpCode::WhyProperty | OpCode::WhyParam | OpCode::GetSuper => {
                 let idx = self.read_u16(offset + 1);
                 let name = &self.names[idx as usize];
-                (format!("{:?} {:04} '{}'", op, idx, name), offset + 3)
+                format!("{:?} {:04} '{}'", op, idx, name)
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::IterLen => {
+                let slot = self.read_u16(offset + 1);
+                format!("{:?} {:04}", op, slot)
+            }
+            OpCode::CheckIteration => {
+                let iterable = self.read_u16(offset + 1);
+                let version = self.read_u16(offset + 3);
+                format!("{:?} slot:{} version:{}", op, iterable, version)
+            }
+            OpCode::IterGet => {
+                let iterable = self.read_u16(offset + 1);
+                let index = self.read_u16(offset + 3);
+                format!("{:?} slot:{} index:{}", op, iterable, index)
+            }
+            OpCode::Transition | OpCode::TransitionProperty => {
+                let idx = self.read_u16(offset + 1);
+                let name = &self.names[idx as usize];
+                let causes = self.format_causes(offset + 3);
+                format!("{:?} {:04} '{}'{}", op, idx, name, causes)
+            }
+            OpCode::InitParamState => {
+                let slot = self.read_u16(offset + 1);
+                let name_idx = self.read_u16(offset + 3);
+                let name = &self.names[name_idx as usize];
+                format!("{:?} slot:{} name:'{}'", op, slot, name)
             }
-            OpCode::GetLocal | OpCode::SetLocal => {
+            OpCode::CheckType => {
                 let slot = self.read_u16(offset + 1);
-                (format!("{:?} {:04}", op, slot), offset + 3)
+                let type_idx = self.read_u16(offset + 3);
+                let type_name = &self.names[type_idx as usize];
+                format!("{:?} slot:{} type:'{}'", op, slot, type_name)
             }
-            OpCode::TransitionLocal => {
+            OpCode::TransitionLocal | OpCode::TransitionParam => {
                 let slot = self.read_u16(offset + 1);
                 let name_idx = self.read_u16(offset + 3);
                 let name = &self.names[name_idx as usize];
-                (format!("{:?} slot:{} name:'{}'", op, slot, name), offset + 5)
+                let causes = self.format_causes(offset + 5);
+                format!("{:?} slot:{} name:'{}'{}", op, slot, name, causes)
             }
 
-            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue => {
-                let jump = self.read_u16(offset + 1);
-                (format!("{:?} -> {:04}", op, offset + 3 + jump as usize), offset + 3)
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue | OpCode::Loop => {
+                format!("{:?} -> {:04}", op, self.jump_target(offset).unwrap_or(0))
             }
-            OpCode::Loop => {
-                let jump = self.read_u16(offset + 1);
-                (format!("{:?} -> {:04}", op, offset + 3 - jump as usize), offset + 3)
+            OpCode::Call | OpCode::TailCall => {
+                let count = self.code[offset + 1];
+                format!("{:?} ({})", op, count)
             }
-            OpCode::Call | OpCode::Print | OpCode::Array => {
+            OpCode::CallState => {
                 let count = self.code[offset + 1];
-                (format!("{:?} ({})", op, count), offset + 2)
+                let idx = self.read_u16(offset + 2);
+                let name = &self.names[idx as usize];
+                format!("{:?} ({}) {:04} '{}'", op, count, idx, name)
+            }
+            OpCode::Print | OpCode::Array => {
+                let count = self.read_u16(offset + 1);
+                format!("{:?} ({})", op, count)
+            }
+            OpCode::CallSpread => {
+                let count = self.code[offset + 1] as usize;
+                let flags: Vec<&str> = self.code[offset + 2..offset + 2 + count]
+                    .iter()
+                    .map(|&f| if f != 0 { "..." } else { "_" })
+                    .collect();
+                format!("{:?} ({}) [{}]", op, count, flags.join(" "))
             }
             OpCode::Closure => {
                 let idx = self.read_u16(offset + 1);
-                (format!("CLOSURE {:04}", idx), offset + 3)
+                let function = match &self.constants[idx as usize] {
+                    Value::Function(handle) => heap.get_function(*handle),
+                    _ => None,
+                };
+                let mut text = format!("CLOSURE {:04} <fn {}>", idx, function.map_or("?", |f| f.name.as_str()));
+                // Each captured variable follows as an (is_local, index) byte pair
+                for i in 0..function.map_or(0, |f| f.upvalue_count) {
+                    let pair = offset + 3 + 2 * i;
+                    let kind = if self.code[pair] == 1 { "local" } else { "upvalue" };
+                    text.push_str(&format!("\n{:04}    |   {} {}", pair, kind, self.code[pair + 1]));
+                }
+                text
             }
             OpCode::GetUpvalue | OpCode::SetUpvalue => {
                 let slot = self.read_u16(offset + 1);
-                (format!("{:?} {:04}", op, slot), offset + 3)
+                format!("{:?} {:04}", op, slot)
             }
             OpCode::TransitionUpvalue => {
                 let slot = self.read_u16(offset + 1);
                 let name_idx = self.read_u16(offset + 3);
                 let name = &self.names[name_idx as usize];
-                (format!("{:?} idx:{} name:'{}'", op, slot, name), offset + 5)
+                let causes = self.format_causes(offset + 5);
+                format!("{:?} idx:{} name:'{}'{}", op, slot, name, causes)
             }
-            OpCode::CloseUpvalue => {
-                (format!("{:?}", op), offset + 1)
+            OpCode::Why => {
+                let idx = self.read_u16(offset + 1);
+                let name = &self.names[idx as usize];
+                format!("{:?} {:04} '{}'", op, idx, name)
             }
-            _ => (format!("{:?}", op), offset + 1),
+            _ => format!("{:?}", op),
         };
         
-        (format!("{:04} {:4} {}", offset, line, instr), new_offset)
+        format!("{:04} {:4} {}", offset, line, instr)
     }
 }
 
-impl Default for Chunk {
-    fn default() -> Self {
-        Self::new()
+// ==================== Serialization ====================
+
+impl Chunk {
+    /// Encode the chunk, and the chunks of the functions in its constant pool,
+    /// as a `.skyc` file. Function constants are looked up in `heap`.
+    pub fn serialize(&self, heap: &crate::gc::Heap) -> Result<Vec<u8>> {
+        let mut out = BYTECODE_MAGIC.to_vec();
+        out.push(BYTECODE_VERSION);
+        self.write_to(&mut out, heap)?;
+        Ok(out)
+    }
+    
+    /// Decode a `.skyc` file written by `serialize`, allocating its functions in `heap`
+    pub fn deserialize(bytes: &[u8], heap: &mut crate::gc::Heap) -> Result<Chunk> {
+        if !is_bytecode(bytes) {
+            return Err(invalid_bytecode("missing SKYC header"));
+        }
+        let version = bytes[BYTECODE_MAGIC.len()];
+        if version != BYTECODE_VERSION {
+            return Err(invalid_bytecode(&format!(
+                "format version {} is not supported (expected {}); rebuild it from source",
+                version, BYTECODE_VERSION
+            )));
+        }
+        let mut reader = Reader { bytes, pos: BYTECODE_MAGIC.len() + 1, depth: 0 };
+        let chunk = reader.chunk(heap, 0, 0)?;
+        if reader.pos != bytes.len() {
+            return Err(invalid_bytecode("trailing bytes after the program"));
+        }
+        Ok(chunk)
+    }
+    
+    fn write_to(&self, out: &mut Vec<u8>, heap: &crate::gc::Heap) -> Result<()> {
+        write_varint(out, self.code.len() as u64);
+        out.extend_from_slice(&self.code);
+        for &line in &self.lines {
+            write_varint(out, line as u64);
+        }
+        write_strings(out, &self.names);
+        write_varint(out, self.causes.len() as u64);
+        for causes in &self.causes {
+            write_strings(out, causes);
+        }
+        write_varint(out, self.guards.len() as u64);
+        for guard in &self.guards {
+            write_varint(out, guard.start as u64);
+            write_varint(out, guard.end as u64);
+            write_strings(out, &guard.conditions);
+        }
+        write_varint(out, self.files.len() as u64);
+        for file in &self.files {
+            write_varint(out, file.start as u64);
+            write_varint(out, file.end as u64);
+            write_str(out, &file.path.to_string_lossy());
+        }
+        write_varint(out, self.locals.len() as u64);
+        for local in &self.locals {
+            write_varint(out, local.start as u64);
+            write_varint(out, local.end as u64);
+            write_varint(out, local.slot as u64);
+            write_str(out, &local.name);
+        }
+        write_varint(out, self.constants.len() as u64);
+        for constant in &self.constants {
+            match constant {
+                Value::Nil => out.push(0),
+                Value::Bool(false) => out.push(1),
+                Value::Bool(true) => out.push(2),
+                Value::Number(n) => {
+                    out.push(3);
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+                Value::String(s) => {
+                    out.push(4);
+                    write_str(out, s);
+                }
+                Value::Function(handle) => {
+                    let function = heap.get_function(*handle)
+                        .ok_or_else(|| invalid_bytecode("function constant is not on the heap"))?;
+                    out.push(5);
+                    write_str(out, &function.name);
+                    write_strings(out, &function.params);
+                    write_varint(out, function.upvalue_count as u64);
+                    function.chunk.write_to(out, heap)?;
+                }
+                other => return Err(invalid_bytecode(&format!("cannot store a {} constant", other.type_name()))),
+            }
+        }
+        Ok(())
+    }
+    
+    /// Check that every instruction is a known opcode with its operands present
+    /// and in range, that every jump lands on an instruction and that the code
+    /// never takes more off the stack than is on it, so a damaged file fails
+    /// to load instead of misbehaving at run time. A function with `arity`
+    /// parameters starts with them and itself on the stack.
+    fn validate(&self, heap: &crate::gc::Heap, upvalue_count: usize, arity: usize) -> Result<()> {
+        if self.lines.len() != self.code.len() {
+            return Err(invalid_bytecode("line table does not match the code"));
+        }
+        let mut starts = Vec::new();
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let byte = self.code[offset];
+            if byte > OpCode::Halt as u8 {
+                return Err(invalid_bytecode(&format!("unknown opcode {} at offset {}", byte, offset)));
+            }
+            let op = OpCode::from(byte);
+            // The compiler turns these into jumps; the VM never runs them
+            if matches!(op, OpCode::Break | OpCode::Continue) {
+                return Err(invalid_bytecode(&format!("{:?} at offset {} is not executable", op, offset)));
+            }
+            let fixed_end = offset + 1 + op.operand_width();
+            if fixed_end > self.code.len() {
+                return Err(invalid_bytecode(&format!("truncated instruction at offset {}", offset)));
+            }
+            self.validate_operands(op, offset, heap, upvalue_count)?;
+            starts.push(offset);
+            offset = self.next_instruction(offset, heap);
+        }
+        if offset != self.code.len() {
+            return Err(invalid_bytecode("truncated instruction at the end of the code"));
+        }
+        let last = starts.last().map(|&start| OpCode::from(self.code[start]));
+        if !matches!(last, Some(OpCode::Return | OpCode::Halt | OpCode::Jump | OpCode::Loop)) {
+            return Err(invalid_bytecode("the code runs past its end"));
+        }
+        for &start in &starts {
+            let op = OpCode::from(self.code[start]);
+            if !matches!(op, OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfTrue | OpCode::Loop) {
+                continue;
+            }
+            // A loop jumping back past the start of the code has no target at all
+            let distance = self.read_u16(start + 1) as usize;
+            let target = if op == OpCode::Loop { (start + 3).checked_sub(distance) } else { Some(start + 3 + distance) };
+            if target.is_none_or(|t| starts.binary_search(&t).is_err()) {
+                return Err(invalid_bytecode(&format!("jump at offset {} does not land on an instruction", start)));
+            }
+        }
+        self.validate_stack(heap, arity + 1)
+    }
+    
+    /// Follow every path through the code from `entry` values on the stack,
+    /// checking that each instruction finds the values and locals it uses and
+    /// that paths meeting at an instruction agree on the stack's depth. Only
+    /// called once jumps are known to land on instructions.
+    fn validate_stack(&self, heap: &crate::gc::Heap, entry: usize) -> Result<()> {
+        let mut depths: Vec<Option<usize>> = vec![None; self.code.len()];
+        let mut pending = vec![(0, entry)];
+        while let Some((offset, depth)) = pending.pop() {
+            match depths[offset] {
+                Some(seen) if seen == depth => continue,
+                Some(_) => return Err(invalid_bytecode(&format!("stack depth differs between paths to offset {}", offset))),
+                None => depths[offset] = Some(depth),
+            }
+            let op = OpCode::from(self.code[offset]);
+            let (pops, pushes) = self.stack_effect(op, offset);
+            if pops > depth {
+                return Err(invalid_bytecode(&format!("instruction at offset {} pops more than the stack holds", offset)));
+            }
+            for slot in self.slot_operands(op, offset, heap) {
+                // A slot written after popping the new value must be below it
+                let writes_after_pop = matches!(op, OpCode::TransitionLocal | OpCode::TransitionParam);
+                if slot >= depth - usize::from(writes_after_pop) {
+                    return Err(invalid_bytecode(&format!("local slot at offset {} is out of range", offset)));
+                }
+            }
+            let Some(pushes) = pushes else { continue };
+            let depth = depth - pops + pushes;
+            let next = self.next_instruction(offset, heap);
+            let jump = || self.read_u16(offset + 1) as usize;
+            match op {
+                OpCode::Jump => pending.push((offset + 3 + jump(), depth)),
+                OpCode::Loop => pending.push((offset + 3 - jump(), depth)),
+                OpCode::JumpIfFalse | OpCode::JumpIfTrue => {
+                    pending.push((offset + 3 + jump(), depth));
+                    pending.push((next, depth));
+                }
+                _ => pending.push((next, depth)),
+            }
+        }
+        Ok(())
+    }
+    
+    /// How many values the `op` at `offset` pops and then pushes. Pushes are
+    /// `None` for instructions that leave the function.
+    fn stack_effect(&self, op: OpCode, offset: usize) -> (usize, Option<usize>) {
+        let count = || self.read_u16(offset + 1) as usize;
+        let args = || self.code[offset + 1] as usize;
+        match op {
+            OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False |
+            OpCode::GetGlobal | OpCode::GetLocal | OpCode::GetUpvalue | OpCode::WhyParam |
+            OpCode::Closure | OpCode::Why | OpCode::Time | OpCode::IterLen | OpCode::IterGet |
+            OpCode::Class => (0, Some(1)),
+            OpCode::Jump | OpCode::Loop | OpCode::InitParamState | OpCode::CheckType |
+            OpCode::CheckIteration | OpCode::Break | OpCode::Continue => (0, Some(0)),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::DefineState | OpCode::DefineConst |
+            OpCode::Transition | OpCode::TransitionLocal | OpCode::TransitionParam |
+            OpCode::TransitionUpvalue | OpCode::CloseUpvalue => (1, Some(0)),
+            OpCode::InitLocalState | OpCode::SetGlobal | OpCode::SetLocal | OpCode::SetUpvalue |
+            OpCode::Negate | OpCode::Not | OpCode::JumpIfFalse | OpCode::JumpIfTrue |
+            OpCode::IterStart | OpCode::GetProperty | OpCode::WhyProperty => (1, Some(1)),
+            OpCode::Dup | OpCode::IterVersion => (1, Some(2)),
+            OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Modulo |
+            OpCode::Equal | OpCode::NotEqual | OpCode::Less | OpCode::LessEqual |
+            OpCode::Greater | OpCode::GreaterEqual | OpCode::Index |
+            OpCode::Method | OpCode::Inherit | OpCode::GetSuper | OpCode::SetProperty => (2, Some(1)),
+            OpCode::TransitionProperty => (2, Some(0)),
+            OpCode::IndexSet | OpCode::IndexSetState => (3, Some(1)),
+            OpCode::Print | OpCode::Array => (count(), Some(1)),
+            OpCode::Call | OpCode::CallSpread | OpCode::CallState => (args() + 1, Some(1)),
+            OpCode::TailCall => (args() + 1, None),
+            OpCode::Return | OpCode::Halt => (1, None),
+        }
+    }
+    
+    /// The local slots the `op` at `offset` reads or writes
+    fn slot_operands(&self, op: OpCode, offset: usize, heap: &crate::gc::Heap) -> Vec<usize> {
+        let slot = |at: usize| self.read_u16(at) as usize;
+        match op {
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::TransitionLocal | OpCode::TransitionParam |
+            OpCode::InitParamState | OpCode::CheckType | OpCode::IterLen => vec![slot(offset + 1)],
+            OpCode::IterGet | OpCode::CheckIteration => vec![slot(offset + 1), slot(offset + 3)],
+            OpCode::Closure => {
+                let pairs = (self.next_instruction(offset, heap) - offset - 3) / 2;
+                (0..pairs)
+                    .map(|i| offset + 3 + 2 * i)
+                    .filter(|&pair| self.code[pair] != 0)
+                    .map(|pair| self.code[pair + 1] as usize)
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+    
+    /// Check the pool and upvalue indices among the operands of the `op` at
+    /// `offset`, in a function with `upvalue_count` upvalues
+    fn validate_operands(&self, op: OpCode, offset: usize, heap: &crate::gc::Heap, upvalue_count: usize) -> Result<()> {
+        let out_of_range = |what: &str, at: usize| {
+            invalid_bytecode(&format!("{} index at offset {} is out of range", what, at))
+        };
+        let check = |len: usize, what: &str, at: usize| {
+            if (self.read_u16(at) as usize) < len { Ok(()) } else { Err(out_of_range(what, offset)) }
+        };
+        match op {
+            OpCode::Constant => check(self.constants.len(), "constant", offset + 1)?,
+            OpCode::Closure => {
+                let idx = self.read_u16(offset + 1) as usize;
+                let Some(Value::Function(handle)) = self.constants.get(idx) else {
+                    return Err(invalid_bytecode(&format!("closure at offset {} does not refer to a function", offset)));
+                };
+                // Captures of the enclosing function's upvalues must exist
+                let captures = heap.get_function(*handle).map_or(0, |f| f.upvalue_count);
+                for pair in (0..captures).map(|i| offset + 3 + 2 * i) {
+                    let inherited = self.code.get(pair) == Some(&0);
+                    if inherited && self.code.get(pair + 1).is_some_and(|&i| i as usize >= upvalue_count) {
+                        return Err(out_of_range("upvalue", offset));
+                    }
+                }
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => check(upvalue_count, "upvalue", offset + 1)?,
+            OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal |
+            OpCode::DefineState | OpCode::DefineConst | OpCode::InitLocalState |
+            OpCode::IndexSetState | OpCode::Why |
+            OpCode::Class | OpCode::Method | OpCode::GetProperty | OpCode::SetProperty |
+            OpCode::WhyProperty | OpCode::WhyParam | OpCode::GetSuper => {
+                check(self.names.len(), "name", offset + 1)?;
+            }
+            OpCode::Transition | OpCode::TransitionProperty => {
+                check(self.names.len(), "name", offset + 1)?;
+                check(self.causes.len(), "causes", offset + 3)?;
+            }
+            OpCode::InitParamState | OpCode::CheckType => check(self.names.len(), "name", offset + 3)?,
+            OpCode::CallState => check(self.names.len(), "name", offset + 2)?,
+            OpCode::TransitionLocal | OpCode::TransitionParam | OpCode::TransitionUpvalue => {
+                if op == OpCode::TransitionUpvalue {
+                    check(upvalue_count, "upvalue", offset + 1)?;
+                }
+                check(self.names.len(), "name", offset + 3)?;
+                check(self.causes.len(), "causes", offset + 5)?;
+            }
+            _ => {}
+        }
+        Ok(())
     }
 }
 
-impl fmt::Display for Chunk {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.disassemble("chunk"))
+/// Whether `bytes` start like a `.skyc` file (of any version)
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.len() > BYTECODE_MAGIC.len() && bytes.starts_with(BYTECODE_MAGIC)
+}
+
+fn invalid_bytecode(msg: &str) -> SkyHetuError {
+    SkyHetuError::new(ErrorKind::InvalidBytecode(msg.to_string()), None)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_strings(out: &mut Vec<u8>, strings: &[impl AsRef<str>]) {
+    write_varint(out, strings.len() as u64);
+    for s in strings {
+        write_str(out, s.as_ref());
+    }
+}
+
+/// How deeply function constants may nest in a `.skyc` file
+const MAX_FUNCTION_NESTING: usize = 64;
+
+/// Cursor over the bytes of a `.skyc` file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Function constants being read around the current chunk
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid_bytecode("unexpected end of file"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+    
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    
+    fn varint(&mut self) -> Result<usize> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                return Err(invalid_bytecode("varint too long"));
+            }
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(result).map_err(|_| invalid_bytecode("length out of range"));
+            }
+            shift += 7;
+        }
+    }
+    
+    /// A count of items that each take at least one byte, checked against what is left
+    fn count(&mut self) -> Result<usize> {
+        let count = self.varint()?;
+        if count > self.bytes.len() - self.pos {
+            return Err(invalid_bytecode("unexpected end of file"));
+        }
+        Ok(count)
+    }
+    
+    fn string(&mut self) -> Result<String> {
+        let len = self.varint()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_bytecode("invalid UTF-8 in a string"))
+    }
+    
+    fn strings(&mut self) -> Result<Vec<String>> {
+        (0..self.count()?).map(|_| self.string()).collect()
+    }
+    
+    /// Read a chunk whose function has `upvalue_count` upvalues and `arity` parameters
+    fn chunk(&mut self, heap: &mut crate::gc::Heap, upvalue_count: usize, arity: usize) -> Result<Chunk> {
+        let mut chunk = self.tables()?;
+        for _ in 0..self.count()? {
+            let constant = self.constant(heap)?;
+            chunk.constants.push(constant);
+        }
+        chunk.validate(heap, upvalue_count, arity)?;
+        Ok(chunk)
+    }
+    
+    /// Read the code of a chunk and everything that describes it, up to its constants
+    fn tables(&mut self) -> Result<Chunk> {
+        let mut chunk = Chunk::new();
+        let code_len = self.count()?;
+        chunk.code = self.take(code_len)?.to_vec();
+        chunk.lines = (0..code_len).map(|_| self.varint()).collect::<Result<_>>()?;
+        chunk.names = self.strings()?.into_iter().map(Rc::from).collect();
+        chunk.causes = (0..self.count()?).map(|_| self.strings()).collect::<Result<_>>()?;
+        for _ in 0..self.count()? {
+            let start = self.varint()?;
+            let end = self.varint()?;
+            let conditions = self.strings()?;
+            chunk.guards.push(Guard { start, end, conditions });
+        }
+        for _ in 0..self.count()? {
+            let start = self.varint()?;
+            let end = self.varint()?;
+            let path = PathBuf::from(self.string()?);
+            chunk.files.push(SourceFile { start, end, path });
+        }
+        for _ in 0..self.count()? {
+            let start = self.varint()?;
+            let end = self.varint()?;
+            let slot = self.varint()?;
+            let name = self.string()?;
+            chunk.locals.push(LocalName { start, end, slot, name });
+        }
+        Ok(chunk)
+    }
+    
+    /// Read a function constant and allocate it in `heap`
+    fn function(&mut self, heap: &mut crate::gc::Heap) -> Result<crate::gc::Handle> {
+        let name = self.string()?;
+        let params = self.strings()?;
+        let captures = self.varint()?;
+        if self.depth == MAX_FUNCTION_NESTING {
+            return Err(invalid_bytecode("functions nested too deeply"));
+        }
+        self.depth += 1;
+        let body = self.chunk(heap, captures, params.len())?;
+        self.depth -= 1;
+        let function = Function::new(name, params, std::rc::Rc::new(body), captures);
+        Ok(heap.alloc_function(function))
+    }
+    
+    fn constant(&mut self, heap: &mut crate::gc::Heap) -> Result<Value> {
+        Ok(match self.u8()? {
+            0 => Value::Nil,
+            1 => Value::Bool(false),
+            2 => Value::Bool(true),
+            3 => {
+                let bytes: [u8; 8] = self.take(8)?.try_into().expect("took 8 bytes");
+                Value::Number(f64::from_le_bytes(bytes))
+            }
+            4 => Value::String(self.string()?.into()),
+            5 => Value::Function(self.function(heap)?),
+            tag => return Err(invalid_bytecode(&format!("unknown constant tag {}", tag))),
+        })
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -282,7 +1141,7 @@ mod tests {
     fn test_chunk_write() {
         let mut chunk = Chunk::new();
         chunk.write(OpCode::Constant, 1);
-        let idx = chunk.add_constant(Value::Number(42.0));
+        let idx = chunk.add_constant(Value::Number(42.0)).unwrap();
         chunk.write_u16(idx, 1);
         chunk.write(OpCode::Return, 1);
         
@@ -294,12 +1153,46 @@ mod tests {
     fn test_disassemble() {
         let mut chunk = Chunk::new();
         chunk.write(OpCode::Constant, 1);
-        let idx = chunk.add_constant(Value::Number(1.5));
+        let idx = chunk.add_constant(Value::Number(1.5)).unwrap();
         chunk.write_u16(idx, 1);
         chunk.write(OpCode::Return, 2);
         
-        let disasm = chunk.disassemble("test");
+        let disasm = chunk.disassemble("test", &crate::gc::Heap::new());
         assert!(disasm.contains("CONSTANT"));
         assert!(disasm.contains("1.5"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_disassemble_wide_counts() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Array, 1);
+        chunk.write_u16(300, 1);
+        chunk.write(OpCode::Return, 1);
+        
+        assert_eq!(OpCode::Array.operand_width(), 2);
+        assert_eq!(OpCode::Print.operand_width(), 2);
+        assert!(chunk.disassemble("test", &crate::gc::Heap::new()).contains("Array (300)"));
+        assert_eq!(chunk.instruction_count(&crate::gc::Heap::new()), 2);
+    }
+    
+    #[test]
+    fn test_constants_are_pooled_by_value() {
+        let mut chunk = Chunk::new();
+        let one = chunk.add_constant(Value::Number(1.0));
+        assert_eq!(chunk.add_constant(Value::Number(1.0)), one);
+        assert_eq!(chunk.add_constant(Value::String("a".into())), chunk.add_constant(Value::String("a".into())));
+        assert_ne!(chunk.add_constant(Value::Number(-0.0)), chunk.add_constant(Value::Number(0.0)));
+        assert_eq!(chunk.add_constant(Value::Number(f64::NAN)), chunk.add_constant(Value::Number(f64::NAN)));
+        assert_eq!(chunk.constants.len(), 5);
+    }
+    
+    #[test]
+    fn test_full_pool_is_refused() {
+        let mut chunk = Chunk::new();
+        for i in 0..=u16::MAX {
+            assert_eq!(chunk.add_constant(Value::Number(i as f64)), Some(i));
+        }
+        assert_eq!(chunk.add_constant(Value::Number(-1.0)), None);
+        assert_eq!(chunk.add_constant(Value::Number(7.0)), Some(7));
+    }
+}
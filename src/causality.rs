@@ -3,20 +3,48 @@
 //! Records all state mutations with timestamps and values,
 //! enabling the `why()` introspection function.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use crate::value::Value;
 use std::time::Instant;
 
+/// Magic bytes at the start of a binary causality log
+pub const BINARY_MAGIC: &[u8; 4] = b"SKHC";
+
+/// Version of the binary causality format; bumped whenever a record is added
+/// or changes. Version 2 added declarations (tag `0x02`) and version 3 the
+/// causes of a transition (tag `0x03`). Each version only adds to the one
+/// before, so older logs still load.
+pub const BINARY_VERSION: u8 = 3;
+
+/// Version of the JSON document written by `export_all_json`
+pub const JSON_VERSION: u32 = 1;
+
+/// Events kept in memory while streaming when no retention limit is set
+pub const DEFAULT_STREAM_RETENTION: usize = 1024;
+
+/// What a causality event records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A `state` declaration giving the variable its initial value
+    Init,
+    /// A `->` transition
+    Transition,
+}
+
 /// A single mutation event
 #[derive(Debug, Clone)]
 pub struct MutationEvent {
     /// Unique event ID
     pub id: usize,
     
+    /// Declaration or transition
+    pub kind: EventKind,
+    
     /// Name of the variable that was mutated
     pub variable: String,
     
-    /// Value before mutation
+    /// Value before mutation (nil for `Init` events)
     pub old_value: Value,
     
     /// Value after mutation
@@ -27,10 +55,16 @@ pub struct MutationEvent {
     
     /// Source location info
     pub location: Option<String>,
+    
+    /// State variables the new value was computed from
+    pub caused_by: Vec<String>,
 }
 
 impl std::fmt::Display for MutationEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.kind == EventKind::Init {
+            return write!(f, "[#{}] {} = {}", self.id, self.variable, self.new_value);
+        }
         write!(
             f,
             "[#{}] {} : {} -> {}",
@@ -42,14 +76,153 @@ impl std::fmt::Display for MutationEvent {
     }
 }
 
+/// Constant-time summary of a variable's transitions, kept even when
+/// individual events have been evicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarSummary {
+    /// ID of the most recent event
+    pub last_event_id: usize,
+    
+    /// Timestamp of the most recent event
+    pub last_timestamp: usize,
+    
+    /// Transitions recorded since the variable was first seen (declarations excluded)
+    pub transition_count: usize,
+    
+    /// Timestamp of the first recorded event, normally the declaration
+    pub first_seen: usize,
+}
+
+impl VarSummary {
+    fn update(summary: Option<&mut VarSummary>, event: &MutationEvent) -> Option<VarSummary> {
+        let transitions = (event.kind == EventKind::Transition) as usize;
+        match summary {
+            Some(summary) => {
+                summary.last_event_id = event.id;
+                summary.last_timestamp = event.timestamp;
+                summary.transition_count += transitions;
+                None
+            }
+            None => Some(VarSummary {
+                last_event_id: event.id,
+                last_timestamp: event.timestamp,
+                transition_count: transitions,
+                first_seen: event.timestamp,
+            }),
+        }
+    }
+}
+
+/// What remains of a variable's evicted events once they leave memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvictionDigest {
+    /// Number of events evicted so far
+    pub count: usize,
+    
+    /// Smallest numeric value among the evicted events' new values
+    pub min: Option<f64>,
+    
+    /// Largest numeric value among the evicted events' new values
+    pub max: Option<f64>,
+    
+    /// Timestamp of the oldest evicted event
+    pub first_timestamp: usize,
+    
+    /// Timestamp of the newest evicted event
+    pub last_timestamp: usize,
+}
+
+impl EvictionDigest {
+    fn new(event: &MutationEvent) -> Self {
+        let mut digest = Self {
+            count: 0,
+            min: None,
+            max: None,
+            first_timestamp: event.timestamp,
+            last_timestamp: event.timestamp,
+        };
+        digest.add(event);
+        digest
+    }
+    
+    fn add(&mut self, event: &MutationEvent) {
+        self.count += 1;
+        self.last_timestamp = event.timestamp;
+        if let Value::Number(n) = event.new_value {
+            self.min = Some(self.min.map_or(n, |m| m.min(n)));
+            self.max = Some(self.max.map_or(n, |m| m.max(n)));
+        }
+    }
+}
+
+/// Callback receiving each batch of evicted events before they are dropped
+pub type EvictionHandler = Box<dyn FnMut(&[MutationEvent])>;
+
+/// Wrapper so the log can keep deriving `Debug`
+struct Evictions(EvictionHandler);
+
+impl std::fmt::Debug for Evictions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EvictionHandler")
+    }
+}
+
+/// Serialization formats for exporting the causality log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per event, newline separated
+    Jsonl,
+    /// Compact length-prefixed records (see `export_to`)
+    Binary,
+}
+
+/// An open writer that receives events as they are recorded
+struct CausalityStream {
+    writer: Box<dyn Write>,
+    format: ExportFormat,
+    /// Variable names already written to a binary stream
+    names: HashMap<String, usize>,
+}
+
+impl std::fmt::Debug for CausalityStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CausalityStream({:?})", self.format)
+    }
+}
+
+impl CausalityStream {
+    fn write_event(&mut self, event: &MutationEvent) -> io::Result<()> {
+        match self.format {
+            ExportFormat::Jsonl => write_json_event(&mut self.writer, event),
+            ExportFormat::Binary => {
+                let next = self.names.len();
+                let name_idx = *self.names.entry(event.variable.clone()).or_insert(next);
+                if name_idx == next {
+                    write_binary_name(&mut self.writer, &event.variable)?;
+                }
+                write_binary_event(&mut self.writer, event, name_idx)
+            }
+        }
+    }
+}
+
 /// The causality log - tracks all state mutations
 #[derive(Debug, Default)]
 pub struct CausalityLog {
-    /// All events in order
-    events: Vec<MutationEvent>,
+    /// Retained events in order (oldest may have been evicted)
+    events: VecDeque<MutationEvent>,
+    
+    /// Retained event ids indexed by variable name
+    by_variable: HashMap<String, VecDeque<usize>>,
+    
+    /// Per-variable summaries, never evicted
+    summaries: HashMap<String, VarSummary>,
     
-    /// Events indexed by variable name
-    by_variable: HashMap<String, Vec<usize>>,
+    /// Per-variable digests of evicted events
+    digests: HashMap<String, EvictionDigest>,
+    
+    /// Called with every batch of evicted events
+    eviction_handler: Option<Evictions>,
     
     /// Logical clock for event ordering
     clock: usize,
@@ -57,6 +230,15 @@ pub struct CausalityLog {
     /// Next event ID
     next_id: usize,
     
+    /// Maximum number of events kept in memory (None = unlimited)
+    retention: Option<usize>,
+    
+    /// Writer receiving every event as it is recorded
+    stream: Option<CausalityStream>,
+    
+    /// First error hit while streaming; streaming stops after it
+    stream_error: Option<io::Error>,
+    
     /// Start time for relative timestamps
     _start: Option<Instant>,
 }
@@ -65,21 +247,180 @@ impl CausalityLog {
     /// Create a new causality log
     pub fn new() -> Self {
         Self {
-            events: Vec::new(),
-            by_variable: HashMap::new(),
-            clock: 0,
-            next_id: 0,
             _start: Some(Instant::now()),
+            ..Self::default()
+        }
+    }
+    
+    /// Limit how many events are kept in memory. Older events are evicted first.
+    pub fn set_retention(&mut self, limit: Option<usize>) {
+        self.retention = limit;
+        self.evict();
+    }
+    
+    /// Call `handler` with each batch of events evicted by the retention
+    /// limit, oldest first, before they are dropped. Streamed events were
+    /// already written when recorded, so streaming needs no handler.
+    pub fn set_eviction_handler(&mut self, handler: Option<EvictionHandler>) {
+        self.eviction_handler = handler.map(Evictions);
+    }
+    
+    /// Append every future event to `writer` as it is recorded.
+    ///
+    /// Streamed events don't need to stay in memory, so while streaming the
+    /// in-memory window is the retention limit, or `DEFAULT_STREAM_RETENTION`
+    /// events when none is set.
+    pub fn stream_to(&mut self, mut writer: Box<dyn Write>, format: ExportFormat) -> io::Result<()> {
+        if format == ExportFormat::Binary {
+            write_binary_header(&mut writer)?;
+        }
+        self.stream = Some(CausalityStream { writer, format, names: HashMap::new() });
+        self.evict();
+        Ok(())
+    }
+    
+    /// Flush and detach the streaming writer, if any
+    pub fn stop_streaming(&mut self) -> io::Result<()> {
+        if let Some(mut stream) = self.stream.take() {
+            stream.writer.flush()?;
+        }
+        Ok(())
+    }
+    
+    /// Take the error that interrupted streaming, if one occurred
+    pub fn take_stream_error(&mut self) -> Option<io::Error> {
+        self.stream_error.take()
+    }
+    
+    /// Number of events currently held in memory
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+    
+    fn effective_retention(&self) -> Option<usize> {
+        match (self.retention, &self.stream) {
+            (Some(limit), _) => Some(limit),
+            (None, Some(_)) => Some(DEFAULT_STREAM_RETENTION),
+            (None, None) => None,
+        }
+    }
+    
+    /// Drop the oldest events beyond the retention window
+    fn evict(&mut self) {
+        let Some(limit) = self.effective_retention() else {
+            return;
+        };
+        if self.events.len() <= limit {
+            return;
+        }
+        let excess = self.events.len() - limit;
+        let evicted: Vec<MutationEvent> = self.events.drain(..excess).collect();
+        for event in &evicted {
+            if let Some(ids) = self.by_variable.get_mut(&event.variable) {
+                ids.pop_front();
+                if ids.is_empty() {
+                    self.by_variable.remove(&event.variable);
+                }
+            }
+            match self.digests.get_mut(&event.variable) {
+                Some(digest) => digest.add(event),
+                None => {
+                    self.digests.insert(event.variable.clone(), EvictionDigest::new(event));
+                }
+            }
+        }
+        if let Some(Evictions(handler)) = &mut self.eviction_handler {
+            handler(&evicted);
+        }
+    }
+    
+    /// Digest of a variable's evicted events, if any were evicted
+    pub fn digest(&self, variable: &str) -> Option<EvictionDigest> {
+        self.digests.get(variable).copied()
+    }
+    
+    /// Look up a retained event by id
+    fn event(&self, id: usize) -> Option<&MutationEvent> {
+        let first = self.events.front()?.id;
+        self.events.get(id.checked_sub(first)?)
+    }
+    
+    fn update_summary(&mut self, event: &MutationEvent) {
+        let summary = self.summaries.get_mut(&event.variable);
+        if let Some(new) = VarSummary::update(summary, event) {
+            self.summaries.insert(event.variable.clone(), new);
+        }
+    }
+    
+    /// Summary of a variable's transitions, if it has any
+    pub fn summary(&self, variable: &str) -> Option<VarSummary> {
+        self.summaries.get(variable).copied()
+    }
+    
+    /// Summaries of every variable that has transitioned, sorted by name
+    pub fn all_summaries(&self) -> impl Iterator<Item = (&str, &VarSummary)> {
+        let mut summaries: Vec<(&str, &VarSummary)> = self.summaries
+            .iter()
+            .map(|(name, summary)| (name.as_str(), summary))
+            .collect();
+        summaries.sort_by_key(|(name, _)| *name);
+        summaries.into_iter()
+    }
+    
+    /// Insert an already-numbered event (used when loading a saved log).
+    /// Fails if the id or timestamp leaves no room for the events after it.
+    fn insert_event(&mut self, event: MutationEvent) -> io::Result<()> {
+        let next_id = event.id.checked_add(1).ok_or_else(|| invalid_data("event id is too large"))?;
+        if event.timestamp == usize::MAX {
+            return Err(invalid_data("event timestamp is too large"));
         }
+        self.clock = self.clock.max(event.timestamp);
+        self.next_id = self.next_id.max(next_id);
+        self.update_summary(&event);
+        self.by_variable
+            .entry(event.variable.clone())
+            .or_default()
+            .push_back(event.id);
+        self.events.push_back(event);
+        Ok(())
     }
     
-    /// Record a state mutation
+    /// Record a state transition
     pub fn record_mutation(
         &mut self,
         variable: &str,
         old_value: Value,
         new_value: Value,
         location: Option<String>,
+    ) -> usize {
+        self.record(EventKind::Transition, variable, old_value, new_value, location, Vec::new())
+    }
+    
+    /// Record a state transition whose new value was computed from the state variables in `caused_by`
+    pub fn record_caused_mutation(
+        &mut self,
+        variable: &str,
+        old_value: Value,
+        new_value: Value,
+        location: Option<String>,
+        caused_by: Vec<String>,
+    ) -> usize {
+        self.record(EventKind::Transition, variable, old_value, new_value, location, caused_by)
+    }
+    
+    /// Record the declaration of a state variable with its initial value
+    pub fn record_init(&mut self, variable: &str, value: Value, location: Option<String>) -> usize {
+        self.record(EventKind::Init, variable, Value::Nil, value, location, Vec::new())
+    }
+    
+    fn record(
+        &mut self,
+        kind: EventKind,
+        variable: &str,
+        old_value: Value,
+        new_value: Value,
+        location: Option<String>,
+        caused_by: Vec<String>,
     ) -> usize {
         let id = self.next_id;
         self.next_id += 1;
@@ -87,21 +428,34 @@ impl CausalityLog {
         
         let event = MutationEvent {
             id,
+            kind,
             variable: variable.to_string(),
             old_value,
             new_value,
             timestamp: self.clock,
             location,
+            caused_by,
         };
         
+        self.update_summary(&event);
+        
+        if let Some(stream) = &mut self.stream {
+            if let Err(e) = stream.write_event(&event) {
+                self.stream = None;
+                self.stream_error = Some(e);
+            }
+        }
+        
         // Store event
-        self.events.push(event);
+        self.events.push_back(event);
         
         // Index by variable
         self.by_variable
             .entry(variable.to_string())
             .or_default()
-            .push(id);
+            .push_back(id);
+        
+        self.evict();
         
         id
     }
@@ -112,40 +466,67 @@ impl CausalityLog {
             .get(variable)
             .map(|ids| {
                 ids.iter()
-                    .filter_map(|id| self.events.get(*id))
+                    .filter_map(|id| self.event(*id))
                     .collect()
             })
             .unwrap_or_default()
     }
     
-    /// Get all events in order
-    pub fn all_events(&self) -> &[MutationEvent] {
-        &self.events
+    /// Get all retained events in order
+    pub fn all_events(&self) -> impl Iterator<Item = &MutationEvent> {
+        self.events.iter()
     }
     
     /// Format the causality chain for a variable (for `why()` function)
     pub fn why(&self, variable: &str) -> String {
+        self.explain(variable, |value| value.to_string())
+    }
+    
+    /// `why`, showing arrays, maps and instances by their contents
+    pub fn why_with_heap(&self, variable: &str, heap: &crate::gc::Heap) -> String {
+        self.explain(variable, |value| value.display(heap))
+    }
+    
+    fn explain(&self, variable: &str, show: impl Fn(&Value) -> String) -> String {
         let history = self.history(variable);
+        let digest = self.digest(variable);
         
-        if history.is_empty() {
+        if history.is_empty() && digest.is_none() {
             return format!("No state history for '{}'", variable);
         }
         
         let mut result = format!("Causality chain for '{}':\n", variable);
         
-        for (i, event) in history.iter().enumerate() {
+        // Evicted events keep their place in the numbering
+        let mut first = 1;
+        if let Some(digest) = digest {
             result.push_str(&format!(
-                "  {}. [t={}] {} -> {}\n",
-                i + 1,
-                event.timestamp,
-                event.old_value,
-                event.new_value
+                "  ({} earlier events evicted, t={}..{}",
+                digest.count, digest.first_timestamp, digest.last_timestamp
             ));
+            if let (Some(min), Some(max)) = (digest.min, digest.max) {
+                result.push_str(&format!(", values {}..{}", Value::Number(min), Value::Number(max)));
+            }
+            result.push_str(")\n");
+            first += digest.count;
+        }
+        
+        for (i, event) in history.iter().enumerate() {
+            result.push_str(&format!("  {}. {}\n", first + i, describe(event, &show)));
         }
         
         result
     }
     
+    /// One entry per retained event of a variable, worded as `why()` words
+    /// them but without the numbering (for the `history()` function)
+    pub fn history_lines(&self, variable: &str, heap: &crate::gc::Heap) -> Vec<String> {
+        self.history(variable)
+            .into_iter()
+            .map(|event| describe(event, |value| value.display(heap)))
+            .collect()
+    }
+    
     /// Get the current logical time
     pub fn current_time(&self) -> usize {
         self.clock
@@ -155,6 +536,8 @@ impl CausalityLog {
     pub fn clear(&mut self) {
         self.events.clear();
         self.by_variable.clear();
+        self.summaries.clear();
+        self.digests.clear();
         self.clock = 0;
         self.next_id = 0;
     }
@@ -171,21 +554,90 @@ impl CausalityLog {
         dot.push_str("  rankdir=LR;\n");
         dot.push_str("  node [shape=box];\n");
         
+        // The declaration provides the first node; without one, start from the
+        // value the first transition replaced
+        let (initial, changes) = match history.split_first() {
+            Some((first, rest)) if first.kind == EventKind::Init => (&first.new_value, rest),
+            _ => (&history[0].old_value, &history[..]),
+        };
+        
         // Create nodes for each state
-        for (i, event) in history.iter().enumerate() {
+        let initial_str = format!("{}", initial).replace("\"", "\\\"");
+        dot.push_str(&format!("  s0 [label=\"{}\"];\n", initial_str));
+        for (i, event) in changes.iter().enumerate() {
             let value_str = format!("{}", event.new_value).replace("\"", "\\\"");
-            if i == 0 {
-                let old_str = format!("{}", event.old_value).replace("\"", "\\\"");
-                dot.push_str(&format!("  s{} [label=\"{}\"];\n", i, old_str));
-            }
             dot.push_str(&format!("  s{} [label=\"{}\"];\n", i + 1, value_str));
         }
         
         // Create edges
-        for (i, event) in history.iter().enumerate() {
+        for (i, event) in changes.iter().enumerate() {
             dot.push_str(&format!("  s{} -> s{} [label=\"t={}\"];\n", i, i + 1, event.timestamp));
         }
         
+        // Dashed edges from the variables each new value was computed from
+        let mut causes: Vec<&str> = Vec::new();
+        for (i, event) in changes.iter().enumerate() {
+            for cause in &event.caused_by {
+                let c = match causes.iter().position(|c| c == cause) {
+                    Some(c) => c,
+                    None => {
+                        causes.push(cause);
+                        dot.push_str(&format!("  c{} [label=\"{}\", shape=ellipse];\n", causes.len() - 1, cause));
+                        causes.len() - 1
+                    }
+                };
+                dot.push_str(&format!("  c{} -> s{} [style=dashed];\n", c, i + 1));
+            }
+        }
+        
+        dot.push_str("}\n");
+        dot
+    }
+    
+    /// Graphviz DOT graph of every retained event. Each event is a node
+    /// labeled `var: old→new @t`, grouped into one cluster per variable, with
+    /// solid edges between consecutive events of a variable and dashed edges
+    /// from the latest earlier event of each variable a new value was
+    /// computed from.
+    pub fn to_dot_all(&self) -> String {
+        let mut dot = String::from("digraph causality {\n");
+        dot.push_str("  rankdir=LR;\n");
+        dot.push_str("  node [shape=box];\n");
+        
+        // Variables in order of their first retained event
+        let mut variables: Vec<&str> = Vec::new();
+        for event in &self.events {
+            if !variables.contains(&event.variable.as_str()) {
+                variables.push(&event.variable);
+            }
+        }
+        
+        for (i, variable) in variables.iter().enumerate() {
+            dot.push_str(&format!("  subgraph cluster_{} {{\n", i));
+            dot.push_str(&format!("    label={};\n", json_escape(variable)));
+            let history = self.history(variable);
+            for event in &history {
+                let label = format!("{}: {}→{} @{}", event.variable, event.old_value, event.new_value, event.timestamp);
+                dot.push_str(&format!("    e{} [label={}];\n", event.id, json_escape(&label)));
+            }
+            for pair in history.windows(2) {
+                dot.push_str(&format!("    e{} -> e{};\n", pair[0].id, pair[1].id));
+            }
+            dot.push_str("  }\n");
+        }
+        
+        for event in &self.events {
+            for cause in &event.caused_by {
+                let source = self.history(cause)
+                    .into_iter()
+                    .rev()
+                    .find(|earlier| earlier.timestamp < event.timestamp);
+                if let Some(source) = source {
+                    dot.push_str(&format!("  e{} -> e{} [style=dashed];\n", source.id, event.id));
+                }
+            }
+        }
+        
         dot.push_str("}\n");
         dot
     }
@@ -207,8 +659,8 @@ impl CausalityLog {
             let old_str = format!("{}", event.old_value).replace("\"", "\\\"");
             let new_str = format!("{}", event.new_value).replace("\"", "\\\"");
             json.push_str(&format!(
-                "{{\"id\":{},\"timestamp\":{},\"old\":\"{}\",\"new\":\"{}\"}}",
-                event.id, event.timestamp, old_str, new_str
+                "{{\"id\":{},\"kind\":\"{}\",\"timestamp\":{},\"old\":\"{}\",\"new\":\"{}\"}}",
+                event.id, kind_name(event.kind), event.timestamp, old_str, new_str
             ));
         }
         
@@ -234,10 +686,11 @@ impl CausalityLog {
             }
         }
         
-        // If no event found, return initial value
+        // Before the first retained event: if it is the declaration the variable didn't
+        // exist yet, otherwise (declaration evicted or never seen) use the value it replaced
         if result.is_none() {
             if let Some(first) = history.first() {
-                if first.timestamp > timestamp {
+                if first.kind == EventKind::Transition && first.timestamp > timestamp {
                     return Some(first.old_value.clone());
                 }
             }
@@ -246,9 +699,640 @@ impl CausalityLog {
         result
     }
     
-    /// Get number of transitions for a variable
+    /// What changed between two logical times: each variable with a retained
+    /// event after `from` and no later than `to`, with its values at `from`
+    /// and at `to`, sorted by name. A variable declared inside the window
+    /// starts from nil.
+    pub fn diff(&self, from: usize, to: usize) -> Vec<(String, Value, Value)> {
+        let changed: std::collections::BTreeSet<&str> = self.events
+            .iter()
+            .filter(|event| event.timestamp > from && event.timestamp <= to)
+            .map(|event| event.variable.as_str())
+            .collect();
+        changed
+            .into_iter()
+            .map(|variable| {
+                let before = self.value_at(variable, from).unwrap_or(Value::Nil);
+                let after = self.value_at(variable, to).unwrap_or(Value::Nil);
+                (variable.to_string(), before, after)
+            })
+            .collect()
+    }
+    
+    /// `diff` as lines of the form `name: before -> after`
+    pub fn diff_lines(&self, from: usize, to: usize, heap: &crate::gc::Heap) -> Vec<String> {
+        self.diff(from, to)
+            .into_iter()
+            .map(|(variable, before, after)| format!("{}: {} -> {}", variable, before.display(heap), after.display(heap)))
+            .collect()
+    }
+    
+    /// Get number of transitions for a variable.
+    /// Counts every transition ever recorded, including evicted ones.
     pub fn transition_count(&self, variable: &str) -> usize {
-        self.history(variable).len()
+        self.summaries.get(variable).map_or(0, |s| s.transition_count)
+    }
+    
+    /// Write all retained events to `writer`.
+    ///
+    /// The binary format is `SKHC`, a version byte, then tagged records until EOF:
+    /// `0x00` declares the next variable name (varint length + UTF-8) and
+    /// `0x01` is a transition and `0x02` a declaration, both followed by varint
+    /// id, timestamp and name index, old and new values, optional location;
+    /// `0x03` is a transition that then lists the variables that caused it
+    /// (varint count, then strings). Values are tagged: nil, false, true, f64,
+    /// string, or a display snapshot for heap values. A full export declares
+    /// every name up front; a stream declares each name before its first use.
+    pub fn export_to(&self, writer: &mut dyn Write, format: ExportFormat) -> io::Result<()> {
+        match format {
+            ExportFormat::Jsonl => {
+                for event in &self.events {
+                    write_json_event(writer, event)?;
+                }
+            }
+            ExportFormat::Binary => {
+                write_binary_header(writer)?;
+                let mut names: HashMap<&str, usize> = HashMap::new();
+                for event in &self.events {
+                    if !names.contains_key(event.variable.as_str()) {
+                        names.insert(&event.variable, names.len());
+                        write_binary_name(writer, &event.variable)?;
+                    }
+                }
+                for event in &self.events {
+                    write_binary_event(writer, event, names[event.variable.as_str()])?;
+                }
+            }
+        }
+        writer.flush()
+    }
+    
+    /// Export every retained event as one JSON document:
+    /// `{"version":1,"clock":N,"events":[...]}`, with events shaped as in the
+    /// JSONL export. Heap values are written as their display strings.
+    pub fn export_all_json(&self) -> String {
+        let events: Vec<String> = self.events.iter().map(json_event).collect();
+        format!(
+            "{{\"version\":{},\"clock\":{},\"events\":[\n{}\n]}}\n",
+            JSON_VERSION,
+            self.clock,
+            events.join(",\n")
+        )
+    }
+    
+    /// Read a log written by `export_all_json`
+    pub fn import_json(text: &str) -> io::Result<CausalityLog> {
+        let document = JsonReader::new(text).document()?;
+        match document.field("version") {
+            Some(Json::Number(v)) if *v == JSON_VERSION as f64 => {}
+            Some(Json::Number(v)) => {
+                return Err(invalid_data(&format!(
+                    "unsupported causality log version {} (expected {})",
+                    v, JSON_VERSION
+                )))
+            }
+            _ => return Err(invalid_data("not a SkyHetu causality log")),
+        }
+        let Some(Json::Array(events)) = document.field("events") else {
+            return Err(invalid_data("causality log has no event list"));
+        };
+        
+        let mut log = CausalityLog::new();
+        for event in events {
+            let number = |name: &str| match event.field(name) {
+                Some(Json::Number(n)) => json_count(*n).ok_or_else(|| {
+                    invalid_data(&format!("event '{}' is not a whole number from 0 to {}", name, MAX_JSON_COUNT))
+                }),
+                _ => Err(invalid_data(&format!("event is missing '{}'", name))),
+            };
+            let string = |name: &str| match event.field(name) {
+                Some(Json::String(s)) => Ok(s.clone()),
+                _ => Err(invalid_data(&format!("event is missing '{}'", name))),
+            };
+            let value = |name: &str| event.field(name).map(Json::to_value).ok_or_else(|| {
+                invalid_data(&format!("event is missing '{}'", name))
+            });
+            let kind = match string("kind")?.as_str() {
+                "init" => EventKind::Init,
+                "transition" => EventKind::Transition,
+                other => return Err(invalid_data(&format!("unknown event kind '{}'", other))),
+            };
+            let location = match event.field("location") {
+                Some(Json::String(s)) => Some(s.clone()),
+                _ => None,
+            };
+            let caused_by = match event.field("caused_by") {
+                Some(Json::Array(names)) => names
+                    .iter()
+                    .map(|name| match name {
+                        Json::String(s) => Ok(s.clone()),
+                        _ => Err(invalid_data("'caused_by' must list variable names")),
+                    })
+                    .collect::<io::Result<Vec<_>>>()?,
+                _ => Vec::new(),
+            };
+            log.insert_event(MutationEvent {
+                id: number("id")?,
+                kind,
+                variable: string("variable")?,
+                old_value: value("old")?,
+                new_value: value("new")?,
+                timestamp: number("timestamp")?,
+                location,
+                caused_by,
+            })?;
+        }
+        if let Some(Json::Number(clock)) = document.field("clock") {
+            let clock = json_count(*clock).ok_or_else(|| invalid_data("causality log clock is out of range"))?;
+            log.clock = log.clock.max(clock);
+        }
+        Ok(log)
+    }
+    
+    /// Read a log written by `export_to` or a binary stream
+    pub fn load_binary(reader: &mut dyn Read) -> io::Result<CausalityLog> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != BINARY_MAGIC {
+            return Err(invalid_data("not a SkyHetu causality log"));
+        }
+        let version = read_u8(&mut reader)?;
+        if !(1..=BINARY_VERSION).contains(&version) {
+            return Err(invalid_data(&format!(
+                "unsupported causality log version {} (expected 1 to {})",
+                version, BINARY_VERSION
+            )));
+        }
+        
+        let mut log = CausalityLog::new();
+        let mut names: Vec<String> = Vec::new();
+        while !reader.fill_buf()?.is_empty() {
+            match read_u8(&mut reader)? {
+                0 => names.push(read_string(&mut reader)?),
+                // Each version adds one event tag to the ones before it
+                tag @ (1..=3) if tag <= version => {
+                    let kind = if tag == 2 { EventKind::Init } else { EventKind::Transition };
+                    let id = read_varint(&mut reader)? as usize;
+                    let timestamp = read_varint(&mut reader)? as usize;
+                    let name_idx = read_varint(&mut reader)? as usize;
+                    let variable = names
+                        .get(name_idx)
+                        .cloned()
+                        .ok_or_else(|| invalid_data("event refers to an undeclared variable"))?;
+                    let old_value = read_value(&mut reader)?;
+                    let new_value = read_value(&mut reader)?;
+                    let location = match read_varint(&mut reader)? {
+                        0 => None,
+                        len => Some(read_utf8(&mut reader, len as usize - 1)?),
+                    };
+                    let mut caused_by = Vec::new();
+                    if tag == 3 {
+                        for _ in 0..read_varint(&mut reader)? {
+                            caused_by.push(read_string(&mut reader)?);
+                        }
+                    }
+                    log.insert_event(MutationEvent { id, kind, variable, old_value, new_value, timestamp, location, caused_by })?;
+                }
+                tag => return Err(invalid_data(&format!("unknown record tag {}", tag))),
+            }
+        }
+        Ok(log)
+    }
+}
+
+/// A single event as one line of `why()`: time, change, then line and causes
+fn describe(event: &MutationEvent, show: impl Fn(&Value) -> String) -> String {
+    let change = match event.kind {
+        EventKind::Init => format!("initialized to {}", show(&event.new_value)),
+        EventKind::Transition => format!("{} -> {}", show(&event.old_value), show(&event.new_value)),
+    };
+    let mut notes: Vec<String> = event.location.iter().cloned().collect();
+    if !event.caused_by.is_empty() {
+        notes.push(format!("caused by {}", event.caused_by.join(", ")));
+    }
+    if notes.is_empty() {
+        format!("[t={}] {}", event.timestamp, change)
+    } else {
+        format!("[t={}] {} ({})", event.timestamp, change, notes.join("; "))
+    }
+}
+
+// ==================== Encoding helpers ====================
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Escape a string for inclusion in a JSON document
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Nil => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) if n.is_finite() => n.to_string(),
+        Value::String(s) => json_escape(s),
+        other => json_escape(&other.to_string()),
+    }
+}
+
+fn kind_name(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Init => "init",
+        EventKind::Transition => "transition",
+    }
+}
+
+fn write_json_event(writer: &mut dyn Write, event: &MutationEvent) -> io::Result<()> {
+    writeln!(writer, "{}", json_event(event))
+}
+
+fn json_event(event: &MutationEvent) -> String {
+    let location = event.location.as_deref().map(json_escape).unwrap_or_else(|| "null".to_string());
+    // Only transitions computed from other state carry the field
+    let caused_by = if event.caused_by.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<String> = event.caused_by.iter().map(|c| json_escape(c)).collect();
+        format!(",\"caused_by\":[{}]", names.join(","))
+    };
+    format!(
+        "{{\"id\":{},\"kind\":\"{}\",\"timestamp\":{},\"variable\":{},\"old\":{},\"new\":{},\"location\":{}{}}}",
+        event.id,
+        kind_name(event.kind),
+        event.timestamp,
+        json_escape(&event.variable),
+        json_value(&event.old_value),
+        json_value(&event.new_value),
+        location,
+        caused_by,
+    )
+}
+
+fn write_varint(writer: &mut dyn Write, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_str(writer: &mut dyn Write, s: &str) -> io::Result<()> {
+    write_varint(writer, s.len() as u64)?;
+    writer.write_all(s.as_bytes())
+}
+
+fn write_binary_header(writer: &mut dyn Write) -> io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&[BINARY_VERSION])
+}
+
+fn write_binary_name(writer: &mut dyn Write, name: &str) -> io::Result<()> {
+    writer.write_all(&[0])?;
+    write_str(writer, name)
+}
+
+fn write_binary_value(writer: &mut dyn Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Nil => writer.write_all(&[0]),
+        Value::Bool(false) => writer.write_all(&[1]),
+        Value::Bool(true) => writer.write_all(&[2]),
+        Value::Number(n) => {
+            writer.write_all(&[3])?;
+            writer.write_all(&n.to_le_bytes())
+        }
+        Value::String(s) => {
+            writer.write_all(&[4])?;
+            write_str(writer, s)
+        }
+        other => {
+            writer.write_all(&[5])?;
+            write_str(writer, &other.to_string())
+        }
+    }
+}
+
+fn write_binary_event(writer: &mut dyn Write, event: &MutationEvent, name_idx: usize) -> io::Result<()> {
+    let tag = match event.kind {
+        EventKind::Transition if !event.caused_by.is_empty() => 3,
+        EventKind::Transition => 1,
+        EventKind::Init => 2,
+    };
+    writer.write_all(&[tag])?;
+    write_varint(writer, event.id as u64)?;
+    write_varint(writer, event.timestamp as u64)?;
+    write_varint(writer, name_idx as u64)?;
+    write_binary_value(writer, &event.old_value)?;
+    write_binary_value(writer, &event.new_value)?;
+    match &event.location {
+        None => write_varint(writer, 0)?,
+        Some(loc) => {
+            write_varint(writer, loc.len() as u64 + 1)?;
+            writer.write_all(loc.as_bytes())?;
+        }
+    }
+    if tag == 3 {
+        write_varint(writer, event.caused_by.len() as u64)?;
+        for cause in &event.caused_by {
+            write_str(writer, cause)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_u8(reader: &mut dyn Read) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_varint(reader: &mut dyn Read) -> io::Result<u64> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_u8(reader)?;
+        if shift >= 64 {
+            return Err(invalid_data("varint too long"));
+        }
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
+}
+
+fn read_utf8(reader: &mut dyn Read, len: usize) -> io::Result<String> {
+    // The length comes from the file, so only grow the buffer as bytes arrive
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(invalid_data("truncated string in causality log"));
+    }
+    String::from_utf8(buf).map_err(|_| invalid_data("invalid UTF-8 in causality log"))
+}
+
+fn read_string(reader: &mut dyn Read) -> io::Result<String> {
+    let len = read_varint(reader)? as usize;
+    read_utf8(reader, len)
+}
+
+fn read_value(reader: &mut dyn Read) -> io::Result<Value> {
+    Ok(match read_u8(reader)? {
+        0 => Value::Nil,
+        1 => Value::Bool(false),
+        2 => Value::Bool(true),
+        3 => {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Value::Number(f64::from_le_bytes(bytes))
+        }
+        4 | 5 => Value::String(read_string(reader)?.into()),
+        tag => return Err(invalid_data(&format!("unknown value tag {}", tag))),
+    })
+}
+
+// ==================== JSON reading ====================
+
+/// A parsed JSON value; objects keep their fields in document order
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// The value of an object's field
+    fn field(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    
+    /// The value `json_value` wrote this as
+    fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::Nil,
+            Json::Bool(b) => Value::Bool(*b),
+            Json::Number(n) => Value::Number(*n),
+            Json::String(s) => Value::String(s.as_str().into()),
+            Json::Array(_) | Json::Object(_) => Value::Nil,
+        }
+    }
+}
+
+/// Largest id, timestamp or clock read from JSON: the largest integer an
+/// f64 holds exactly, which leaves room to count on from it
+const MAX_JSON_COUNT: f64 = 9_007_199_254_740_991.0;
+
+/// How deeply arrays and objects may nest in a JSON causality log
+const MAX_JSON_DEPTH: usize = 64;
+
+/// `n` as a count, if it is a whole number from 0 to `MAX_JSON_COUNT`
+fn json_count(n: f64) -> Option<usize> {
+    ((0.0..=MAX_JSON_COUNT).contains(&n) && n.fract() == 0.0).then_some(n as usize)
+}
+
+/// Just enough of a JSON parser to read back `export_all_json`
+struct JsonReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Arrays and objects open around the current position
+    depth: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { bytes: text.as_bytes(), pos: 0, depth: 0 }
+    }
+    
+    /// Parse a whole document: one value and nothing after it
+    fn document(mut self) -> io::Result<Json> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.pos < self.bytes.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(value)
+    }
+    
+    fn error(&self, msg: &str) -> io::Error {
+        invalid_data(&format!("invalid JSON at byte {}: {}", self.pos, msg))
+    }
+    
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+    
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    
+    fn keyword(&mut self, word: &str, value: Json) -> io::Result<Json> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+    
+    fn value(&mut self) -> io::Result<Json> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'{') => self.nested(Self::object),
+            Some(_) => self.number(),
+        }
+    }
+    
+    /// Parse an array or object with `parse`, unless too many are already open
+    fn nested(&mut self, parse: fn(&mut Self) -> io::Result<Json>) -> io::Result<Json> {
+        if self.depth == MAX_JSON_DEPTH {
+            return Err(self.error("arrays and objects nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+    
+    fn array(&mut self) -> io::Result<Json> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+    
+    fn object(&mut self) -> io::Result<Json> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+    
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|b| b"+-.eE".contains(b) || b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+    
+    fn string(&mut self) -> io::Result<String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.bytes.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(out).map_err(|_| self.error("invalid UTF-8"));
+                }
+                Some(b'\\') => {
+                    let escaped = match self.bytes.get(self.pos + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => {
+                            let hex = self.bytes.get(self.pos + 2..self.pos + 6).and_then(|h| std::str::from_utf8(h).ok());
+                            let code = hex.and_then(|h| u32::from_str_radix(h, 16).ok());
+                            self.pos += 4;
+                            code.and_then(char::from_u32).ok_or_else(|| self.error("invalid \\u escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 2;
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(&b) => {
+                    out.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
     }
 }
 
@@ -283,4 +1367,218 @@ mod tests {
         assert!(why.contains("0 -> 1"));
         assert!(why.contains("1 -> 2"));
     }
+    
+    #[test]
+    fn test_diff_between_times() {
+        let mut log = CausalityLog::new();
+        
+        // Times 1 to 6: x declared, y declared, x changed, z declared, y changed, x changed
+        log.record_init("x", Value::Number(0.0), None);
+        log.record_init("y", Value::String("a".into()), None);
+        log.record_mutation("x", Value::Number(0.0), Value::Number(1.0), None);
+        log.record_init("z", Value::Bool(true), None);
+        log.record_mutation("y", Value::String("a".into()), Value::String("b".into()), None);
+        log.record_mutation("x", Value::Number(1.0), Value::Number(2.0), None);
+        
+        let entry = |name: &str, before: Value, after: Value| (name.to_string(), before, after);
+        assert_eq!(log.diff(0, 6), [
+            entry("x", Value::Nil, Value::Number(2.0)),
+            entry("y", Value::Nil, Value::String("b".into())),
+            entry("z", Value::Nil, Value::Bool(true)),
+        ]);
+        // y is untouched between t=2 and t=4
+        assert_eq!(log.diff(2, 4), [
+            entry("x", Value::Number(0.0), Value::Number(1.0)),
+            entry("z", Value::Nil, Value::Bool(true)),
+        ]);
+        assert_eq!(log.diff(4, 5), [entry("y", Value::String("a".into()), Value::String("b".into()))]);
+        assert_eq!(log.diff(3, 9), [
+            entry("x", Value::Number(1.0), Value::Number(2.0)),
+            entry("y", Value::String("a".into()), Value::String("b".into())),
+            entry("z", Value::Nil, Value::Bool(true)),
+        ]);
+        assert!(log.diff(6, 10).is_empty());
+        assert!(log.diff(3, 3).is_empty());
+        assert!(log.diff(5, 2).is_empty());
+    }
+    
+    #[test]
+    fn test_retention_evicts_oldest() {
+        let mut log = CausalityLog::new();
+        log.set_retention(Some(2));
+        
+        log.record_mutation("x", Value::Number(0.0), Value::Number(1.0), None);
+        log.record_mutation("y", Value::Nil, Value::Bool(true), None);
+        log.record_mutation("x", Value::Number(1.0), Value::Number(2.0), None);
+        
+        assert_eq!(log.event_count(), 2);
+        let history = log.history("x");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, 2);
+        assert_eq!(log.history("y").len(), 1);
+    }
+    
+    #[test]
+    fn test_eviction_digest_and_handler() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        
+        let mut log = CausalityLog::new();
+        log.set_retention(Some(10));
+        let batches = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&batches);
+        log.set_eviction_handler(Some(Box::new(move |events: &[MutationEvent]| {
+            seen.borrow_mut().push(events.iter().map(|e| e.id).collect::<Vec<_>>());
+        })));
+        
+        for i in 0..100 {
+            log.record_mutation("x", Value::Number(i as f64 - 1.0), Value::Number(i as f64), None);
+        }
+        
+        let digest = log.digest("x").unwrap();
+        assert_eq!(digest.count, 90);
+        assert_eq!((digest.min, digest.max), (Some(0.0), Some(89.0)));
+        assert_eq!((digest.first_timestamp, digest.last_timestamp), (1, 90));
+        
+        let evicted: Vec<usize> = batches.borrow().iter().flatten().copied().collect();
+        assert_eq!(evicted, (0..90).collect::<Vec<_>>());
+        
+        let why = log.why("x");
+        assert!(why.contains("(90 earlier events evicted, t=1..90, values 0..89)"), "{}", why);
+        assert!(why.contains("  91. [t=91] 89 -> 90"), "{}", why);
+        assert!(log.digest("y").is_none());
+    }
+    
+    #[test]
+    fn test_binary_round_trip() {
+        let mut log = CausalityLog::new();
+        log.record_mutation("x", Value::Nil, Value::Number(-1.5), Some("line 3".to_string()));
+        log.record_mutation("név", Value::Bool(false), Value::String("a\"b\n".into()), None);
+        log.record_mutation("x", Value::Number(-1.5), Value::Bool(true), None);
+        log.record_caused_mutation("y", Value::Nil, Value::Number(2.0), None, vec!["x".to_string(), "név".to_string()]);
+        
+        let mut bytes = Vec::new();
+        log.export_to(&mut bytes, ExportFormat::Binary).unwrap();
+        assert_eq!(&bytes[..4], BINARY_MAGIC);
+        
+        let loaded = CausalityLog::load_binary(&mut bytes.as_slice()).unwrap();
+        let original: Vec<_> = log.all_events().collect();
+        let restored: Vec<_> = loaded.all_events().collect();
+        assert_eq!(original.len(), restored.len());
+        for (a, b) in original.iter().zip(&restored) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.timestamp, b.timestamp);
+            assert_eq!(a.variable, b.variable);
+            assert_eq!(a.old_value, b.old_value);
+            assert_eq!(a.new_value, b.new_value);
+            assert_eq!(a.location, b.location);
+            assert_eq!(a.caused_by, b.caused_by);
+        }
+        assert_eq!(loaded.current_time(), log.current_time());
+    }
+    
+    #[test]
+    fn test_jsonl_export() {
+        let mut log = CausalityLog::new();
+        log.record_mutation("x", Value::Number(0.0), Value::String("q\"".into()), None);
+        log.record_mutation("x", Value::String("q\"".into()), Value::Nil, None);
+        log.record_caused_mutation("y", Value::Nil, Value::Bool(true), None, vec!["x".to_string()]);
+        
+        let mut out = Vec::new();
+        log.export_to(&mut out, ExportFormat::Jsonl).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines, vec![
+            r#"{"id":0,"kind":"transition","timestamp":1,"variable":"x","old":0,"new":"q\"","location":null}"#,
+            r#"{"id":1,"kind":"transition","timestamp":2,"variable":"x","old":"q\"","new":null,"location":null}"#,
+            r#"{"id":2,"kind":"transition","timestamp":3,"variable":"y","old":null,"new":true,"location":null,"caused_by":["x"]}"#,
+        ]);
+    }
+    
+    #[test]
+    fn test_load_binary_rejects_garbage() {
+        let err = CausalityLog::load_binary(&mut &b"nope!"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        
+        // Logs from older versions load; newer ones don't
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0, 1, b'x', 1, 0, 1, 0, 0, 3]);
+        bytes.extend_from_slice(&2.0f64.to_le_bytes());
+        bytes.push(0);
+        let log = CausalityLog::load_binary(&mut &bytes[..]).unwrap();
+        assert_eq!(log.history("x").len(), 1);
+        bytes[BINARY_MAGIC.len()] = BINARY_VERSION + 1;
+        let err = CausalityLog::load_binary(&mut &bytes[..]).unwrap_err();
+        assert!(err.to_string().contains("unsupported causality log version 4"), "{}", err);
+        
+        // A name claiming to be far longer than the file is not allocated up front
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[BINARY_VERSION, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, b'x']);
+        let err = CausalityLog::load_binary(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    
+    #[test]
+    fn test_load_binary_rejects_corrupt_events() {
+        // Version 1 log declaring `x`, then `tag` with `id`, timestamp 1, nil -> nil
+        let log_with = |version: u8, tag: u8, id: &[u8]| {
+            let mut bytes = BINARY_MAGIC.to_vec();
+            bytes.extend_from_slice(&[version, 0, 1, b'x', tag]);
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&[1, 0, 0, 0, 0]);
+            if tag == 3 {
+                bytes.push(0);
+            }
+            CausalityLog::load_binary(&mut &bytes[..])
+        };
+        assert!(log_with(1, 1, &[0]).is_ok());
+        
+        let max_id = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let err = log_with(1, 1, &max_id).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "event id is too large");
+        
+        // Tags only exist from the version that added them
+        let err = log_with(1, 2, &[0]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown record tag 2");
+        let err = log_with(2, 3, &[0]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown record tag 3");
+        assert!(log_with(2, 2, &[0]).is_ok());
+        assert!(log_with(3, 3, &[0]).is_ok());
+    }
+    
+    #[test]
+    fn test_summary_survives_eviction() {
+        let mut log = CausalityLog::new();
+        log.set_retention(Some(3));
+        for i in 0..10 {
+            log.record_mutation("x", Value::Number(i as f64), Value::Number(i as f64 + 1.0), None);
+            log.record_mutation("y", Value::Nil, Value::Nil, None);
+        }
+        
+        assert_eq!(log.history("x").len(), 1);
+        assert_eq!(log.transition_count("x"), 10);
+        let summary = log.summary("x").unwrap();
+        assert_eq!(summary.first_seen, 1);
+        assert_eq!(summary.last_event_id, 18);
+        assert_eq!(summary.last_timestamp, 19);
+        assert!(log.summary("z").is_none());
+    }
+    
+    #[test]
+    fn test_summaries_match_history_without_eviction() {
+        let mut log = CausalityLog::new();
+        for (i, var) in ["a", "b", "a", "c", "a", "b"].iter().enumerate() {
+            log.record_mutation(var, Value::Number(i as f64), Value::Number(i as f64 + 1.0), None);
+        }
+        
+        assert_eq!(log.all_summaries().count(), 3);
+        for (name, summary) in log.all_summaries() {
+            let history = log.history(name);
+            assert_eq!(summary.transition_count, history.len());
+            assert_eq!(summary.first_seen, history[0].timestamp);
+            assert_eq!(summary.last_event_id, history.last().unwrap().id);
+            assert_eq!(summary.last_timestamp, history.last().unwrap().timestamp);
+        }
+    }
 }
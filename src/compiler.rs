@@ -3,17 +3,58 @@
 //! Compiles the Abstract Syntax Tree into bytecode for the VM.
 
 use crate::ast::{BinaryOp, Expr, LogicalOp, Program, Stmt, UnaryOp};
+use crate::bindings::{redefinition_message, BindingKind};
 use crate::bytecode::{Chunk, OpCode};
-use crate::error::{ErrorKind, Result, SkyHetuError};
-use crate::value::{Function, Value};
+use crate::error::{did_you_mean, suggest, ErrorKind, Result, SkyHetuError, Warning};
+use crate::token::Span;
+use crate::types::{infer, StaticType, TypeScope};
+use crate::value::{format_number, Function, Value};
+use std::collections::HashMap;
 use std::rc::Rc;
 
+/// Longest string an optimized compile computes ahead of time; longer ones
+/// are built at runtime rather than stored in the constant pool
+const MAX_FOLDED_STRING: usize = 1024;
+
+/// Longest string a `const` initializer may build, in bytes
+const MAX_CONST_STRING: usize = 1 << 20;
+
 /// Local variable in scope
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct Local {
     name: String,
     depth: usize,
     is_state: bool,  // mutable state variable
+    is_param: bool,  // a `state` parameter, tracked per call
+    is_captured: bool,  // closed over by a nested function
+    /// Where a `let` or `state` declared it; other locals are never reported unused
+    declared_at: Option<Span>,
+    /// Code offset from which it is in scope
+    start: usize,
+    is_read: bool,
+    is_transitioned: bool,
+}
+
+impl Local {
+    /// Record that this local, in `slot`, is in scope from its start up to
+    /// the end of `chunk` so far, so a debugger can find it by name
+    fn record_name(&self, chunk: &mut Chunk, slot: usize) {
+        if !self.name.is_empty() {
+            chunk.add_local_name(self.start, chunk.len(), slot, self.name.clone());
+        }
+    }
+}
+
+/// A loop being compiled, for `break` and `continue`
+#[derive(Debug)]
+struct Loop {
+    /// Where `continue` jumps back to, when that is known before the body
+    /// (a `for` loop's increment comes after it)
+    start: Option<usize>,
+    /// Locals in scope outside the body; `break` and `continue` discard the rest
+    locals: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
 }
 
 /// Upvalue being captured
@@ -21,20 +62,23 @@ struct Local {
 struct Upvalue {
     index: u8,
     is_local: bool,
+    /// Whether the captured variable is a `state`
+    is_state: bool,
 }
 
 /// Function being compiled
 #[derive(Debug)]
 struct FunctionCompiler {
-    // usage for debugging or error reporting
-    #[allow(dead_code)]
     function_name: String,
     chunk: Chunk,
     locals: Vec<Local>,
+    /// Most locals in scope at once (excluding the reserved slot 0)
+    max_locals: usize,
     upvalues: Vec<Upvalue>,
     scope_depth: usize,
-    loop_starts: Vec<usize>,
-    loop_exits: Vec<Vec<usize>>,
+    loops: Vec<Loop>,
+    /// Conditions of the enclosing ifs and loops, outermost first (debug info only)
+    conditions: Vec<String>,
 }
 
 impl FunctionCompiler {
@@ -43,58 +87,206 @@ impl FunctionCompiler {
             function_name: name.to_string(),
             chunk: Chunk::new(),
             // Slot 0 is ALWAYS reserved for the closure/function itself
-            locals: vec![Local {
-                name: "".to_string(),
-                depth: 0,
-                is_state: false,
-            }],
+            locals: vec![Local::default()],
+            max_locals: 0,
             upvalues: Vec::new(),
             scope_depth: 0,
-            loop_starts: Vec::new(),
-            loop_exits: Vec::new(),
+            loops: Vec::new(),
+            conditions: Vec::new(),
         }
     }
 }
 
+/// Size metrics for one compiled function, or the top-level script
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FunctionStats {
+    pub name: String,
+    /// Length of the bytecode in bytes
+    pub bytes: usize,
+    pub instructions: usize,
+    pub constants: usize,
+    pub names: usize,
+    pub max_locals: usize,
+    pub upvalues: usize,
+}
+
+/// Compile-time metrics collected by `Compiler::compile`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompileStats {
+    /// One entry per function in the order they finished compiling; the script is last
+    pub functions: Vec<FunctionStats>,
+    /// Constants that reused an identical number, string, bool or nil already in the pool
+    pub constants_deduplicated: usize,
+    /// Peephole rewrites applied (no peephole pass yet, so always 0)
+    pub peephole_rewrites: usize,
+    /// Bytes of unreachable code removed, over all functions
+    pub dead_code_removed: usize,
+}
+
+impl CompileStats {
+    /// Sums over all functions; `max_locals` is the largest single function's
+    pub fn totals(&self) -> FunctionStats {
+        let mut total = FunctionStats { name: "total".to_string(), ..FunctionStats::default() };
+        for f in &self.functions {
+            total.bytes += f.bytes;
+            total.instructions += f.instructions;
+            total.constants += f.constants;
+            total.names += f.names;
+            total.max_locals = total.max_locals.max(f.max_locals);
+            total.upvalues += f.upvalues;
+        }
+        total
+    }
+}
+
+impl std::fmt::Display for CompileStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<20} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}", "function", "bytes", "instrs", "consts", "names", "locals", "upvals")?;
+        for stats in self.functions.iter().chain(std::iter::once(&self.totals())) {
+            writeln!(
+                f,
+                "{:<20} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                stats.name, stats.bytes, stats.instructions, stats.constants, stats.names, stats.max_locals, stats.upvalues
+            )?;
+        }
+        writeln!(f, "constants deduplicated: {}", self.constants_deduplicated)?;
+        writeln!(f, "peephole rewrites: {}", self.peephole_rewrites)?;
+        write!(f, "dead code removed: {} bytes", self.dead_code_removed)
+    }
+}
+
 /// The bytecode compiler
 pub struct Compiler {
     /// Stack of function compilers (for nested functions)
     compilers: Vec<FunctionCompiler>,
-    /// All compiled chunks (indexed by chunk_index)
-    compiled_chunks: Vec<Chunk>,
     /// Exported names from the current module
     exports: std::collections::HashSet<String>,
     /// Base path for resolving module imports
     module_base_path: Option<std::path::PathBuf>,
+    /// Top-level names seen so far with their kind and declaration site
+    declared_globals: HashMap<String, (BindingKind, Option<Span>)>,
+    /// Diagnostics that don't stop compilation
+    warnings: Vec<Warning>,
+    /// Size metrics of the last compile
+    stats: CompileStats,
+    /// Values of top-level `const` declarations, inlined at each use
+    consts: HashMap<String, Value>,
+    /// Top-level `let`s bound to a literal, until the name is declared again.
+    /// Only top-level code may rely on them: it runs in declaration order,
+    /// while a function may be called after a later redefinition.
+    known_lets: HashMap<String, Value>,
+    /// Leave out code behind conditions known at compile time
+    optimize: bool,
+    /// While compiling an imported module: the global each of its top-level
+    /// names is bound to. Imported names keep their own, the rest are prefixed
+    /// with the module path so they can't collide with the importer's.
+    module_aliases: HashMap<String, String>,
+    /// Modules compiled so far, by canonical path, with the global each export is bound to
+    modules: HashMap<std::path::PathBuf, HashMap<String, String>>,
+    /// Modules being compiled, outermost first, to detect import cycles
+    importing: Vec<std::path::PathBuf>,
+    /// Path of the module being compiled, as resolved from the importer's
+    /// directory, so runtime errors in its code name the right file
+    module_file: Option<std::path::PathBuf>,
+    /// Time spent reading, parsing and compiling each module, in import order
+    module_timings: Vec<(String, std::time::Duration)>,
+    /// Record the conditions guarding each region of code, for runtime errors
+    debug_info: bool,
+    /// One entry per class body being compiled, innermost last: whether the
+    /// class has a superclass, which `super` needs
+    class_supers: Vec<bool>,
+    /// Set while compiling the call a function returns directly, which can
+    /// reuse the returning function's frame
+    tail_position: bool,
+    /// Let a top-level name be declared again, replacing the earlier binding
+    allow_redefine: bool,
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Self {
             compilers: vec![FunctionCompiler::new("")],
-            compiled_chunks: Vec::new(),
             exports: std::collections::HashSet::new(),
             module_base_path: None,
+            declared_globals: HashMap::new(),
+            warnings: Vec::new(),
+            stats: CompileStats::default(),
+            consts: HashMap::new(),
+            known_lets: HashMap::new(),
+            optimize: true,
+            module_aliases: HashMap::new(),
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            module_file: None,
+            module_timings: Vec::new(),
+            debug_info: false,
+            class_supers: Vec::new(),
+            tail_position: false,
+            allow_redefine: false,
         }
     }
     
     pub fn with_base_path(base_path: std::path::PathBuf) -> Self {
         Self {
-            compilers: vec![FunctionCompiler::new("")],
-            compiled_chunks: Vec::new(),
-            exports: std::collections::HashSet::new(),
             module_base_path: Some(base_path),
+            ..Self::new()
         }
     }
     
-    pub fn with_offset(_chunk_offset: usize) -> Self {
-        // chunk_offset reserved for future REPL improvements
-        Self::new()
+    /// Make the compiler aware of a global that already exists at runtime (e.g. a native),
+    /// so redefining it in the program is reported.
+    pub fn declare_global(&mut self, name: &str, kind: BindingKind) {
+        self.declared_globals.insert(name.to_string(), (kind, None));
+    }
+    
+    /// Make the compiler aware of a constant defined by an earlier compile (e.g. a REPL line),
+    /// so uses of it are inlined.
+    pub fn declare_const(&mut self, name: &str, value: Value) {
+        self.consts.insert(name.to_string(), value);
     }
     
-    /// Compile a program to bytecode. Returns the main chunk and a list of function chunks.
-    /// Compile a program to bytecode. Returns the main chunk and a list of function chunks.
-    pub fn compile(&mut self, program: &Program, heap: &mut crate::gc::Heap) -> Result<(Chunk, Vec<Chunk>)> {
+    /// Record which `if`/`while`/`for` conditions guard each region of code, so
+    /// runtime errors can show the conditions that led to them
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.debug_info = enabled;
+    }
+    
+    /// Compute operators on literals and constants ahead of time, and skip the
+    /// bytecode of branches whose condition is known at compile time and the
+    /// condition check of loops that always run. On by default.
+    pub fn set_optimize(&mut self, enabled: bool) {
+        self.optimize = enabled;
+    }
+    
+    /// Let the program declare a top-level name more than once, as a REPL
+    /// entry may; later declarations replace earlier ones with a warning
+    /// when the kind changes. Otherwise a redeclaration is an error.
+    pub fn set_allow_redefine(&mut self, enabled: bool) {
+        self.allow_redefine = enabled;
+    }
+    
+    /// Time spent on each imported module, by path, including the modules it imports
+    pub fn module_timings(&self) -> &[(String, std::time::Duration)] {
+        &self.module_timings
+    }
+    
+    /// Warnings collected by the last `compile` call
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+    
+    /// Size metrics of the functions produced by the last `compile` call
+    pub fn stats(&self) -> &CompileStats {
+        &self.stats
+    }
+    
+    /// Compile a program to bytecode. Returns the script's chunk; function
+    /// chunks are owned by the `Function` objects allocated in `heap`.
+    pub fn compile(&mut self, program: &Program, heap: &mut crate::gc::Heap) -> Result<Chunk> {
+        self.warnings.clear();
+        self.collect_global_names(&program.statements)?;
+        self.stats = CompileStats::default();
+        
         let len = program.statements.len();
         
         for (i, stmt) in program.statements.iter().enumerate() {
@@ -120,7 +312,51 @@ impl Compiler {
         
         self.emit(OpCode::Return, 0);
         
-        Ok((self.current().chunk.clone(), self.compiled_chunks.clone()))
+        self.stats.dead_code_removed += self.current().chunk.eliminate_dead_code(heap);
+        let script = self.compilers.last().unwrap();
+        let stats = Self::function_stats(script, heap);
+        self.stats.functions.push(stats);
+        
+        Ok(self.current().chunk.clone())
+    }
+    
+    /// First pass: record every top-level declaration. Declaring a name twice
+    /// is an error unless redefinition is allowed, in which case changing its
+    /// kind (let / state / fn / class) warns. Redefining a native always warns.
+    fn collect_global_names(&mut self, stmts: &[Stmt]) -> Result<()> {
+        for stmt in stmts {
+            let stmt = match stmt {
+                Stmt::Export { stmt, .. } => stmt.as_ref(),
+                other => other,
+            };
+            let (name, kind, span) = match stmt {
+                Stmt::Let { name, span, .. } => (name, BindingKind::Let, *span),
+                Stmt::State { name, span, .. } => (name, BindingKind::State, *span),
+                Stmt::Function { name, span, .. } => (name, BindingKind::Function, *span),
+                Stmt::Class { name, span, .. } => (name, BindingKind::Class, *span),
+                Stmt::Const { name, span, .. } => (name, BindingKind::Const, *span),
+                _ => continue,
+            };
+            
+            if let Some((old_kind, old_span)) = self.declared_globals.get(name) {
+                if old_span.is_some() && !self.allow_redefine {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::DuplicateDefinition(name.clone(), old_span.map(|s| s.line)),
+                        Some(span),
+                    ));
+                }
+                if *old_kind != kind {
+                    let mut message = redefinition_message(name, *old_kind, kind);
+                    match old_span {
+                        Some(old) => message.push_str(&format!(" (previously declared at line {})", old.line)),
+                        None => message.push_str(" (previously a built-in)"),
+                    }
+                    self.warnings.push(Warning::new(message, Some(span)));
+                }
+            }
+            self.declared_globals.insert(name.clone(), (kind, Some(span)));
+        }
+        Ok(())
     }
     
     fn current(&mut self) -> &mut FunctionCompiler {
@@ -139,10 +375,39 @@ impl Compiler {
         self.current().chunk.write_u16(value, line);
     }
     
-    fn emit_constant(&mut self, value: Value, line: usize) {
-        let idx = self.current().chunk.add_constant(value);
-        self.emit(OpCode::Constant, line);
-        self.emit_u16(idx, line);
+    /// Emit a u16 count operand, rejecting counts that do not fit
+    fn emit_count(&mut self, count: usize, what: &str, span: Span) -> Result<()> {
+        let count = u16::try_from(count).map_err(|_| SkyHetuError::new(
+            ErrorKind::TooManyOperands(what.to_string(), count, u16::MAX as usize),
+            Some(span),
+        ))?;
+        self.emit_u16(count, span.line);
+        Ok(())
+    }
+    
+    fn emit_constant(&mut self, value: Value, span: Span) -> Result<()> {
+        let idx = self.make_constant(value, span)?;
+        self.emit(OpCode::Constant, span.line);
+        self.emit_u16(idx, span.line);
+        Ok(())
+    }
+    
+    /// Pool index for `value` in the current chunk, reusing an identical
+    /// constant when there is one
+    fn make_constant(&mut self, value: Value, span: Span) -> Result<u16> {
+        let pooled = self.current().chunk.constants().len();
+        let idx = self.current().chunk.add_constant(value)
+            .ok_or_else(|| SkyHetuError::new(ErrorKind::TooManyConstants("constants".to_string()), Some(span)))?;
+        if (idx as usize) < pooled {
+            self.stats.constants_deduplicated += 1;
+        }
+        Ok(idx)
+    }
+    
+    /// Name table index for `name` in the current chunk
+    fn make_name(&mut self, name: String, span: Span) -> Result<u16> {
+        self.current().chunk.add_name(name)
+            .ok_or_else(|| SkyHetuError::new(ErrorKind::TooManyConstants("names".to_string()), Some(span)))
     }
     
     fn emit_jump(&mut self, op: OpCode, line: usize) -> usize {
@@ -155,6 +420,114 @@ impl Compiler {
         self.current().chunk.patch_jump(offset);
     }
     
+    /// Make `names` from the module at `path` visible to the code being compiled.
+    ///
+    /// Each module is compiled once, the first time it is imported; later imports
+    /// only map the listed names to the globals it already defined.
+    fn compile_import(&mut self, names: &[String], path: &str, span: Span, heap: &mut crate::gc::Heap) -> Result<()> {
+        let not_found = |detail: String| SkyHetuError::new(ErrorKind::ModuleNotFound(format!("{}: {}", path, detail)), Some(span));
+        
+        // Resolve module path relative to the importing file's directory
+        let module_path = match &self.module_base_path {
+            Some(base) => base.join(path),
+            None => std::path::PathBuf::from(path),
+        };
+        
+        // Add .skyh extension if not present
+        let module_path = if module_path.extension().is_none() {
+            module_path.with_extension("skyh")
+        } else {
+            module_path
+        };
+        let canonical = module_path.canonicalize().map_err(|e| not_found(e.to_string()))?;
+        
+        if let Some(first) = self.importing.iter().position(|p| p == &canonical) {
+            let cycle: Vec<String> = self.importing[first..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|p| p.file_name().map_or_else(|| p.display().to_string(), |n| n.to_string_lossy().into_owned()))
+                .collect();
+            return Err(SkyHetuError::new(ErrorKind::CircularImport(cycle.join(" -> ")), Some(span)));
+        }
+        
+        if !self.modules.contains_key(&canonical) {
+            let start = std::time::Instant::now();
+            let timing_slot = self.module_timings.len();
+            self.module_timings.push((canonical.display().to_string(), std::time::Duration::ZERO));
+            let source = std::fs::read_to_string(&canonical).map_err(|e| not_found(e.to_string()))?;
+            let tokens = crate::lexer::Lexer::new(&source).tokenize().map_err(|e| not_found(e.to_string()))?;
+            let module_program = crate::parser::Parser::new(tokens).parse().map_err(|e| not_found(e.to_string()))?;
+            
+            let exported = exported_names(&module_program.statements);
+            if let Some(missing) = names.iter().find(|name| !exported.contains(*name)) {
+                return Err(not_found(format!("'{}' is not exported", missing)));
+            }
+            check_module_duplicates(&module_program.statements).map_err(|e| e.with_file(module_path.clone()))?;
+            
+            // Names the top-level program imports keep their own; everything else the
+            // module declares gets a private name so it can't collide with other globals
+            let top_level = self.importing.is_empty();
+            let aliases: HashMap<String, String> = declared_names(&module_program.statements)
+                .into_iter()
+                .map(|name| {
+                    let alias = if top_level && names.contains(&name) {
+                        self.global_name(&name)
+                    } else {
+                        format!("{}::{}", path, name)
+                    };
+                    (name, alias)
+                })
+                .collect();
+            
+            // Compile the module's statements directly in the current compiler
+            // This ensures constants and functions are in the current chunk
+            let outer_aliases = std::mem::replace(&mut self.module_aliases, aliases);
+            let outer_base = std::mem::replace(&mut self.module_base_path, canonical.parent().map(|p| p.to_path_buf()));
+            let outer_file = self.module_file.replace(module_path.clone());
+            self.importing.push(canonical.clone());
+            let code_start = self.current().chunk.len();
+            let compiled = module_program.statements
+                .iter()
+                .try_for_each(|stmt| self.compile_stmt(stmt, heap))
+                .map_err(|e| if e.file().is_none() { e.with_file(module_path.clone()) } else { e });
+            let code_end = self.current().chunk.len();
+            self.current().chunk.add_file(code_start, code_end, module_path.clone());
+            self.importing.pop();
+            self.module_file = outer_file;
+            self.module_base_path = outer_base;
+            let module_aliases = std::mem::replace(&mut self.module_aliases, outer_aliases);
+            compiled?;
+            
+            let exports = module_aliases.into_iter().filter(|(name, _)| exported.contains(name)).collect();
+            self.modules.insert(canonical.clone(), exports);
+            self.module_timings[timing_slot].1 = start.elapsed();
+        }
+        
+        let exports = &self.modules[&canonical];
+        if let Some(missing) = names.iter().find(|name| !exports.contains_key(*name)) {
+            return Err(not_found(format!("'{}' is not exported", missing)));
+        }
+        let bindings: Vec<(String, String)> = names.iter().map(|name| (name.clone(), exports[name].clone())).collect();
+        self.module_aliases.extend(bindings);
+        Ok(())
+    }
+    
+    /// Compile a branch or loop body, recording `condition` as its guard when
+    /// compiling with debug info
+    fn compile_guarded(&mut self, condition: impl FnOnce() -> String, body: &Stmt, heap: &mut crate::gc::Heap) -> Result<()> {
+        if !self.debug_info {
+            return self.compile_stmt(body, heap);
+        }
+        self.current().conditions.push(condition());
+        let start = self.current().chunk.len();
+        let compiled = self.compile_stmt(body, heap);
+        let end = self.current().chunk.len();
+        let conditions = self.current().conditions.clone();
+        self.current().chunk.add_guard(start, end, conditions);
+        self.current().conditions.pop();
+        compiled
+    }
+    
     fn emit_loop(&mut self, loop_start: usize, line: usize) {
         self.emit(OpCode::Loop, line);
         let offset = self.current().chunk.len() - loop_start + 2;
@@ -178,12 +551,25 @@ impl Compiler {
                 
                 if self.current().scope_depth == 0 {
                     // Global
-                    let idx = self.current().chunk.add_name(name.clone());
+                    let name = self.global_name(name);
+                    self.consts.remove(&name);
+                    match literal_value(value) {
+                        Some(literal) if self.compilers.len() == 1 => {
+                            self.known_lets.insert(name.clone(), literal);
+                        }
+                        _ => {
+                            self.known_lets.remove(&name);
+                        }
+                    }
+                    let idx = self.make_name(name, *span)?;
                     self.emit(OpCode::DefineGlobal, span.line);
                     self.emit_u16(idx, span.line);
                 } else {
                     // Local
+                    self.check_duplicate_local(name, *span)?;
+                    self.check_local_shadowing(name, false, *span);
                     self.add_local(name.clone(), false);
+                    self.current().locals.last_mut().unwrap().declared_at = Some(*span);
                 }
             }
             
@@ -192,25 +578,59 @@ impl Compiler {
                 
                 if self.current().scope_depth == 0 {
                     // Global state
-                    let idx = self.current().chunk.add_name(name.clone());
+                    let name = self.global_name(name);
+                    self.consts.remove(&name);
+                    self.known_lets.remove(&name);
+                    let idx = self.make_name(name, *span)?;
                     self.emit(OpCode::DefineState, span.line);
                     self.emit_u16(idx, span.line);
                 } else {
-                    // Local state
+                    // Local state: the value stays in its slot, only the declaration is recorded
+                    self.check_duplicate_local(name, *span)?;
+                    self.check_state_shadowing(name, *span);
+                    self.check_local_shadowing(name, true, *span);
                     self.add_local(name.clone(), true);
+                    self.current().locals.last_mut().unwrap().declared_at = Some(*span);
+                    let key = self.tracked_name(name);
+                    let idx = self.make_name(key, *span)?;
+                    self.emit(OpCode::InitLocalState, span.line);
+                    self.emit_u16(idx, span.line);
                 }
             }
             
+            Stmt::Const { name, value, span } => {
+                if self.current().scope_depth != 0 {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::ConstOutsideTopLevel,
+                        Some(*span),
+                    ));
+                }
+                
+                let value = self.eval_const(value)?;
+                let name = self.global_name(name);
+                self.consts.insert(name.clone(), value.clone());
+                
+                // Still define the global so functions compiled before this line can read it
+                self.emit_constant(value, *span)?;
+                let idx = self.make_name(name, *span)?;
+                self.emit(OpCode::DefineConst, span.line);
+                self.emit_u16(idx, span.line);
+            }
+            
             Stmt::Transition { name, value, span } => {
+                let causes = self.state_reads(value, Some(name));
+                
                 // Compile new value
                 self.compile_expr(value, heap)?;
+                let causes_idx = self.current().chunk.add_causes(causes);
                 
                 // Check if local or global
-                if let Some(slot) = self.resolve_local(&name) {
+                if let Some(slot) = self.resolve_local(name) {
                     // Local transition
                     
                     // Check immutability
                     let slot_usize = slot as usize;
+                    self.current().locals[slot_usize].is_transitioned = true;
                     if !self.current().locals[slot_usize].is_state {
                          return Err(SkyHetuError::new(
                             ErrorKind::ImmutableVariable(name.clone()),
@@ -218,54 +638,81 @@ impl Compiler {
                         ));
                     }
                     
-                    let name_idx = self.current().chunk.add_name(name.clone());
+                    let key = self.tracked_name(name);
+                    let name_idx = self.make_name(key, *span)?;
                     
-                    self.emit(OpCode::TransitionLocal, span.line);
+                    let op = if self.current().locals[slot_usize].is_param {
+                        OpCode::TransitionParam
+                    } else {
+                        OpCode::TransitionLocal
+                    };
+                    self.emit(op, span.line);
                     self.emit_u16(slot, span.line);
                     self.emit_u16(name_idx, span.line);
+                    self.emit_u16(causes_idx, span.line);
                     
-                } else if let Some(idx) = self.resolve_upvalue(self.compilers.len() - 1, &name) {
-                    // Upvalue transition
-                    // TODO: Check immutability (need to track is_state in Upvalue?)
-                    // Currently Upvalue struct tracks is_local (bool). We don't track is_state in Upvalue struct.
-                    // But we can check the *source* of the upvalue?
-                    // Actually, compiler resolves upvalue recursively. The base local `is_state`.
-                    // We should propagate `is_state` through Upvalue struct or just assume runtime check?
-                    // Or static check?
-                    // Static check requires `Upvalue` to store `is_state`.
-                    // Let's assume we want static check.
-                    // But for now, let's omit the check or assume if it resolves, we trust user? 
-                    // No, `is_state` is important.
-                    // Let's modify Upvalue resolution to return `is_state`??
-                    // `resolve_upvalue` currently returns `Option<usize>`.
-                    // `FunctionCompiler.upvalues` stores `Upvalue` struct.
-                    // I can look up `self.current().upvalues[idx]`.
-                    // But `Upvalue` struct doesn't have `is_state`.
-                    // I should add `is_state` to `Upvalue` struct in `compiler.rs`?
-                    // Yes.
+                } else if let Some(idx) = self.resolve_upvalue(self.compilers.len() - 1, name) {
+                    // Upvalue transition: only a captured `state` may change
+                    if !self.current().upvalues[idx].is_state {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::ImmutableVariable(name.clone()),
+                            Some(*span),
+                        ));
+                    }
                     
-                    let name_idx = self.current().chunk.add_name(name.clone());
+                    let key = self.tracked_name(name);
+                    let name_idx = self.make_name(key, *span)?;
                     self.emit(OpCode::TransitionUpvalue, span.line);
                     self.emit_u16(idx as u16, span.line);
                     self.emit_u16(name_idx, span.line);
+                    self.emit_u16(causes_idx, span.line);
                     
                 } else {
                     // Global transition
-                    let idx = self.current().chunk.add_name(name.clone());
+                    let global = self.global_name(name);
+                    if self.consts.contains_key(&global) {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::ConstantReassignment(name.clone()),
+                            Some(*span),
+                        ));
+                    }
+                    self.check_global_defined(name, *span);
+                    let idx = self.make_name(global, *span)?;
                     self.emit(OpCode::Transition, span.line);
                     self.emit_u16(idx, span.line);
+                    self.emit_u16(causes_idx, span.line);
                 }
             }
             
+            Stmt::PropertyTransition { object, name, value, span } => {
+                let causes = self.state_reads(value, None);
+                self.compile_expr(object, heap)?;
+                self.compile_expr(value, heap)?;
+                let idx = self.make_name(name.clone(), *span)?;
+                let causes_idx = self.current().chunk.add_causes(causes);
+                self.emit(OpCode::TransitionProperty, span.line);
+                self.emit_u16(idx, span.line);
+                self.emit_u16(causes_idx, span.line);
+            }
+            
             Stmt::Block { stmts, .. } => {
                 self.begin_scope();
-                for stmt in stmts {
-                    self.compile_stmt(stmt, heap)?;
-                }
+                self.compile_stmts(stmts, heap)?;
                 self.end_scope();
             }
             
             Stmt::If { condition, then_branch, else_branch, span } => {
+                let known = self.known_condition(condition, *span);
+                if let (Some(truthy), true) = (known, self.optimize) {
+                    // Only the branch that can run is compiled
+                    if truthy {
+                        self.compile_guarded(|| format!("if {}", condition), then_branch, heap)?;
+                    } else if let Some(else_stmt) = else_branch {
+                        self.compile_guarded(|| format!("if !({})", condition), else_stmt, heap)?;
+                    }
+                    return Ok(());
+                }
+                
                 self.compile_expr(condition, heap)?;
                 
                 // Jump over then branch if false
@@ -273,7 +720,7 @@ impl Compiler {
                 self.emit(OpCode::Pop, span.line);  // Pop condition
                 
                 // Compile then branch
-                self.compile_stmt(then_branch, heap)?;
+                self.compile_guarded(|| format!("if {}", condition), then_branch, heap)?;
                 
                 // Jump over else branch
                 let else_jump = self.emit_jump(OpCode::Jump, span.line);
@@ -284,45 +731,98 @@ impl Compiler {
                 
                 // Compile else branch if present
                 if let Some(else_stmt) = else_branch {
-                    self.compile_stmt(else_stmt, heap)?;
+                    self.compile_guarded(|| format!("if !({})", condition), else_stmt, heap)?;
                 }
                 
                 self.patch_jump(else_jump);
             }
             
+            Stmt::Match { subject, arms, default, span } => {
+                self.compile_expr(subject, heap)?;
+                
+                // The subject stays on the stack while the patterns are tried.
+                // An arm that matches pops it, runs, and jumps to the end.
+                let mut exits = Vec::new();
+                for (patterns, body) in arms {
+                    let mut matched = Vec::new();
+                    for pattern in patterns {
+                        let line = pattern.span().line;
+                        self.emit(OpCode::Dup, line);
+                        self.compile_expr(pattern, heap)?;
+                        self.emit(OpCode::Equal, line);
+                        matched.push(self.emit_jump(OpCode::JumpIfTrue, line));
+                        self.emit(OpCode::Pop, line);
+                    }
+                    let next_arm = self.emit_jump(OpCode::Jump, span.line);
+                    
+                    for jump in matched {
+                        self.patch_jump(jump);
+                    }
+                    self.emit(OpCode::Pop, span.line);  // Pop comparison
+                    self.emit(OpCode::Pop, span.line);  // Pop subject
+                    self.compile_stmt(body, heap)?;
+                    exits.push(self.emit_jump(OpCode::Jump, span.line));
+                    
+                    self.patch_jump(next_arm);
+                }
+                
+                self.emit(OpCode::Pop, span.line);  // No arm matched: pop subject
+                if let Some(default) = default {
+                    self.compile_stmt(default, heap)?;
+                }
+                for exit in exits {
+                    self.patch_jump(exit);
+                }
+            }
+            
             Stmt::While { condition, body, span } => {
+                let known = self.known_condition(condition, *span).filter(|_| self.optimize);
+                if known == Some(false) {
+                    return Ok(());
+                }
+                
                 let loop_start = self.current().chunk.len();
-                self.current().loop_starts.push(loop_start);
-                self.current().loop_exits.push(Vec::new());
+                self.begin_loop(Some(loop_start));
                 
-                self.compile_expr(condition, heap)?;
-                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, span.line);
-                self.emit(OpCode::Pop, span.line);
+                // A loop that always runs doesn't test its condition
+                let exit_jump = if known.is_none() {
+                    self.compile_expr(condition, heap)?;
+                    let jump = self.emit_jump(OpCode::JumpIfFalse, span.line);
+                    self.emit(OpCode::Pop, span.line);
+                    Some(jump)
+                } else {
+                    None
+                };
                 
-                self.compile_stmt(body, heap)?;
+                self.compile_guarded(|| format!("while {}", condition), body, heap)?;
                 self.emit_loop(loop_start, span.line);
                 
-                self.patch_jump(exit_jump);
-                self.emit(OpCode::Pop, span.line);
-                
-                // Patch all break statements
-                let exits = self.current().loop_exits.pop().unwrap();
-                for exit in exits {
-                    self.patch_jump(exit);
+                if let Some(exit_jump) = exit_jump {
+                    self.patch_jump(exit_jump);
+                    self.emit(OpCode::Pop, span.line);
                 }
-                self.current().loop_starts.pop();
+                
+                // `break` jumps past the Pop above: the condition was popped
+                // when the body was entered
+                self.end_loop();
             }
             
             Stmt::For { var, iterable, body, span } => {
                 self.begin_scope();
                 
                 // 1. Compile Iterator Expression -> __iter__
-                //    This pushes the array (or string) onto the stack
+                //    Strings and maps become arrays of their characters or
+                //    keys; arrays and numbers are walked as they are
                 self.compile_expr(iterable, heap)?;
+                self.emit(OpCode::IterStart, span.line);
                 self.add_local("__iter__".to_string(), false);
                 
+                // Arrays may not grow or shrink while iterated -> __version__
+                self.emit(OpCode::IterVersion, span.line);
+                self.add_local("__version__".to_string(), false);
+                
                 // 2. Initialize Index -> __idx__ = 0
-                self.emit_constant(Value::Number(0.0), span.line);
+                self.emit_constant(Value::Number(0.0), *span)?;
                 self.add_local("__idx__".to_string(), true);
                 
                 // 3. User Loop Variable -> var (initialized to nil)
@@ -330,69 +830,44 @@ impl Compiler {
                 self.add_local(var.clone(), false);
                 
                 let loop_start = self.current().chunk.len();
-                self.current().loop_starts.push(loop_start);
-                self.current().loop_exits.push(Vec::new());
-                
-                // --- Condition: __idx__ < len(__iter__) ---
+                // `continue` jumps forward to the increment
+                self.begin_loop(None);
+                
+                // --- Check __iter__ was not resized since __version__ was taken ---
+                if let (Some(iter_slot), Some(version_slot)) = (self.resolve_local("__iter__"), self.resolve_local("__version__")) {
+                    self.emit(OpCode::CheckIteration, span.line);
+                    self.emit_u16(iter_slot, span.line);
+                    self.emit_u16(version_slot, span.line);
+                }
                 
-                // First: Call len(__iter__) and leave result on stack
-                // Get 'len' function
-                let len_idx = self.current().chunk.add_name("len".to_string());
-                self.emit(OpCode::GetGlobal, span.line);
-                self.emit_u16(len_idx, span.line);
+                // --- Condition: __idx__ < number of elements in __iter__ ---
                 
-                // Load __iter__ as argument
+                // IterLen leaves the count on the stack; numbers count up to themselves
                 if let Some(slot) = self.resolve_local("__iter__") {
-                    self.emit(OpCode::GetLocal, span.line);
+                    self.emit(OpCode::IterLen, span.line);
                     self.emit_u16(slot, span.line);
                 }
                 
-                // Call len(1 arg) - leaves length on stack
-                self.emit(OpCode::Call, span.line);
-                self.emit_byte(1, span.line);
-                
-                // Second: Load __idx__
+                // Load __idx__
                 if let Some(slot) = self.resolve_local("__idx__") {
                     self.emit(OpCode::GetLocal, span.line);
                     self.emit_u16(slot, span.line);
                 }
                 
-                // Now stack is: [length, __idx__] 
-                // We need: __idx__ < length
-                // But Less pops right then left: left < right
-                // Stack: [length, __idx__] -> Less compares length (2nd pop) < __idx__ (1st pop) = WRONG
-                // We need __idx__ < length, so swap order
-                // Actually, push __idx__ first, then length, then Less
-                // Let me fix: push __idx__, push length, Less => __idx__ < length
-                
-                // Correction: Swap the order
-                // Stack after above: [length, __idx__]
-                // Binary ops: pop b, pop a, compute a op b
-                // So: a=length, b=__idx__, computes length < __idx__ (WRONG)
-                // We want __idx__ < length
-                // Fix: Push __idx__ first, then call len, then Less
-                
-                // Actually simpler: use Greater instead (length > __idx__)
+                // Stack is [count, __idx__]: count > __idx__
                 self.emit(OpCode::Greater, span.line);
                 
                 // Jump if False (Exit Loop)
                 let exit_jump = self.emit_jump(OpCode::JumpIfFalse, span.line);
                 self.emit(OpCode::Pop, span.line); // Pop condition result (true)
                 
-                // --- Body Prologue: var = __iter__[__idx__] ---
+                // --- Body Prologue: var = element __idx__ of __iter__ ---
                 
-                // Push __iter__
-                if let Some(slot) = self.resolve_local("__iter__") {
-                    self.emit(OpCode::GetLocal, span.line);
-                    self.emit_u16(slot, span.line);
+                if let (Some(iter_slot), Some(idx_slot)) = (self.resolve_local("__iter__"), self.resolve_local("__idx__")) {
+                    self.emit(OpCode::IterGet, span.line);
+                    self.emit_u16(iter_slot, span.line);
+                    self.emit_u16(idx_slot, span.line);
                 }
-                // Push __idx__
-                if let Some(slot) = self.resolve_local("__idx__") {
-                    self.emit(OpCode::GetLocal, span.line);
-                    self.emit_u16(slot, span.line);
-                }
-                // Index Operation
-                self.emit(OpCode::Index, span.line);
                 
                 // Assign to user variable 'var'
                 if let Some(slot) = self.resolve_local(var) {
@@ -402,17 +877,22 @@ impl Compiler {
                 }
                 
                 // Execute Body
-                self.compile_stmt(body, heap)?;
+                self.compile_guarded(|| format!("for {} in {}", var, iterable), body, heap)?;
                 
                 // --- Increment Index: __idx__ = __idx__ + 1 ---
                 
+                let continues = std::mem::take(&mut self.current().loops.last_mut().unwrap().continues);
+                for jump in continues {
+                    self.patch_jump(jump);
+                }
+                
                 // Load __idx__
                 if let Some(slot) = self.resolve_local("__idx__") {
                     self.emit(OpCode::GetLocal, span.line);
                     self.emit_u16(slot, span.line);
                 }
                 // Load 1
-                self.emit_constant(Value::Number(1.0), span.line);
+                self.emit_constant(Value::Number(1.0), *span)?;
                 // Add
                 self.emit(OpCode::Add, span.line);
                 
@@ -431,26 +911,36 @@ impl Compiler {
                 self.emit(OpCode::Pop, span.line); // Pop condition result (false)
                 
                 // Patch breaks
-                let exits = self.current().loop_exits.pop().unwrap();
-                for exit in exits {
-                    self.patch_jump(exit);
-                }
-                self.current().loop_starts.pop();
+                self.end_loop();
                 
                 self.end_scope();
             }
             
-            Stmt::Class { name, methods, span } => {
+            Stmt::Class { name, superclass, methods, span } => {
+                self.check_class_body(methods)?;
+                if let Some(Expr::Ident { name: parent, span: parent_span }) = superclass {
+                    if parent == name {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::SelfInheritance(name.clone()),
+                            Some(*parent_span),
+                        ));
+                    }
+                }
+                
                 // 1. Declare class name var
                 let global_idx = if self.current().scope_depth == 0 {
-                    Some(self.current().chunk.add_name(name.clone()))
+                    let global = self.global_name(name);
+                    self.consts.remove(&global);
+                    self.known_lets.remove(&global);
+                    Some(self.make_name(global, *span)?)
                 } else {
+                    self.check_duplicate_local(name, *span)?;
                     self.add_local(name.clone(), false);
                     None
                 };
                 
                 // 2. Class creation
-                let name_idx = self.current().chunk.add_name(name.clone());
+                let name_idx = self.make_name(name.clone(), *span)?;
                 self.emit(OpCode::Class, span.line);
                 self.emit_u16(name_idx, span.line);
                 
@@ -460,20 +950,25 @@ impl Compiler {
                     self.emit_u16(idx, span.line);
                 }
                 
-                // 4. Load class back onto stack for method binding
-                if let Some(idx) = global_idx {
-                    self.emit(OpCode::GetGlobal, span.line);
-                    self.emit_u16(idx, span.line);
-                } else {
-                    // Local: peek/get it
-                     let slot = self.resolve_local(&name).unwrap();
-                     self.emit(OpCode::GetLocal, span.line);
-                     self.emit_u16(slot, span.line);
+                let class_ref = Expr::Ident { name: name.clone(), span: *span };
+                
+                // The superclass lives in a local named `super` around the
+                // methods, which capture it to resolve `super.name`
+                if let Some(superclass) = superclass {
+                    self.begin_scope();
+                    self.compile_expr(superclass, heap)?;
+                    self.add_local("super".to_string(), false);
+                    self.compile_expr(&class_ref, heap)?;
+                    self.emit(OpCode::Inherit, span.line);
                 }
+                self.class_supers.push(superclass.is_some());
+                
+                // 4. Load class back onto stack for method binding
+                self.compile_expr(&class_ref, heap)?;
                 
                 // 5. Compile methods
                 for method in methods {
-                    if let Stmt::Function { name: m_name, params, body, span: m_span } = method {
+                    if let Stmt::Function { name: m_name, params, state_params, param_types, body, span: m_span } = method {
                         // --- Compile Closure (Inline) ---
                         self.compilers.push(FunctionCompiler::new(m_name));
                         self.begin_scope();
@@ -483,13 +978,9 @@ impl Compiler {
                             local.name = "this".to_string();
                         }
                         
-                        for param in params {
-                            self.add_local(param.clone(), false);
-                        }
+                        self.declare_params(params, state_params, param_types, *m_span)?;
                         
-                        for stmt in body {
-                            self.compile_stmt(stmt, heap)?;
-                        }
+                        self.compile_stmts(body, heap)?;
                         
                         if m_name == "init" {
                              // Init returns 'this'
@@ -501,7 +992,7 @@ impl Compiler {
                              self.emit(OpCode::Return, m_span.line);
                         }
                         
-                        let func_compiler = self.compilers.pop().unwrap();
+                        let func_compiler = self.end_function(heap);
                         let chunk = Rc::new(func_compiler.chunk);
                         let upvalues = func_compiler.upvalues;
                         
@@ -510,10 +1001,10 @@ impl Compiler {
                             params.clone(),
                             chunk,
                             upvalues.len(),
-                        );
+                        ).with_state_upvalues(upvalues.iter().map(|u| u.is_state).collect());
                         
                         let handle = heap.alloc_function(function);
-                        let func_idx = self.current().chunk.add_constant(Value::Function(handle));
+                        let func_idx = self.make_constant(Value::Function(handle), *m_span)?;
                         self.emit(OpCode::Closure, m_span.line);
                         self.emit_u16(func_idx, m_span.line);
                         
@@ -523,7 +1014,7 @@ impl Compiler {
                         }
                         // --- End Closure ---
                         
-                        let m_name_idx = self.current().chunk.add_name(m_name.clone());
+                        let m_name_idx = self.make_name(m_name.clone(), *m_span)?;
                         self.emit(OpCode::Method, m_span.line);
                         self.emit_u16(m_name_idx, m_span.line);
                     }
@@ -531,12 +1022,24 @@ impl Compiler {
                 
                 // 6. Pop class
                 self.emit(OpCode::Pop, span.line);
+                self.class_supers.pop();
+                
+                if superclass.is_some() {
+                    // Methods may have captured `super`, so close it rather than pop it
+                    self.current().scope_depth -= 1;
+                    self.current().locals.pop();
+                    self.emit(OpCode::CloseUpvalue, span.line);
+                }
             }
             
-            Stmt::Function { name, params, body, span } => {
+            Stmt::Function { name, params, state_params, param_types, body, span } => {
                 let global_idx = if self.current().scope_depth == 0 {
-                    Some(self.current().chunk.add_name(name.clone()))
+                    let global = self.global_name(name);
+                    self.consts.remove(&global);
+                    self.known_lets.remove(&global);
+                    Some(self.make_name(global, *span)?)
                 } else {
+                    self.check_duplicate_local(name, *span)?;
                     self.add_local(name.clone(), false);
                     // Mark initialized immediately to allow recursion
                     let depth = self.current().scope_depth;
@@ -549,21 +1052,17 @@ impl Compiler {
                 self.begin_scope();
                 
                 // Define parameters as locals
-                for param in params {
-                    self.add_local(param.clone(), false);
-                }
+                self.declare_params(params, state_params, param_types, *span)?;
                 
                 // Compile body
-                for stmt in body {
-                    self.compile_stmt(stmt, heap)?;
-                }
+                self.compile_stmts(body, heap)?;
                 
                 // Implicit return nil
                 self.emit(OpCode::Nil, span.line);
                 self.emit(OpCode::Return, span.line);
                 
                 // Pop the function compiler
-                let func_compiler = self.compilers.pop().unwrap();
+                let func_compiler = self.end_function(heap);
                 let chunk = Rc::new(func_compiler.chunk); // Wrap in Rc
                 let upvalues = func_compiler.upvalues;
                 
@@ -573,13 +1072,13 @@ impl Compiler {
                     params.clone(),
                     chunk, // Pass Rc<Chunk>
                     upvalues.len(),
-                );
+                ).with_state_upvalues(upvalues.iter().map(|u| u.is_state).collect());
                 
                 // Alloc function
                 let handle = heap.alloc_function(function);
                 
                 // Main compiler: emit constant
-                let func_idx = self.current().chunk.add_constant(Value::Function(handle));
+                let func_idx = self.make_constant(Value::Function(handle), *span)?;
                 self.emit(OpCode::Closure, span.line);
                 self.emit_u16(func_idx, span.line);
                 
@@ -597,6 +1096,8 @@ impl Compiler {
             
             Stmt::Return { value, span } => {
                 if let Some(expr) = value {
+                    // Only inside functions: the script's frame has no caller to return to
+                    self.tail_position = self.compilers.len() > 1 && matches!(expr, Expr::Call { .. });
                     self.compile_expr(expr, heap)?;
                 } else {
                     self.emit(OpCode::Nil, span.line);
@@ -605,72 +1106,36 @@ impl Compiler {
             }
             
             Stmt::Break { span } => {
-                if self.current().loop_exits.is_empty() {
+                if self.current().loops.is_empty() {
                     return Err(SkyHetuError::new(ErrorKind::BreakOutsideLoop, Some(*span)));
                 }
+                self.discard_loop_locals(span.line);
                 let exit = self.emit_jump(OpCode::Jump, span.line);
-                self.current().loop_exits.last_mut().unwrap().push(exit);
+                self.current().loops.last_mut().unwrap().breaks.push(exit);
             }
             
             Stmt::Continue { span } => {
-                if self.current().loop_starts.is_empty() {
+                if self.current().loops.is_empty() {
                     return Err(SkyHetuError::new(ErrorKind::ContinueOutsideLoop, Some(*span)));
                 }
-                let loop_start = *self.current().loop_starts.last().unwrap();
-                self.emit_loop(loop_start, span.line);
+                self.discard_loop_locals(span.line);
+                match self.current().loops.last().unwrap().start {
+                    Some(loop_start) => self.emit_loop(loop_start, span.line),
+                    None => {
+                        let jump = self.emit_jump(OpCode::Jump, span.line);
+                        self.current().loops.last_mut().unwrap().continues.push(jump);
+                    }
+                }
             }
             
             Stmt::Import { names, path, span } => {
-                // Resolve module path relative to current file's directory
-                let module_path = if let Some(base) = &self.module_base_path {
-                    base.join(path)
-                } else {
-                    std::path::PathBuf::from(path)
-                };
-                
-                // Add .skyh extension if not present
-                let module_path = if module_path.extension().is_none() {
-                    module_path.with_extension("skyh")
-                } else {
-                    module_path
-                };
-                
-                // Read the module source
-                let source = std::fs::read_to_string(&module_path).map_err(|e| {
-                    SkyHetuError::new(
-                        ErrorKind::ModuleNotFound(format!("{}: {}", path, e)),
-                        Some(*span),
-                    )
-                })?;
-                
-                // Parse the module
-                let mut lexer = crate::lexer::Lexer::new(&source);
-                let tokens = lexer.tokenize().map_err(|e| {
-                    SkyHetuError::new(ErrorKind::ModuleNotFound(format!("{}: {}", path, e)), Some(*span))
-                })?;
-                let mut parser = crate::parser::Parser::new(tokens);
-                let module_program = parser.parse().map_err(|e| {
-                    SkyHetuError::new(ErrorKind::ModuleNotFound(format!("{}: {}", path, e)), Some(*span))
-                })?;
-                
-                // Compile the module's statements directly in the current compiler
-                // This ensures constants and functions are in the current chunk
-                for stmt in &module_program.statements {
-                    self.compile_stmt(stmt, heap)?;
-                }
-                
-                // Track which names were imported (for future use)
-                let _ = names; // TODO: Filter which names are actually imported
+                self.compile_import(names, path, *span, heap)?;
             }
             
             Stmt::Export { stmt, span } => {
                 // Track the exported name
-                match stmt.as_ref() {
-                    Stmt::Function { name, .. } => { self.exports.insert(name.clone()); }
-                    Stmt::Let { name, .. } => { self.exports.insert(name.clone()); }
-                    Stmt::State { name, .. } => { self.exports.insert(name.clone()); }
-                    Stmt::Class { name, .. } => { self.exports.insert(name.clone()); }
-                    _ => {}
+                if let Some(name) = declared_name(stmt) {
+                    self.exports.insert(name);
                 }
                 let _ = span;
                 self.compile_stmt(stmt, heap)?;
@@ -685,11 +1150,11 @@ impl Compiler {
     fn compile_expr(&mut self, expr: &Expr, heap: &mut crate::gc::Heap) -> Result<()> {
         match expr {
             Expr::Number { value, span } => {
-                self.emit_constant(Value::Number(*value), span.line);
+                self.emit_constant(Value::Number(*value), *span)?;
             }
             
             Expr::String { value, span } => {
-                self.emit_constant(Value::String(value.clone()), span.line);
+                self.emit_constant(Value::String(value.as_str().into()), *span)?;
             }
             
             Expr::Bool { value, span } => {
@@ -703,21 +1168,34 @@ impl Compiler {
             Expr::Ident { name, span } => {
                 // Check for local variable first
                 if let Some(slot) = self.resolve_local(name) {
+                    self.current().locals[slot as usize].is_read = true;
                     self.emit(OpCode::GetLocal, span.line);
                     self.emit_u16(slot, span.line);
                 } else if let Some(idx) = self.resolve_upvalue(self.compilers.len() - 1, name) {
                     // Upvalue
                     self.emit(OpCode::GetUpvalue, span.line);
                     self.emit_u16(idx as u16, span.line);
+                } else if let Some(value) = self.consts.get(&self.global_name(name)) {
+                    // Constant: inline its value
+                    let value = value.clone();
+                    self.emit_constant(value, *span)?;
                 } else {
                     // Global
-                    let idx = self.current().chunk.add_name(name.clone());
+                    self.check_global_defined(name, *span);
+                    let global = self.global_name(name);
+                    let idx = self.make_name(global, *span)?;
                     self.emit(OpCode::GetGlobal, span.line);
                     self.emit_u16(idx, span.line);
                 }
             }
             
             Expr::Binary { left, op, right, span } => {
+                if matches!(op, BinaryOp::Eq | BinaryOp::Ne) {
+                    self.check_comparison_types(left, *op, right, *span);
+                }
+                if self.emit_folded(expr)? {
+                    return Ok(());
+                }
                 self.compile_expr(left, heap)?;
                 self.compile_expr(right, heap)?;
                 
@@ -737,6 +1215,9 @@ impl Compiler {
             }
             
             Expr::Unary { op, operand, span } => {
+                if self.emit_folded(expr)? {
+                    return Ok(());
+                }
                 self.compile_expr(operand, heap)?;
                 match op {
                     UnaryOp::Neg => self.emit(OpCode::Negate, span.line),
@@ -745,6 +1226,9 @@ impl Compiler {
             }
             
             Expr::Logical { left, op, right, span } => {
+                if self.emit_folded(expr)? {
+                    return Ok(());
+                }
                 self.compile_expr(left, heap)?;
                 
                 match op {
@@ -767,7 +1251,50 @@ impl Compiler {
                 self.compile_expr(expr, heap)?;
             }
             
+            Expr::Super { method, span } => {
+                if self.class_supers.last() != Some(&true) {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::SuperOutsideSubclass,
+                        Some(*span),
+                    ));
+                }
+                self.compile_expr(&Expr::Ident { name: "this".to_string(), span: *span }, heap)?;
+                self.compile_expr(&Expr::Ident { name: "super".to_string(), span: *span }, heap)?;
+                let idx = self.make_name(method.clone(), *span)?;
+                self.emit(OpCode::GetSuper, span.line);
+                self.emit_u16(idx, span.line);
+            }
+            
+            Expr::If { condition, then_branch, else_branch, span } => {
+                let known = self.known_condition(condition, *span);
+                if let (Some(truthy), true) = (known, self.optimize) {
+                    match (truthy, else_branch) {
+                        (true, _) => self.compile_expr(then_branch, heap)?,
+                        (false, Some(else_branch)) => self.compile_expr(else_branch, heap)?,
+                        (false, None) => self.emit(OpCode::Nil, span.line),
+                    }
+                    return Ok(());
+                }
+                
+                // Same jumps as an if statement, but each branch leaves its value
+                self.compile_expr(condition, heap)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, span.line);
+                self.emit(OpCode::Pop, span.line);
+                self.compile_expr(then_branch, heap)?;
+                let else_jump = self.emit_jump(OpCode::Jump, span.line);
+                
+                self.patch_jump(then_jump);
+                self.emit(OpCode::Pop, span.line);
+                match else_branch {
+                    Some(else_branch) => self.compile_expr(else_branch, heap)?,
+                    None => self.emit(OpCode::Nil, span.line),
+                }
+                self.patch_jump(else_jump);
+            }
+            
             Expr::Call { callee, args, span } => {
+                let tail = std::mem::take(&mut self.tail_position);
+                
                 // Special built-in handling
                 if let Expr::Ident { name, .. } = callee.as_ref() {
                     match name.as_str() {
@@ -776,22 +1303,78 @@ impl Compiler {
                                 self.compile_expr(arg, heap)?;
                             }
                             self.emit(OpCode::Print, span.line);
+                            self.emit_count(args.len(), "print arguments", *span)?;
+                            return Ok(());
+                        }
+                        // watch(x, f) and unwatch(x) name x by its history, as why(x) does
+                        "watch" | "unwatch" if self.is_builtin(name) && matches!(args.first(), Some(Expr::Ident { .. })) => {
+                            let Expr::Ident { name: var_name, span: var_span } = &args[0] else { unreachable!() };
+                            if let Some(slot) = self.resolve_local(var_name) {
+                                self.current().locals[slot as usize].is_read = true;
+                            }
+                            let mut args = args.clone();
+                            args[0] = Expr::String { value: self.tracked_name(var_name), span: *var_span };
+                            self.tail_position = tail;
+                            return self.compile_expr(&Expr::Call { callee: callee.clone(), args, span: *span }, heap);
+                        }
+                        // push(x, v) and pop(x) on a state array are recorded like element writes
+                        "push" | "pop" if self.is_builtin(name)
+                            && args.len() == if name == "push" { 2 } else { 1 }
+                            && matches!(&args[0], Expr::Ident { name: target, .. } if self.is_state_variable(target)) => {
+                            let Expr::Ident { name: var_name, .. } = &args[0] else { unreachable!() };
+                            if let Some(slot) = self.resolve_local(var_name) {
+                                self.current().locals[slot as usize].is_transitioned = true;
+                            }
+                            self.compile_expr(callee, heap)?;
+                            for arg in args {
+                                self.compile_expr(arg, heap)?;
+                            }
+                            let key = self.tracked_name(var_name);
+                            let idx = self.make_name(key, *span)?;
+                            self.emit(OpCode::CallState, span.line);
                             self.emit_byte(args.len() as u8, span.line);
+                            self.emit_u16(idx, span.line);
                             return Ok(());
                         }
-                        "why" => {
+                        "why" if self.is_builtin("why") => {
                             if args.len() != 1 {
                                 return Err(SkyHetuError::new(
-                                    ErrorKind::WrongArity(1, args.len()),
+                                    ErrorKind::WrongArity(Some("why".to_string()), 1, args.len()),
                                     Some(*span),
                                 ));
                             }
                             if let Expr::Ident { name: var_name, .. } = &args[0] {
-                                let idx = self.current().chunk.add_name(var_name.clone());
+                                if let Some(slot) = self.resolve_local(var_name) {
+                                    self.current().locals[slot as usize].is_read = true;
+                                }
+                                // State parameters are recorded under a per-call name
+                                let is_param = self.resolve_local(var_name)
+                                    .is_some_and(|slot| self.current().locals[slot as usize].is_param);
+                                if is_param {
+                                    let idx = self.make_name(var_name.clone(), *span)?;
+                                    self.emit(OpCode::WhyParam, span.line);
+                                    self.emit_u16(idx, span.line);
+                                    return Ok(());
+                                }
+                                let key = self.tracked_name(var_name);
+                                let idx = self.make_name(key, *span)?;
                                 self.emit(OpCode::Why, span.line);
                                 self.emit_u16(idx, span.line);
                                 return Ok(());
                             }
+                            // why(obj.field), including why(this.field) inside methods
+                            if let Expr::Get { object, name: field, .. } = &args[0] {
+                                self.compile_expr(object, heap)?;
+                                let idx = self.make_name(field.clone(), *span)?;
+                                self.emit(OpCode::WhyProperty, span.line);
+                                self.emit_u16(idx, span.line);
+                                return Ok(());
+                            }
+                            // A string names the variable and goes to the native;
+                            // anything else would be asking about a value
+                            if infer(&args[0], self) != StaticType::String {
+                                return Err(SkyHetuError::new(ErrorKind::WhyOfExpression, Some(args[0].span())));
+                            }
                         }
                         "time" => {
                             self.emit(OpCode::Time, span.line);
@@ -802,27 +1385,57 @@ impl Compiler {
                 }
                 
                 // Regular function call
+                if args.len() > u8::MAX as usize {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::TooManyOperands("arguments".to_string(), args.len(), u8::MAX as usize),
+                        Some(*span),
+                    ));
+                }
                 self.compile_expr(callee, heap)?;
+                let mut has_spread = false;
                 for arg in args {
-                    self.compile_expr(arg, heap)?;
+                    if let Expr::Spread { expr, .. } = arg {
+                        has_spread = true;
+                        self.compile_expr(expr, heap)?;
+                    } else {
+                        self.compile_expr(arg, heap)?;
+                    }
+                }
+                if has_spread {
+                    // The VM expands the flagged arrays, so the final count is only known at runtime
+                    self.emit(OpCode::CallSpread, span.line);
+                    self.emit_byte(args.len() as u8, span.line);
+                    for arg in args {
+                        let is_spread = matches!(arg, Expr::Spread { .. });
+                        self.emit_byte(is_spread as u8, span.line);
+                    }
+                } else {
+                    // A tail call is still followed by the Return, which runs when the
+                    // callee finishes without a frame of its own (natives, classes)
+                    self.emit(if tail { OpCode::TailCall } else { OpCode::Call }, span.line);
+                    self.emit_byte(args.len() as u8, span.line);
                 }
-                self.emit(OpCode::Call, span.line);
-                self.emit_byte(args.len() as u8, span.line);
             }
             
-            Expr::Lambda { params, body, span } => {
+            Expr::Spread { span, .. } => {
+                return Err(SkyHetuError::new(
+                    ErrorKind::UnexpectedToken("...".to_string()),
+                    Some(*span),
+                ));
+            }
+            
+            Expr::Lambda { params, state_params, param_types, body, span } => {
                 // Compile lambda as a function
                 self.compilers.push(FunctionCompiler::new("<lambda>"));
                 self.begin_scope();
                 
-                for param in params {
-                    self.add_local(param.clone(), false);
-                }
+                self.declare_params(params, state_params, param_types, *span)?;
                 
-                self.compile_expr(body, heap)?;
+                self.compile_stmts(body, heap)?;
+                self.emit(OpCode::Nil, span.line);
                 self.emit(OpCode::Return, span.line);
                 
-                let func_compiler = self.compilers.pop().unwrap();
+                let func_compiler = self.end_function(heap);
                 let chunk = Rc::new(func_compiler.chunk);
                 let upvalues = func_compiler.upvalues;
                 
@@ -831,10 +1444,10 @@ impl Compiler {
                     params.clone(),
                     chunk,
                     upvalues.len(),
-                );
+                ).with_state_upvalues(upvalues.iter().map(|u| u.is_state).collect());
                 
                 let handle = heap.alloc_function(function);
-                let idx = self.current().chunk.add_constant(Value::Function(handle));
+                let idx = self.make_constant(Value::Function(handle), *span)?;
                 self.emit(OpCode::Closure, span.line);
                 self.emit_u16(idx, span.line);
                 
@@ -847,15 +1460,49 @@ impl Compiler {
             
             Expr::Get { object, name, span } => {
                 self.compile_expr(object, heap)?;
-                let idx = self.current().chunk.add_name(name.clone());
+                let idx = self.make_name(name.clone(), *span)?;
                 self.emit(OpCode::GetProperty, span.line);
                 self.emit_u16(idx, span.line);
             }
             
+            Expr::Array { elements, span } => {
+                for element in elements {
+                    self.compile_expr(element, heap)?;
+                }
+                self.emit(OpCode::Array, span.line);
+                self.emit_count(elements.len(), "array elements", *span)?;
+            }
+            
+            Expr::Index { object, index, span } => {
+                self.compile_expr(object, heap)?;
+                self.compile_expr(index, heap)?;
+                self.emit(OpCode::Index, span.line);
+            }
+            
+            Expr::IndexSet { object, index, value, span } => {
+                self.compile_expr(object, heap)?;
+                self.compile_expr(index, heap)?;
+                self.compile_expr(value, heap)?;
+                
+                // Element writes on a state array are recorded against the variable
+                match object.as_ref() {
+                    Expr::Ident { name, .. } if self.is_state_variable(name) => {
+                        if let Some(slot) = self.resolve_local(name) {
+                            self.current().locals[slot as usize].is_transitioned = true;
+                        }
+                        let key = self.tracked_name(name);
+                        let idx = self.make_name(key, *span)?;
+                        self.emit(OpCode::IndexSetState, span.line);
+                        self.emit_u16(idx, span.line);
+                    }
+                    _ => self.emit(OpCode::IndexSet, span.line),
+                }
+            }
+            
             Expr::Set { object, name, value, span } => {
                 self.compile_expr(object, heap)?;
                 self.compile_expr(value, heap)?;
-                let idx = self.current().chunk.add_name(name.clone());
+                let idx = self.make_name(name.clone(), *span)?;
                 self.emit(OpCode::SetProperty, span.line);
                 self.emit_u16(idx, span.line);
             }
@@ -864,6 +1511,171 @@ impl Compiler {
         Ok(())
     }
     
+    // ==================== Constants ====================
+    
+    /// Evaluate a `const` initializer at compile time. Only literals, other
+    /// constants and operators on them are allowed; the operators follow the
+    /// same rules as the VM so folding never changes a program's result.
+    fn eval_const(&self, expr: &Expr) -> Result<Value> {
+        self.fold(expr, false)
+    }
+    
+    /// `eval_const`, also reading top-level `let`s bound to literals when
+    /// `with_lets` is set. Names shadowed by a local are then never folded.
+    fn fold(&self, expr: &Expr, with_lets: bool) -> Result<Value> {
+        let type_error = |expected: &str, got: String, span: Span| {
+            SkyHetuError::new(ErrorKind::TypeMismatch(expected.to_string(), got), Some(span))
+        };
+        
+        match expr {
+            Expr::Number { value, .. } => Ok(Value::Number(*value)),
+            Expr::String { value, .. } => Ok(Value::String(value.as_str().into())),
+            Expr::Bool { value, .. } => Ok(Value::Bool(*value)),
+            Expr::Nil { .. } => Ok(Value::Nil),
+            Expr::Grouping { expr, .. } => self.fold(expr, with_lets),
+            
+            Expr::Ident { name, span } => {
+                let global = self.global_name(name);
+                let value = if !with_lets {
+                    self.consts.get(&global)
+                } else if self.is_local_anywhere(name) {
+                    None
+                } else if self.compilers.len() == 1 {
+                    self.consts.get(&global).or_else(|| self.known_lets.get(&global))
+                } else {
+                    self.consts.get(&global)
+                };
+                value.cloned().ok_or_else(|| SkyHetuError::new(ErrorKind::NotConstant, Some(*span)))
+            }
+            
+            Expr::Unary { op, operand, span } => {
+                let value = self.fold(operand, with_lets)?;
+                match op {
+                    UnaryOp::Neg => match value {
+                        Value::Number(n) => Ok(Value::Number(-n)),
+                        other => Err(type_error("number", other.type_name().to_string(), *span)),
+                    },
+                    UnaryOp::Not => Ok(Value::Bool(!value.is_truthy())),
+                }
+            }
+            
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                if self.fold(condition, with_lets)?.is_truthy() {
+                    self.fold(then_branch, with_lets)
+                } else {
+                    else_branch.as_ref().map_or(Ok(Value::Nil), |e| self.fold(e, with_lets))
+                }
+            }
+            
+            Expr::Logical { left, op, right, .. } => {
+                let left = self.fold(left, with_lets)?;
+                let right = self.fold(right, with_lets)?;
+                Ok(match op {
+                    LogicalOp::And => if left.is_truthy() { right } else { left },
+                    LogicalOp::Or => if left.is_truthy() { left } else { right },
+                })
+            }
+            
+            Expr::Binary { left, op, right, span } => {
+                let a = self.fold(left, with_lets)?;
+                let b = self.fold(right, with_lets)?;
+                let mismatch = |expected: &str| {
+                    type_error(&format!("{} for '{}'", expected, op), format!("{} and {}", a.type_name(), b.type_name()), *span)
+                };
+                // Strings are built only up to a limit: an optimizing fold
+                // leaves longer ones to the VM, which checks them against the
+                // heap limit, and a `const` can't be that long
+                let limit = if with_lets { MAX_FOLDED_STRING } else { MAX_CONST_STRING };
+                let built = |len: usize, text: &dyn Fn() -> String| {
+                    if len > limit {
+                        Err(SkyHetuError::new(ErrorKind::ConstantTooLong(limit), Some(*span)))
+                    } else {
+                        Ok(Value::String(text().into()))
+                    }
+                };
+                let ordered = |ordering: std::cmp::Ordering| Value::Bool(match op {
+                    BinaryOp::Lt => ordering.is_lt(),
+                    BinaryOp::Le => ordering.is_le(),
+                    BinaryOp::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                });
+                
+                match (op, &a, &b) {
+                    (BinaryOp::Eq, _, _) => Ok(Value::Bool(a == b)),
+                    (BinaryOp::Ne, _, _) => Ok(Value::Bool(a != b)),
+                    
+                    (BinaryOp::Add, Value::Number(x), Value::Number(y)) => Ok(Value::Number(x + y)),
+                    (BinaryOp::Add, Value::String(s1), Value::String(s2)) => built(s1.len() + s2.len(), &|| format!("{}{}", s1, s2)),
+                    (BinaryOp::Add, Value::String(s), Value::Number(n)) => {
+                        let n = format_number(*n);
+                        built(s.len() + n.len(), &|| format!("{}{}", s, n))
+                    }
+                    (BinaryOp::Add, Value::Number(n), Value::String(s)) => {
+                        let n = format_number(*n);
+                        built(n.len() + s.len(), &|| format!("{}{}", n, s))
+                    }
+                    (BinaryOp::Add, _, _) => Err(mismatch("numbers or strings")),
+                    
+                    (BinaryOp::Div | BinaryOp::Mod, Value::Number(_), Value::Number(y)) if *y == 0.0 => {
+                        Err(SkyHetuError::new(ErrorKind::DivisionByZero, Some(*span)))
+                    }
+                    (BinaryOp::Mul, Value::String(s), Value::Number(n)) => {
+                        built(s.len().saturating_mul(*n as usize), &|| s.repeat(*n as usize))
+                    }
+                    (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, Value::String(x), Value::String(y)) => Ok(ordered(x.cmp(y))),
+                    (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, Value::Bool(x), Value::Bool(y)) => Ok(ordered(x.cmp(y))),
+                    
+                    (_, Value::Number(x), Value::Number(y)) => Ok(match op {
+                        BinaryOp::Sub => Value::Number(x - y),
+                        BinaryOp::Mul => Value::Number(x * y),
+                        BinaryOp::Div => Value::Number(x / y),
+                        BinaryOp::Mod => Value::Number(x % y),
+                        BinaryOp::Lt => Value::Bool(x < y),
+                        BinaryOp::Le => Value::Bool(x <= y),
+                        BinaryOp::Gt => Value::Bool(x > y),
+                        BinaryOp::Ge => Value::Bool(x >= y),
+                        BinaryOp::Add | BinaryOp::Eq | BinaryOp::Ne => unreachable!(),
+                    }),
+                    (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, _, _) => Err(mismatch("two numbers, strings or bools")),
+                    _ => Err(mismatch("numbers")),
+                }
+            }
+            
+            other => Err(SkyHetuError::new(ErrorKind::NotConstant, Some(other.span()))),
+        }
+    }
+    
+    /// When optimizing, emit the value of an operator expression whose operands
+    /// are all known instead of the code computing it. Returns whether it did.
+    /// Expressions that fail, such as a division by zero, are compiled as
+    /// written so the error is raised at runtime, on their line.
+    fn emit_folded(&mut self, expr: &Expr) -> Result<bool> {
+        if !self.optimize {
+            return Ok(false);
+        }
+        let span = expr.span();
+        match self.fold(expr, true) {
+            Ok(Value::Bool(b)) => self.emit(if b { OpCode::True } else { OpCode::False }, span.line),
+            Ok(Value::Nil) => self.emit(OpCode::Nil, span.line),
+            Ok(value) => self.emit_constant(value, span)?,
+            Err(_) => return Ok(false),
+        }
+        Ok(true)
+    }
+    
+    /// Value of an `if` / `while` condition when it is known at compile time.
+    /// Warns unless the condition is spelled as a boolean literal.
+    fn known_condition(&mut self, condition: &Expr, span: Span) -> Option<bool> {
+        let truthy = self.fold(condition, true).ok()?.is_truthy();
+        if !matches!(condition, Expr::Bool { .. }) {
+            self.warnings.push(Warning::new(
+                format!("condition '{}' is always {}", condition, truthy),
+                Some(span),
+            ));
+        }
+        Some(truthy)
+    }
+    
     // ==================== Scope Management ====================
     
     fn begin_scope(&mut self) {
@@ -873,18 +1685,379 @@ impl Compiler {
     fn end_scope(&mut self) {
         self.current().scope_depth -= 1;
         
-        // Pop locals from this scope
+        // Pop locals from this scope, closing any a nested function captured
         while !self.current().locals.is_empty() 
             && self.current().locals.last().unwrap().depth > self.current().scope_depth 
         {
-            self.emit(OpCode::Pop, 0);
-            self.current().locals.pop();
+            let local = self.current().locals.pop().unwrap();
+            if self.debug_info {
+                let compiler = self.current();
+                local.record_name(&mut compiler.chunk, compiler.locals.len());
+            }
+            self.emit(if local.is_captured { OpCode::CloseUpvalue } else { OpCode::Pop }, 0);
+            self.check_local_used(&local);
+        }
+    }
+    
+    /// Warn about a `let` or `state` local going out of scope without being
+    /// read, or a `state` one that never changed. Locals named with a leading
+    /// `_` are exempt; captured ones may be used by the closure at any time.
+    fn check_local_used(&mut self, local: &Local) {
+        let Some(span) = local.declared_at else { return };
+        if local.name.starts_with('_') || local.is_captured {
+            return;
+        }
+        let kind = if local.is_state { "state" } else { "local" };
+        let message = if !local.is_read {
+            format!("{} '{}' is never read", kind, local.name)
+        } else if local.is_state && !local.is_transitioned {
+            format!("state '{}' is never transitioned; it could be a let", local.name)
+        } else {
+            return;
+        };
+        self.warnings.push(Warning::new(message, Some(span)));
+    }
+    
+    /// Fail when `name` is already a local of the innermost scope, parameters
+    /// included; inner scopes may still shadow it
+    fn check_duplicate_local(&self, name: &str, span: Span) -> Result<()> {
+        let compiler = self.compilers.last().unwrap();
+        let same_scope = compiler.locals.iter().rev().take_while(|l| l.depth == compiler.scope_depth);
+        match same_scope.into_iter().find(|l| l.name == name) {
+            Some(first) => Err(SkyHetuError::new(
+                ErrorKind::DuplicateDefinition(name.to_string(), first.declared_at.map(|s| s.line)),
+                Some(span),
+            )),
+            None => Ok(()),
+        }
+    }
+    
+    /// Warn when a `let` or `state` local hides a local of the same name in
+    /// this or an enclosing function. A state hiding a state gets the more
+    /// specific warning from `check_state_shadowing` instead.
+    fn check_local_shadowing(&mut self, name: &str, is_state: bool, span: Span) {
+        let Some((idx, slot)) = self.find_local(name) else { return };
+        let compiler = &self.compilers[idx];
+        let local = &compiler.locals[slot];
+        if is_state && local.is_state {
+            return;
+        }
+        let origin = if idx + 1 == self.compilers.len() {
+            match local.declared_at {
+                Some(old) => format!("the local declared at line {}", old.line),
+                None => "an earlier local or parameter".to_string(),
+            }
+        } else {
+            match compiler.function_name.as_str() {
+                "" => "a local of the enclosing script".to_string(),
+                function => format!("a local of enclosing function '{}'", function),
+            }
+        };
+        self.warnings.push(Warning::new(format!("'{}' shadows {}", name, origin), Some(span)));
+    }
+    
+    /// Compile a function body or block, warning once about statements that
+    /// follow a `return` and so can never run
+    fn compile_stmts(&mut self, stmts: &[Stmt], heap: &mut crate::gc::Heap) -> Result<()> {
+        let first_return = stmts.iter().position(|s| matches!(s, Stmt::Return { .. }));
+        if let Some(unreachable) = first_return.and_then(|i| stmts.get(i + 1)) {
+            self.warnings.push(Warning::new("unreachable code after return".to_string(), Some(unreachable.span())));
+        }
+        for stmt in stmts {
+            self.compile_stmt(stmt, heap)?;
+        }
+        Ok(())
+    }
+    
+    /// Discard the locals declared inside the innermost loop's body, as
+    /// leaving their scopes would, before `break` or `continue` jumps out.
+    /// The compiler keeps tracking them: code after the jump still sees them.
+    fn discard_loop_locals(&mut self, line: usize) {
+        let compiler = self.current();
+        let keep = compiler.loops.last().map_or(compiler.locals.len(), |l| l.locals);
+        let captured: Vec<bool> = compiler.locals[keep..].iter().rev().map(|l| l.is_captured).collect();
+        for is_captured in captured {
+            self.emit(if is_captured { OpCode::CloseUpvalue } else { OpCode::Pop }, line);
+        }
+    }
+    
+    /// Start compiling a loop whose body begins after the current locals
+    fn begin_loop(&mut self, start: Option<usize>) {
+        let compiler = self.current();
+        let locals = compiler.locals.len();
+        compiler.loops.push(Loop { start, locals, breaks: Vec::new(), continues: Vec::new() });
+    }
+    
+    /// Point the innermost loop's `break`s here
+    fn end_loop(&mut self) {
+        let lp = self.current().loops.pop().expect("end_loop without begin_loop");
+        for exit in lp.breaks {
+            self.patch_jump(exit);
         }
     }
     
     fn add_local(&mut self, name: String, is_state: bool) {
-        let depth = self.current().scope_depth;
-        self.current().locals.push(Local { name, depth, is_state });
+        let compiler = self.current();
+        let depth = compiler.scope_depth;
+        let start = compiler.chunk.len();
+        compiler.locals.push(Local { name, depth, is_state, start, ..Local::default() });
+        compiler.max_locals = compiler.max_locals.max(compiler.locals.len() - 1);
+    }
+    
+    /// Bind parameters to their slots. Annotated parameters are checked against
+    /// their type, and each `state` parameter's incoming value is recorded as
+    /// the start of its history for this call.
+    fn declare_params(
+        &mut self,
+        params: &[String],
+        state_params: &[String],
+        param_types: &[Option<String>],
+        span: Span,
+    ) -> Result<()> {
+        for (param, param_type) in params.iter().zip(param_types) {
+            self.check_duplicate_local(param, span)?;
+            let is_state = state_params.contains(param);
+            self.add_local(param.clone(), is_state);
+            let slot = (self.current().locals.len() - 1) as u16;
+            if let Some(type_name) = param_type {
+                let idx = self.make_name(type_name.clone(), span)?;
+                self.emit(OpCode::CheckType, span.line);
+                self.emit_u16(slot, span.line);
+                self.emit_u16(idx, span.line);
+            }
+            if is_state {
+                let compiler = self.current();
+                compiler.locals.last_mut().unwrap().is_param = true;
+                let idx = self.make_name(param.clone(), span)?;
+                self.emit(OpCode::InitParamState, span.line);
+                self.emit_u16(slot, span.line);
+                self.emit_u16(idx, span.line);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Finish the innermost function, recording its size metrics
+    fn end_function(&mut self, heap: &crate::gc::Heap) -> FunctionCompiler {
+        let mut func_compiler = self.compilers.pop().unwrap();
+        for (slot, local) in func_compiler.locals.iter().enumerate() {
+            self.check_local_used(local);
+            if self.debug_info {
+                local.record_name(&mut func_compiler.chunk, slot);
+            }
+        }
+        self.stats.dead_code_removed += func_compiler.chunk.eliminate_dead_code(heap);
+        if let Some(path) = &self.module_file {
+            let len = func_compiler.chunk.len();
+            func_compiler.chunk.add_file(0, len, path.clone());
+        }
+        self.stats.functions.push(Self::function_stats(&func_compiler, heap));
+        func_compiler
+    }
+    
+    fn function_stats(compiler: &FunctionCompiler, heap: &crate::gc::Heap) -> FunctionStats {
+        let name = match compiler.function_name.as_str() {
+            "" => "<script>".to_string(),
+            name => name.to_string(),
+        };
+        FunctionStats {
+            name,
+            bytes: compiler.chunk.len(),
+            instructions: compiler.chunk.instruction_count(heap),
+            constants: compiler.chunk.constants().len(),
+            names: compiler.chunk.names().len(),
+            max_locals: compiler.max_locals,
+            upvalues: compiler.upvalues.len(),
+        }
+    }
+    
+    /// Whether `name` refers to a `state` variable
+    fn is_state_variable(&mut self, name: &str) -> bool {
+        if let Some(slot) = self.resolve_local(name) {
+            return self.current().locals[slot as usize].is_state;
+        }
+        if let Some(idx) = self.resolve_upvalue(self.compilers.len() - 1, name) {
+            return self.current().upvalues[idx].is_state;
+        }
+        matches!(self.declared_globals.get(name), Some((BindingKind::State, _)))
+    }
+    
+    /// State variables read by `expr`, in first-read order, excluding `target`.
+    /// Reads inside lambdas and called functions are not followed.
+    fn state_reads(&mut self, expr: &Expr, target: Option<&str>) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_state_reads(expr, &mut names);
+        names.retain(|n| Some(n.as_str()) != target);
+        names.iter().map(|n| self.tracked_name(n)).collect()
+    }
+    
+    fn collect_state_reads(&mut self, expr: &Expr, names: &mut Vec<String>) {
+        match expr {
+            Expr::Ident { name, .. } => {
+                if !names.contains(name) && self.reads_state(name) {
+                    names.push(name.clone());
+                }
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.collect_state_reads(left, names);
+                self.collect_state_reads(right, names);
+            }
+            Expr::Unary { operand: inner, .. } |
+            Expr::Grouping { expr: inner, .. } |
+            Expr::Spread { expr: inner, .. } |
+            Expr::Get { object: inner, .. } => self.collect_state_reads(inner, names),
+            Expr::Call { callee, args, .. } => {
+                self.collect_state_reads(callee, names);
+                for arg in args {
+                    self.collect_state_reads(arg, names);
+                }
+            }
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.collect_state_reads(element, names);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.collect_state_reads(object, names);
+                self.collect_state_reads(index, names);
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.collect_state_reads(condition, names);
+                self.collect_state_reads(then_branch, names);
+                if let Some(else_branch) = else_branch {
+                    self.collect_state_reads(else_branch, names);
+                }
+            }
+            Expr::Set { object, value, .. } => {
+                self.collect_state_reads(object, names);
+                self.collect_state_reads(value, names);
+            }
+            Expr::IndexSet { object, index, value, .. } => {
+                self.collect_state_reads(object, names);
+                self.collect_state_reads(index, names);
+                self.collect_state_reads(value, names);
+            }
+            Expr::Number { .. } | Expr::String { .. } | Expr::Bool { .. } | Expr::Nil { .. } |
+            Expr::Lambda { .. } | Expr::Super { .. } => {}
+        }
+    }
+    
+    /// Whether `name` resolves to a state variable, looking through enclosing functions
+    fn reads_state(&mut self, name: &str) -> bool {
+        match self.find_local(name) {
+            Some((idx, slot)) => self.compilers[idx].locals[slot].is_state,
+            None => matches!(self.declared_globals.get(name), Some((BindingKind::State, _))),
+        }
+    }
+    
+    /// The innermost local named `name` in this or an enclosing function, as
+    /// (compiler index, slot)
+    fn find_local(&self, name: &str) -> Option<(usize, usize)> {
+        self.compilers.iter().enumerate().rev().find_map(|(idx, compiler)| {
+            compiler.locals.iter().rposition(|l| l.name == name).map(|slot| (idx, slot))
+        })
+    }
+    
+    /// Causality log name of the local `state` in `slot` of compiler `idx`:
+    /// `function.name`, or `function.name.2`, `.3`... for a state shadowing
+    /// another of the same name in the same function
+    fn local_state_key(&self, idx: usize, slot: usize) -> String {
+        let compiler = &self.compilers[idx];
+        let local = &compiler.locals[slot];
+        let function = match compiler.function_name.as_str() {
+            "" => "<script>",
+            name => name,
+        };
+        let shadowed = compiler.locals[..slot]
+            .iter()
+            .filter(|l| l.is_state && !l.is_param && l.name == local.name)
+            .count();
+        match shadowed {
+            0 => format!("{}.{}", function, local.name),
+            n => format!("{}.{}.{}", function, local.name, n + 1),
+        }
+    }
+    
+    /// Name the causality log records `name` under, as seen from here. State
+    /// parameters are keyed per call at runtime, so they keep their plain name.
+    fn tracked_name(&self, name: &str) -> String {
+        match self.find_local(name) {
+            Some((idx, slot)) => {
+                let local = &self.compilers[idx].locals[slot];
+                if local.is_state && !local.is_param {
+                    self.local_state_key(idx, slot)
+                } else {
+                    name.to_string()
+                }
+            }
+            None => self.global_name(name),
+        }
+    }
+    
+    /// Warn when a local `state` hides another state variable of the same name
+    fn check_state_shadowing(&mut self, name: &str, span: Span) {
+        let origin = match self.find_local(name) {
+            Some((idx, slot)) => {
+                let compiler = &self.compilers[idx];
+                let local = &compiler.locals[slot];
+                if !local.is_state {
+                    return;
+                }
+                let function = compiler.function_name.as_str();
+                if local.is_param {
+                    format!("state parameter '{}' of '{}'", name, function)
+                } else if idx + 1 == self.compilers.len() || function.is_empty() {
+                    format!("state '{}' of an enclosing block", name)
+                } else {
+                    format!("state '{}' of enclosing function '{}'", name, function)
+                }
+            }
+            None => match self.declared_globals.get(name) {
+                Some((BindingKind::State, _)) => format!("global state '{}'", name),
+                _ => return,
+            },
+        };
+        self.warnings.push(Warning::new(
+            format!("state '{}' shadows {}; histories will be tracked separately", name, origin),
+            Some(span),
+        ));
+    }
+    
+    /// Warn about a global that can't exist, suggesting similar names in scope.
+    /// Only known when the VM's globals were declared (natives included):
+    /// otherwise the name may well be defined by the time the code runs.
+    fn check_global_defined(&mut self, name: &str, span: Span) {
+        let globals_known = self.declared_globals.values().any(|(_, span)| span.is_none());
+        if !globals_known
+            || self.declared_globals.contains_key(name)
+            || self.module_aliases.contains_key(name)
+        {
+            return;
+        }
+        let locals = self.compilers.iter().flat_map(|c| c.locals.iter().map(|l| l.name.as_str()));
+        let candidates = locals
+            .chain(self.declared_globals.keys().map(String::as_str))
+            .chain(self.module_aliases.keys().map(String::as_str));
+        let suggestions = suggest(name, candidates);
+        self.warnings.push(Warning::new(
+            format!("undefined variable '{}'{}", name, did_you_mean(&suggestions)),
+            Some(span),
+        ));
+    }
+    
+    /// Whether a local of any enclosing function is named `name`
+    fn is_local_anywhere(&self, name: &str) -> bool {
+        self.compilers.iter().any(|c| c.locals.iter().any(|l| l.name == name))
+    }
+    
+    /// The global a top-level name refers to: its module alias while compiling
+    /// an imported module, unless a local of that name shadows it
+    fn global_name(&self, name: &str) -> String {
+        let shadowed = self.is_local_anywhere(name);
+        match self.module_aliases.get(name) {
+            Some(alias) if !shadowed => alias.clone(),
+            _ => name.to_string(),
+        }
     }
     
     fn resolve_local(&mut self, name: &str) -> Option<u16> {
@@ -912,19 +2085,76 @@ impl Compiler {
         
         if let Some(index) = parent_local {
             // Found local in parent -> capture it
-            return Some(self.add_upvalue(compiler_idx, index as u8, true));
+            let local = &mut self.compilers[parent_idx].locals[index];
+            local.is_captured = true;
+            let is_state = local.is_state;
+            return Some(self.add_upvalue(compiler_idx, index as u8, true, is_state));
         }
         
         // Recursive step: resolve upvalue in parent's parent
         if let Some(index) = self.resolve_upvalue(parent_idx, name) {
             // Found upvalue in parent -> capture it
-            return Some(self.add_upvalue(compiler_idx, index as u8, false));
+            let is_state = self.compilers[parent_idx].upvalues[index].is_state;
+            return Some(self.add_upvalue(compiler_idx, index as u8, false, is_state));
         }
         
         None
     }
     
-    fn add_upvalue(&mut self, compiler_idx: usize, index: u8, is_local: bool) -> usize {
+    /// Reject duplicate methods (including a second `init`) and warn when a
+    /// field set in `init` hides a method of the same name
+    fn check_class_body(&mut self, methods: &[Stmt]) -> Result<()> {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+        let mut fields = Vec::new();
+        for method in methods {
+            let Stmt::Function { name, body, span, .. } = method else {
+                continue;
+            };
+            if let Some(first) = seen.get(name.as_str()) {
+                return Err(SkyHetuError::new(
+                    ErrorKind::DuplicateMethod(name.clone(), first.line),
+                    Some(*span),
+                ));
+            }
+            seen.insert(name, *span);
+            if name == "init" {
+                fields_set_on_this(body, &mut fields);
+            }
+        }
+        
+        for (field, field_span) in fields {
+            if let Some(method_span) = seen.get(field.as_str()) {
+                self.warnings.push(Warning::new(
+                    format!(
+                        "field '{}' set in init hides the method defined at line {}; fields are looked up before methods",
+                        field, method_span.line
+                    ),
+                    Some(field_span),
+                ));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Warn about `==` / `!=` between operands whose types are known to differ:
+    /// values of different types are never equal, so the result is fixed.
+    /// `nil` on either side is left alone, as nil checks are idiomatic.
+    fn check_comparison_types(&mut self, left: &Expr, op: BinaryOp, right: &Expr, span: Span) {
+        let (a, b) = (infer(left, self), infer(right, self));
+        if a == b || [a, b].iter().any(|t| matches!(t, StaticType::Unknown | StaticType::Nil)) {
+            return;
+        }
+        let always = if op == BinaryOp::Eq { "false" } else { "true" };
+        self.warnings.push(Warning::new(
+            format!(
+                "'{} {} {}' compares a {} with a {} and is always {}; values of different types are never equal",
+                left, op, right, a.name(), b.name(), always
+            ),
+            Some(span),
+        ));
+    }
+    
+    fn add_upvalue(&mut self, compiler_idx: usize, index: u8, is_local: bool, is_state: bool) -> usize {
         let compiler = &mut self.compilers[compiler_idx];
         
         // Check if upvalue already exists to avoid duplicates
@@ -934,17 +2164,133 @@ impl Compiler {
             }
         }
         
-        compiler.upvalues.push(Upvalue { index, is_local });
+        compiler.upvalues.push(Upvalue { index, is_local, is_state });
         compiler.upvalues.len() - 1
     }
 }
 
+/// Collect the fields `stmts` assign on `this`, each once, in order
+fn fields_set_on_this(stmts: &[Stmt], fields: &mut Vec<(String, Span)>) {
+    for stmt in stmts {
+        let is_this = |object: &Expr| matches!(object, Expr::Ident { name, .. } if name == "this");
+        let (name, span) = match stmt {
+            Stmt::Expr { expr: Expr::Set { object, name, span, .. } } if is_this(object) => (name, *span),
+            Stmt::PropertyTransition { object, name, span, .. } if is_this(object) => (name, *span),
+            Stmt::Block { stmts, .. } => {
+                fields_set_on_this(stmts, fields);
+                continue;
+            }
+            Stmt::If { then_branch, else_branch, .. } => {
+                fields_set_on_this(std::slice::from_ref(then_branch), fields);
+                if let Some(else_branch) = else_branch {
+                    fields_set_on_this(std::slice::from_ref(else_branch), fields);
+                }
+                continue;
+            }
+            Stmt::Match { arms, default, .. } => {
+                for (_, body) in arms {
+                    fields_set_on_this(std::slice::from_ref(body), fields);
+                }
+                if let Some(default) = default {
+                    fields_set_on_this(std::slice::from_ref(default), fields);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if !fields.iter().any(|(seen, _)| seen == name) {
+            fields.push((name.clone(), span));
+        }
+    }
+}
+
+/// Value of a literal expression: a number (optionally negated), string, bool or nil
+fn literal_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Number { value, .. } => Some(Value::Number(*value)),
+        Expr::Unary { op: UnaryOp::Neg, operand, .. } => match **operand {
+            Expr::Number { value, .. } => Some(Value::Number(-value)),
+            _ => None,
+        },
+        Expr::String { value, .. } => Some(Value::String(value.as_str().into())),
+        Expr::Bool { value, .. } => Some(Value::Bool(*value)),
+        Expr::Nil { .. } => Some(Value::Nil),
+        _ => None,
+    }
+}
+
+impl TypeScope for Compiler {
+    fn constant_type(&self, name: &str) -> Option<StaticType> {
+        if self.is_local_anywhere(name) {
+            return None;
+        }
+        self.consts.get(name).map(StaticType::of_value)
+    }
+    
+    fn is_builtin(&self, name: &str) -> bool {
+        !self.is_local_anywhere(name)
+            && self.declared_globals.get(name).is_none_or(|(_, span)| span.is_none())
+    }
+}
+
 impl Default for Compiler {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Names a module marks with `export`
+fn exported_names(stmts: &[Stmt]) -> std::collections::HashSet<String> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Export { stmt, .. } => declared_name(stmt),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every name a module declares at its top level, exported or not
+fn declared_names(stmts: &[Stmt]) -> Vec<String> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Export { stmt, .. } => declared_name(stmt),
+            other => declared_name(other),
+        })
+        .collect()
+}
+
+/// Fail when a module's top level declares a name twice. Modules are never
+/// compiled with redefinition allowed, so this holds in sessions too.
+fn check_module_duplicates(stmts: &[Stmt]) -> Result<()> {
+    let mut first_lines: HashMap<String, usize> = HashMap::new();
+    for stmt in stmts {
+        let stmt = match stmt {
+            Stmt::Export { stmt, .. } => stmt.as_ref(),
+            other => other,
+        };
+        let Some(name) = declared_name(stmt) else { continue };
+        let span = stmt.span();
+        if let Some(&line) = first_lines.get(&name) {
+            return Err(SkyHetuError::new(ErrorKind::DuplicateDefinition(name, Some(line)), Some(span)));
+        }
+        first_lines.insert(name, span.line);
+    }
+    Ok(())
+}
+
+fn declared_name(stmt: &Stmt) -> Option<String> {
+    match stmt {
+        Stmt::Function { name, .. }
+        | Stmt::Let { name, .. }
+        | Stmt::State { name, .. }
+        | Stmt::Class { name, .. }
+        | Stmt::Const { name, .. } => Some(name.clone()),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -957,8 +2303,10 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         let mut parser = Parser::new(tokens);
         let program = parser.parse().unwrap();
+        // Unoptimized, so each construct's own code is emitted
         let mut compiler = Compiler::new();
-        let (chunk, _) = compiler.compile(&program, heap).unwrap(); // Pass heap
+        compiler.set_optimize(false);
+        let chunk = compiler.compile(&program, heap).unwrap(); // Pass heap
         chunk
     }
     
@@ -980,7 +2328,7 @@ mod tests {
     fn test_compile_number() {
         let mut heap = Heap::new();
         let chunk = compile("42", &mut heap);
-        assert!(chunk.code.len() > 0);
+        assert!(!chunk.code.is_empty());
         assert_eq!(chunk.constants[0], Value::Number(42.0));
     }
     
@@ -991,7 +2339,7 @@ mod tests {
         // Should have: CONSTANT 1, CONSTANT 2, ADD, POP, NIL, RETURN
         // With optimization (expr only): CONSTANT 1, CONSTANT 2, ADD, NIL, RETURN
         assert!(chunk.code.len() >= 6);
-        assert!(chunk.code.len() > 0);
+        assert!(!chunk.code.is_empty());
     }
     
     #[test]
@@ -999,16 +2347,16 @@ mod tests {
         let mut heap = Heap::new();
         let chunk = compile("let x = 10", &mut heap);
         // Should have: CONSTANT, DEFINE_GLOBAL
-        assert!(chunk.names.contains(&"x".to_string()));
-        assert!(chunk.code.len() > 0);
+        assert!(chunk.names.contains(&"x".into()));
+        assert!(!chunk.code.is_empty());
     }
     
     #[test]
     fn test_compile_state_decl() {
         let mut heap = Heap::new();
         let chunk = compile("state counter = 0", &mut heap);
-        assert!(chunk.names.contains(&"counter".to_string()));
-        assert!(chunk.code.len() > 0);
+        assert!(chunk.names.contains(&"counter".into()));
+        assert!(!chunk.code.is_empty());
     }
     
     #[test]
@@ -1016,7 +2364,33 @@ mod tests {
         let mut heap = Heap::new();
         let chunk = compile("if true { 1 }", &mut heap);
         // Should contain JumpIfFalse
-        assert!(chunk.code.iter().any(|&b| b == OpCode::JumpIfFalse as u8));
-        assert!(chunk.code.len() > 0);
+        assert!(chunk.code.contains(&(OpCode::JumpIfFalse as u8)));
+        assert!(!chunk.code.is_empty());
+    }
+    
+    #[test]
+    fn test_compile_stats_baseline() {
+        let source = "fn add(a, b) {\n  let c = a + b\n  return c\n}\nfn outer() {\n  state n = 0\n  fn bump() { n -> n + 1 }\n  return bump\n}\nadd(1, 2)";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let mut heap = Heap::new();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program, &mut heap).unwrap();
+        
+        let stats = |name: &str, bytes, instructions, constants, names, max_locals, upvalues| FunctionStats {
+            name: name.to_string(), bytes, instructions, constants, names, max_locals, upvalues,
+        };
+        assert_eq!(compiler.stats(), &CompileStats {
+            functions: vec![
+                stats("add", 11, 5, 0, 0, 3, 0),
+                stats("bump", 16, 6, 1, 1, 0, 1),
+                stats("outer", 15, 5, 2, 1, 2, 0),
+                stats("<script>", 24, 9, 4, 2, 0, 0),
+            ],
+            constants_deduplicated: 0,
+            peephole_rewrites: 0,
+            // The implicit `nil` return after `return c` and `return bump`
+            dead_code_removed: 4,
+        });
+        assert_eq!(compiler.stats().totals(), stats("total", 66, 25, 7, 4, 3, 1));
     }
 }
@@ -0,0 +1,116 @@
+//! Stepping through a running program
+//!
+//! `VM::run_with_hook` calls a hook before each instruction where execution
+//! should stop. The hook looks at the VM and answers with a [`DebugAction`]
+//! saying how far to run before stopping again. Plain `run` never consults
+//! any of this, so programs that aren't being debugged pay nothing for it.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use crate::bytecode::OpCode;
+
+/// Where execution has stopped: just before `opcode` runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugEvent {
+    /// Source line of the instruction
+    pub line: usize,
+
+    pub opcode: OpCode,
+
+    /// Number of active calls, the script itself included
+    pub depth: usize,
+
+    /// Whether execution stopped because it reached a breakpoint
+    pub at_breakpoint: bool,
+}
+
+/// How far to run before the hook is called again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until a breakpoint is reached
+    Continue,
+
+    /// Run until a different line starts in this call or a caller, without
+    /// stopping inside the calls it makes
+    StepOver,
+
+    /// Stop again before the next instruction
+    Pause,
+}
+
+/// Lines to stop at. Clones share the same set, so a hook can add
+/// breakpoints while the program is stopped.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints(Rc<RefCell<BTreeSet<usize>>>);
+
+impl Breakpoints {
+    /// Stop whenever execution reaches `line`. Returns false if it was already set.
+    pub fn add(&self, line: usize) -> bool {
+        self.0.borrow_mut().insert(line)
+    }
+
+    /// Returns false if there was no breakpoint on `line`
+    pub fn remove(&self, line: usize) -> bool {
+        self.0.borrow_mut().remove(&line)
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        self.0.borrow().contains(&line)
+    }
+
+    /// Every breakpoint line, in order
+    pub fn lines(&self) -> Vec<usize> {
+        self.0.borrow().iter().copied().collect()
+    }
+}
+
+/// Decides, before each instruction, whether to call the hook
+#[derive(Debug)]
+pub(crate) struct Stepper {
+    action: DebugAction,
+
+    /// Line and call depth of the instruction where the hook last answered
+    stopped: (usize, usize),
+
+    /// Line and call depth of the previous instruction
+    previous: Option<(usize, usize)>,
+}
+
+impl Stepper {
+    /// Stops before the first instruction
+    pub(crate) fn new() -> Self {
+        Self { action: DebugAction::Pause, stopped: (0, 0), previous: None }
+    }
+
+    /// Whether to stop before an instruction on `line` at call `depth`, and
+    /// if so whether that is because of a breakpoint
+    pub(crate) fn check(&mut self, line: usize, depth: usize, breakpoints: &Breakpoints) -> Option<bool> {
+        // Instructions the compiler adds on its own, like a script's final
+        // return, have no line to show
+        if line == 0 {
+            return None;
+        }
+        let here = (line, depth);
+        // A breakpoint fires as execution enters its line, not on every
+        // instruction of it
+        let entered = self.previous != Some(here);
+        self.previous = Some(here);
+
+        if entered && breakpoints.contains(line) {
+            return Some(true);
+        }
+        let stop = match self.action {
+            DebugAction::Pause => true,
+            DebugAction::StepOver => depth < self.stopped.1 || (depth == self.stopped.1 && line != self.stopped.0),
+            DebugAction::Continue => false,
+        };
+        stop.then_some(false)
+    }
+
+    /// The hook answered `action` while stopped on `line` at `depth`
+    pub(crate) fn resume(&mut self, action: DebugAction, line: usize, depth: usize) {
+        self.action = action;
+        self.stopped = (line, depth);
+    }
+}
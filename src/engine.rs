@@ -0,0 +1,117 @@
+//! A persistent session for hosts that run many snippets against one VM
+//!
+//! Each `eval` compiles against the session's heap and constants and runs on
+//! the same VM, so globals, heap objects and causality history carry over
+//! from one call to the next, as they do between REPL entries.
+
+use std::path::PathBuf;
+use crate::bytecode::Chunk;
+use crate::error::{Result, Warning};
+use crate::value::{NativeFn, Value};
+use crate::vm::VM;
+
+/// A VM plus what compiling snippets for it needs
+pub struct Engine {
+    vm: VM,
+
+    /// Warnings from the most recent `compile` or `eval`
+    warnings: Vec<Warning>,
+
+    /// Whether snippets are compiled with constant folding and dead branch removal
+    optimize: bool,
+}
+
+impl Engine {
+    /// A session whose imports resolve against the working directory
+    pub fn new() -> Self {
+        Self { vm: VM::new(), warnings: Vec::new(), optimize: true }
+    }
+
+    /// A session whose imports resolve against `path`
+    pub fn with_base_path(path: impl Into<PathBuf>) -> Self {
+        let mut engine = Self::new();
+        engine.vm.set_base_path(path);
+        engine
+    }
+
+    /// Compile later snippets with or without optimization (on by default),
+    /// e.g. so the REPL can show a snippet's bytecode as written
+    pub fn set_optimize(&mut self, enabled: bool) {
+        self.optimize = enabled;
+    }
+
+    /// Compile and run `source`, keeping its definitions for later calls.
+    /// Errors carry an excerpt of `source`.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        let chunk = self.compile(source)?;
+        self.run(chunk).map_err(|e| e.with_source(source))
+    }
+
+    /// Compile `source` for this session without running it. The compiler
+    /// knows the session's constants, so their uses are inlined.
+    pub fn compile(&mut self, source: &str) -> Result<Chunk> {
+        self.warnings.clear();
+        let compiled = crate::lexer::Lexer::new(source)
+            .tokenize()
+            .and_then(|tokens| crate::parser::Parser::new(tokens).parse())
+            .and_then(|program| {
+                let mut compiler = self.vm.session_compiler();
+                compiler.set_optimize(self.optimize);
+                let chunk = compiler.compile(&program, &mut self.vm.heap);
+                self.warnings = compiler.warnings().to_vec();
+                chunk
+            });
+        compiled.map_err(|e| e.with_source(source))
+    }
+
+    /// Run a chunk compiled by `compile`
+    pub fn run(&mut self, chunk: Chunk) -> Result<Value> {
+        self.vm.run(chunk)
+    }
+
+    /// Warnings from the most recent `compile` or `eval`
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Current value of a global, if defined
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vm.get_global(name)
+    }
+
+    /// Bind a host-provided value to a global. A `state` global records the
+    /// value as its initial one, and scripts may then transition it.
+    pub fn set_global(&mut self, name: &str, value: Value, is_state: bool) {
+        if is_state {
+            self.vm.set_state(name, value);
+        } else {
+            self.vm.set_global(name, value);
+        }
+    }
+
+    /// The causality chain of a state variable, as `why()` shows it
+    pub fn why(&self, name: &str) -> String {
+        self.vm.why(name)
+    }
+
+    /// Make a host function callable from scripts under its name, replacing
+    /// any global of that name
+    pub fn register_native(&mut self, native: NativeFn) {
+        self.vm.register_native(native);
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// The VM itself, for settings and inspection the session doesn't wrap
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -7,6 +7,7 @@ use std::fmt;
 
 /// Error kinds in SkyHetu
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum ErrorKind {
     // Lexer errors
     UnexpectedCharacter(char),
@@ -15,24 +16,63 @@ pub enum ErrorKind {
     
     // Parser errors
     UnexpectedToken(String),
+    /// What the parser wanted, as a full `expected ...` phrase, and the token it found
     ExpectedToken(String, String),
     ExpectedExpression,
     ExpectedStatement,
     InvalidAssignmentTarget,
     InvalidAssignment,
+    /// The input ended inside a declaration; holds the error the parser hit there
+    UnexpectedEof(String),
+    /// Two statements on one line with nothing between them; holds the second's first token
+    MissingSeparator(String),
+    
+    // Compile errors
+    NotConstant,
+    ConstantReassignment(String),
+    /// A `const` declared inside a function or block
+    ConstOutsideTopLevel,
+    /// A name declared twice in one scope; holds the line it was first declared on, when known
+    DuplicateDefinition(String, Option<usize>),
+    /// A function needs more entries in one of its pools (the field names
+    /// which) than a 16-bit operand can index
+    TooManyConstants(String),
+    /// A constant string would be longer than this many bytes
+    ConstantTooLong(usize),
+    /// A method defined twice in one class body; holds the line of the first
+    DuplicateMethod(String, usize),
+    /// A class named as its own superclass
+    SelfInheritance(String),
+    /// `super` outside the methods of a class with a superclass
+    SuperOutsideSubclass,
+    /// `why()` given an expression that is neither a variable nor a string
+    WhyOfExpression,
+    /// An instruction would need more operands of a kind (the first field)
+    /// than it can count; holds how many there are and the maximum
+    TooManyOperands(String, usize, usize),
     
     // Runtime errors
     UndefinedVariable(String),
     UndefinedProperty(String),
     TypeMismatch(String, String),
     DivisionByZero,
-    NotCallable,
-    WrongArity(usize, usize),
+    /// A call on a value that isn't a function or class; holds its type
+    NotCallable(String),
+    /// Holds the callee's name when known, then the expected and actual counts
+    WrongArity(Option<String>, usize, usize),
     ImmutableVariable(String),
     BreakOutsideLoop,
     ContinueOutsideLoop,
     ReturnOutsideFunction,
     StackOverflow,
+    /// An instruction popped from an empty stack, which only malformed bytecode does
+    StackUnderflow,
+    /// An instruction named a local slot outside the current call, which only malformed bytecode does
+    InvalidSlot(usize),
+    /// The instruction budget set with `VM::set_fuel` ran out
+    FuelExhausted,
+    /// An allocation of this many bytes would exceed the heap limit (the second field)
+    OutOfMemory(usize, usize),
     
     // Causality errors
     NoStateHistory(String),
@@ -42,6 +82,10 @@ pub enum ErrorKind {
     
     // Module errors
     ModuleNotFound(String),
+    CircularImport(String),
+    
+    // Bytecode file errors
+    InvalidBytecode(String),
 }
 
 impl fmt::Display for ErrorKind {
@@ -51,21 +95,50 @@ impl fmt::Display for ErrorKind {
             ErrorKind::UnterminatedString => write!(f, "unterminated string"),
             ErrorKind::InvalidNumber(s) => write!(f, "invalid number '{}'", s),
             ErrorKind::UnexpectedToken(t) => write!(f, "unexpected token '{}'", t),
-            ErrorKind::ExpectedToken(expected, got) => {
-                write!(f, "expected '{}', got '{}'", expected, got)
-            }
+            // The parser's messages already say what was expected
+            ErrorKind::ExpectedToken(expected, found) => write!(f, "{}, found '{}'", expected, found),
             ErrorKind::ExpectedExpression => write!(f, "expected expression"),
             ErrorKind::ExpectedStatement => write!(f, "expected statement"),
             ErrorKind::InvalidAssignmentTarget => write!(f, "invalid assignment target"),
             ErrorKind::InvalidAssignment => write!(f, "invalid assignment"),
+            ErrorKind::UnexpectedEof(error) => write!(f, "unexpected end of input: {}", error),
+            ErrorKind::MissingSeparator(token) => {
+                write!(f, "expected ';' or a newline before '{}' to start another statement", token)
+            }
+            ErrorKind::NotConstant => write!(f, "expression is not a compile-time constant"),
+            ErrorKind::ConstantReassignment(name) => write!(f, "cannot change constant '{}'", name),
+            ErrorKind::ConstOutsideTopLevel => write!(f, "const declarations are only allowed at the top level"),
+            ErrorKind::DuplicateDefinition(name, Some(first)) => {
+                write!(f, "'{}' is already defined in this scope (first defined at line {})", name, first)
+            }
+            ErrorKind::DuplicateDefinition(name, None) => write!(f, "'{}' is already defined in this scope", name),
+            ErrorKind::TooManyConstants(pool) => {
+                write!(f, "too many {} in one function (maximum is {})", pool, u16::MAX as usize + 1)
+            }
+            ErrorKind::ConstantTooLong(max) => {
+                write!(f, "constant string would be longer than the {} bytes allowed at compile time", max)
+            }
+            ErrorKind::DuplicateMethod(name, first) => write!(f, "method '{}' already defined at line {}", name, first),
+            ErrorKind::SelfInheritance(name) => write!(f, "class '{}' cannot inherit from itself", name),
+            ErrorKind::SuperOutsideSubclass => {
+                write!(f, "'super' can only be used in methods of a class with a superclass")
+            }
+            ErrorKind::WhyOfExpression => write!(
+                f,
+                "why() takes a variable, not an expression; pass its name in quotes, as in why(\"total\")"
+            ),
+            ErrorKind::TooManyOperands(what, count, max) => write!(f, "too many {}: {} (maximum is {})", what, count, max),
             ErrorKind::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
             ErrorKind::UndefinedProperty(name) => write!(f, "undefined property '{}'", name),
             ErrorKind::TypeMismatch(expected, got) => {
                 write!(f, "type mismatch: expected {}, got {}", expected, got)
             }
             ErrorKind::DivisionByZero => write!(f, "division by zero"),
-            ErrorKind::NotCallable => write!(f, "value is not callable"),
-            ErrorKind::WrongArity(expected, got) => {
+            ErrorKind::NotCallable(type_name) => write!(f, "cannot call value of type '{}'", type_name),
+            ErrorKind::WrongArity(Some(name), expected, got) => {
+                write!(f, "function '{}' expected {} arguments, got {}", name, expected, got)
+            }
+            ErrorKind::WrongArity(None, expected, got) => {
                 write!(f, "expected {} arguments, got {}", expected, got)
             }
             ErrorKind::ImmutableVariable(name) => {
@@ -75,11 +148,19 @@ impl fmt::Display for ErrorKind {
             ErrorKind::ContinueOutsideLoop => write!(f, "continue outside of loop"),
             ErrorKind::ReturnOutsideFunction => write!(f, "return outside of function"),
             ErrorKind::StackOverflow => write!(f, "stack overflow"),
+            ErrorKind::StackUnderflow => write!(f, "stack underflow: the bytecode is malformed"),
+            ErrorKind::InvalidSlot(slot) => write!(f, "local slot {} is outside the current call: the bytecode is malformed", slot),
+            ErrorKind::FuelExhausted => write!(f, "out of fuel: the instruction budget ran out"),
+            ErrorKind::OutOfMemory(size, limit) => {
+                write!(f, "out of memory: allocating {} bytes would exceed the heap limit of {} bytes", size, limit)
+            }
             ErrorKind::NoStateHistory(name) => {
                 write!(f, "no state history for '{}'", name)
             }
             ErrorKind::RuntimeError(msg) => write!(f, "{}", msg),
             ErrorKind::ModuleNotFound(msg) => write!(f, "module not found: {}", msg),
+            ErrorKind::CircularImport(cycle) => write!(f, "circular import: {}", cycle),
+            ErrorKind::InvalidBytecode(msg) => write!(f, "invalid bytecode file: {}", msg),
         }
     }
 }
@@ -89,7 +170,23 @@ impl fmt::Display for ErrorKind {
 pub struct SkyHetuError {
     pub kind: ErrorKind,
     pub span: Option<Span>,
-    pub source_line: Option<String>,
+    pub source_line: Option<Box<str>>,
+    /// Where a runtime error was raised and what may have been meant
+    /// (boxed to keep errors small)
+    context: Option<Box<RuntimeContext>>,
+}
+
+/// Where a runtime error was raised
+#[derive(Debug, Clone, Default)]
+struct RuntimeContext {
+    /// Module the error was raised in, when not the program's own file
+    file: Option<std::path::PathBuf>,
+    /// Calls active at the time, innermost first
+    trace: Vec<String>,
+    /// Conditions guarding the failing code, outermost first
+    conditions: Vec<String>,
+    /// Defined names close to an undefined one, closest first
+    suggestions: Vec<String>,
 }
 
 impl SkyHetuError {
@@ -98,14 +195,81 @@ impl SkyHetuError {
             kind,
             span,
             source_line: None,
+            context: None,
         }
     }
     
+    pub fn with_stack_trace(mut self, trace: Vec<String>) -> Self {
+        if !trace.is_empty() {
+            self.context.get_or_insert_with(Default::default).trace = trace;
+        }
+        self
+    }
+    
+    /// Calls active when the error was raised, innermost first; empty for
+    /// compile errors and errors at the top level of a script
+    pub fn stack_trace(&self) -> &[String] {
+        self.context.as_ref().map_or(&[], |c| &c.trace)
+    }
+    
+    pub fn with_conditions(mut self, conditions: Vec<String>) -> Self {
+        if !conditions.is_empty() {
+            self.context.get_or_insert_with(Default::default).conditions = conditions;
+        }
+        self
+    }
+    
+    /// Conditions that held on the way to a runtime error, outermost first;
+    /// only known for code compiled with debug info
+    pub fn conditions(&self) -> &[String] {
+        self.context.as_ref().map_or(&[], |c| &c.conditions)
+    }
+    
+    pub fn with_suggestions(mut self, suggestions: Vec<String>) -> Self {
+        if !suggestions.is_empty() {
+            self.context.get_or_insert_with(Default::default).suggestions = suggestions;
+        }
+        self
+    }
+    
+    /// Names that were probably meant instead of an undefined one, closest first
+    pub fn suggestions(&self) -> &[String] {
+        self.context.as_ref().map_or(&[], |c| &c.suggestions)
+    }
+    
+    pub fn with_file(mut self, path: std::path::PathBuf) -> Self {
+        self.context.get_or_insert_with(Default::default).file = Some(path);
+        self
+    }
+    
+    /// The imported module the error was raised in; `None` when it is in the
+    /// program's own source
+    pub fn file(&self) -> Option<&std::path::Path> {
+        self.context.as_ref().and_then(|c| c.file.as_deref())
+    }
+    
+    /// Whether this runtime error only restates `warning`: the compiler already
+    /// reported the same undefined global on the same line of the program
+    pub fn repeats(&self, warning: &Warning) -> bool {
+        let (ErrorKind::UndefinedVariable(_), Some(span), Some(at)) = (&self.kind, &self.span, &warning.span) else {
+            return false;
+        };
+        self.file().is_none() && span.line == at.line && warning.message.starts_with(&self.kind.to_string())
+    }
+    
+    /// Fill in the source line the error points at. `source` is the program's
+    /// own text; errors in an imported module read the module's file instead.
     pub fn with_source(mut self, source: &str) -> Self {
+        let module_source = self.file().and_then(|path| std::fs::read_to_string(path).ok());
+        let source = match (self.file(), &module_source) {
+            (None, _) => source,
+            (Some(_), Some(text)) => text,
+            (Some(_), None) => return self,
+        };
         if let Some(span) = &self.span {
             let lines: Vec<&str> = source.lines().collect();
             if span.line > 0 && span.line <= lines.len() {
-                self.source_line = Some(lines[span.line - 1].to_string());
+                self.source_line = Some(lines[span.line - 1].into());
             }
         }
         self
@@ -115,14 +279,33 @@ impl SkyHetuError {
 impl fmt::Display for SkyHetuError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(span) = &self.span {
-            write!(f, "[line {}:{}] Error: {}", span.line, span.column, self.kind)?;
+            let file = self.file().map_or_else(String::new, |path| format!("{} ", path.display()));
+            // Runtime errors only know their line, and leave the column at 0
+            if span.column == 0 {
+                write!(f, "[{}line {}] Error: {}", file, span.line, self.kind)?;
+            } else {
+                write!(f, "[{}line {}:{}] Error: {}", file, span.line, span.column, self.kind)?;
+            }
+            write!(f, "{}", did_you_mean(self.suggestions()))?;
             
             if let Some(ref line) = self.source_line {
                 write!(f, "\n  | {}", line)?;
-                write!(f, "\n  | {}^", " ".repeat(span.column.saturating_sub(1)))?;
+                if span.column > 0 {
+                    write!(f, "\n  | {}^", " ".repeat(span.column - 1))?;
+                }
             }
         } else {
-            write!(f, "Error: {}", self.kind)?;
+            write!(f, "Error: {}{}", self.kind, did_you_mean(self.suggestions()))?;
+        }
+        if !self.conditions().is_empty() {
+            write!(f, "\n  guarded by: {}", self.conditions().join(" → "))?;
+        }
+        // Runaway recursion fills the trace with the same call; show it once
+        for calls in self.stack_trace().chunk_by(|a, b| a == b) {
+            match calls.len() {
+                1 => write!(f, "\n  {}", calls[0])?,
+                n => write!(f, "\n  {} (repeated {} times)", calls[0], n)?,
+            }
         }
         Ok(())
     }
@@ -130,5 +313,91 @@ impl fmt::Display for SkyHetuError {
 
 impl std::error::Error for SkyHetuError {}
 
+/// `". Did you mean 'a', 'b' or 'c'?"`, or nothing without suggestions
+pub fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [only] => format!(". Did you mean {}?", only),
+        [rest @ .., last] => format!(". Did you mean {} or {}?", rest.join(", "), last),
+    }
+}
+
+/// Up to three of `candidates` within a few edits of `name`, closest first.
+/// Allows about one edit per three characters, so one-letter names get none.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let limit = (name.chars().count() + 1) / 3;
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    close.sort();
+    close.dedup();
+    close.into_iter().take(3).map(|(_, c)| c.to_string()).collect()
+}
+
+/// Levenshtein distance between two strings, by character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A non-fatal diagnostic reported by the compiler
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Warning {
+    pub fn new(message: String, span: Option<Span>) -> Self {
+        Self { message, span }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "[line {}:{}] Warning: {}", span.line, span.column, self.message)
+        } else {
+            write!(f, "Warning: {}", self.message)
+        }
+    }
+}
+
 /// Result type for SkyHetu operations
 pub type Result<T> = std::result::Result<T, SkyHetuError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("counter", "countr"), 1);
+        assert_eq!(edit_distance("pritn", "print"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("né", "ne"), 1);
+    }
+    
+    #[test]
+    fn test_did_you_mean() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(did_you_mean(&[]), "");
+        assert_eq!(did_you_mean(&names(&["a"])), ". Did you mean 'a'?");
+        assert_eq!(did_you_mean(&names(&["a", "b", "c"])), ". Did you mean 'a', 'b' or 'c'?");
+        assert_eq!(suggest("x", ["y", "z"]), Vec::<String>::new());
+    }
+}
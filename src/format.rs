@@ -0,0 +1,495 @@
+//! Canonical source formatting for SkyHetu
+//!
+//! Re-emits a parsed program as source: 4-space indentation, one statement
+//! per line, spaces around binary operators, braces opening on the line of
+//! the statement they belong to, and a blank line around top-level functions
+//! and classes. Other blank lines are kept, runs of them collapsed to one.
+//!
+//! Comments are not part of the AST. [`format_with_comments`] finds them in
+//! the source and attaches each one to the statement it precedes, or ends
+//! when it shares that statement's line. A comment inside a statement that
+//! spans several lines, such as between array elements, moves up to stand
+//! on its own line before the next statement.
+
+use std::collections::HashSet;
+
+use crate::ast::{Expr, Program, Stmt};
+use crate::lexer::Lexer;
+use crate::token::TokenKind;
+
+const INDENT: &str = "    ";
+
+/// Format a program. It has no comments to keep; see [`format_with_comments`].
+pub fn format_program(program: &Program) -> String {
+    let mut formatter = Formatter::new(Trivia::default());
+    formatter.program(&program.statements);
+    formatter.out
+}
+
+/// Format `program`, keeping the comments and blank lines of `source`, the
+/// text it was parsed from
+pub fn format_with_comments(program: &Program, source: &str) -> String {
+    let mut formatter = Formatter::new(Trivia::new(source));
+    formatter.program(&program.statements);
+    formatter.out
+}
+
+/// A `//` comment, with the line it is on
+struct Comment {
+    line: usize,
+    text: String,
+    /// Whether code comes before it on its line
+    trailing: bool,
+}
+
+/// What the AST leaves out of the source
+#[derive(Default)]
+struct Trivia {
+    comments: Vec<Comment>,
+    /// Offset of each `{` and the line of its matching `}`, by offset
+    braces: Vec<(usize, usize)>,
+    /// Lines holding nothing but whitespace
+    blank_lines: HashSet<usize>,
+}
+
+impl Trivia {
+    fn new(source: &str) -> Self {
+        let blank_lines = source.lines()
+            .enumerate()
+            .filter(|(_, line)| line.trim().is_empty())
+            .map(|(i, _)| i + 1)
+            .collect();
+
+        let mut braces = Vec::new();
+        let mut open = Vec::new();
+        for token in Lexer::new(source).tokenize().unwrap_or_default() {
+            match token.kind {
+                TokenKind::LeftBrace => open.push(token.span.start),
+                TokenKind::RightBrace => {
+                    if let Some(start) = open.pop() {
+                        braces.push((start, token.span.line));
+                    }
+                }
+                _ => {}
+            }
+        }
+        braces.sort_unstable();
+
+        Self { comments: scan_comments(source), braces, blank_lines }
+    }
+
+    /// Line of the `}` closing the first `{` at or after `offset`
+    fn closing_line(&self, offset: usize) -> Option<usize> {
+        let i = self.braces.partition_point(|&(start, _)| start < offset);
+        self.braces.get(i).map(|&(_, line)| line)
+    }
+
+    /// Whether the line before `line` is blank
+    fn blank_before(&self, line: usize) -> bool {
+        line > 1 && self.blank_lines.contains(&(line - 1))
+    }
+}
+
+/// Every `//` comment outside string literals, in order
+fn scan_comments(source: &str) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut line = 1;
+    let mut line_has_code = false;
+    let mut in_string = false;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => {
+                line += 1;
+                line_has_code = false;
+            }
+            '\\' if in_string => {
+                chars.next_if(|&(_, next)| next != '\n');
+            }
+            '"' => {
+                in_string = !in_string;
+                line_has_code = true;
+            }
+            '/' if !in_string && source[i..].starts_with("//") => {
+                let end = source[i..].find('\n').map_or(source.len(), |n| i + n);
+                let text = source[i..end].trim_end().to_string();
+                comments.push(Comment { line, text, trailing: line_has_code });
+                while chars.next_if(|&(j, _)| j < end).is_some() {}
+            }
+            _ if !c.is_whitespace() => line_has_code = true,
+            _ => {}
+        }
+    }
+    comments
+}
+
+struct Formatter {
+    out: String,
+    depth: usize,
+    trivia: Trivia,
+    /// Index of the first comment not written yet
+    next_comment: usize,
+    /// Whether nothing has been written in the innermost block yet
+    block_start: bool,
+}
+
+impl Formatter {
+    fn new(trivia: Trivia) -> Self {
+        Self { out: String::new(), depth: 0, trivia, next_comment: 0, block_start: true }
+    }
+
+    fn program(&mut self, stmts: &[Stmt]) {
+        self.statements(stmts, true);
+        self.comments_before(usize::MAX);
+    }
+
+    // ==================== Layout ====================
+
+    /// Write `text` on a line of its own at the current indentation
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.block_start = false;
+    }
+
+    /// Separate what comes next with a blank line, unless it starts a block
+    /// or one is already there
+    fn blank_line(&mut self) {
+        if !self.block_start && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Write the comments on lines before `line`
+    fn comments_before(&mut self, line: usize) {
+        while let Some(comment) = self.trivia.comments.get(self.next_comment).filter(|c| c.line < line) {
+            let text = comment.text.clone();
+            if self.trivia.blank_before(comment.line) {
+                self.blank_line();
+            }
+            self.line(&text);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Append a comment that ended source line `line` to the line just written
+    fn trailing_comment(&mut self, line: usize) {
+        let Some(comment) = self.trivia.comments.get(self.next_comment) else { return };
+        if comment.line == line && comment.trailing {
+            self.out.pop();
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+            self.next_comment += 1;
+        }
+    }
+
+    /// Write `header {` for a block starting on source line `line`
+    fn open(&mut self, header: &str, line: usize) {
+        if header.is_empty() {
+            self.line("{");
+        } else {
+            self.line(&format!("{} {{", header));
+        }
+        self.trailing_comment(line);
+        self.depth += 1;
+        self.block_start = true;
+    }
+
+    /// Close the block whose `{` is the first at or after `offset`, and open
+    /// `then` (such as `else`) on the same line if given
+    fn close(&mut self, offset: usize, then: Option<&str>) {
+        let closing_line = self.trivia.closing_line(offset);
+        if let Some(line) = closing_line {
+            self.comments_before(line);
+        }
+        let empty = self.block_start && self.out.ends_with("{\n");
+        self.depth -= 1;
+        let text = match then {
+            Some(then) => format!("}} {} {{", then),
+            None => "}".to_string(),
+        };
+        if empty {
+            // `{}` on the line that opened it
+            self.out.pop();
+            self.out.push_str(&text);
+            self.out.push('\n');
+        } else {
+            self.line(&text);
+        }
+        match then {
+            Some(_) => {
+                self.depth += 1;
+                self.block_start = true;
+            }
+            None => {
+                self.block_start = false;
+                if let Some(line) = closing_line {
+                    self.trailing_comment(line);
+                }
+            }
+        }
+    }
+
+    // ==================== Statements ====================
+
+    /// Write a list of statements; `spaced` puts a blank line around each
+    /// function and class, as at the top level and in class bodies
+    fn statements(&mut self, stmts: &[Stmt], spaced: bool) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            let line = stmt.span().line;
+            let first_line = self.trivia.comments.get(self.next_comment)
+                .filter(|c| c.line < line)
+                .map_or(line, |c| c.line);
+            let around_declaration = spaced && i > 0 && (is_declaration(stmt) || is_declaration(&stmts[i - 1]));
+            if around_declaration || self.trivia.blank_before(first_line) {
+                self.blank_line();
+            }
+            self.comments_before(line);
+            if self.trivia.blank_before(line) {
+                self.blank_line();
+            }
+            self.stmt(stmt, "");
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, prefix: &str) {
+        let line = stmt.span().line;
+        let simple = match stmt {
+            Stmt::Expr { expr } => {
+                let text = self.expr(expr);
+                if starts_with_if(expr) {
+                    // An `if` opening a statement is an if statement
+                    format!("({})", text)
+                } else {
+                    text
+                }
+            }
+            Stmt::Let { name, value, .. } => format!("{}let {} = {}", prefix, name, self.expr(value)),
+            Stmt::State { name, value, .. } => format!("{}state {} = {}", prefix, name, self.expr(value)),
+            Stmt::Const { name, value, .. } => format!("{}const {} = {}", prefix, name, self.expr(value)),
+            Stmt::Transition { name, value, .. } => format!("{} -> {}", name, self.expr(value)),
+            Stmt::PropertyTransition { object, name, value, .. } => {
+                format!("{}.{} -> {}", self.expr(object), name, self.expr(value))
+            }
+            Stmt::Return { value: Some(value), .. } => format!("return {}", self.expr(value)),
+            Stmt::Return { value: None, .. } => "return".to_string(),
+            Stmt::Break { .. } => "break".to_string(),
+            Stmt::Continue { .. } => "continue".to_string(),
+            Stmt::Import { names, path, .. } => format!("import {{ {} }} from {}", names.join(", "), quote(path)),
+            Stmt::Export { stmt, .. } => return self.stmt(stmt, "export "),
+            Stmt::Block { stmts, span } => {
+                self.open("", line);
+                self.statements(stmts, false);
+                return self.close(span.start, None);
+            }
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                return self.if_stmt(condition, then_branch, else_branch.as_deref(), line);
+            }
+            Stmt::Match { subject, arms, default, .. } => {
+                let header = format!("match {}", self.expr(subject));
+                self.open(&header, line);
+                for (patterns, body) in arms {
+                    let patterns: Vec<String> = patterns.iter().map(|p| self.expr(p)).collect();
+                    self.arm(&patterns.join(" | "), body);
+                }
+                if let Some(body) = default {
+                    self.arm("_", body);
+                }
+                return self.close(subject.span().end, None);
+            }
+            Stmt::While { condition, body, span } => {
+                let header = format!("while {}", self.expr(condition));
+                return self.body(&header, line, body_stmts(body), span.start);
+            }
+            Stmt::For { var, iterable, body, span } => {
+                let header = format!("for {} in {}", var, self.expr(iterable));
+                return self.body(&header, line, body_stmts(body), span.start);
+            }
+            Stmt::Function { name, params, state_params, param_types, body, span } => {
+                let header = format!("{}fn {}({})", prefix, name, format_params(params, state_params, param_types));
+                return self.body(&header, line, body, span.start);
+            }
+            Stmt::Class { name, superclass, methods, span } => {
+                let header = match superclass {
+                    Some(parent) => format!("{}class {} : {}", prefix, name, self.expr(parent)),
+                    None => format!("{}class {}", prefix, name),
+                };
+                self.open(&header, line);
+                for (i, method) in methods.iter().enumerate() {
+                    let Stmt::Function { name, params, state_params, param_types, body, span } = method else {
+                        continue;
+                    };
+                    if i > 0 {
+                        self.blank_line();
+                    }
+                    self.comments_before(span.line);
+                    let header = format!("{}({})", name, format_params(params, state_params, param_types));
+                    self.body(&header, span.line, body, span.start);
+                }
+                return self.close(span.start, None);
+            }
+        };
+        self.line(&simple);
+        if !simple.contains('\n') {
+            self.trailing_comment(line);
+        }
+    }
+
+    /// Write `header { stmts }` for a block whose `{` is the first at or after `offset`
+    fn body(&mut self, header: &str, line: usize, stmts: &[Stmt], offset: usize) {
+        self.open(header, line);
+        self.statements(stmts, false);
+        self.close(offset, None);
+    }
+
+    fn arm(&mut self, patterns: &str, body: &Stmt) {
+        let span = body.span();
+        self.comments_before(span.line);
+        self.body(&format!("{} =>", patterns), span.line, body_stmts(body), span.start);
+    }
+
+    fn if_stmt(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>, line: usize) {
+        let header = format!("if {}", self.expr(condition));
+        self.open(&header, line);
+        self.statements(body_stmts(then_branch), false);
+        let mut offset = then_branch.span().start;
+        let mut else_branch = else_branch;
+        loop {
+            match else_branch {
+                Some(Stmt::If { condition, then_branch, else_branch: next, .. }) => {
+                    let then = format!("else if {}", self.expr(condition));
+                    self.close(offset, Some(&then));
+                    self.statements(body_stmts(then_branch), false);
+                    offset = then_branch.span().start;
+                    else_branch = next.as_deref();
+                }
+                Some(block) => {
+                    self.close(offset, Some("else"));
+                    self.statements(body_stmts(block), false);
+                    offset = block.span().start;
+                    else_branch = None;
+                }
+                None => return self.close(offset, None),
+            }
+        }
+    }
+
+    // ==================== Expressions ====================
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number { .. } | Expr::Bool { .. } | Expr::Nil { .. } | Expr::Ident { .. } | Expr::Super { .. } => {
+                expr.to_string()
+            }
+            Expr::String { value, .. } => quote(value),
+            Expr::Binary { left, op, right, .. } => format!("{} {} {}", self.expr(left), op, self.expr(right)),
+            Expr::Logical { left, op, right, .. } => format!("{} {} {}", self.expr(left), op, self.expr(right)),
+            Expr::Unary { op, operand, .. } => format!("{}{}", op, self.expr(operand)),
+            Expr::Grouping { expr, .. } => format!("({})", self.expr(expr)),
+            Expr::Call { callee, args, .. } => format!("{}({})", self.expr(callee), self.list(args)),
+            Expr::Get { object, name, .. } => format!("{}.{}", self.expr(object), name),
+            Expr::Set { object, name, value, .. } => {
+                format!("{}.{} = {}", self.expr(object), name, self.expr(value))
+            }
+            Expr::Index { object, index, .. } => format!("{}[{}]", self.expr(object), self.expr(index)),
+            Expr::IndexSet { object, index, value, .. } => {
+                format!("{}[{}] = {}", self.expr(object), self.expr(index), self.expr(value))
+            }
+            Expr::Array { elements, .. } => format!("[{}]", self.list(elements)),
+            Expr::Spread { expr, .. } => format!("...{}", self.expr(expr)),
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                let mut text = format!("if {} {{ {} }}", self.expr(condition), self.expr(then_branch));
+                match else_branch.as_deref() {
+                    Some(chained @ Expr::If { .. }) => text += &format!(" else {}", self.expr(chained)),
+                    Some(value) => text += &format!(" else {{ {} }}", self.expr(value)),
+                    None => {}
+                }
+                text
+            }
+            Expr::Lambda { params, state_params, param_types, body, span } => {
+                let params = format_params(params, state_params, param_types);
+                if let [Stmt::Return { value: Some(value), .. }] = body.as_slice() {
+                    return format!("fn({}) => {}", params, self.expr(value));
+                }
+                // Write the body on lines of its own, indented from this one
+                let outer = std::mem::take(&mut self.out);
+                let block_start = self.block_start;
+                self.body(&format!("fn({})", params), span.line, body, span.start);
+                let text = std::mem::replace(&mut self.out, outer);
+                self.block_start = block_start;
+                text.trim_start().trim_end_matches('\n').to_string()
+            }
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr]) -> String {
+        exprs.iter().map(|e| self.expr(e)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Statements of a block statement
+fn body_stmts(stmt: &Stmt) -> &[Stmt] {
+    match stmt {
+        Stmt::Block { stmts, .. } => stmts,
+        other => std::slice::from_ref(other),
+    }
+}
+
+/// Whether a statement gets a blank line around it at the top level
+fn is_declaration(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Function { .. } | Stmt::Class { .. } => true,
+        Stmt::Export { stmt, .. } => is_declaration(stmt),
+        _ => false,
+    }
+}
+
+/// Whether an expression's source starts with `if`
+fn starts_with_if(expr: &Expr) -> bool {
+    match expr {
+        Expr::If { .. } => true,
+        Expr::Binary { left, .. } | Expr::Logical { left, .. } => starts_with_if(left),
+        Expr::Call { callee: object, .. }
+        | Expr::Get { object, .. }
+        | Expr::Set { object, .. }
+        | Expr::Index { object, .. }
+        | Expr::IndexSet { object, .. } => starts_with_if(object),
+        _ => false,
+    }
+}
+
+fn format_params(params: &[String], state_params: &[String], param_types: &[Option<String>]) -> String {
+    params.iter()
+        .zip(param_types.iter().chain(std::iter::repeat(&None)))
+        .map(|(name, param_type)| {
+            let state = if state_params.contains(name) { "state " } else { "" };
+            match param_type {
+                Some(param_type) => format!("{}{}: {}", state, name, param_type),
+                None => format!("{}{}", state, name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A string literal that lexes back to `value`
+fn quote(value: &str) -> String {
+    let mut text = String::with_capacity(value.len() + 2);
+    text.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
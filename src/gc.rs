@@ -3,9 +3,12 @@ use std::collections::{HashSet, HashMap};
 use crate::value::Value;
 
 /// A safe handle to a heap-allocated object.
-/// This acts as an index into the Heap's object storage.
+/// This acts as an index into the Heap's object storage, along with the
+/// generation of that slot when the object was allocated: a slot's generation
+/// changes when its object is freed, so a stale handle never resolves to
+/// whatever is allocated there next.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Handle(pub usize);
+pub struct Handle(pub u32, pub u32);
 
 /// State of an upvalue
 #[derive(Debug, Clone)]
@@ -34,6 +37,9 @@ pub struct Closure {
 pub struct Class {
     pub name: String,
     pub methods: HashMap<String, Handle>, // Name -> Closure/Function
+    /// The class this one inherits from. Its methods are copied into
+    /// `methods` when the subclass is created, so lookups never walk the chain.
+    pub superclass: Option<Handle>,
 }
 
 /// Instance object
@@ -50,6 +56,10 @@ pub struct BoundMethod {
     pub method: Handle,  // The closure
 }
 
+/// Heap size at which the first collection happens, unless changed with
+/// `Heap::set_gc_threshold`
+const DEFAULT_GC_THRESHOLD: usize = 1024 * 1024;
+
 pub struct Heap {
     objects: Vec<Option<Object>>,
     free_list: Vec<usize>,
@@ -59,14 +69,113 @@ pub struct Heap {
     /// String interner for deduplication
     interned_strings: HashMap<String, Handle>,
     
-    pub bytes_allocated: usize,
-    pub next_gc: usize,
+    /// Identity of the object in each slot; never reused, unlike slots
+    ids: Vec<u64>,
+    
+    /// Generation of each slot, bumped whenever its object is freed
+    generations: Vec<u32>,
+    next_id: u64,
+    
+    /// What allocated the object in each slot, as an `AllocCategory`
+    categories: Vec<u8>,
+    
+    pub(crate) bytes_allocated: usize,
+    pub(crate) next_gc: usize,
+    
+    /// Heap size below which no collection is triggered
+    gc_threshold: usize,
+    
+    /// Collections performed so far
+    collections: usize,
+    
+    /// Collect at every opportunity, to expose objects that aren't rooted
+    stress: bool,
+    
+    /// Ceiling on `bytes_allocated`, if any
+    max_bytes: Option<usize>,
+    
+    /// Size of the allocation that took the heap over `max_bytes`, or that
+    /// was refused for lack of room, until the VM deals with it
+    limit_hit: Option<usize>,
+}
+
+/// What an object was allocated for, so heap dumps can tell user data
+/// apart from values the runtime creates on its own behalf
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum AllocCategory {
+    /// Arrays the program builds: literals and the results of array natives
+    UserArray,
+    /// Arrays returned by `range()`
+    RangeResult,
+    /// Arrays read out of the causality log by `history()` and `diff()`, and
+    /// the copies of state arrays and maps the log keeps
+    CausalitySnapshot,
+    /// Variables captured by closures
+    ClosureUpvalue,
+    /// Interned strings
+    StringIntern,
+    Other,
+}
+
+impl AllocCategory {
+    const ALL: [AllocCategory; 6] = [
+        AllocCategory::UserArray,
+        AllocCategory::RangeResult,
+        AllocCategory::CausalitySnapshot,
+        AllocCategory::ClosureUpvalue,
+        AllocCategory::StringIntern,
+        AllocCategory::Other,
+    ];
+    
+    fn from_u8(byte: u8) -> Self {
+        Self::ALL.get(byte as usize).copied().unwrap_or(AllocCategory::Other)
+    }
+}
+
+impl std::fmt::Display for AllocCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AllocCategory::UserArray => "user array",
+            AllocCategory::RangeResult => "range result",
+            AllocCategory::CausalitySnapshot => "causality snapshot",
+            AllocCategory::ClosureUpvalue => "closure upvalue",
+            AllocCategory::StringIntern => "string intern",
+            AllocCategory::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Live objects of one type and allocation category
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeapDumpEntry {
+    pub type_name: &'static str,
+    pub category: AllocCategory,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// A heap dump as a table with one row per group and a total row
+pub fn format_dump(entries: &[HeapDumpEntry]) -> String {
+    let mut out = format!("{:<14} {:<20} {:>8} {:>10}\n", "type", "category", "count", "bytes");
+    for entry in entries {
+        out.push_str(&format!("{:<14} {:<20} {:>8} {:>10}\n", entry.type_name, entry.category.to_string(), entry.count, entry.bytes));
+    }
+    let count: usize = entries.iter().map(|e| e.count).sum();
+    let bytes: usize = entries.iter().map(|e| e.bytes).sum();
+    out.push_str(&format!("{:<14} {:<20} {:>8} {:>10}", "total", "", count, bytes));
+    out
 }
 
 pub enum Object {
     String(String),
     Function(crate::value::Function),
-    Array(Vec<Value>),
+    /// Elements, and a version bumped whenever elements are added or removed
+    Array { items: Vec<Value>, version: u64 },
+    Map(HashMap<String, Value>),
+    /// Text built up in place by `buf_push`
+    Buffer(String),
     Closure(Closure),
     Upvalue(Upvalue),
     Class(Class),
@@ -75,16 +184,38 @@ pub enum Object {
 }
 
 impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::String(_) => "string",
+            Object::Function(_) => "function",
+            Object::Array { .. } => "array",
+            Object::Map(_) => "map",
+            Object::Buffer(_) => "buffer",
+            Object::Closure(_) => "closure",
+            Object::Upvalue(_) => "upvalue",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+            Object::BoundMethod(_) => "bound method",
+        }
+    }
+    
     pub fn children(&self) -> Vec<Handle> {
         match self {
-            Object::String(_) => vec![],
-            Object::Function(_f) => {
-                // Constants trace roots
-                vec![]
-            },
-            Object::Array(arr) => {
+            Object::String(_) | Object::Buffer(_) => vec![],
+            Object::Function(f) => {
+                // The chunk's constants include nested functions and any heap values
+                f.chunk.constants().iter().flat_map(Value::children).collect()
+            }
+            Object::Array { items, .. } => {
                 let mut children = Vec::new();
-                for val in arr {
+                for val in items {
+                    children.extend(val.children());
+                }
+                children
+            }
+            Object::Map(map) => {
+                let mut children = Vec::new();
+                for val in map.values() {
                     children.extend(val.children());
                 }
                 children
@@ -101,8 +232,8 @@ impl Object {
                 }
             }
             Object::Class(c) => {
-                // Methods are children (Closures)
-                c.methods.values().cloned().collect()
+                // Methods are children (Closures), as is the superclass
+                c.methods.values().cloned().chain(c.superclass).collect()
             }
             Object::Instance(i) => {
                 let mut children = vec![i.class];
@@ -121,9 +252,10 @@ impl Object {
     
     pub fn size_bytes(&self) -> usize {
         match self {
-            Object::String(s) => std::mem::size_of::<Object>() + s.len(),
+            Object::String(s) | Object::Buffer(s) => std::mem::size_of::<Object>() + s.len(),
             Object::Function(_f) => std::mem::size_of::<Object>() + std::mem::size_of::<crate::value::Function>(),
-            Object::Array(arr) => std::mem::size_of::<Object>() + arr.len() * std::mem::size_of::<Value>(),
+            Object::Array { items, .. } => std::mem::size_of::<Object>() + items.len() * std::mem::size_of::<Value>(),
+            Object::Map(map) => std::mem::size_of::<Object>() + map.keys().map(|k| std::mem::size_of::<String>() + k.len() + std::mem::size_of::<Value>()).sum::<usize>(),
             Object::Closure(c) => std::mem::size_of::<Object>() + std::mem::size_of::<Closure>() + c.upvalues.len() * std::mem::size_of::<Handle>(),
             Object::Upvalue(_) => std::mem::size_of::<Object>() + std::mem::size_of::<Upvalue>(),
             Object::Class(c) => std::mem::size_of::<Object>() + std::mem::size_of::<Class>() + c.name.len() + c.methods.len() * (std::mem::size_of::<String>() + std::mem::size_of::<Handle>()),
@@ -141,8 +273,17 @@ impl Heap {
             marked: HashSet::new(),
             grey_stack: Vec::new(),
             interned_strings: HashMap::new(),
+            ids: Vec::new(),
+            generations: Vec::new(),
+            next_id: 1,
+            categories: Vec::new(),
             bytes_allocated: 0,
-            next_gc: 1024 * 1024, // Start at 1MB
+            next_gc: DEFAULT_GC_THRESHOLD,
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+            collections: 0,
+            stress: false,
+            max_bytes: None,
+            limit_hit: None,
         }
     }
     
@@ -154,150 +295,337 @@ impl Heap {
         }
         
         // Allocate new string and intern it
-        let handle = self.alloc(Object::String(s.clone()));
+        let handle = self.alloc(Object::String(s.clone()), AllocCategory::StringIntern);
         self.interned_strings.insert(s, handle);
         handle
     }
     
     pub fn alloc_function(&mut self, f: crate::value::Function) -> Handle {
-        self.alloc(Object::Function(f))
+        self.alloc(Object::Function(f), AllocCategory::Other)
     }
     
     pub fn alloc_array(&mut self, arr: Vec<Value>) -> Handle {
-        self.alloc(Object::Array(arr))
+        self.alloc_array_as(arr, AllocCategory::UserArray)
+    }
+    
+    /// Allocate an array on behalf of `category`, for heap dumps
+    pub fn alloc_array_as(&mut self, arr: Vec<Value>, category: AllocCategory) -> Handle {
+        self.alloc(Object::Array { items: arr, version: 0 }, category)
+    }
+    
+    pub fn alloc_map(&mut self, map: HashMap<String, Value>) -> Handle {
+        self.alloc_map_as(map, AllocCategory::Other)
+    }
+    
+    pub fn alloc_map_as(&mut self, map: HashMap<String, Value>, category: AllocCategory) -> Handle {
+        self.alloc(Object::Map(map), category)
+    }
+    
+    pub fn alloc_buffer(&mut self, text: String) -> Handle {
+        self.alloc(Object::Buffer(text), AllocCategory::Other)
     }
     
     pub fn alloc_closure(&mut self, function: Handle, upvalues: Vec<Handle>) -> Handle {
-        self.alloc(Object::Closure(Closure { function, upvalues }))
+        self.alloc(Object::Closure(Closure { function, upvalues }), AllocCategory::Other)
     }
     
     pub fn alloc_upvalue(&mut self, slot: usize) -> Handle {
-        self.alloc(Object::Upvalue(Upvalue { location: RefCell::new(UpvalueState::Open(slot)) }))
+        self.alloc(Object::Upvalue(Upvalue { location: RefCell::new(UpvalueState::Open(slot)) }), AllocCategory::ClosureUpvalue)
     }
     
     pub fn alloc_class(&mut self, name: String) -> Handle {
-        self.alloc(Object::Class(Class { name, methods: HashMap::new() }))
+        self.alloc(Object::Class(Class { name, methods: HashMap::new(), superclass: None }), AllocCategory::Other)
     }
     
     pub fn alloc_instance(&mut self, class: Handle) -> Handle {
-        self.alloc(Object::Instance(Instance { class, fields: RefCell::new(HashMap::new()) }))
+        self.alloc(Object::Instance(Instance { class, fields: RefCell::new(HashMap::new()) }), AllocCategory::Other)
     }
     
     pub fn alloc_bound_method(&mut self, receiver: Value, method: Handle) -> Handle {
-        self.alloc(Object::BoundMethod(BoundMethod { receiver, method }))
+        self.alloc(Object::BoundMethod(BoundMethod { receiver, method }), AllocCategory::Other)
     }
     
-    fn alloc(&mut self, obj: Object) -> Handle {
+    fn alloc(&mut self, obj: Object, category: AllocCategory) -> Handle {
         let size = obj.size_bytes();
-        self.bytes_allocated += size;
-        
-        // Simple threshold trigger would go here, but VM orchestrates it
+        self.grow(size);
         
+        let id = self.next_id;
+        self.next_id += 1;
         if let Some(idx) = self.free_list.pop() {
             self.objects[idx] = Some(obj);
-            Handle(idx)
+            self.ids[idx] = id;
+            self.categories[idx] = category as u8;
+            Handle(idx as u32, self.generations[idx])
         } else {
             let idx = self.objects.len();
             self.objects.push(Some(obj));
-            Handle(idx)
+            self.ids.push(id);
+            self.generations.push(0);
+            self.categories.push(category as u8);
+            Handle(idx as u32, 0)
         }
     }
     
+    /// Whether `handle` still refers to the object it was allocated for
+    pub fn is_live(&self, handle: Handle) -> bool {
+        self.generations.get(handle.0 as usize) == Some(&handle.1)
+            && self.objects[handle.0 as usize].is_some()
+    }
+    
+    /// Slot of the object behind `handle`. A stale handle resolves to nothing;
+    /// holding one is a bug, so debug builds stop at it.
+    fn slot(&self, handle: Handle) -> Option<usize> {
+        let index = handle.0 as usize;
+        let generation = *self.generations.get(index)?;
+        debug_assert!(
+            generation == handle.1,
+            "stale handle {:?}: slot {} is at generation {}",
+            handle, index, generation
+        );
+        (generation == handle.1).then_some(index)
+    }
+    
+    fn object(&self, handle: Handle) -> Option<&Object> {
+        self.objects[self.slot(handle)?].as_ref()
+    }
+    
+    fn object_mut(&mut self, handle: Handle) -> Option<&mut Object> {
+        let index = self.slot(handle)?;
+        self.objects[index].as_mut()
+    }
+    
+    /// Identity of a live object: assigned at allocation and unique for the
+    /// heap's lifetime, even when the object's slot is later reused
+    pub fn object_id(&self, handle: Handle) -> Option<u64> {
+        self.is_live(handle).then(|| self.ids[handle.0 as usize])
+    }
+    
     pub fn get_string(&self, handle: Handle) -> Option<&String> {
-        match self.objects.get(handle.0)? {
-            Some(Object::String(s)) => Some(s),
+        match self.object(handle)? {
+            Object::String(s) => Some(s),
             _ => None,
         }
     }
 
     pub fn get_function(&self, handle: Handle) -> Option<&crate::value::Function> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Function(f)) => Some(f),
+        match self.object(handle)? {
+            Object::Function(f) => Some(f),
             _ => None,
         }
     }
 
     pub fn get_array(&self, handle: Handle) -> Option<&Vec<Value>> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Array(arr)) => Some(arr),
+        match self.object(handle)? {
+            Object::Array { items, .. } => Some(items),
             _ => None,
         }
     }
     
+    /// An array for replacing elements in place. Use `resize_array` to add or
+    /// remove elements, so loops iterating over it notice.
     pub fn get_array_mut(&mut self, handle: Handle) -> Option<&mut Vec<Value>> {
-        match self.objects.get_mut(handle.0)? {
-            Some(Object::Array(arr)) => Some(arr),
+        match self.object_mut(handle)? {
+            Object::Array { items, .. } => Some(items),
+            _ => None,
+        }
+    }
+    
+    /// An array for adding or removing elements; bumps its version
+    pub fn resize_array(&mut self, handle: Handle) -> Option<&mut Vec<Value>> {
+        match self.object_mut(handle)? {
+            Object::Array { items, version } => {
+                *version += 1;
+                Some(items)
+            }
+            _ => None,
+        }
+    }
+    
+    /// How many times elements have been added to or removed from an array
+    pub fn array_version(&self, handle: Handle) -> Option<u64> {
+        match self.object(handle)? {
+            Object::Array { version, .. } => Some(*version),
+            _ => None,
+        }
+    }
+    
+    pub fn get_map(&self, handle: Handle) -> Option<&HashMap<String, Value>> {
+        match self.object(handle)? {
+            Object::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+    
+    pub fn get_map_mut(&mut self, handle: Handle) -> Option<&mut HashMap<String, Value>> {
+        match self.object_mut(handle)? {
+            Object::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+    
+    pub fn get_buffer(&self, handle: Handle) -> Option<&String> {
+        match self.object(handle)? {
+            Object::Buffer(text) => Some(text),
+            _ => None,
+        }
+    }
+    
+    pub fn get_buffer_mut(&mut self, handle: Handle) -> Option<&mut String> {
+        match self.object_mut(handle)? {
+            Object::Buffer(text) => Some(text),
             _ => None,
         }
     }
     
     pub fn get_closure(&self, handle: Handle) -> Option<&Closure> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Closure(c)) => Some(c),
+        match self.object(handle)? {
+            Object::Closure(c) => Some(c),
             _ => None,
         }
     }
     
     pub fn get_upvalue(&self, handle: Handle) -> Option<&Upvalue> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Upvalue(u)) => Some(u),
+        match self.object(handle)? {
+            Object::Upvalue(u) => Some(u),
             _ => None,
         }
     }
     
     pub fn get_class(&self, handle: Handle) -> Option<&Class> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Class(c)) => Some(c),
+        match self.object(handle)? {
+            Object::Class(c) => Some(c),
             _ => None,
         }
     }
     
     pub fn get_class_mut(&mut self, handle: Handle) -> Option<&mut Class> {
-        match self.objects.get_mut(handle.0)? {
-            Some(Object::Class(c)) => Some(c),
+        match self.object_mut(handle)? {
+            Object::Class(c) => Some(c),
             _ => None,
         }
     }
     
     pub fn get_instance(&self, handle: Handle) -> Option<&Instance> {
-        match self.objects.get(handle.0)? {
-            Some(Object::Instance(i)) => Some(i),
+        match self.object(handle)? {
+            Object::Instance(i) => Some(i),
             _ => None,
         }
     }
     
     pub fn get_bound_method(&self, handle: Handle) -> Option<&BoundMethod> {
-        match self.objects.get(handle.0)? {
-            Some(Object::BoundMethod(b)) => Some(b),
+        match self.object(handle)? {
+            Object::BoundMethod(b) => Some(b),
             _ => None,
         }
     }
     
-    pub fn is_marked(&self, handle: Handle) -> bool {
-        self.marked.contains(&handle.0)
+    pub(crate) fn is_marked(&self, handle: Handle) -> bool {
+        self.marked.contains(&(handle.0 as usize))
     }
 
-    pub fn should_collect(&self) -> bool {
-        self.bytes_allocated > self.next_gc
+    /// Approximate bytes currently held by live objects
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+    
+    /// Number of live objects
+    pub fn object_count(&self) -> usize {
+        self.objects.len() - self.free_list.len()
+    }
+    
+    /// Number of collections performed, whether triggered by allocation or
+    /// requested with `VM::collect_garbage`
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+    
+    /// Live objects grouped by type and allocation category, largest first
+    pub fn dump(&self) -> Vec<HeapDumpEntry> {
+        let mut groups: HashMap<(&'static str, AllocCategory), (usize, usize)> = HashMap::new();
+        for (obj, &category) in self.objects.iter().zip(&self.categories) {
+            if let Some(obj) = obj {
+                let group = groups.entry((obj.type_name(), AllocCategory::from_u8(category))).or_default();
+                group.0 += 1;
+                group.1 += obj.size_bytes();
+            }
+        }
+        let mut entries: Vec<HeapDumpEntry> = groups
+            .into_iter()
+            .map(|((type_name, category), (count, bytes))| HeapDumpEntry { type_name, category, count, bytes })
+            .collect();
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then((a.type_name, a.category).cmp(&(b.type_name, b.category))));
+        entries
+    }
+    
+    /// Start collecting once the heap holds more than `bytes`, instead of
+    /// 1MB. After each collection the next one waits until the heap has
+    /// doubled, but never for less than `bytes`. A low threshold makes
+    /// collections frequent without the cost of `set_stress`.
+    pub fn set_gc_threshold(&mut self, bytes: usize) {
+        self.gc_threshold = bytes;
+        self.next_gc = bytes;
+    }
+    
+    /// Collect whenever the VM checks, instead of when the heap has grown
+    pub fn set_stress(&mut self, enabled: bool) {
+        self.stress = enabled;
+    }
+    
+    /// Cap the bytes the heap may hold. Allocation itself never fails: going
+    /// over the limit is recorded, and the VM collects and, if that doesn't
+    /// make room, raises an out-of-memory error before the next instruction.
+    pub fn set_max_bytes(&mut self, max: Option<usize>) {
+        self.max_bytes = max;
+    }
+    
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+    
+    /// Whether `size` more bytes fit under the limit
+    pub fn has_room(&self, size: usize) -> bool {
+        self.max_bytes.is_none_or(|max| self.bytes_allocated.saturating_add(size) <= max)
+    }
+    
+    /// Count `size` bytes added to a live object, such as an array growing
+    pub fn grow(&mut self, size: usize) {
+        self.bytes_allocated += size;
+        if !self.has_room(0) {
+            self.limit_hit.get_or_insert(size);
+        }
+    }
+    
+    /// Record that an allocation of `size` bytes was turned down for lack of room
+    pub(crate) fn refuse(&mut self, size: usize) {
+        self.limit_hit = Some(size);
+    }
+    
+    /// The allocation that hit the limit since the last call, if any
+    pub(crate) fn take_limit_hit(&mut self) -> Option<usize> {
+        self.limit_hit.take()
+    }
+    
+    pub(crate) fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
     }
 
     
-    pub fn mark(&mut self, handle: Handle) {
-        if self.marked.contains(&handle.0) {
+    pub(crate) fn mark(&mut self, handle: Handle) {
+        if self.marked.contains(&(handle.0 as usize)) {
             return;
         }
         
-        if self.objects.get(handle.0).and_then(|o| o.as_ref()).is_some() {
-            self.marked.insert(handle.0);
+        if self.object(handle).is_some() {
+            self.marked.insert(handle.0 as usize);
             self.grey_stack.push(handle);
         }
     }
     
-    pub fn trace_references(&mut self) {
+    pub(crate) fn trace_references(&mut self) {
         while let Some(handle) = self.grey_stack.pop() {
             // Get children. Note: we cannot borrow self.objects while calling self.mark
             // So we extract children first.
-            let children = if let Some(Some(obj)) = self.objects.get(handle.0) {
+            let children = if let Some(obj) = self.object(handle) {
                 obj.children()
             } else {
                 Vec::new()
@@ -309,7 +637,7 @@ impl Heap {
         }
     }
     
-    pub fn sweep(&mut self) {
+    pub(crate) fn sweep(&mut self) {
         let mut freed_bytes = 0;
         
         for i in 0..self.objects.len() {
@@ -317,6 +645,7 @@ impl Heap {
                 if let Some(obj) = &self.objects[i] {
                     freed_bytes += obj.size_bytes();
                     self.objects[i] = None;
+                    self.generations[i] = self.generations[i].wrapping_add(1);
                     self.free_list.push(i);
                 }
             }
@@ -324,13 +653,22 @@ impl Heap {
         
         // Clean up interned strings that were freed
         self.interned_strings.retain(|_, &mut handle| {
-            self.marked.contains(&handle.0)
+            self.marked.contains(&(handle.0 as usize))
         });
         
-        self.bytes_allocated -= freed_bytes;
+        // Containers grow after allocation without being re-counted, so a freed
+        // object can report more bytes than it added
+        self.bytes_allocated = self.bytes_allocated.saturating_sub(freed_bytes);
         self.marked.clear();
         
         // Adjust threshold
-        self.next_gc = std::cmp::max(self.bytes_allocated * 2, 1024 * 1024);
+        self.next_gc = std::cmp::max(self.bytes_allocated * 2, self.gc_threshold);
+        self.collections += 1;
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,255 @@
+//! Glob-style pattern matching for the `matches()` and `capture()` natives
+//!
+//! Supported syntax:
+//! - `*` matches any run of characters, as few as possible (lazy)
+//! - `?` matches exactly one character
+//! - `[abc]`, `[a-z]` match one character from a class; `[!abc]` negates it
+//! - `\` escapes the next character, so `\*` matches a literal star
+//!
+//! This is not a regular expression engine: there is no alternation,
+//! grouping or repetition of sub-patterns, and a pattern always has to match
+//! the whole input. Matching only ever backtracks to the most recent `*`, so
+//! the work is bounded by input length times pattern length; it gives up with
+//! an error after `MAX_STEPS` steps rather than hang on huge inputs.
+
+/// Upper bound on matching steps before a pattern is rejected
+pub const MAX_STEPS: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) != *negated
+            }
+        }
+    }
+}
+
+fn parse(pattern: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => {
+                // Consecutive stars behave like one
+                if tokens.last() == Some(&Token::Star) {
+                    continue;
+                }
+                Token::Star
+            }
+            '?' => Token::Any,
+            '\\' => match chars.next() {
+                Some(escaped) => Token::Literal(escaped),
+                None => return Err("pattern ends with an unfinished escape '\\'".to_string()),
+            },
+            '[' => {
+                let negated = chars.next_if_eq(&'!').is_some();
+                let mut ranges = Vec::new();
+                loop {
+                    let lo = match chars.next() {
+                        // A ']' right after '[' or '[!' is a literal member
+                        Some(']') if !ranges.is_empty() => break,
+                        Some('\\') => chars.next().ok_or("unterminated character class")?,
+                        Some(c) => c,
+                        None => return Err("unterminated character class".to_string()),
+                    };
+                    let hi = if chars.peek() == Some(&'-') {
+                        chars.next();
+                        match chars.next() {
+                            // A trailing '-' is a literal member
+                            Some(']') => {
+                                ranges.push((lo, lo));
+                                ranges.push(('-', '-'));
+                                break;
+                            }
+                            Some('\\') => chars.next().ok_or("unterminated character class")?,
+                            Some(c) => c,
+                            None => return Err("unterminated character class".to_string()),
+                        }
+                    } else {
+                        lo
+                    };
+                    if hi < lo {
+                        return Err(format!("invalid range '{}-{}' in character class", lo, hi));
+                    }
+                    ranges.push((lo, hi));
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Literal(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Match `input` against `tokens`, returning the (start, end) char range of each `*`
+fn run(input: &[char], tokens: &[Token], limit: usize) -> Result<Option<Vec<(usize, usize)>>, String> {
+    let mut ti = 0;
+    let mut pi = 0;
+    let mut stars: Vec<(usize, usize)> = Vec::new();
+    // Pattern index just after the most recent star, and where that star currently ends
+    let mut backtrack: Option<(usize, usize)> = None;
+    let mut steps = 0;
+
+    while ti < input.len() {
+        steps += 1;
+        if steps > limit {
+            return Err("pattern is too complex to match against this input".to_string());
+        }
+
+        match tokens.get(pi) {
+            Some(Token::Star) => {
+                stars.push((ti, ti));
+                pi += 1;
+                backtrack = Some((pi, ti));
+            }
+            Some(token) if token.matches(input[ti]) => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => {
+                // Let the most recent star swallow one more character and retry
+                let Some((star_pi, star_end)) = backtrack else {
+                    return Ok(None);
+                };
+                let star_end = star_end + 1;
+                backtrack = Some((star_pi, star_end));
+                if let Some(last) = stars.last_mut() {
+                    last.1 = star_end;
+                }
+                pi = star_pi;
+                ti = star_end;
+            }
+        }
+    }
+
+    while tokens.get(pi) == Some(&Token::Star) {
+        stars.push((ti, ti));
+        pi += 1;
+    }
+
+    Ok(if pi == tokens.len() { Some(stars) } else { None })
+}
+
+/// Does the whole of `input` match `pattern`?
+pub fn is_match(input: &str, pattern: &str) -> Result<bool, String> {
+    let tokens = parse(pattern)?;
+    let input: Vec<char> = input.chars().collect();
+    Ok(run(&input, &tokens, MAX_STEPS)?.is_some())
+}
+
+/// The text matched by each `*` in `pattern`, or None if `input` doesn't match
+pub fn captures(input: &str, pattern: &str) -> Result<Option<Vec<String>>, String> {
+    let tokens = parse(pattern)?;
+    let input: Vec<char> = input.chars().collect();
+    Ok(run(&input, &tokens, MAX_STEPS)?.map(|stars| {
+        stars
+            .into_iter()
+            .map(|(start, end)| input[start..end].iter().collect())
+            .collect()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_table() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("", "", true),
+            ("", "*", true),
+            ("", "?", false),
+            ("abc", "abc", true),
+            ("abc", "ab", false),
+            ("abc", "abcd", false),
+            ("abc", "a*", true),
+            ("abc", "*c", true),
+            ("abc", "*b*", true),
+            ("abc", "a?c", true),
+            ("ac", "a?c", false),
+            ("abc", "???", true),
+            ("abc", "a**c", true),
+            ("abcabc", "*bc", true),
+            ("abcabd", "*bc", false),
+            ("mississippi", "m*iss*ppi", true),
+            ("mississippi", "m*iss*x", false),
+            ("cat", "[bc]at", true),
+            ("hat", "[bc]at", false),
+            ("hat", "[!bc]at", true),
+            ("q", "[a-z]", true),
+            ("Q", "[a-z]", false),
+            ("-", "[a-]", true),
+            ("]", "[]]", true),
+            ("a*b", "a\\*b", true),
+            ("axb", "a\\*b", false),
+            ("a?", "a\\?", true),
+            ("a\\", "a\\\\", true),
+            ("[", "\\[", true),
+            ("héllo", "h?llo", true),
+            ("日本語", "日*", true),
+            ("日本語", "??語", true),
+            ("日本語", "[日月]本?", true),
+            ("é", "[a-z]", false),
+        ];
+
+        for (input, pattern, expected) in cases {
+            assert_eq!(
+                is_match(input, pattern).unwrap(),
+                *expected,
+                "{:?} against {:?}",
+                input,
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_captures_are_lazy() {
+        let caps = |input, pattern| captures(input, pattern).unwrap();
+
+        assert_eq!(caps("key=value", "*=*"), Some(vec!["key".to_string(), "value".to_string()]));
+        assert_eq!(caps("a=b=c", "*=*"), Some(vec!["a".to_string(), "b=c".to_string()]));
+        assert_eq!(caps("abc", "abc"), Some(vec![]));
+        assert_eq!(caps("abc", "abc*"), Some(vec!["".to_string()]));
+        assert_eq!(caps("x.tar.gz", "*.*"), Some(vec!["x".to_string(), "tar.gz".to_string()]));
+        assert_eq!(caps("日本語", "*本*"), Some(vec!["日".to_string(), "語".to_string()]));
+        assert_eq!(caps("abc", "x*"), None);
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(is_match("a", "[abc").is_err());
+        assert!(is_match("a", "abc\\").is_err());
+        assert!(is_match("a", "[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_pathological_patterns_stay_polynomial() {
+        let input = "a".repeat(2000);
+        assert!(!is_match(&input, "*a*a*a*a*a*a*a*a*b").unwrap());
+        assert!(is_match(&input, "*a*a*a*a*a*a*a*a*").unwrap());
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let input: Vec<char> = "a".repeat(100).chars().collect();
+        let tokens = parse("*a*b").unwrap();
+        assert!(run(&input, &tokens, 50).is_err());
+        assert_eq!(run(&input, &tokens, MAX_STEPS).unwrap(), None);
+    }
+}
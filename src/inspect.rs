@@ -0,0 +1,177 @@
+//! Heap-aware descriptions of values, shared by `inspect()` and the REPL's `:inspect`
+
+use std::collections::BTreeMap;
+use crate::gc::Heap;
+use crate::value::Value;
+
+/// How deep `render` follows nested containers
+const MAX_DEPTH: usize = 3;
+
+/// Elements or entries shown per container before eliding the rest
+const MAX_ITEMS: usize = 10;
+
+/// Render a value, following arrays, maps and instances up to a fixed depth.
+/// Strings inside containers are quoted.
+pub fn render(heap: &Heap, value: &Value) -> String {
+    render_at(heap, value, 0)
+}
+
+fn render_at(heap: &Heap, value: &Value, depth: usize) -> String {
+    match value {
+        Value::String(s) if depth > 0 => format!("{:?}", &**s),
+        Value::Array(handle) => match heap.get_array(*handle) {
+            Some(_) if depth >= MAX_DEPTH => "[...]".to_string(),
+            Some(items) => format!("[{}]", render_items(items.iter().map(|v| render_at(heap, v, depth + 1)), items.len())),
+            None => value.to_string(),
+        },
+        Value::Map(handle) => match heap.get_map(*handle) {
+            Some(_) if depth >= MAX_DEPTH => "{...}".to_string(),
+            Some(map) => {
+                let sorted: BTreeMap<_, _> = map.iter().collect();
+                let entries = sorted.iter().map(|(k, v)| format!("{:?}: {}", k, render_at(heap, v, depth + 1)));
+                format!("{{{}}}", render_items(entries, map.len()))
+            }
+            None => value.to_string(),
+        },
+        Value::Instance(handle) => {
+            let class = class_name(heap, value).unwrap_or("?");
+            match heap.get_instance(*handle) {
+                Some(_) if depth >= MAX_DEPTH => format!("{} {{...}}", class),
+                Some(instance) => {
+                    let fields = instance.fields.borrow();
+                    let sorted: BTreeMap<_, _> = fields.iter().collect();
+                    let entries = sorted.iter().map(|(k, v)| format!("{}: {}", k, render_at(heap, v, depth + 1)));
+                    format!("{} {{{}}}", class, render_items(entries, fields.len()))
+                }
+                None => value.to_string(),
+            }
+        }
+        Value::Closure(_) | Value::Function(_) => match function(heap, value) {
+            Some(f) => format!("<fn {}>", f.name),
+            None => value.to_string(),
+        },
+        Value::Class(handle) => match heap.get_class(*handle) {
+            Some(class) => format!("<class {}>", class.name),
+            None => value.to_string(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+fn render_items(items: impl Iterator<Item = String>, len: usize) -> String {
+    let mut shown: Vec<String> = items.take(MAX_ITEMS).collect();
+    if len > MAX_ITEMS {
+        shown.push(format!("... {} more", len - MAX_ITEMS));
+    }
+    shown.join(", ")
+}
+
+/// A multi-line description of a value: its type, rendering, and details
+/// specific to its kind. Names are listed in sorted order.
+pub fn describe(heap: &Heap, value: &Value) -> String {
+    let mut lines = vec![
+        format!("type: {}", value.type_name()),
+        format!("value: {}", render_at(heap, value, 1)),
+    ];
+
+    match value {
+        Value::Number(n) => {
+            let kind = if n.is_nan() {
+                "NaN"
+            } else if n.is_infinite() {
+                "infinite"
+            } else if n.fract() == 0.0 {
+                "integral"
+            } else {
+                "fractional"
+            };
+            lines.push(format!("kind: {}", kind));
+        }
+        Value::String(s) => lines.push(format!("length: {}", s.chars().count())),
+        Value::Array(handle) => {
+            if let Some(items) = heap.get_array(*handle) {
+                lines.push(format!("length: {}", items.len()));
+                let mut histogram: BTreeMap<&str, usize> = BTreeMap::new();
+                for item in items {
+                    *histogram.entry(item.type_name()).or_default() += 1;
+                }
+                if !histogram.is_empty() {
+                    let counts: Vec<String> = histogram.iter().map(|(t, n)| format!("{} x{}", t, n)).collect();
+                    lines.push(format!("elements: {}", counts.join(", ")));
+                }
+            }
+        }
+        Value::Map(handle) => {
+            if let Some(map) = heap.get_map(*handle) {
+                lines.push(format!("length: {}", map.len()));
+            }
+        }
+        Value::Buffer(handle) => {
+            if let Some(text) = heap.get_buffer(*handle) {
+                lines.push(format!("length: {}", text.chars().count()));
+            }
+        }
+        Value::Instance(handle) => {
+            lines.push(format!("class: {}", class_name(heap, value).unwrap_or("?")));
+            if let Some(instance) = heap.get_instance(*handle) {
+                let fields = instance.fields.borrow();
+                let sorted: BTreeMap<_, _> = fields.iter().collect();
+                lines.push(format!("fields: {}", fields.len()));
+                for (name, field) in sorted {
+                    lines.push(format!("  {} = {}", name, render_at(heap, field, 1)));
+                }
+            }
+        }
+        Value::Closure(_) | Value::Function(_) => {
+            if let Some(f) = function(heap, value) {
+                lines.push(format!("name: {}", f.name));
+                lines.push(format!("params: ({})", f.params.join(", ")));
+                lines.push(format!("upvalues: {}", f.upvalue_count));
+            }
+        }
+        Value::NativeFunction(native) => {
+            lines.push(format!("name: {}", native.name));
+            match native.arity {
+                Some(n) => lines.push(format!("arity: {}", n)),
+                None => lines.push("arity: variadic".to_string()),
+            }
+        }
+        Value::Class(handle) => {
+            if let Some(class) = heap.get_class(*handle) {
+                lines.push(format!("name: {}", class.name));
+                let mut methods: Vec<&str> = class.methods.keys().map(String::as_str).collect();
+                methods.sort_unstable();
+                lines.push(format!("methods: {}", methods.join(", ")));
+            }
+        }
+        Value::BoundMethod(handle) => {
+            if let Some(bound) = heap.get_bound_method(*handle) {
+                if let Some(f) = function(heap, &Value::Closure(bound.method)) {
+                    lines.push(format!("name: {}", f.name));
+                }
+                lines.push(format!("receiver: {}", render_at(heap, &bound.receiver, 1)));
+            }
+        }
+        _ => {}
+    }
+
+    lines.join("\n")
+}
+
+/// The function behind a closure, function or bound method value
+pub(crate) fn function<'a>(heap: &'a Heap, value: &Value) -> Option<&'a crate::value::Function> {
+    match value {
+        Value::Closure(handle) => heap.get_function(heap.get_closure(*handle)?.function),
+        Value::Function(handle) => heap.get_function(*handle),
+        Value::BoundMethod(handle) => function(heap, &Value::Closure(heap.get_bound_method(*handle)?.method)),
+        _ => None,
+    }
+}
+
+fn class_name<'a>(heap: &'a Heap, value: &Value) -> Option<&'a str> {
+    let Value::Instance(handle) = value else {
+        return None;
+    };
+    let class = heap.get_class(heap.get_instance(*handle)?.class)?;
+    Some(class.name.as_str())
+}
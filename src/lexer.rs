@@ -68,7 +68,19 @@ impl<'a> Lexer<'a> {
             ',' => { self.advance(); TokenKind::Comma }
             ';' => { self.advance(); TokenKind::Semicolon }
             ':' => { self.advance(); TokenKind::Colon }
-            '.' => { self.advance(); TokenKind::Dot }
+            '?' => { self.advance(); TokenKind::Question }
+            '|' => { self.advance(); TokenKind::Pipe }
+            '.' => {
+                self.advance();
+                let mut ahead = self.chars.clone();
+                if ahead.next().map(|(_, c)| c) == Some('.') && ahead.next().map(|(_, c)| c) == Some('.') {
+                    self.advance();
+                    self.advance();
+                    TokenKind::Ellipsis
+                } else {
+                    TokenKind::Dot
+                }
+            }
             '+' => { self.advance(); TokenKind::Plus }
             '*' => { self.advance(); TokenKind::Star }
             '%' => { self.advance(); TokenKind::Percent }
@@ -269,7 +281,7 @@ impl<'a> Lexer<'a> {
         if self.peek_char() == Some('.') {
             // Look ahead to see if it's followed by a digit
             let remaining = &self.source[self.current_pos..];
-            if remaining.len() > 1 && remaining.chars().nth(1).map_or(false, |c| c.is_ascii_digit()) {
+            if remaining.len() > 1 && remaining.chars().nth(1).is_some_and(|c| c.is_ascii_digit()) {
                 self.advance(); // Consume the dot
                 
                 // Consume decimal digits
@@ -364,6 +376,7 @@ mod tests {
     }
     
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_numbers() {
         let tokens = tokenize("42 3.14 0 100.0");
         assert_eq!(tokens, vec![
@@ -1,44 +1,76 @@
 //! SkyHetu - A causality-first programming language
 //!
 //! SkyHetu makes state, time, and causality explicit by default.
+//!
+//! The types re-exported at the crate root are the supported embedding API:
+//! lex and parse with [`Lexer`] and [`Parser`], compile with [`Compiler`],
+//! execute on a [`VM`], and inspect state history through [`CausalityLog`].
+//! [`Engine`] bundles these into a session that keeps its globals between
+//! evaluations.
+//! The submodules stay public for tooling, but their other items may change
+//! between releases.
 
 pub mod token;
 pub mod lexer;
 pub mod parser;
 pub mod ast;
 pub mod value;
+pub mod bindings;
 pub mod environment;
 // pub mod interpreter;
 pub mod causality;
 pub mod gc;
+pub mod glob;
+pub mod inspect;
 pub mod error;
 pub mod bytecode;
 pub mod compiler;
 pub mod vm;
+pub mod engine;
+pub mod observer;
+pub mod debugger;
+pub mod timings;
+pub mod types;
+pub mod testing;
+pub mod format;
 
-pub use error::{Result, SkyHetuError};
+pub use ast::Program;
+pub use bindings::BindingKind;
+pub use bytecode::Chunk;
+pub use causality::{CausalityLog, EvictionDigest, ExportFormat, MutationEvent, VarSummary};
+pub use compiler::Compiler;
+pub use debugger::{Breakpoints, DebugAction, DebugEvent};
+pub use engine::Engine;
+pub use error::{ErrorKind, Result, SkyHetuError, Warning};
+pub use gc::{Handle, Heap};
 // pub use interpreter::Interpreter;
 pub use lexer::Lexer;
+pub use observer::{Observer, Snapshot};
 pub use parser::Parser;
-pub use value::Value;
+pub use timings::Timings;
+pub use value::{NativeFn, NativeFnPtr, Value};
+pub use vm::{AssertFailure, AssertMode, CaptureBuffer, VM};
 
-/// Convenience function to run SkyHetu code
+/// Convenience function to run SkyHetu code on a fresh VM
 pub fn run(source: &str) -> Result<Value> {
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize()?;
-    let mut parser = Parser::new(tokens);
-    let program = parser.parse()?;
-    
-    // Create VM first to access Heap
-    let mut vm = vm::VM::new();
-    
-    // Compile to bytecode
-    let mut compiler = compiler::Compiler::new();
-    let (chunk, chunks) = compiler.compile(&program, &mut vm.heap)?;
-    
-    // Run on VM
-    vm.register_chunks(chunks);
-    vm.run(chunk)
+    run_in(&mut VM::new(), source)
+}
+
+/// Compile `source` against `vm`'s heap and run it there.
+///
+/// This is the supported way to embed SkyHetu: globals, heap objects and the
+/// causality log accumulate across calls, so a host can inject data once
+/// (with [`VM::set_global`] or an earlier snippet) and run many scripts
+/// against it. Imports resolve against the VM's base path, if one is set.
+pub fn run_in(vm: &mut VM, source: &str) -> Result<Value> {
+    vm.eval_in_place(source, &mut Timings::default())
+}
+
+/// [`run_in`], also reporting how long each phase took
+pub fn run_timed(vm: &mut VM, source: &str) -> Result<(Value, Timings)> {
+    let mut timings = Timings::default();
+    let value = vm.eval_in_place(source, &mut timings)?;
+    Ok((value, timings))
 }
 
 /// Version of the SkyHetu language
@@ -1,18 +1,46 @@
 //! SkyHetu CLI and REPL
 //!
 //! Usage:
-//!   skyhetu run <file.sky>   - Execute a SkyHetu file
+//!   skyhetu run <file.sky> [args...]
+//!                            - Execute a SkyHetu file, or a .skyc file made by `build`;
+//!                              the arguments after it are the script's `args()`
+//!       --collect-asserts    - Keep running past failing asserts, report them at the end
+//!       --deterministic      - Seed rand() with 0 and use a fake clock, for reproducible output
+//!       --debug-info         - Show the conditions guarding the code that raised an error
+//!       --max-heap <bytes>   - Fail with an out-of-memory error instead of growing the heap past this
+//!       --max-instructions <n> - Fail once the program has run this many instructions
+//!       --no-optimize        - Compile the program as written, without folding constants or dropping dead branches
+//!       --quiet              - Don't print compiler warnings
+//!       --timings            - Print time spent lexing, parsing, compiling and running
+//!       --trace <out.json>   - Write the whole causality log to a file at exit
+//!       --graph <out.dot>    - Write a Graphviz graph of every state change at exit
+//!   skyhetu check <file.sky> - Compile without running
+//!       --stats              - Print bytecode size metrics per function
+//!       --timings            - Print time spent lexing, parsing and compiling
+//!   skyhetu build <file.sky> - Compile to a bytecode file that `run` loads without reparsing
+//!       -o <file.skyc>       - Output path (default: the input with a .skyc extension)
+//!       --debug-info         - Keep the conditions guarding each line, as for `run`
+//!       --no-optimize        - Keep every expression and branch, as for `run`
+//!   skyhetu test [dir|file]  - Run the test_ functions of every *_test.skyh file
+//!   skyhetu disasm <file.sky> - Print the bytecode of the script and every function
+//!   skyhetu fmt <file.sky>   - Rewrite a file in the canonical layout
+//!       --check              - Only report whether the file would change, exiting 1 if so
+//!   skyhetu debug <file.sky> - Step through a program line by line, with breakpoints
 //!   skyhetu repl             - Start interactive REPL
+//!       --load <file>        - Run a file into the session first (repeatable)
+//!   skyhetu causality convert <in.bin> <out>
+//!                            - Convert a binary causality log
 //!   skyhetu help             - Show help message
 
 use std::env;
 use std::fs;
 use std::process;
+use std::time::Instant;
 use colored::Colorize;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 
-use skyhetu::{Lexer, Parser, VERSION};
+use skyhetu::{Lexer, Parser, Timings, VERSION};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -24,20 +52,144 @@ fn main() {
     
     match args[1].as_str() {
         "run" => {
-            if args.len() < 3 {
+            let mut options = RunOptions::default();
+            let mut path = None;
+            let mut rest = args[2..].iter();
+            // Flags come before the file; everything after it is for the script
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--collect-asserts" => options.collect_asserts = true,
+                    "--deterministic" => options.deterministic = true,
+                    "--debug-info" => options.debug_info = true,
+                    "--timings" => options.timings = true,
+                    "--no-optimize" => options.no_optimize = true,
+                    "--quiet" => options.quiet = true,
+                    "--trace" => options.trace = rest.next().cloned(),
+                    "--graph" => options.graph = rest.next().cloned(),
+                    "--max-heap" => {
+                        let Some(bytes) = rest.next().and_then(|n| n.parse::<usize>().ok()) else {
+                            eprintln!("{}: --max-heap expects a number of bytes", "error".red());
+                            process::exit(1);
+                        };
+                        options.max_heap = Some(bytes);
+                    }
+                    "--max-instructions" => {
+                        let Some(count) = rest.next().and_then(|n| n.parse::<u64>().ok()) else {
+                            eprintln!("{}: --max-instructions expects a number of instructions", "error".red());
+                            process::exit(1);
+                        };
+                        options.max_instructions = Some(count);
+                    }
+                    _ if !arg.starts_with("--") => {
+                        path = Some(arg);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let Some(path) = path else {
+                eprintln!("{}: missing file argument", "error".red());
+                eprintln!("Usage: skyhetu run [--collect-asserts] [--deterministic] [--debug-info] [--max-heap <bytes>] [--max-instructions <n>] [--no-optimize] [--quiet] [--timings] [--trace <out.json>] [--graph <out.dot>] <file.sky> [args...]");
+                process::exit(1);
+            };
+            options.script_args = rest.cloned().collect();
+            run_file(path, &options);
+        }
+        "build" => {
+            let debug_info = args[2..].iter().any(|a| a == "--debug-info");
+            let optimize = !args[2..].iter().any(|a| a == "--no-optimize");
+            let mut input = None;
+            let mut output = None;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "-o" => output = rest.next().cloned(),
+                    "--debug-info" | "--no-optimize" => {}
+                    _ if input.is_none() && !arg.starts_with('-') => input = Some(arg.clone()),
+                    _ => {
+                        input = None;
+                        break;
+                    }
+                }
+            }
+            let Some(input) = input else {
+                eprintln!("{}: expected a source file", "error".red());
+                eprintln!("Usage: skyhetu build [--debug-info] [--no-optimize] <file.skyh> [-o <file.skyc>]");
+                process::exit(1);
+            };
+            let output = output.unwrap_or_else(|| {
+                std::path::Path::new(&input).with_extension("skyc").to_string_lossy().into_owned()
+            });
+            build_file(&input, &output, debug_info, optimize);
+        }
+        "test" => {
+            let path = args.get(2).map_or(".", |p| p.as_str());
+            test_path(path);
+        }
+        "disasm" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("{}: missing file argument", "error".red());
+                eprintln!("Usage: skyhetu disasm <file.skyh>");
+                process::exit(1);
+            };
+            disasm_file(path);
+        }
+        "fmt" => {
+            let check = args[2..].iter().any(|a| a == "--check");
+            let Some(path) = args[2..].iter().find(|a| !a.starts_with("--")) else {
+                eprintln!("{}: missing file argument", "error".red());
+                eprintln!("Usage: skyhetu fmt [--check] <file.skyh>");
+                process::exit(1);
+            };
+            fmt_file(path, check);
+        }
+        "debug" => {
+            let Some(path) = args.get(2) else {
                 eprintln!("{}: missing file argument", "error".red());
-                eprintln!("Usage: skyhetu run <file.sky>");
+                eprintln!("Usage: skyhetu debug <file.skyh>");
                 process::exit(1);
+            };
+            debug_file(path);
+        }
+        "repl" => {
+            let mut preloads = Vec::new();
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match (arg.as_str(), rest.next()) {
+                    ("--load", Some(path)) => preloads.push(path.clone()),
+                    _ => {
+                        eprintln!("{}: unexpected argument '{}'", "error".red(), arg);
+                        eprintln!("Usage: skyhetu repl [--load <file.skyh>]...");
+                        process::exit(1);
+                    }
+                }
             }
-            run_file(&args[2]);
+            run_repl(&preloads);
+        }
+        "check" => {
+            let show_stats = args[2..].iter().any(|a| a == "--stats");
+            let show_timings = args[2..].iter().any(|a| a == "--timings");
+            let Some(path) = args[2..].iter().find(|a| !a.starts_with("--")) else {
+                eprintln!("{}: missing file argument", "error".red());
+                eprintln!("Usage: skyhetu check [--stats] [--timings] <file.sky>");
+                process::exit(1);
+            };
+            check_file(path, show_stats, show_timings);
+        }
+        "causality" => {
+            if args.len() < 5 || args[2] != "convert" {
+                eprintln!("{}: expected 'causality convert <in.bin> <out.jsonl|out.bin>'", "error".red());
+                process::exit(1);
+            }
+            convert_causality(&args[3], &args[4]);
         }
-        "repl" => run_repl(),
         "help" | "--help" | "-h" => print_help(),
         "version" | "--version" | "-v" => println!("SkyHetu {}", VERSION),
         _ => {
             // Assume it's a file
-            if args[1].ends_with(".skyh") {
-                run_file(&args[1]);
+            if args[1].ends_with(".skyh") || args[1].ends_with(".skyc") {
+                let options = RunOptions { script_args: args[2..].to_vec(), ..RunOptions::default() };
+                run_file(&args[1], &options);
             } else {
                 eprintln!("{}: unknown command '{}'", "error".red(), args[1]);
                 print_help();
@@ -52,8 +204,36 @@ fn print_help() {
     println!("A causality-first programming language");
     println!("{} {}\n", "Version".cyan(), VERSION);
     println!("{}", "USAGE:".yellow());
-    println!("  skyhetu run <file.skyh>   Execute a SkyHetu file");
+    println!("  skyhetu run <file.skyh> [args...]");
+    println!("                           Execute a SkyHetu file; args() returns the args");
+    println!("      --collect-asserts    Report every failing assert at the end");
+    println!("      --deterministic      Seeded rand() and fake clock for reproducible output");
+    println!("      --debug-info         Show the conditions guarding a failing line");
+    println!("      --max-heap <bytes>   Stop with an error instead of using more heap");
+    println!("      --max-instructions <n>");
+    println!("                           Stop with an error after running n instructions");
+    println!("      --no-optimize        Don't precompute constants or drop dead branches");
+    println!("      --quiet              Don't print compiler warnings");
+    println!("      --timings            Print time spent in each phase");
+    println!("      --trace <out.json>   Write the causality log to a file at exit");
+    println!("      --graph <out.dot>    Write a Graphviz graph of all state changes at exit");
+    println!("  skyhetu check <file.skyh> Compile without running");
+    println!("      --stats              Print bytecode size metrics per function");
+    println!("      --timings            Print time spent in each phase");
+    println!("  skyhetu build <file.skyh> [-o <file.skyc>]");
+    println!("                           Compile to bytecode; run it with skyhetu run");
+    println!("      --debug-info         Keep guarding conditions for error reports");
+    println!("      --no-optimize        Don't precompute constants or drop dead branches");
+    println!("  skyhetu test [dir|file]  Run the test_ functions of *_test.skyh files");
+    println!("  skyhetu disasm <file.skyh>");
+    println!("                           Print the bytecode of the script and its functions");
+    println!("  skyhetu fmt <file.skyh>  Rewrite a file in the canonical layout");
+    println!("      --check              Exit with 1 instead if the file would change");
+    println!("  skyhetu debug <file.skyh> Step through a program; type help at the prompt");
     println!("  skyhetu repl             Start interactive REPL");
+    println!("      --load <file>        Load a file's definitions first (repeatable)");
+    println!("  skyhetu causality convert <in.bin> <out.jsonl>");
+    println!("                           Convert a binary causality log");
     println!("  skyhetu help             Show this help message");
     println!("  skyhetu version          Show version\n");
     println!("{}", "EXAMPLES:".yellow());
@@ -67,7 +247,38 @@ fn print_help() {
     println!("  fn f(a) {{ return a }}     Function definition");
 }
 
-fn run_file(path: &str) {
+fn convert_causality(input: &str, output: &str) {
+    use skyhetu::causality::{CausalityLog, ExportFormat};
+    
+    let format = match std::path::Path::new(output).extension().and_then(|e| e.to_str()) {
+        Some("jsonl") => ExportFormat::Jsonl,
+        Some("bin") => ExportFormat::Binary,
+        _ => {
+            eprintln!("{}: output must end in .jsonl or .bin", "error".red());
+            process::exit(1);
+        }
+    };
+    
+    let log = match fs::File::open(input).and_then(|mut f| CausalityLog::load_binary(&mut f)) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("{}: cannot read causality log '{}': {}", "error".red(), input, e);
+            process::exit(1);
+        }
+    };
+    
+    let written = fs::File::create(output).and_then(|f| {
+        let mut writer = std::io::BufWriter::new(f);
+        log.export_to(&mut writer, format)
+    });
+    if let Err(e) = written {
+        eprintln!("{}: cannot write '{}': {}", "error".red(), output, e);
+        process::exit(1);
+    }
+}
+
+/// Read and parse a source file, exiting with a diagnostic on failure
+fn load_program(path: &str, timings: &mut Timings) -> (String, skyhetu::ast::Program) {
     let source = match fs::read_to_string(path) {
         Ok(content) => content,
         Err(e) => {
@@ -75,55 +286,384 @@ fn run_file(path: &str) {
             process::exit(1);
         }
     };
-    
-    let mut lexer = Lexer::new(&source);
+    let program = parse_source(&source, timings);
+    (source, program)
+}
+
+/// Lex and parse source text, exiting with a diagnostic on failure
+fn parse_source(source: &str, timings: &mut Timings) -> skyhetu::ast::Program {
+    let start = Instant::now();
+    let mut lexer = Lexer::new(source);
     let tokens = match lexer.tokenize() {
         Ok(t) => t,
         Err(e) => {
-            let err = e.with_source(&source);
+            let err = e.with_source(source);
             eprintln!("{}", err);
             process::exit(1);
         }
     };
+    timings.lex = start.elapsed();
     
+    let start = Instant::now();
     let mut parser = Parser::new(tokens);
-    let program = match parser.parse() {
+    let program = match parser.parse_all() {
         Ok(p) => p,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("{}", e.with_source(source));
+            }
+            process::exit(1);
+        }
+    };
+    timings.parse = start.elapsed();
+    program
+}
+
+/// Directory imports are resolved against
+fn module_base_path(path: &str) -> std::path::PathBuf {
+    std::path::Path::new(path)
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+}
+
+fn check_file(path: &str, show_stats: bool, show_timings: bool) {
+    let mut timings = Timings::default();
+    let (source, program) = load_program(path, &mut timings);
+    
+    let start = Instant::now();
+    let mut heap = skyhetu::gc::Heap::new();
+    let mut compiler = skyhetu::vm::VM::new().program_compiler(module_base_path(path));
+    if let Err(e) = compiler.compile(&program, &mut heap) {
+        let err = e.with_source(&source);
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    timings.compile = start.elapsed();
+    timings.modules = compiler.module_timings().to_vec();
+    print_warnings(compiler.warnings());
+    
+    if show_stats {
+        println!("{}", compiler.stats());
+    }
+    if show_timings {
+        eprintln!("{}", timings);
+    }
+}
+
+/// Flags accepted by `skyhetu run`
+#[derive(Default)]
+struct RunOptions {
+    collect_asserts: bool,
+    deterministic: bool,
+    debug_info: bool,
+    /// Heap limit in bytes
+    max_heap: Option<usize>,
+    /// Instruction budget
+    max_instructions: Option<u64>,
+    /// Compile without folding constants or leaving out dead branches
+    no_optimize: bool,
+    /// Skip printing compiler warnings
+    quiet: bool,
+    timings: bool,
+    /// Where to write the causality log when the program exits
+    trace: Option<String>,
+    /// Where to write the DOT graph of every state change when the program exits
+    graph: Option<String>,
+    /// What the script's `args()` returns
+    script_args: Vec<String>,
+}
+
+fn build_file(input: &str, output: &str, debug_info: bool, optimize: bool) {
+    let mut timings = Timings::default();
+    let (source, program) = load_program(input, &mut timings);
+    
+    let mut heap = skyhetu::gc::Heap::new();
+    let mut compiler = skyhetu::vm::VM::new().program_compiler(module_base_path(input));
+    compiler.set_debug_info(debug_info);
+    compiler.set_optimize(optimize);
+    let bytes = compiler.compile(&program, &mut heap).and_then(|chunk| chunk.serialize(&heap));
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
         Err(e) => {
-            let err = e.with_source(&source);
-            eprintln!("{}", err);
+            eprintln!("{}", e.with_source(&source));
             process::exit(1);
         }
     };
+    print_warnings(compiler.warnings());
+    
+    if let Err(e) = fs::write(output, bytes) {
+        eprintln!("{}: cannot write '{}': {}", "error".red(), output, e);
+        process::exit(1);
+    }
+}
+
+fn disasm_file(path: &str) {
+    let mut timings = Timings::default();
+    let (source, program) = load_program(path, &mut timings);
+    
+    let mut heap = skyhetu::gc::Heap::new();
+    let mut compiler = skyhetu::vm::VM::new().program_compiler(module_base_path(path));
+    match compiler.compile(&program, &mut heap) {
+        Ok(chunk) => print!("{}", chunk.disassemble_all("script", &heap)),
+        Err(e) => {
+            eprintln!("{}", e.with_source(&source));
+            process::exit(1);
+        }
+    }
+}
+
+fn fmt_file(path: &str, check: bool) {
+    let mut timings = Timings::default();
+    let (source, program) = load_program(path, &mut timings);
+    let formatted = skyhetu::format::format_with_comments(&program, &source);
+    if formatted == source {
+        return;
+    }
+    if check {
+        eprintln!("{}: {} is not formatted", "error".red(), path);
+        process::exit(1);
+    }
+    // Never replace a file with something that no longer parses
+    let reparsed = Lexer::new(&formatted).tokenize().and_then(|tokens| Parser::new(tokens).parse());
+    if let Err(e) = reparsed {
+        eprintln!("{}: formatting {} produced invalid source: {}", "error".red(), path, e);
+        process::exit(1);
+    }
+    if let Err(e) = fs::write(path, formatted) {
+        eprintln!("{}: cannot write file '{}': {}", "error".red(), path, e);
+        process::exit(1);
+    }
+}
+
+fn debug_file(path: &str) {
+    let mut timings = Timings::default();
+    let (source, program) = load_program(path, &mut timings);
     
     let mut vm = skyhetu::vm::VM::new();
+    vm.enable_io(true);
+    let mut compiler = vm.program_compiler(module_base_path(path));
+    // Debug info names each function's locals for `print`
+    compiler.set_debug_info(true);
+    let chunk = match compiler.compile(&program, &mut vm.heap) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            eprintln!("{}", e.with_source(&source));
+            process::exit(1);
+        }
+    };
+    print_warnings(compiler.warnings());
     
-    // Get the base path for module resolution
-    let base_path = std::path::Path::new(path)
-        .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    let mut rl = DefaultEditor::new().expect("Failed to create debugger prompt");
+    let lines: Vec<&str> = source.lines().collect();
+    let breakpoints = vm.breakpoints();
+    let mut hook = |vm: &skyhetu::vm::VM, event: skyhetu::DebugEvent| {
+        let text = lines.get(event.line.wrapping_sub(1)).map_or("", |l| l.trim());
+        let marker = if event.at_breakpoint { "breakpoint ".yellow() } else { "".normal() };
+        println!("{}{} {}", marker, format!("[line {}]", event.line).cyan(), text);
+        loop {
+            let input = match rl.readline(&format!("{} ", "(debug)".green().bold())) {
+                Ok(input) => input,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => process::exit(0),
+                Err(e) => {
+                    eprintln!("{}: {}", "error".red(), e);
+                    process::exit(1);
+                }
+            };
+            let _ = rl.add_history_entry(input.trim());
+            let (command, arg) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+            match (command, arg.trim()) {
+                ("step" | "s" | "", _) => return skyhetu::DebugAction::Pause,
+                ("next" | "n", _) => return skyhetu::DebugAction::StepOver,
+                ("continue" | "c", _) => return skyhetu::DebugAction::Continue,
+                ("print" | "p", name) if !name.is_empty() => match vm.get_local(name).or_else(|| vm.get_global(name)) {
+                    Some(value) => println!("{} = {}", name, skyhetu::inspect::render(&vm.heap, &value)),
+                    None => println!("{}", format!("no variable named '{}' here", name).dimmed()),
+                },
+                ("stack", _) => {
+                    for (i, value) in vm.stack().iter().enumerate() {
+                        println!("  {:>3}  {}", i, skyhetu::inspect::render(&vm.heap, value));
+                    }
+                }
+                ("frames", _) => {
+                    let mut frames = vm.stack_trace();
+                    // The trace reports where each call last was; the innermost
+                    // one is about to run `event.line`
+                    if let Some(innermost) = frames.first_mut() {
+                        let call = innermost.rsplit_once(" (line ").map_or(innermost.as_str(), |(call, _)| call);
+                        *innermost = format!("{} (line {})", call, event.line);
+                    }
+                    for frame in frames {
+                        println!("  {}", frame);
+                    }
+                }
+                ("break" | "b", line) => match line.parse::<usize>() {
+                    Ok(line) if breakpoints.add(line) => println!("breakpoint set on line {}", line),
+                    Ok(line) => println!("{}", format!("line {} already has a breakpoint", line).dimmed()),
+                    Err(_) => println!("breakpoints: {:?}", breakpoints.lines()),
+                },
+                ("quit" | "q", _) => process::exit(0),
+                _ => {
+                    println!("  step (s, or empty)  run one instruction");
+                    println!("  next (n)            run to the next line, stepping over calls");
+                    println!("  continue (c)        run to the next breakpoint");
+                    println!("  print <name> (p)    show a local of the current call, or a global");
+                    println!("  stack               show the value stack");
+                    println!("  frames              show the active calls");
+                    println!("  break <line> (b)    stop when that line is reached; no line lists them");
+                    println!("  quit (q)            stop the program");
+                }
+            }
+        }
+    };
+    match vm.run_with_hook(chunk, &mut hook) {
+        Ok(value) => println!("{} {}", "program finished =>".dimmed(), value.display(&vm.heap).cyan()),
+        Err(e) => {
+            eprintln!("{}", e.with_source(&source));
+            process::exit(1);
+        }
+    }
+}
+
+fn run_file(path: &str, options: &RunOptions) {
+    let mut timings = Timings::default();
+    let mut bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: cannot read file '{}': {}", "error".red(), path, e);
+            process::exit(1);
+        }
+    };
+    // Built files carry no source, so their errors show line numbers only
+    let (source, program) = if skyhetu::bytecode::is_bytecode(&bytes) {
+        (None, None)
+    } else {
+        let Ok(source) = String::from_utf8(std::mem::take(&mut bytes)) else {
+            eprintln!("{}: '{}' is neither UTF-8 source nor a .skyc file", "error".red(), path);
+            process::exit(1);
+        };
+        let program = parse_source(&source, &mut timings);
+        (Some(source), Some(program))
+    };
+    let excerpt = |e: skyhetu::SkyHetuError| match &source {
+        Some(source) => e.with_source(source),
+        None => e,
+    };
     
-    let mut compiler = skyhetu::compiler::Compiler::with_base_path(base_path);
-    let (chunk, chunks) = match compiler.compile(&program, &mut vm.heap) {
+    let start = Instant::now();
+    let mut vm = if options.deterministic {
+        skyhetu::vm::VM::deterministic(0)
+    } else {
+        skyhetu::vm::VM::new()
+    };
+    if options.collect_asserts {
+        vm.set_assert_mode(skyhetu::vm::AssertMode::Collect);
+    }
+    vm.enable_io(true);
+    vm.set_args(options.script_args.clone());
+    
+    let mut warnings = Vec::new();
+    let chunk = match &program {
+        Some(program) => {
+            let mut compiler = vm.program_compiler(module_base_path(path));
+            compiler.set_debug_info(options.debug_info);
+            compiler.set_optimize(!options.no_optimize);
+            let compiled = compiler.compile(program, &mut vm.heap);
+            timings.modules = compiler.module_timings().to_vec();
+            if compiled.is_ok() && !options.quiet {
+                warnings = compiler.warnings().to_vec();
+                print_warnings(&warnings);
+            }
+            compiled
+        }
+        None => skyhetu::Chunk::deserialize(&bytes, &mut vm.heap),
+    };
+    let chunk = match chunk {
         Ok(c) => c,
         Err(e) => {
-            let err = e.with_source(&source);
-            eprintln!("{}", err);
+            eprintln!("{}", excerpt(e));
             process::exit(1);
         }
     };
+    timings.compile = start.elapsed();
+    
+    let start = Instant::now();
+    vm.set_max_heap(options.max_heap);
+    vm.set_fuel(options.max_instructions);
+    let result = vm.run(chunk);
+    timings.execute = start.elapsed();
+    timings.gc = vm.gc_time();
+    if options.timings {
+        eprintln!("{}", timings);
+    }
+    if let Some(trace) = &options.trace {
+        if let Err(e) = fs::write(trace, vm.causality.export_all_json()) {
+            eprintln!("{}: cannot write trace '{}': {}", "error".red(), trace, e);
+        }
+    }
+    if let Some(graph) = &options.graph {
+        if let Err(e) = fs::write(graph, vm.causality.to_dot_all()) {
+            eprintln!("{}: cannot write graph '{}': {}", "error".red(), graph, e);
+        }
+    }
+    if let Err(e) = result {
+        // An undefined global the compiler already warned about isn't repeated
+        if !warnings.iter().any(|w| e.repeats(w)) {
+            eprintln!("{}", excerpt(e));
+        }
+        process::exit(1);
+    }
+    
+    let failures = vm.assert_failures();
+    if !failures.is_empty() {
+        for failure in failures {
+            eprintln!("{}: {}", "assertion failed".red(), failure);
+        }
+        eprintln!("{} assertion(s) failed", failures.len());
+        process::exit(1);
+    }
+}
 
-    vm.register_chunks(chunks);
+fn test_path(path: &str) {
+    let report = match skyhetu::testing::run_tests(std::path::Path::new(path)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: cannot read tests in '{}': {}", "error".red(), path, e);
+            process::exit(1);
+        }
+    };
     
-    if let Err(e) = vm.run(chunk) {
-        let err = e.with_source(&source);
-        eprintln!("{}", err);
+    let mut file = None;
+    for result in &report.results {
+        if file != Some(&result.file) {
+            println!("{}", result.file.display());
+            file = Some(&result.file);
+        }
+        if result.passed() {
+            println!("  {} {}", "ok  ".green(), result.name);
+        } else {
+            println!("  {} {}", "FAIL".red(), result.name);
+        }
+    }
+    
+    for result in report.failures() {
+        eprintln!("\n{} {} ({})", "failed:".red(), result.name, result.file.display());
+        if let Some(failure) = &result.failure {
+            eprintln!("{}", failure);
+        }
+        if !result.output.is_empty() {
+            eprintln!("{}", "output:".dimmed());
+            eprint!("{}", result.output);
+        }
+    }
+    
+    println!("\n{}", report.summary());
+    if report.failed() > 0 {
         process::exit(1);
     }
 }
 
-fn run_repl() {
+fn run_repl(preloads: &[String]) {
     println!("{} {} - {}", 
         "SkyHetu".cyan().bold(), 
         VERSION.cyan(),
@@ -137,12 +677,35 @@ fn run_repl() {
     let mut rl = DefaultEditor::new().expect("Failed to create REPL");
     
     // Persist VM state across REPL lines for globals and causality
-    let mut vm = skyhetu::vm::VM::new();
-    let mut chunk_count = 0;
+    let mut engine = repl_engine();
+    
+    for path in preloads {
+        if let Err(e) = engine.vm_mut().load_file(path) {
+            eprintln!("{}: cannot load '{}'", "error".red(), path);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    
+    // Lines of an unfinished declaration, waiting for its closing brackets
+    let mut pending = String::new();
     
     loop {
-        match rl.readline(&format!("{} ", "sky>".green().bold())) {
+        let prompt = if pending.is_empty() { "sky>".green().bold() } else { "...>".dimmed() };
+        match rl.readline(&format!("{} ", prompt)) {
             Ok(line) => {
+                if !pending.is_empty() {
+                    let _ = rl.add_history_entry(line.trim());
+                    pending.push('\n');
+                    pending.push_str(&line);
+                    if skyhetu::parser::is_incomplete(&pending) {
+                        continue;
+                    }
+                    let source = std::mem::take(&mut pending);
+                    eval_repl_input(&mut engine, &source);
+                    continue;
+                }
+                
                 let line = line.trim();
                 
                 if line.is_empty() {
@@ -162,8 +725,7 @@ fn run_repl() {
                         continue;
                     }
                     "clear" => {
-                        vm = skyhetu::vm::VM::new();
-                        chunk_count = 0;
+                        engine = repl_engine();
                         println!("{}", "State cleared.".dimmed());
                         continue;
                     }
@@ -174,56 +736,87 @@ fn run_repl() {
                     _ => {}
                 }
                 
-                // Tokenize
-                let mut lexer = Lexer::new(line);
-                let tokens = match lexer.tokenize() {
-                    Ok(t) => t,
-                    Err(e) => {
-                        let err = e.with_source(line);
-                        eprintln!("{}", format!("{}", err).red());
-                        continue;
+                if let Some(path) = line.strip_prefix(":load ") {
+                    let path = path.trim();
+                    match engine.vm_mut().load_file(path) {
+                        Ok(_) => println!("{}", format!("Loaded {}", path).dimmed()),
+                        Err(e) => eprintln!("{}", format!("{}", e).red()),
                     }
-                };
+                    continue;
+                }
                 
-                // Parse
-                let mut parser = Parser::new(tokens);
-                let program = match parser.parse() {
-                    Ok(p) => p,
-                    Err(e) => {
-                        let err = e.with_source(line);
-                        eprintln!("{}", format!("{}", err).red());
-                        continue;
+                let vm = engine.vm();
+                if line == ":env" {
+                    for (name, kind, value) in vm.globals_iter().filter(|(_, kind, _)| *kind != skyhetu::BindingKind::Native) {
+                        println!("{:<6} {} = {} {}", kind.to_string().dimmed(), name, skyhetu::inspect::render(&vm.heap, value).cyan(), format!("({})", value.type_name()).dimmed());
                     }
-                };
+                    continue;
+                }
                 
-                // Compile
-                let mut compiler = skyhetu::compiler::Compiler::with_offset(chunk_count);
-                let (chunk, chunks) = match compiler.compile(&program, &mut vm.heap) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        let err = e.with_source(line);
-                        eprintln!("{}", format!("{}", err).red());
-                        continue;
+                if line == ":log" || line.starts_with(":log ") {
+                    let name = line[":log".len()..].trim();
+                    let events: Vec<_> = if name.is_empty() {
+                        vm.causality.all_events().collect()
+                    } else {
+                        vm.causality.history(name)
+                    };
+                    if events.is_empty() {
+                        println!("{}", "No events recorded.".dimmed());
                     }
-                };
+                    for event in events {
+                        println!("{}", event);
+                    }
+                    continue;
+                }
                 
-                chunk_count += chunks.len();
-                vm.register_chunks(chunks);
+                if line == ":diff" || line.starts_with(":diff ") {
+                    let times: Vec<usize> = line[":diff".len()..].split_whitespace().filter_map(|t| t.parse().ok()).collect();
+                    let [from, to] = times[..] else {
+                        eprintln!("{}", "usage: :diff <t1> <t2>".red());
+                        continue;
+                    };
+                    let lines = vm.causality.diff_lines(from.min(to), from.max(to), &vm.heap);
+                    if lines.is_empty() {
+                        println!("{}", format!("No changes between t={} and t={}.", from.min(to), from.max(to)).dimmed());
+                    }
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                    continue;
+                }
                 
-                // Execute
-                match vm.run(chunk) {
-                    Ok(value) => {
-                        if !matches!(value, skyhetu::Value::Nil) {
-                            println!("{} {}", "=>".dimmed(), format!("{}", value).cyan());
-                        }
+                if let Some(setting) = line.strip_prefix(":optimize ") {
+                    match setting.trim() {
+                        "on" => engine.set_optimize(true),
+                        "off" => engine.set_optimize(false),
+                        _ => eprintln!("{}", "usage: :optimize on|off".red()),
                     }
-                    Err(e) => {
-                        let err = e.with_source(line);
-                        eprintln!("{}", format!("{}", err).red());
+                    continue;
+                }
+                
+                if let Some(expr) = line.strip_prefix(":disasm ") {
+                    disasm_repl_input(&mut engine, expr);
+                    continue;
+                }
+                
+                if let Some(expr) = line.strip_prefix(":inspect ") {
+                    match engine.eval(expr) {
+                        Ok(value) => println!("{}", skyhetu::inspect::describe(&engine.vm().heap, &value)),
+                        Err(e) => eprintln!("{}", format!("{}", e).red()),
                     }
+                    continue;
+                }
+                
+                if skyhetu::parser::is_incomplete(line) {
+                    pending = line.to_string();
+                    continue;
                 }
+                
+                eval_repl_input(&mut engine, line);
             }
             Err(ReadlineError::Interrupted) => {
+                // Ctrl-C abandons an unfinished declaration
+                pending.clear();
                 println!("{}", "^C".dimmed());
             }
             Err(ReadlineError::Eof) => {
@@ -238,12 +831,68 @@ fn run_repl() {
     }
 }
 
+/// A fresh REPL session: reports redefinitions and may use files and stdin
+fn repl_engine() -> skyhetu::Engine {
+    let mut engine = skyhetu::Engine::new();
+    engine.vm_mut().set_repl_mode(true);
+    engine.vm_mut().enable_io(true);
+    engine
+}
+
+/// Compile a REPL entry without running it and print its bytecode
+fn disasm_repl_input(engine: &mut skyhetu::Engine, source: &str) {
+    match engine.compile(source) {
+        Ok(chunk) => print!("{}", chunk.disassemble_all("input", &engine.vm().heap)),
+        Err(e) => eprintln!("{}", format!("{}", e).red()),
+    }
+}
+
+/// Compile and run one complete REPL entry, printing its value or error
+fn eval_repl_input(engine: &mut skyhetu::Engine, source: &str) {
+    // Warnings come before anything the entry prints
+    let chunk = match engine.compile(source) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            eprintln!("{}", format!("{}", e).red());
+            return;
+        }
+    };
+    print_warnings(engine.warnings());
+    
+    match engine.run(chunk) {
+        Ok(value) => {
+            if !matches!(value, skyhetu::Value::Nil) {
+                println!("{} {}", "=>".dimmed(), value.display(&engine.vm().heap).cyan());
+            }
+        }
+        Err(e) if engine.warnings().iter().any(|w| e.repeats(w)) => {}
+        Err(e) => {
+            let err = e.with_source(source);
+            eprintln!("{}", format!("{}", err).red());
+        }
+    }
+}
+
+fn print_warnings(warnings: &[skyhetu::Warning]) {
+    for warning in warnings {
+        eprintln!("{}", format!("{}", warning).yellow());
+    }
+}
+
 fn print_repl_help() {
     println!("{}", "REPL Commands:".yellow());
     println!("  exit, quit   Exit the REPL");
     println!("  clear        Clear state and causality history");
     println!("  history      Show all state mutations");
-    println!("  help         Show this help\n");
+    println!("  :load <file> Run a file, keeping its definitions");
+    println!("  :inspect <expr>  Describe the value of an expression");
+    println!("  :env         List globals with their kind and value");
+    println!("  :log [name]  Show every causality event, or one variable's");
+    println!("  :diff <t1> <t2>  Show state that changed between two logical times");
+    println!("  :disasm <code>  Show the bytecode of code without running it");
+    println!("  :optimize on|off  Precompute constants and drop dead branches (default on)");
+    println!("  help         Show this help");
+    println!("  Ctrl-C       Discard an unfinished multi-line entry\n");
     println!("{}", "Language Examples:".yellow());
     println!("  let x = 10");
     println!("  state counter = 0");
@@ -0,0 +1,81 @@
+//! Read-only observation of a running VM from other threads
+//!
+//! The VM is single-threaded, so observers never touch it directly. When
+//! observation is enabled the execute loop publishes a [`Snapshot`] into
+//! shared storage every `interval` instructions, and an [`Observer`] handle
+//! reads the most recent one.
+
+use std::sync::{Arc, RwLock};
+use crate::causality::VarSummary;
+
+/// A consistent view of the VM taken at an instruction boundary
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Logical clock of the causality log
+    pub logical_time: usize,
+    
+    /// Instructions executed since observation was enabled
+    pub instructions: u64,
+    
+    /// Approximate bytes held by live heap objects
+    pub heap_bytes: usize,
+    
+    /// Number of live heap objects
+    pub heap_objects: usize,
+    
+    /// Causality summary of every tracked variable, sorted by name
+    pub summaries: Vec<(String, VarSummary)>,
+}
+
+/// Cheaply cloned, `Send` handle to the latest published snapshot
+#[derive(Debug, Clone)]
+pub struct Observer(Arc<RwLock<Snapshot>>);
+
+impl Observer {
+    /// The most recently published snapshot
+    pub fn snapshot(&self) -> Snapshot {
+        // A panicking publisher can't leave a half-written snapshot behind, so poisoning is harmless
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// VM-side publication state
+#[derive(Debug)]
+pub(crate) struct Observation {
+    shared: Arc<RwLock<Snapshot>>,
+    interval: u64,
+    until_publish: u64,
+    pub(crate) instructions: u64,
+}
+
+impl Observation {
+    pub(crate) fn new(interval: u64) -> Self {
+        let interval = interval.max(1);
+        Self {
+            shared: Arc::new(RwLock::new(Snapshot::default())),
+            interval,
+            until_publish: interval,
+            instructions: 0,
+        }
+    }
+    
+    pub(crate) fn observer(&self) -> Observer {
+        Observer(Arc::clone(&self.shared))
+    }
+    
+    /// Count one instruction; true when a snapshot is due
+    pub(crate) fn tick(&mut self) -> bool {
+        self.instructions += 1;
+        self.until_publish -= 1;
+        if self.until_publish == 0 {
+            self.until_publish = self.interval;
+            true
+        } else {
+            false
+        }
+    }
+    
+    pub(crate) fn publish(&self, snapshot: Snapshot) {
+        *self.shared.write().unwrap_or_else(|e| e.into_inner()) = snapshot;
+    }
+}
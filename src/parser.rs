@@ -4,28 +4,70 @@
 
 use crate::ast::{BinaryOp, Expr, LogicalOp, Program, Stmt, UnaryOp};
 use crate::error::{ErrorKind, Result, SkyHetuError};
+use crate::lexer::Lexer;
 use crate::token::{Span, Token, TokenKind};
 
+/// A parameter list: every name, those marked `state`, and each one's type annotation
+type Parameters = (Vec<String>, Vec<String>, Vec<Option<String>>);
+
 /// The parser state
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Whether to record statement errors and carry on, rather than stop
+    recovering: bool,
+    errors: Vec<SkyHetuError>,
 }
 
 impl Parser {
     /// Create a new parser from tokens
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self { tokens, current: 0, recovering: false, errors: Vec::new() }
     }
     
-    /// Parse the tokens into a program
+    /// Parse the tokens into a program, stopping at the first error
     pub fn parse(&mut self) -> Result<Program> {
+        self.recovering = false;
+        self.program()
+    }
+    
+    /// Parse the tokens into a program, skipping past each statement that
+    /// fails to parse so that every syntax error is reported at once
+    pub fn parse_all(&mut self) -> std::result::Result<Program, Vec<SkyHetuError>> {
+        let (program, errors) = self.parse_recovering();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+    
+    /// Like `parse_all`, but also returns the statements that did parse
+    pub fn parse_recovering(&mut self) -> (Program, Vec<SkyHetuError>) {
+        self.recovering = true;
+        self.errors.clear();
+        // Errors are all recorded when recovering, so this never fails
+        let program = self.program().unwrap_or_else(|e| {
+            self.errors.push(e);
+            Program::new(Vec::new())
+        });
+        (program, std::mem::take(&mut self.errors))
+    }
+    
+    fn program(&mut self) -> Result<Program> {
         let mut statements = Vec::new();
         
         while !self.is_at_end() {
-            self.skip_newlines();
+            self.skip_separators();
             if !self.is_at_end() {
-                statements.push(self.declaration()?);
+                let start = self.current;
+                match self.declaration() {
+                    Ok(stmt) => {
+                        self.end_statement_or_record(&stmt)?;
+                        statements.push(stmt);
+                    }
+                    Err(e) => self.recover(e, start)?,
+                }
             }
         }
         
@@ -39,7 +81,10 @@ impl Parser {
             self.let_declaration()
         } else if self.check(&TokenKind::State) {
             self.state_declaration()
-        } else if self.check(&TokenKind::Fn) {
+        } else if self.check(&TokenKind::Const) {
+            self.const_declaration()
+        } else if self.check(&TokenKind::Fn) && !matches!(self.peek_next().map(|t| &t.kind), Some(TokenKind::LeftParen)) {
+            // `fn(` starts a lambda expression statement
             self.function_declaration()
         } else if self.check(&TokenKind::Class) {
             self.class_declaration()
@@ -78,17 +123,53 @@ impl Parser {
         Ok(Stmt::State { name, value, span })
     }
     
+    fn const_declaration(&mut self) -> Result<Stmt> {
+        let span = self.advance().span; // consume 'const'
+        
+        let name = self.expect_ident("expected constant name")?;
+        
+        self.expect(&TokenKind::Equal, "expected '=' after constant name")?;
+        
+        let value = self.expression()?;
+        self.skip_newlines();
+        
+        Ok(Stmt::Const { name, value, span })
+    }
+    
     fn function_declaration(&mut self) -> Result<Stmt> {
         let span = self.advance().span; // consume 'fn'
         
         let name = self.expect_ident("expected function name")?;
         
         self.expect(&TokenKind::LeftParen, "expected '(' after function name")?;
+        let (params, state_params, param_types) = self.parameters()?;
         
+        self.skip_newlines();
+        self.expect(&TokenKind::LeftBrace, "expected '{' before function body")?;
+        
+        let body = self.block_statements()?;
+        
+        Ok(Stmt::Function { name, params, state_params, param_types, body, span })
+    }
+    
+    /// Parse a parameter list after its '(': a, state b, c: number)
+    fn parameters(&mut self) -> Result<Parameters> {
         let mut params = Vec::new();
+        let mut state_params = Vec::new();
+        let mut param_types = Vec::new();
         if !self.check(&TokenKind::RightParen) {
             loop {
-                params.push(self.expect_ident("expected parameter name")?);
+                let is_state = self.match_token(&TokenKind::State);
+                let name = self.expect_ident("expected parameter name")?;
+                if is_state {
+                    state_params.push(name.clone());
+                }
+                param_types.push(if self.match_token(&TokenKind::Colon) {
+                    Some(self.expect_ident("expected type name after ':'")?)
+                } else {
+                    None
+                });
+                params.push(name);
                 if !self.match_token(&TokenKind::Comma) {
                     break;
                 }
@@ -96,19 +177,21 @@ impl Parser {
         }
         
         self.expect(&TokenKind::RightParen, "expected ')' after parameters")?;
-        
-        self.skip_newlines();
-        self.expect(&TokenKind::LeftBrace, "expected '{' before function body")?;
-        
-        let body = self.block_statements()?;
-        
-        Ok(Stmt::Function { name, params, body, span })
+        Ok((params, state_params, param_types))
     }
     
     fn class_declaration(&mut self) -> Result<Stmt> {
         let span = self.advance().span; // consume 'class'
         let name = self.expect_ident("expected class name")?;
         
+        let superclass = if self.match_token(&TokenKind::Colon) {
+            let span = self.peek().span;
+            let name = self.expect_ident("expected superclass name after ':'")?;
+            Some(Expr::Ident { name, span })
+        } else {
+            None
+        };
+        
         self.skip_newlines();
         self.expect(&TokenKind::LeftBrace, "expected '{' before class body")?;
         self.skip_newlines();
@@ -117,12 +200,12 @@ impl Parser {
         let mut methods = Vec::new();
         while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
             methods.push(self.method_declaration()?);
-            self.skip_newlines();
+            self.skip_separators();
         }
         
         self.expect(&TokenKind::RightBrace, "expected '}' after class body")?;
         
-        Ok(Stmt::Class { name, methods, span })
+        Ok(Stmt::Class { name, superclass, methods, span })
     }
     
     fn method_declaration(&mut self) -> Result<Stmt> {
@@ -130,25 +213,14 @@ impl Parser {
         let name = self.expect_ident("expected method name")?;
         
         self.expect(&TokenKind::LeftParen, "expected '(' after method name")?;
-        
-        let mut params = Vec::new();
-        if !self.check(&TokenKind::RightParen) {
-            loop {
-                params.push(self.expect_ident("expected parameter name")?);
-                if !self.match_token(&TokenKind::Comma) {
-                    break;
-                }
-            }
-        }
-        
-        self.expect(&TokenKind::RightParen, "expected ')' after parameters")?;
+        let (params, state_params, param_types) = self.parameters()?;
         
         self.skip_newlines();
         self.expect(&TokenKind::LeftBrace, "expected '{' before method body")?;
         
         let body = self.block_statements()?;
         
-        Ok(Stmt::Function { name, params, body, span })
+        Ok(Stmt::Function { name, params, state_params, param_types, body, span })
     }
     
     /// Parse import declaration: import { a, b } from "path"
@@ -186,7 +258,7 @@ impl Parser {
         Ok(Stmt::Import { names, path, span })
     }
     
-    /// Parse export declaration: export fn foo() { } or export let x = 1
+    /// Parse export declaration: export fn foo() { }, export let x = 1 or export const X = 1
     fn export_declaration(&mut self) -> Result<Stmt> {
         let span = self.advance().span; // consume 'export'
         
@@ -197,11 +269,13 @@ impl Parser {
             self.let_declaration()?
         } else if self.check(&TokenKind::State) {
             self.state_declaration()?
+        } else if self.check(&TokenKind::Const) {
+            self.const_declaration()?
         } else if self.check(&TokenKind::Class) {
             self.class_declaration()?
         } else {
             return Err(SkyHetuError::new(
-                ErrorKind::UnexpectedToken("expected fn, let, state, or class after export".to_string()),
+                ErrorKind::UnexpectedToken("expected fn, let, state, const, or class after export".to_string()),
                 Some(self.peek().span),
             ));
         };
@@ -214,6 +288,8 @@ impl Parser {
     fn statement(&mut self) -> Result<Stmt> {
         if self.check(&TokenKind::If) {
             self.if_statement()
+        } else if self.check(&TokenKind::Match) {
+            self.match_statement()
         } else if self.check(&TokenKind::While) {
             self.while_statement()
         } else if self.check(&TokenKind::For) {
@@ -253,6 +329,16 @@ impl Parser {
         }
         
         let expr = self.expression()?;
+        
+        // Property transition: obj.field -> expr
+        if let (true, Expr::Get { object, name, span }) = (self.check(&TokenKind::Arrow), &expr) {
+            let (object, name, span) = ((**object).clone(), name.clone(), *span);
+            self.advance(); // consume arrow
+            let value = self.expression()?;
+            self.skip_newlines();
+            return Ok(Stmt::PropertyTransition { object, name, value, span });
+        }
+        
         self.skip_newlines();
         Ok(Stmt::Expr { expr })
     }
@@ -263,10 +349,9 @@ impl Parser {
         let condition = self.expression()?;
         
         self.skip_newlines();
-        self.expect(&TokenKind::LeftBrace, "expected '{' after if condition")?;
-        
+        let then_span = self.expect(&TokenKind::LeftBrace, "expected '{' after if condition")?.span;
         let then_stmts = self.block_statements()?;
-        let then_branch = Box::new(Stmt::Block { stmts: then_stmts, span });
+        let then_branch = Box::new(Stmt::Block { stmts: then_stmts, span: then_span });
         
         self.skip_newlines();
         
@@ -275,9 +360,9 @@ impl Parser {
             if self.check(&TokenKind::If) {
                 Some(Box::new(self.if_statement()?))
             } else {
-                self.expect(&TokenKind::LeftBrace, "expected '{' after else")?;
+                let else_span = self.expect(&TokenKind::LeftBrace, "expected '{' after else")?.span;
                 let else_stmts = self.block_statements()?;
-                Some(Box::new(Stmt::Block { stmts: else_stmts, span }))
+                Some(Box::new(Stmt::Block { stmts: else_stmts, span: else_span }))
             }
         } else {
             None
@@ -286,6 +371,52 @@ impl Parser {
         Ok(Stmt::If { condition, then_branch, else_branch, span })
     }
     
+    fn match_statement(&mut self) -> Result<Stmt> {
+        let span = self.advance().span; // consume 'match'
+        
+        let subject = self.expression()?;
+        
+        self.skip_newlines();
+        self.expect(&TokenKind::LeftBrace, "expected '{' after match subject")?;
+        self.skip_separators();
+        
+        let mut arms = Vec::new();
+        let mut default = None;
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+            let is_default = matches!(&self.peek().kind, TokenKind::Ident(name) if name == "_")
+                && matches!(self.peek_next().map(|t| &t.kind), Some(TokenKind::FatArrow));
+            if is_default {
+                self.advance(); // consume '_'
+                self.advance(); // consume '=>'
+                default = Some(Box::new(self.arm_body()?));
+                self.skip_separators();
+                break;
+            }
+            
+            let mut patterns = vec![self.expression()?];
+            while self.match_token(&TokenKind::Pipe) {
+                self.skip_newlines();
+                patterns.push(self.expression()?);
+            }
+            self.expect(&TokenKind::FatArrow, "expected '=>' after match pattern")?;
+            arms.push((patterns, self.arm_body()?));
+            self.skip_separators();
+        }
+        
+        let closing = if default.is_some() { "expected '}' after the '_' arm, which must come last in a match" } else { "expected '}' after match arms" };
+        self.expect(&TokenKind::RightBrace, closing)?;
+        
+        Ok(Stmt::Match { subject, arms, default, span })
+    }
+    
+    /// The block run by a match arm
+    fn arm_body(&mut self) -> Result<Stmt> {
+        self.skip_newlines();
+        let span = self.expect(&TokenKind::LeftBrace, "expected '{' after '=>' in match arm")?.span;
+        let stmts = self.block_statements()?;
+        Ok(Stmt::Block { stmts, span })
+    }
+    
     fn while_statement(&mut self) -> Result<Stmt> {
         let span = self.advance().span; // consume 'while'
         
@@ -305,10 +436,9 @@ impl Parser {
         
         let var = self.expect_ident("expected variable name in for loop")?;
         
-        // Expect 'in' keyword (we'll use an identifier check)
-        if !matches!(self.peek().kind, TokenKind::Ident(ref s) if s == "in") {
+        if !matches!(self.peek().kind, TokenKind::In) {
             return Err(SkyHetuError::new(
-                ErrorKind::ExpectedToken("in".to_string(), format!("{}", self.peek().kind)),
+                ErrorKind::ExpectedToken("expected 'in' after loop variable".to_string(), format!("{}", self.peek().kind)),
                 Some(self.peek().span),
             ));
         }
@@ -328,7 +458,9 @@ impl Parser {
     fn return_statement(&mut self) -> Result<Stmt> {
         let span = self.advance().span; // consume 'return'
         
-        let value = if self.check(&TokenKind::Newline) || self.check(&TokenKind::RightBrace) || self.is_at_end() {
+        let value = if self.check(&TokenKind::Newline) || self.check(&TokenKind::Semicolon)
+            || self.check(&TokenKind::RightBrace) || self.is_at_end()
+        {
             None
         } else {
             Some(self.expression()?)
@@ -353,11 +485,17 @@ impl Parser {
     fn block_statements(&mut self) -> Result<Vec<Stmt>> {
         let mut stmts = Vec::new();
         
-        self.skip_newlines();
+        self.skip_separators();
         
         while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
-            stmts.push(self.declaration()?);
-            self.skip_newlines();
+            let start = self.current;
+            match self.declaration() {
+                Ok(stmt) => {
+                    self.end_statement_or_record(&stmt)?;
+                    stmts.push(stmt);
+                }
+                Err(e) => self.recover(e, start)?,
+            }
         }
         
         self.expect(&TokenKind::RightBrace, "expected '}' after block")?;
@@ -372,7 +510,7 @@ impl Parser {
     }
     
     fn assignment(&mut self) -> Result<Expr> {
-        let expr = self.or_expr()?;
+        let expr = self.conditional()?;
         
         if self.match_token(&TokenKind::Equal) {
             let equals = self.previous().clone();
@@ -387,6 +525,15 @@ impl Parser {
                 });
             }
             
+            if let Expr::Index { object, index, span } = expr {
+                return Ok(Expr::IndexSet {
+                    object,
+                    index,
+                    value: Box::new(value),
+                    span,
+                });
+            }
+            
             return Err(SkyHetuError::new(
                 ErrorKind::InvalidAssignment,
                 Some(equals.span),
@@ -396,6 +543,29 @@ impl Parser {
         Ok(expr)
     }
     
+    /// Parse a conditional: cond ? a : b, grouping to the right. It is
+    /// stored as the equivalent if-expression.
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.or_expr()?;
+        if !self.match_token(&TokenKind::Question) {
+            return Ok(condition);
+        }
+        self.skip_newlines();
+        let then_branch = self.conditional()?;
+        self.skip_newlines();
+        self.expect(&TokenKind::Colon, "expected ':' after the first branch of '?'")?;
+        self.skip_newlines();
+        let else_branch = self.conditional()?;
+        
+        let span = condition.span();
+        Ok(Expr::If {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Some(Box::new(else_branch)),
+            span,
+        })
+    }
+    
     fn or_expr(&mut self) -> Result<Expr> {
         let mut left = self.and_expr()?;
         
@@ -605,6 +775,16 @@ impl Parser {
                     name, 
                     span: Span::new(expr_span.start, dot_span.end, expr_span.line, expr_span.column) 
                 };
+            } else if self.match_token(&TokenKind::LeftBracket) {
+                let index = self.expression()?;
+                let end_span = self.peek().span;
+                self.expect(&TokenKind::RightBracket, "expected ']' after index")?;
+                let expr_span = expr.span();
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    span: Span::new(expr_span.start, end_span.end, expr_span.line, expr_span.column),
+                };
             } else {
                 break;
             }
@@ -618,7 +798,14 @@ impl Parser {
         
         if !self.check(&TokenKind::RightParen) {
             loop {
-                args.push(self.expression()?);
+                if self.match_token(&TokenKind::Ellipsis) {
+                    let start = self.previous().span;
+                    let expr = self.expression()?;
+                    let span = Span::new(start.start, expr.span().end, start.line, start.column);
+                    args.push(Expr::Spread { expr: Box::new(expr), span });
+                } else {
+                    args.push(self.expression()?);
+                }
                 if !self.match_token(&TokenKind::Comma) {
                     break;
                 }
@@ -683,6 +870,15 @@ impl Parser {
                     span: start_span,
                 })
             }
+            TokenKind::LeftBracket => self.array_literal(),
+            TokenKind::Fn => self.lambda(),
+            TokenKind::If => self.if_expression(),
+            TokenKind::Super => {
+                self.advance();
+                self.expect(&TokenKind::Dot, "expected '.' after 'super'")?;
+                let method = self.expect_ident("expected superclass method name")?;
+                Ok(Expr::Super { method, span: token.span })
+            }
             _ => Err(SkyHetuError::new(
                 ErrorKind::ExpectedExpression,
                 Some(token.span),
@@ -690,6 +886,91 @@ impl Parser {
         }
     }
     
+    /// Parse a lambda: fn(a, b) => a + b, or fn(a, b) { ... } with a block body
+    fn lambda(&mut self) -> Result<Expr> {
+        let span = self.advance().span; // consume 'fn'
+        self.expect(&TokenKind::LeftParen, "expected '(' after 'fn' in lambda")?;
+        let (params, state_params, param_types) = self.parameters()?;
+        
+        let body = if self.match_token(&TokenKind::FatArrow) {
+            self.skip_newlines();
+            let value = self.expression()?;
+            vec![Stmt::Return { span: value.span(), value: Some(value) }]
+        } else {
+            self.skip_newlines();
+            self.expect(&TokenKind::LeftBrace, "expected '=>' or '{' after lambda parameters")?;
+            self.block_statements()?
+        };
+        
+        Ok(Expr::Lambda { params, state_params, param_types, body, span })
+    }
+    
+    /// Parse an if-expression: if cond { a } else { b }, where each branch is
+    /// a single expression
+    fn if_expression(&mut self) -> Result<Expr> {
+        let span = self.advance().span; // consume 'if'
+        let condition = self.expression()?;
+        self.skip_newlines();
+        let then_branch = self.branch_value()?;
+        
+        // Newlines before an `else` belong to this expression; otherwise they
+        // are left for whatever follows it
+        let mut ahead = self.current;
+        while self.tokens[ahead].kind == TokenKind::Newline {
+            ahead += 1;
+        }
+        let else_branch = if self.tokens[ahead].kind == TokenKind::Else {
+            self.current = ahead + 1;
+            self.skip_newlines();
+            if self.check(&TokenKind::If) {
+                Some(Box::new(self.if_expression()?))
+            } else {
+                Some(Box::new(self.branch_value()?))
+            }
+        } else {
+            None
+        };
+        
+        Ok(Expr::If { condition: Box::new(condition), then_branch: Box::new(then_branch), else_branch, span })
+    }
+    
+    /// One branch of an if-expression: `{ value }`, or `{}` for nil
+    fn branch_value(&mut self) -> Result<Expr> {
+        let open = self.expect(&TokenKind::LeftBrace, "expected '{' before the branch of an if expression")?.span;
+        self.skip_newlines();
+        if self.match_token(&TokenKind::RightBrace) {
+            return Ok(Expr::Nil { span: open });
+        }
+        let value = self.expression()?;
+        self.skip_newlines();
+        self.expect(&TokenKind::RightBrace, "expected '}' after the value of an if-expression branch")?;
+        Ok(value)
+    }
+    
+    /// Parse an array literal: [a, b, c], with an optional trailing comma
+    fn array_literal(&mut self) -> Result<Expr> {
+        let start = self.advance().span; // consume '['
+        let mut elements = Vec::new();
+        
+        self.skip_newlines();
+        while !self.check(&TokenKind::RightBracket) {
+            elements.push(self.expression()?);
+            self.skip_newlines();
+            if !self.match_token(&TokenKind::Comma) {
+                break;
+            }
+            self.skip_newlines();
+        }
+        
+        let end = self.peek().span;
+        self.expect(&TokenKind::RightBracket, "expected ']' after array elements")?;
+        
+        Ok(Expr::Array {
+            elements,
+            span: Span::new(start.start, end.end, start.line, start.column),
+        })
+    }
+    
     // ==================== Helpers ====================
     
     fn peek(&self) -> &Token {
@@ -755,12 +1036,127 @@ impl Parser {
     fn skip_newlines(&mut self) {
         while self.match_token(&TokenKind::Newline) {}
     }
+    
+    /// Skip what may separate statements: newlines and semicolons
+    fn skip_separators(&mut self) {
+        while self.match_token(&TokenKind::Newline) || self.match_token(&TokenKind::Semicolon) {}
+    }
+    
+    /// After a statement: skip its separators, and require one unless the
+    /// statement ends with its own block or the enclosing block ends here
+    fn end_statement(&mut self, stmt: &Stmt) -> Result<()> {
+        self.skip_separators();
+        let separated = matches!(self.previous().kind, TokenKind::Newline | TokenKind::Semicolon);
+        if separated || ends_with_block(stmt) || self.check(&TokenKind::RightBrace) || self.is_at_end() {
+            return Ok(());
+        }
+        Err(SkyHetuError::new(
+            ErrorKind::MissingSeparator(format!("{}", self.peek().kind)),
+            Some(self.peek().span),
+        ))
+    }
+    
+    /// Like `end_statement`, but a missing separator is only recorded when
+    /// recovering: the statement before it parsed, so nothing needs skipping
+    fn end_statement_or_record(&mut self, stmt: &Stmt) -> Result<()> {
+        match self.end_statement(stmt) {
+            Err(e) if self.recovering => {
+                self.record(e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+    
+    /// Handle an error from the statement starting at token `start`: pass it
+    /// on, or when recovering record it and skip to where the next statement
+    /// should begin
+    fn recover(&mut self, mut error: SkyHetuError, start: usize) -> Result<()> {
+        if self.is_at_end() {
+            // The token found is always the end of input, which the kind already says
+            let message = match &error.kind {
+                ErrorKind::UnexpectedEof(_) => None,
+                ErrorKind::ExpectedToken(expected, _) => Some(expected.clone()),
+                kind => Some(kind.to_string()),
+            };
+            if let Some(message) = message {
+                error.kind = ErrorKind::UnexpectedEof(message);
+            }
+        }
+        if !self.recovering {
+            return Err(error);
+        }
+        self.record(error);
+        self.synchronize(start);
+        self.skip_separators();
+        Ok(())
+    }
+    
+    /// Keep an error, unless it is at the same place as the last one: blocks
+    /// cut short by the end of input would otherwise each report it again
+    fn record(&mut self, error: SkyHetuError) {
+        let repeated = self.errors.last().is_some_and(|last| last.span == error.span);
+        if !repeated {
+            self.errors.push(error);
+        }
+    }
+    
+    /// Skip tokens up to a separator or statement keyword outside any braces,
+    /// or up to the `}` closing the block the statement was in
+    fn synchronize(&mut self, start: usize) {
+        // Always move past the token the failed statement started at
+        if self.current == start {
+            self.advance();
+        }
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek().kind {
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace if depth == 0 => return,
+                TokenKind::RightBrace => depth -= 1,
+                TokenKind::Newline | TokenKind::Semicolon
+                | TokenKind::Let | TokenKind::State | TokenKind::Fn | TokenKind::If
+                | TokenKind::While | TokenKind::For | TokenKind::Class | TokenKind::Return
+                    if depth == 0 => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+}
+
+/// Whether a statement's syntax closes with a block of its own, so that the
+/// next statement may follow on the same line
+fn ends_with_block(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Function { .. } | Stmt::Class { .. } | Stmt::If { .. } | Stmt::Match { .. }
+        | Stmt::While { .. } | Stmt::For { .. } | Stmt::Block { .. } => true,
+        Stmt::Export { stmt, .. } => ends_with_block(stmt),
+        _ => false,
+    }
+}
+
+/// Whether `source` is the beginning of a program that continues on later
+/// lines: it runs out before a `(`, `[` or `{` is closed. The REPL keeps
+/// reading lines while this holds.
+pub fn is_incomplete(source: &str) -> bool {
+    let Ok(tokens) = Lexer::new(source).tokenize() else {
+        return false;
+    };
+    let depth = tokens.iter().fold(0i64, |depth, token| match token.kind {
+        TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => depth + 1,
+        TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket => depth - 1,
+        _ => depth,
+    });
+    depth > 0 && matches!(
+        Parser::new(tokens).parse(),
+        Err(SkyHetuError { kind: ErrorKind::UnexpectedEof(_), .. })
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::Lexer;
     
     fn parse(source: &str) -> Program {
         let mut lexer = Lexer::new(source);
@@ -799,6 +1195,34 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_property_transition() {
+        let program = parse("this.count -> this.count + 1");
+        match &program.statements[0] {
+            Stmt::PropertyTransition { object: Expr::Ident { name: object, .. }, name, .. } => {
+                assert_eq!(object, "this");
+                assert_eq!(name, "count");
+            }
+            _ => panic!("expected property transition"),
+        }
+    }
+    
+    #[test]
+    fn test_lambda() {
+        let program = parse("let add = fn(a, b) => a + b\nfn(x) {\n  return x\n}(1)");
+        match &program.statements[0] {
+            Stmt::Let { value: Expr::Lambda { params, body, .. }, .. } => {
+                assert_eq!(params, &["a", "b"]);
+                assert!(matches!(body.as_slice(), [Stmt::Return { value: Some(Expr::Binary { .. }), .. }]));
+            }
+            _ => panic!("expected lambda"),
+        }
+        match &program.statements[1] {
+            Stmt::Expr { expr: Expr::Call { callee, .. } } => assert!(matches!(**callee, Expr::Lambda { .. })),
+            _ => panic!("expected a called lambda"),
+        }
+    }
+    
     #[test]
     fn test_function() {
         let program = parse("fn add(a, b) { return a + b }");
@@ -829,4 +1253,87 @@ mod tests {
         let program = parse("1 + 2 * 3");
         assert_eq!(program.statements.len(), 1);
     }
+    
+    #[test]
+    fn test_index_assignment() {
+        let program = parse("arr[i + 1] = 5");
+        match &program.statements[0] {
+            Stmt::Expr { expr: Expr::IndexSet { object, index, .. } } => {
+                assert!(matches!(object.as_ref(), Expr::Ident { name, .. } if name == "arr"));
+                assert!(matches!(index.as_ref(), Expr::Binary { .. }));
+            }
+            other => panic!("expected index assignment, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_index_chaining() {
+        let program = parse("matrix[i][j]\nf()[0]");
+        match &program.statements[0] {
+            Stmt::Expr { expr: Expr::Index { object, index, .. } } => {
+                assert!(matches!(object.as_ref(), Expr::Index { .. }));
+                assert!(matches!(index.as_ref(), Expr::Ident { name, .. } if name == "j"));
+            }
+            other => panic!("expected nested index, got {:?}", other),
+        }
+        match &program.statements[1] {
+            Stmt::Expr { expr: Expr::Index { object, .. } } => {
+                assert!(matches!(object.as_ref(), Expr::Call { .. }));
+            }
+            other => panic!("expected index of call, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_array_literal() {
+        let lengths = |source: &str| match &parse(source).statements[0] {
+            Stmt::Expr { expr: Expr::Array { elements, .. } } => elements.len(),
+            other => panic!("expected array literal, got {:?}", other),
+        };
+        assert_eq!(lengths("[]"), 0);
+        assert_eq!(lengths("[1, 2, 3]"), 3);
+        assert_eq!(lengths("[1, 2,]"), 2);
+        assert_eq!(lengths("[[1, 2], [3, 4]]"), 2);
+        assert_eq!(lengths("[\n  1,\n  2,\n]"), 2);
+    }
+    
+    #[test]
+    fn test_eof_inside_block_is_reported_as_such() {
+        let tokens = Lexer::new("fn add(a, b) {\n  return a + b").tokenize().unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::UnexpectedEof(_)), "{:?}", err.kind);
+        assert!(err.to_string().contains("unexpected end of input"), "{}", err);
+        
+        let tokens = Lexer::new("let x = )").tokenize().unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert!(!matches!(err.kind, ErrorKind::UnexpectedEof(_)), "{:?}", err.kind);
+    }
+    
+    #[test]
+    fn test_else_if_blocks_have_their_own_spans() {
+        let program = parse("if a {\n  1\n} else if b {\n  2\n}\nelse {\n  3\n}");
+        let Stmt::If { then_branch, else_branch: Some(else_if), .. } = &program.statements[0] else {
+            panic!("expected if statement");
+        };
+        assert!(matches!(**then_branch, Stmt::Block { span, .. } if span.line == 1));
+        let Stmt::If { then_branch, else_branch: Some(last), span, .. } = &**else_if else {
+            panic!("expected else-if");
+        };
+        assert_eq!(span.line, 3);
+        assert!(matches!(**then_branch, Stmt::Block { span, .. } if span.line == 3));
+        assert!(matches!(**last, Stmt::Block { span, .. } if span.line == 6));
+    }
+    
+    #[test]
+    fn test_semicolons_separate_statements() {
+        assert_eq!(parse("let a = 1; let b = 2; print(a + b)").statements.len(), 3);
+        assert_eq!(parse(";;let a = 1;\n\n;let b = 2;;").statements.len(), 2);
+        assert_eq!(parse("fn f() { return; }\nif true { 1 } f()").statements.len(), 3);
+        
+        let tokens = Lexer::new("let a = 1\nlet b = 2 print(b)").tokenize().unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert!(matches!(&err.kind, ErrorKind::MissingSeparator(t) if t == "print"), "{:?}", err.kind);
+        let span = err.span.unwrap();
+        assert_eq!((span.line, span.column), (2, 11));
+    }
 }
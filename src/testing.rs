@@ -0,0 +1,163 @@
+//! The test runner behind `skyhetu test`
+//!
+//! A test file is a `*_test.skyh` file, and each of its top-level functions
+//! whose name starts with `test_` is a test. Every test runs on a fresh,
+//! deterministic VM: the whole file runs first, so its definitions and setup
+//! code are in place, and then the test function is called. A test passes
+//! when that finishes without an error; a failing `assert` is such an error.
+
+use std::path::{Path, PathBuf};
+use crate::ast::{Expr, Program, Stmt};
+use crate::error::{ErrorKind, Result, SkyHetuError};
+use crate::vm::{CaptureBuffer, VM};
+
+/// Suffix that marks a file as holding tests
+pub const TEST_FILE_SUFFIX: &str = "_test.skyh";
+
+/// Prefix that marks a top-level function as a test
+pub const TEST_FN_PREFIX: &str = "test_";
+
+/// How one test went
+#[derive(Debug)]
+pub struct TestResult {
+    pub file: PathBuf,
+    /// The test function, or `<file>` when the file itself failed to load
+    pub name: String,
+    /// The error that failed the test, with an excerpt of the file
+    pub failure: Option<SkyHetuError>,
+    /// What the test printed
+    pub output: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Results of every test found under a path, file by file in path order
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// One line counting passes and failures, e.g. `2 passed, 1 failed`
+    pub fn summary(&self) -> String {
+        format!("{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+/// Run every test in `path`: a test file, or a directory searched recursively
+/// for test files
+pub fn run_tests(path: &Path) -> std::io::Result<TestReport> {
+    let mut report = TestReport::default();
+    for file in find_test_files(path)? {
+        report.results.extend(run_test_file(&file));
+    }
+    Ok(report)
+}
+
+/// `path` itself when it is a file, otherwise the `*_test.skyh` files below it, sorted
+pub fn find_test_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                dirs.push(entry_path);
+            } else if entry_path.to_string_lossy().ends_with(TEST_FILE_SUFFIX) {
+                files.push(entry_path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Run each test function of one file on its own VM
+pub fn run_test_file(path: &Path) -> Vec<TestResult> {
+    let loaded = std::fs::read_to_string(path)
+        .map_err(|e| SkyHetuError::new(
+            ErrorKind::RuntimeError(format!("cannot read file '{}': {}", path.display(), e)),
+            None,
+        ))
+        .and_then(|source| {
+            let program = parse(&source).map_err(|e| e.with_source(&source))?;
+            Ok((source, program))
+        });
+    let (source, program) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            return vec![TestResult { file: path.to_path_buf(), name: "<file>".to_string(), failure: Some(e), output: String::new() }];
+        }
+    };
+
+    test_functions(&program)
+        .into_iter()
+        .map(|(name, span)| {
+            let output = CaptureBuffer::new();
+            let failure = run_one(path, &program, &name, span, output.clone())
+                .err()
+                .map(|e| e.with_source(&source));
+            TestResult { file: path.to_path_buf(), name, failure, output: output.contents() }
+        })
+        .collect()
+}
+
+fn parse(source: &str) -> Result<Program> {
+    let tokens = crate::lexer::Lexer::new(source).tokenize()?;
+    crate::parser::Parser::new(tokens).parse()
+}
+
+/// Top-level `test_` functions, exported or not, in source order
+fn test_functions(program: &Program) -> Vec<(String, crate::token::Span)> {
+    program.statements.iter()
+        .map(|stmt| match stmt {
+            Stmt::Export { stmt, .. } => stmt,
+            _ => stmt,
+        })
+        .filter_map(|stmt| match stmt {
+            Stmt::Function { name, span, .. } if name.starts_with(TEST_FN_PREFIX) => Some((name.clone(), *span)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Run the file with a call to `name` appended. The call is placed at the
+/// function's declaration, which is the line a stack trace shows for it.
+fn run_one(path: &Path, program: &Program, name: &str, span: crate::token::Span, output: CaptureBuffer) -> Result<()> {
+    let mut program = program.clone();
+    program.statements.push(Stmt::Expr {
+        expr: Expr::Call {
+            callee: Box::new(Expr::Ident { name: name.to_string(), span }),
+            args: Vec::new(),
+            span,
+        },
+    });
+
+    let base = path.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| PathBuf::from("."));
+    let mut vm = VM::deterministic(0);
+    vm.enable_io(true);
+    vm.set_base_path(base.clone());
+    vm.set_output(Box::new(output));
+    let mut compiler = vm.program_compiler(base);
+    let chunk = compiler.compile(&program, &mut vm.heap)?;
+    vm.run(chunk).map(|_| ())
+}
@@ -0,0 +1,49 @@
+//! Wall-clock time spent in each phase of running a program
+//!
+//! Phases are timed once around each stage, never per instruction, so
+//! collecting timings costs the same whether or not anyone reads them.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Time spent lexing, parsing, compiling and executing one program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    pub lex: Duration,
+    pub parse: Duration,
+    /// Compiling, including reading and compiling imported modules
+    pub compile: Duration,
+    /// Running the bytecode, including garbage collection
+    pub execute: Duration,
+    /// Garbage collection pauses during execution
+    pub gc: Duration,
+    /// Reading, parsing and compiling each imported module (and the modules it
+    /// imports), by path, in the order they were first imported
+    pub modules: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    /// Sum of the lex, parse, compile and execute phases
+    pub fn total(&self) -> Duration {
+        self.lex + self.parse + self.compile + self.execute
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timings: lex {}, parse {}, compile {}, execute {} (gc {}), total {}",
+            millis(self.lex), millis(self.parse), millis(self.compile),
+            millis(self.execute), millis(self.gc), millis(self.total())
+        )?;
+        for (path, duration) in &self.modules {
+            write!(f, "\n  module {}: {}", path, millis(*duration))?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -35,6 +35,7 @@ pub enum TokenKind {
     // Keywords
     Let,        // immutable binding
     State,      // mutable state
+    Const,      // compile-time constant
     Fn,         // function definition
     Return,     // return from function
     If,         // conditional
@@ -44,10 +45,12 @@ pub enum TokenKind {
     Break,      // break out of loop
     Continue,   // continue to next iteration
     Class,      // class definition
+    Super,      // superclass method access
     Import,     // import from module
     Export,     // export from module
     From,       // import ... from "path"
     In,         // for x in iterable
+    Match,      // match value { ... }
     
     // Operators
     Plus,       // +
@@ -72,7 +75,8 @@ pub enum TokenKind {
     
     // Special
     Arrow,      // -> (state transition)
-    FatArrow,   // => (for future use)
+    FatArrow,   // => (lambda bodies, match arms)
+    Pipe,       // | (alternative match patterns)
     
     // Delimiters
     LeftParen,  // (
@@ -84,7 +88,9 @@ pub enum TokenKind {
     Comma,      // ,
     Semicolon,  // ;
     Colon,      // :
+    Question,   // ?
     Dot,        // .
+    Ellipsis,   // ...
     
     // Special tokens
     Newline,    // line separator
@@ -101,6 +107,7 @@ impl fmt::Display for TokenKind {
             TokenKind::False => write!(f, "false"),
             TokenKind::Nil => write!(f, "nil"),
             TokenKind::Let => write!(f, "let"),
+            TokenKind::Const => write!(f, "const"),
             TokenKind::State => write!(f, "state"),
             TokenKind::Fn => write!(f, "fn"),
             TokenKind::Return => write!(f, "return"),
@@ -111,10 +118,12 @@ impl fmt::Display for TokenKind {
             TokenKind::Break => write!(f, "break"),
             TokenKind::Continue => write!(f, "continue"),
             TokenKind::Class => write!(f, "class"),
+            TokenKind::Super => write!(f, "super"),
             TokenKind::Import => write!(f, "import"),
             TokenKind::Export => write!(f, "export"),
             TokenKind::From => write!(f, "from"),
             TokenKind::In => write!(f, "in"),
+            TokenKind::Match => write!(f, "match"),
             TokenKind::Plus => write!(f, "+"),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Star => write!(f, "*"),
@@ -132,6 +141,7 @@ impl fmt::Display for TokenKind {
             TokenKind::Bang => write!(f, "!"),
             TokenKind::Arrow => write!(f, "->"),
             TokenKind::FatArrow => write!(f, "=>"),
+            TokenKind::Pipe => write!(f, "|"),
             TokenKind::LeftParen => write!(f, "("),
             TokenKind::RightParen => write!(f, ")"),
             TokenKind::LeftBrace => write!(f, "{{"),
@@ -141,7 +151,9 @@ impl fmt::Display for TokenKind {
             TokenKind::Comma => write!(f, ","),
             TokenKind::Semicolon => write!(f, ";"),
             TokenKind::Colon => write!(f, ":"),
+            TokenKind::Question => write!(f, "?"),
             TokenKind::Dot => write!(f, "."),
+            TokenKind::Ellipsis => write!(f, "..."),
             TokenKind::Newline => write!(f, "\\n"),
             TokenKind::Eof => write!(f, "EOF"),
         }
@@ -167,6 +179,7 @@ pub fn lookup_keyword(ident: &str) -> Option<TokenKind> {
     match ident {
         "let" => Some(TokenKind::Let),
         "state" => Some(TokenKind::State),
+        "const" => Some(TokenKind::Const),
         "fn" => Some(TokenKind::Fn),
         "return" => Some(TokenKind::Return),
         "if" => Some(TokenKind::If),
@@ -176,6 +189,7 @@ pub fn lookup_keyword(ident: &str) -> Option<TokenKind> {
         "break" => Some(TokenKind::Break),
         "continue" => Some(TokenKind::Continue),
         "class" => Some(TokenKind::Class),
+        "super" => Some(TokenKind::Super),
         "true" => Some(TokenKind::True),
         "false" => Some(TokenKind::False),
         "nil" => Some(TokenKind::Nil),
@@ -185,6 +199,7 @@ pub fn lookup_keyword(ident: &str) -> Option<TokenKind> {
         "export" => Some(TokenKind::Export),
         "from" => Some(TokenKind::From),
         "in" => Some(TokenKind::In),
+        "match" => Some(TokenKind::Match),
         _ => None,
     }
 }
@@ -0,0 +1,111 @@
+//! Static types: a small lattice over expressions for best-effort lints.
+//!
+//! Inference only claims a type when every way the expression can evaluate
+//! without error produces it; anything else is `Unknown`, and lints stay
+//! silent about unknown operands.
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::value::Value;
+
+/// The type an expression is known to have at compile time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticType {
+    Number,
+    String,
+    Bool,
+    Nil,
+    Unknown,
+}
+
+impl StaticType {
+    pub fn of_value(value: &Value) -> Self {
+        match value {
+            Value::Number(_) => StaticType::Number,
+            Value::String(_) => StaticType::String,
+            Value::Bool(_) => StaticType::Bool,
+            Value::Nil => StaticType::Nil,
+            _ => StaticType::Unknown,
+        }
+    }
+
+    /// Name as reported by `type()`
+    pub fn name(self) -> &'static str {
+        match self {
+            StaticType::Number => "number",
+            StaticType::String => "string",
+            StaticType::Bool => "bool",
+            StaticType::Nil => "nil",
+            StaticType::Unknown => "unknown",
+        }
+    }
+
+    /// The type both branches agree on, or `Unknown`
+    fn join(self, other: Self) -> Self {
+        if self == other { self } else { StaticType::Unknown }
+    }
+}
+
+/// What inference needs to know about the names in scope
+pub trait TypeScope {
+    /// Type of the value bound to `name`, when it is fixed at compile time (a `const`)
+    fn constant_type(&self, name: &str) -> Option<StaticType>;
+
+    /// Whether `name` still refers to the built-in function of that name
+    fn is_builtin(&self, name: &str) -> bool;
+}
+
+/// Infer the static type of an expression
+pub fn infer(expr: &Expr, scope: &impl TypeScope) -> StaticType {
+    match expr {
+        Expr::Number { .. } => StaticType::Number,
+        Expr::String { .. } => StaticType::String,
+        Expr::Bool { .. } => StaticType::Bool,
+        Expr::Nil { .. } => StaticType::Nil,
+        Expr::Ident { name, .. } => scope.constant_type(name).unwrap_or(StaticType::Unknown),
+        Expr::Grouping { expr, .. } => infer(expr, scope),
+
+        Expr::Unary { op: UnaryOp::Not, .. } => StaticType::Bool,
+        Expr::Unary { op: UnaryOp::Neg, operand, .. } => match infer(operand, scope) {
+            StaticType::Number => StaticType::Number,
+            _ => StaticType::Unknown,
+        },
+
+        Expr::Binary { left, op, right, .. } => {
+            let (a, b) = (infer(left, scope), infer(right, scope));
+            match op {
+                BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => StaticType::Bool,
+                BinaryOp::Add => match (a, b) {
+                    (StaticType::Number, StaticType::Number) => StaticType::Number,
+                    (StaticType::String, _) | (_, StaticType::String) => StaticType::String,
+                    _ => StaticType::Unknown,
+                },
+                // `*` also repeats strings
+                BinaryOp::Mul => match (a, b) {
+                    (StaticType::Number, StaticType::Number) => StaticType::Number,
+                    (StaticType::String, _) => StaticType::String,
+                    _ => StaticType::Unknown,
+                },
+                BinaryOp::Sub | BinaryOp::Div | BinaryOp::Mod => StaticType::Number,
+            }
+        }
+
+        // `and` / `or` produce one of their operands
+        Expr::Logical { left, right, .. } => infer(left, scope).join(infer(right, scope)),
+        Expr::If { then_branch, else_branch, .. } => {
+            let otherwise = else_branch.as_ref().map_or(StaticType::Nil, |e| infer(e, scope));
+            infer(then_branch, scope).join(otherwise)
+        }
+
+        Expr::Call { callee, .. } => match &**callee {
+            Expr::Ident { name, .. } if scope.is_builtin(name) => match name.as_str() {
+                "type" | "str" | "upper" | "lower" | "trim" | "join" | "replace" | "chr" | "read_file" | "buf_str" | "to_fixed" | "why" => StaticType::String,
+                "len" | "ord" | "int" | "clock_ms" | "rand" => StaticType::Number,
+                "contains" | "bool" => StaticType::Bool,
+                _ => StaticType::Unknown,
+            },
+            _ => StaticType::Unknown,
+        },
+
+        _ => StaticType::Unknown,
+    }
+}
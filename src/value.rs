@@ -1,16 +1,18 @@
 //! Runtime value types for SkyHetu
 
 use std::fmt;
+use std::rc::Rc;
 use crate::gc::Heap;
 
 /// Runtime values in SkyHetu
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum Value {
     /// Numeric value
     Number(f64),
     
-    /// String value
-    String(String),
+    /// String value. Shared, so loading a literal from the constant pool doesn't copy it
+    String(Rc<str>),
     
     /// Boolean value
     Bool(bool),
@@ -30,6 +32,12 @@ pub enum Value {
     /// Array/list
     Array(crate::gc::Handle),
     
+    /// Map from string keys to values
+    Map(crate::gc::Handle),
+    
+    /// String buffer that `buf_push` appends to in place
+    Buffer(crate::gc::Handle),
+    
     /// Class definition
     Class(crate::gc::Handle),
     
@@ -51,12 +59,17 @@ impl Value {
             Value::Closure(_) => "closure",
             Value::NativeFunction(_) => "native function",
             Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Buffer(_) => "buffer",
             Value::Class(_) => "class",
             Value::Instance(_) => "instance",
             Value::BoundMethod(_) => "method",
         }
     }
     
+    /// Whether conditions, `!`, `and` and `or` treat the value as true: `nil`,
+    /// `false`, `0` and `""` are false; every other value is true, including
+    /// `NaN`, empty arrays and empty maps
     pub fn is_truthy(&self) -> bool {
         match self {
             Value::Nil => false,
@@ -68,10 +81,22 @@ impl Value {
     }
 
 
+    /// The text `print` shows: containers list their contents, with strings
+    /// inside them quoted. A container reached again while it is being
+    /// printed, as in an array holding itself, shows as `[...]` / `{...}`.
     pub fn display(&self, heap: &Heap) -> String {
+        self.display_in(heap, &mut Vec::new())
+    }
+    
+    /// `open` holds the containers being printed further out
+    fn display_in(&self, heap: &Heap, open: &mut Vec<crate::gc::Handle>) -> String {
+        let nested = |value: &Value, open: &mut Vec<crate::gc::Handle>| match value {
+            Value::String(s) => format!("{:?}", &**s),
+            other => other.display_in(heap, open),
+        };
         match self {
-            Value::Number(n) => format!("{}", n),
-            Value::String(s) => s.clone(), 
+            Value::Number(n) => format_number(*n),
+            Value::String(s) => s.to_string(),
             Value::Bool(b) => format!("{}", b),
             Value::Nil => "nil".to_string(),
             Value::Function(handle) => {
@@ -93,8 +118,31 @@ impl Value {
                 }
             }
             Value::NativeFunction(nf) => format!("<native fn {}>", nf.name),
-            Value::Array(_handle) => {
-                "<array>".to_string() 
+            Value::Array(handle) => match heap.get_array(*handle) {
+                Some(_) if open.contains(handle) => "[...]".to_string(),
+                Some(items) => {
+                    open.push(*handle);
+                    let items: Vec<String> = items.iter().map(|v| nested(v, open)).collect();
+                    open.pop();
+                    format!("[{}]", items.join(", "))
+                }
+                None => "<array (collected)>".to_string(),
+            },
+            Value::Map(handle) => match heap.get_map(*handle) {
+                Some(_) if open.contains(handle) => "{...}".to_string(),
+                Some(map) => {
+                    open.push(*handle);
+                    let mut keys: Vec<&String> = map.keys().collect();
+                    keys.sort();
+                    let entries: Vec<String> = keys.iter().map(|k| format!("{:?}: {}", k, nested(&map[*k], open))).collect();
+                    open.pop();
+                    format!("{{{}}}", entries.join(", "))
+                }
+                None => "<map (collected)>".to_string(),
+            },
+            Value::Buffer(handle) => match heap.get_buffer(*handle) {
+                Some(text) => format!("<buffer {:?}>", text),
+                None => "<buffer (collected)>".to_string(),
             },
             Value::Class(handle) => {
                 if let Some(c) = heap.get_class(*handle) {
@@ -105,11 +153,17 @@ impl Value {
             },
             Value::Instance(handle) => {
                 if let Some(i) = heap.get_instance(*handle) {
-                    if let Some(c) = heap.get_class(i.class) {
-                        format!("<{} instance>", c.name)
-                    } else {
-                        "<instance (class collected)>".to_string()
+                    let class = heap.get_class(i.class).map_or("<class collected>", |c| c.name.as_str());
+                    if open.contains(handle) {
+                        return format!("{} {{...}}", class);
                     }
+                    open.push(*handle);
+                    let fields = i.fields.borrow();
+                    let mut names: Vec<&String> = fields.keys().collect();
+                    names.sort();
+                    let entries: Vec<String> = names.iter().map(|k| format!("{}: {}", k, nested(&fields[*k], open))).collect();
+                    open.pop();
+                    format!("{} {{{}}}", class, entries.join(", "))
                 } else {
                      "<instance (collected)>".to_string()
                 }
@@ -119,11 +173,11 @@ impl Value {
                     let mut s = "<method".to_string();
                     if let Some(c) = heap.get_closure(b.method) {
                         if let Some(f) = heap.get_function(c.function) {
-                            s.push_str(" ");
+                            s.push(' ');
                             s.push_str(&f.name);
                         }
                     }
-                    s.push_str(">");
+                    s.push('>');
                     s
                  } else {
                      "<method (collected)>".to_string()
@@ -132,11 +186,28 @@ impl Value {
         }
     }
 
+    /// The heap object a value refers to; `None` for values held inline
+    pub fn handle(&self) -> Option<crate::gc::Handle> {
+        match self {
+            Value::Function(handle)
+            | Value::Closure(handle)
+            | Value::Array(handle)
+            | Value::Map(handle)
+            | Value::Buffer(handle)
+            | Value::Class(handle)
+            | Value::Instance(handle)
+            | Value::BoundMethod(handle) => Some(*handle),
+            _ => None,
+        }
+    }
+    
     pub fn children(&self) -> Vec<crate::gc::Handle> {
         match self {
             Value::Function(handle) => vec![*handle],
             Value::Closure(handle) => vec![*handle],
             Value::Array(handle) => vec![*handle],
+            Value::Map(handle) => vec![*handle],
+            Value::Buffer(handle) => vec![*handle],
             Value::Class(handle) => vec![*handle],
             Value::Instance(handle) => vec![*handle],
             Value::BoundMethod(handle) => vec![*handle],
@@ -147,10 +218,29 @@ impl Value {
 
 
 
+/// How `print`, `str` and string concatenation show a number: whole numbers
+/// without a decimal point, others with the fewest digits that read back as
+/// the same value. Magnitudes from 1e21 up and below 1e-7 use an exponent
+/// (`1e21`, `2.5e-8`), and the special values are `nan`, `inf` and `-inf`.
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if n == 0.0 {
+        // Negative zero too
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-7 {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::String(s) => write!(f, "{}", s),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
@@ -158,6 +248,8 @@ impl fmt::Display for Value {
             Value::Closure(_) => write!(f, "<fn>"), // Cannot access name without heap
             Value::NativeFunction(nf) => write!(f, "<native fn {}>", nf.name),
             Value::Array(_) => write!(f, "<array>"), // Cannot access elements without heap
+            Value::Map(_) => write!(f, "<map>"),
+            Value::Buffer(_) => write!(f, "<buffer>"),
             Value::Class(_) => write!(f, "<class>"),
             Value::Instance(_) => write!(f, "<instance>"),
             Value::BoundMethod(_) => write!(f, "<method>"),
@@ -199,6 +291,9 @@ pub struct Function {
     pub upvalue_count: usize,
     pub name: String, // moved for packing? no, just keep order
     pub params: Vec<String>,
+    /// Whether each upvalue captures a `state` variable. Empty when unknown,
+    /// as for functions loaded from bytecode files.
+    pub state_upvalues: Vec<bool>,
 }
 
 impl Function {
@@ -213,31 +308,45 @@ impl Function {
             params, 
             chunk, 
             upvalue_count, 
+            state_upvalues: Vec::new(),
         }
     }
     
+    /// Record which upvalues capture `state` variables, so the VM can refuse
+    /// transitions of captured immutable bindings
+    pub fn with_state_upvalues(mut self, state_upvalues: Vec<bool>) -> Self {
+        self.state_upvalues = state_upvalues;
+        self
+    }
+    
     pub fn children(&self) -> Vec<crate::gc::Handle> {
         vec![] // No heap references yet (Environment is Rc)
     }
 }
 
-/// Native function type
-pub type NativeFnPtr = fn(&mut crate::vm::VM, &[Value]) -> Result<Value, String>;
+/// Native function type. A closure, so host functions can carry their own
+/// state, such as a connection or a channel sender.
+pub type NativeFnPtr = Rc<dyn Fn(&mut crate::vm::VM, &[Value]) -> Result<Value, String>>;
 
-/// Native/built-in function
+/// Native/built-in function.
+/// Cheap to clone: the name and function are shared, so referencing a built-in doesn't allocate.
 #[derive(Clone)]
 pub struct NativeFn {
-    pub name: String,
+    pub name: Rc<str>,
     pub arity: Option<usize>, // None means variadic
     pub func: NativeFnPtr,
 }
 
 impl NativeFn {
-    pub fn new(name: &str, arity: Option<usize>, func: NativeFnPtr) -> Self {
+    pub fn new(
+        name: &str,
+        arity: Option<usize>,
+        func: impl Fn(&mut crate::vm::VM, &[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
         Self {
-            name: name.to_string(),
+            name: name.into(),
             arity,
-            func,
+            func: Rc::new(func),
         }
     }
 }
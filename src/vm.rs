@@ -2,13 +2,24 @@
 //!
 //! Executes bytecode with causality tracking.
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
+pub use crate::bindings::{redefinition_message, BindingKind};
 use crate::bytecode::{Chunk, OpCode};
-use crate::causality::CausalityLog;
+use crate::causality::{CausalityLog, ExportFormat};
 use crate::error::{ErrorKind, Result, SkyHetuError};
+use crate::gc::AllocCategory;
+use crate::debugger::{Breakpoints, DebugAction, DebugEvent, Stepper};
+use crate::observer::{Observation, Observer, Snapshot};
+use crate::timings::Timings;
+use crate::token::Span;
 
-use crate::value::{NativeFn, Value};
+use crate::value::{format_number, NativeFn, Value};
 
 /// Maximum stack size
 const STACK_MAX: usize = 2048;
@@ -16,6 +27,45 @@ const STACK_MAX: usize = 2048;
 /// Maximum call depth
 const FRAMES_MAX: usize = 64;
 
+/// How far the fake clock moves on each `clock_ms()` call in deterministic mode
+const DETERMINISTIC_TICK_MS: f64 = 1.0;
+
+/// Most guarding conditions shown on a runtime error
+const MAX_ERROR_CONDITIONS: usize = 4;
+
+/// Longest array `array_of()` builds, so a stray length cannot exhaust memory
+const MAX_ARRAY_OF_LEN: usize = 10_000_000;
+
+/// Small xorshift64* generator behind `rand()`; not suitable for cryptography
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Run the seed through splitmix64 so small seeds still give a good state; xorshift needs non-zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+    
+    fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+    
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        bits as f64 / (1u64 << 53) as f64
+    }
+}
+
 /// A call frame for function calls
 #[derive(Debug, Clone)]
 struct CallFrame {
@@ -30,6 +80,9 @@ struct CallFrame {
     
     /// Stack slot where this frame begins
     slot: usize,
+    
+    /// Identifies this call in the causality names of `state` parameters (0 for scripts)
+    call_id: u64,
 }
 
 impl CallFrame {
@@ -39,15 +92,82 @@ impl CallFrame {
             chunk,
             ip: 0,
             slot,
+            call_id: 0,
         }
     }
 }
 
+/// How a failing `assert()` is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AssertMode {
+    /// Raise a runtime error (the default)
+    #[default]
+    Abort,
+    /// Record the failure and keep running
+    Collect,
+}
+
+/// A failed assertion recorded in `AssertMode::Collect`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertFailure {
+    pub message: String,
+    pub line: usize,
+    /// Logical time of the causality log when the assert ran
+    pub time: usize,
+}
+
+impl fmt::Display for AssertFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {} (t={})", self.line, self.message, self.time)
+    }
+}
+
 /// Binding in the VM
 #[derive(Debug, Clone)]
 struct Binding {
     value: Value,
-    is_state: bool,
+    kind: BindingKind,
+}
+
+impl Binding {
+    fn is_state(&self) -> bool {
+        self.kind == BindingKind::State
+    }
+}
+
+/// In-memory output sink that the host can read back, e.g. to capture `print` in tests
+#[derive(Debug, Clone, Default)]
+pub struct CaptureBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl CaptureBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+    
+    /// Raw bytes written so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+    
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 /// The Virtual Machine
@@ -59,31 +179,110 @@ pub struct VM {
     frames: Vec<CallFrame>,
     
     /// Global variables
-    globals: HashMap<String, Binding>,
+    /// Keyed by the chunk's own name strings, so lookups share rather than copy them
+    globals: HashMap<Rc<str>, Binding>,
     
     /// Causality log
     pub causality: CausalityLog,
     
-    /// Compiled function chunks (indexed by chunk_index)
-    function_chunks: Vec<Rc<Chunk>>,
-    
     /// Garbage collected heap
     pub heap: crate::gc::Heap,
 
     /// Open upvalues (pointing to stack)
     open_upvalues: Vec<crate::gc::Handle>,
+    
+    /// Where `print` and REPL notices are written
+    output: Box<dyn Write>,
+    
+    /// Interactive session: report global redefinitions as they happen
+    repl_mode: bool,
+    
+    /// Whether failing asserts abort or are collected
+    assert_mode: AssertMode,
+    
+    /// Failures recorded in `AssertMode::Collect`
+    assert_failures: Vec<AssertFailure>,
+    
+    /// Source for `rand()`
+    rng: Rng,
+    
+    /// Deterministic mode: the fake time `clock_ms()` reports instead of the wall clock
+    fake_clock_ms: Option<f64>,
+    
+    /// Periodic snapshot publication, when enabled
+    observation: Option<Observation>,
+    
+    /// Directory that imports in `eval`'d source resolve against
+    base_path: Option<std::path::PathBuf>,
+    
+    /// Time spent collecting garbage
+    gc_time: Duration,
+    
+    /// Calls made so far; numbers each call for `state` parameter histories
+    call_count: u64,
+    
+    /// Whether the file and input natives may be used
+    io_enabled: bool,
+    
+    /// Where `read_line()` reads from
+    input: Box<dyn BufRead>,
+    
+    /// What `args()` returns
+    args: Vec<String>,
+    
+    /// Lines `run_with_hook` stops at
+    breakpoints: Breakpoints,
+    
+    /// Most values the stack may hold
+    stack_max: usize,
+    
+    /// Most calls that may be active at once
+    frames_max: usize,
+    
+    /// Instructions left to run, when the budget is limited
+    fuel: Option<u64>,
+    
+    /// Set when `push`, `pop` or `peek` leaves the stack's bounds, and reported as an
+    /// error once the instruction finishes
+    stack_fault: Option<ErrorKind>,
+    
+    /// Functions `watch()` registered, by causality log name, called with
+    /// (old, new) after each transition of that variable
+    watchers: HashMap<String, Vec<Value>>,
+    
+    /// Variables whose watchers are running, innermost last
+    notifying: Vec<String>,
 }
 
 impl VM {
     pub fn new() -> Self {
         let mut vm = Self {
             frames: Vec::with_capacity(FRAMES_MAX),
+            call_count: 0,
             stack: Vec::with_capacity(STACK_MAX),
             globals: HashMap::new(),
-            function_chunks: Vec::new(),
             causality: CausalityLog::new(),
             heap: crate::gc::Heap::new(),
             open_upvalues: Vec::new(),
+            output: Box::new(std::io::stdout()),
+            repl_mode: false,
+            assert_mode: AssertMode::Abort,
+            assert_failures: Vec::new(),
+            rng: Rng::from_time(),
+            fake_clock_ms: None,
+            observation: None,
+            base_path: None,
+            gc_time: Duration::ZERO,
+            io_enabled: false,
+            input: Box::new(std::io::BufReader::new(std::io::stdin())),
+            args: Vec::new(),
+            breakpoints: Breakpoints::default(),
+            stack_max: STACK_MAX,
+            frames_max: FRAMES_MAX,
+            stack_fault: None,
+            fuel: None,
+            watchers: HashMap::new(),
+            notifying: Vec::new(),
         };
 
         
@@ -91,6 +290,70 @@ impl VM {
         vm
     }
     
+    /// A VM whose runs are reproducible, for golden-output tests: `rand()` is
+    /// seeded with `seed`, `clock_ms()` starts at 0 and advances by a fixed
+    /// tick per call, and `sleep_ms()` only advances that clock.
+    pub fn deterministic(seed: u64) -> Self {
+        let mut vm = Self::new();
+        vm.rng = Rng::new(seed);
+        vm.fake_clock_ms = Some(0.0);
+        vm
+    }
+    
+    /// A VM whose value stack holds at most `stack` values and that allows at
+    /// most `frames` active calls, the script included, instead of the
+    /// defaults of 2048 and 64. Going past either fails with
+    /// `ErrorKind::StackOverflow`.
+    pub fn with_limits(stack: usize, frames: usize) -> Self {
+        let mut vm = Self::new();
+        vm.stack_max = stack;
+        vm.frames_max = frames;
+        vm
+    }
+    
+    /// Whether this VM was created with `VM::deterministic`
+    pub fn is_deterministic(&self) -> bool {
+        self.fake_clock_ms.is_some()
+    }
+    
+    /// Publish a [`Snapshot`] every `interval` instructions (at least 1) and
+    /// return a handle other threads can read it through. Calling this again
+    /// restarts observation; earlier handles stop receiving updates.
+    pub fn enable_observation(&mut self, interval: u64) -> Observer {
+        let observation = Observation::new(interval);
+        let observer = observation.observer();
+        self.observation = Some(observation);
+        self.publish_snapshot();
+        observer
+    }
+    
+    /// Stop publishing snapshots
+    pub fn disable_observation(&mut self) {
+        self.observation = None;
+    }
+    
+    /// Handle to the published snapshots, if observation is enabled
+    pub fn observer(&self) -> Option<Observer> {
+        self.observation.as_ref().map(Observation::observer)
+    }
+    
+    /// Take a snapshot of the VM's current state
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            logical_time: self.causality.current_time(),
+            instructions: self.observation.as_ref().map_or(0, |o| o.instructions),
+            heap_bytes: self.heap.bytes_allocated(),
+            heap_objects: self.heap.object_count(),
+            summaries: self.causality.all_summaries().map(|(name, summary)| (name.to_string(), *summary)).collect(),
+        }
+    }
+    
+    fn publish_snapshot(&self) {
+        if let Some(observation) = &self.observation {
+            observation.publish(self.snapshot());
+        }
+    }
+    
     fn define_natives(&mut self) {
         let natives = vec![
             // len(val)
@@ -99,7 +362,7 @@ impl VM {
                 Some(1),
                 |vm, args| {
                     match &args[0] {
-                        Value::String(s) => Ok(Value::Number(s.len() as f64)),
+                        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
                         Value::Array(handle) => {
                             if let Some(arr) = vm.heap.get_array(*handle) {
                                 Ok(Value::Number(arr.len() as f64))
@@ -107,38 +370,41 @@ impl VM {
                                 Err("Array not found (GC error?)".to_string())
                             }
                         }
-                        _ => Err(format!("len() requires string or array")),
+                        Value::Map(handle) => {
+                            let map = vm.heap.get_map(*handle).ok_or("Map not found (GC error?)")?;
+                            Ok(Value::Number(map.len() as f64))
+                        }
+                        Value::Buffer(handle) => {
+                            let text = vm.heap.get_buffer(*handle).ok_or("Buffer not found (GC error?)")?;
+                            Ok(Value::Number(text.chars().count() as f64))
+                        }
+                        _ => Err("len() requires string, array, map or buffer".to_string()),
                     }
                 },
             ),
             
-            // substr(s, start, end?)
+            // slice(x, start, end?)
             NativeFn::new(
-                "substr",
+                "slice",
                 None,
-                |_vm, args| {
-                    if args.is_empty() || args.len() > 3 {
-                        return Err("substr() takes 2 or 3 arguments".to_string());
+                |vm, args| {
+                    if args.len() < 2 || args.len() > 3 {
+                        return Err("slice() takes 2 or 3 arguments".to_string());
                     }
-                    let s = match &args[0] {
-                        Value::String(s) => s,
-                        _ => return Err("substr() requires a string as first argument".to_string()),
-                    };
-                    let start = match &args[1] {
-                        Value::Number(n) => *n as usize,
-                        _ => return Err("substr() requires a number as second argument".to_string()),
-                    };
-                    let end = if args.len() == 3 {
-                        match &args[2] {
-                            Value::Number(n) => *n as usize,
-                            _ => return Err("substr() requires a number as third argument".to_string()),
+                    match &args[0] {
+                        Value::String(s) => {
+                            let range = slice_range(s.chars().count(), &args[1], args.get(2))?;
+                            let text: String = s.chars().skip(range.start).take(range.len()).collect();
+                            Ok(Value::String(text.into()))
                         }
-                    } else {
-                        s.len()
-                    };
-                    let end = std::cmp::min(end, s.len());
-                    let start = std::cmp::min(start, end);
-                    Ok(Value::String(s[start..end].to_string()))
+                        Value::Array(handle) => {
+                            let items = vm.heap.get_array(*handle).ok_or("Array not found (GC error?)")?;
+                            let range = slice_range(items.len(), &args[1], args.get(2))?;
+                            let part = items[range].to_vec();
+                            Ok(Value::Array(vm.heap.alloc_array(part)))
+                        }
+                        other => Err(format!("slice() requires an array or string, got {}", other.type_name())),
+                    }
                 },
             ),
             
@@ -146,7 +412,7 @@ impl VM {
             NativeFn::new(
                 "str",
                 Some(1),
-                |_vm, args| Ok(Value::String(format!("{}", args[0]))),
+                |_vm, args| Ok(Value::String(format!("{}", args[0]).into())),
             ),
             
             // num(val)
@@ -160,8 +426,104 @@ impl VM {
                             .map(Value::Number)
                             .map_err(|_| format!("cannot convert '{}' to number", s)),
                         Value::Bool(b) => Ok(Value::Number(if *b { 1.0 } else { 0.0 })),
-                        _ => Err(format!("cannot convert to number")),
+                        _ => Err("cannot convert to number".to_string()),
+                    }
+                },
+            ),
+            
+            // bool(val) - Truthiness as a boolean
+            NativeFn::new(
+                "bool",
+                Some(1),
+                |_vm, args| Ok(Value::Bool(args[0].is_truthy())),
+            ),
+            
+            // int(n) - Truncate toward zero
+            NativeFn::new(
+                "int",
+                Some(1),
+                |_vm, args| {
+                    match args[0] {
+                        Value::Number(n) if n.is_finite() => Ok(Value::Number(n.trunc())),
+                        Value::Number(n) => Err(format!("int() requires a finite number, got {}", n)),
+                        _ => Err(format!("int() requires a number, got {}", args[0].type_name())),
+                    }
+                },
+            ),
+            
+            // to_fixed(n, digits) - n rounded to that many decimal places, as a string
+            NativeFn::new(
+                "to_fixed",
+                Some(2),
+                |_vm, args| {
+                    let Value::Number(n) = args[0] else {
+                        return Err(format!("to_fixed() requires a number, got {}", args[0].type_name()));
+                    };
+                    let digits = match args[1] {
+                        Value::Number(d) if (0.0..=100.0).contains(&d) && d.fract() == 0.0 => d as usize,
+                        _ => return Err("to_fixed() requires a whole number of digits from 0 to 100".to_string()),
+                    };
+                    if !n.is_finite() {
+                        return Ok(Value::String(format_number(n).into()));
+                    }
+                    Ok(Value::String(format!("{:.*}", digits, n).into()))
+                },
+            ),
+            
+            // parse_int(s) - Whole number written in decimal digits, or nil
+            NativeFn::new(
+                "parse_int",
+                Some(1),
+                |_vm, args| {
+                    let s = string_arg("parse_int", &args[0])?.trim();
+                    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+                    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                        return Ok(Value::Nil);
                     }
+                    Ok(s.parse::<f64>().map_or(Value::Nil, Value::Number))
+                },
+            ),
+            
+            // parse_float(s) - Finite number in decimal or exponent notation, or nil
+            NativeFn::new(
+                "parse_float",
+                Some(1),
+                |_vm, args| {
+                    let s = string_arg("parse_float", &args[0])?.trim();
+                    // Rust also reads "inf" and "NaN", which aren't written numbers
+                    if s.bytes().any(|b| b.is_ascii_alphabetic() && b != b'e' && b != b'E') {
+                        return Ok(Value::Nil);
+                    }
+                    Ok(s.parse::<f64>().ok().filter(|n| n.is_finite()).map_or(Value::Nil, Value::Number))
+                },
+            ),
+            
+            // ord(s) - Unicode scalar value of the first character
+            NativeFn::new(
+                "ord",
+                Some(1),
+                |_vm, args| {
+                    let Value::String(s) = &args[0] else {
+                        return Err(format!("ord() requires a string, got {}", args[0].type_name()));
+                    };
+                    let c = s.chars().next().ok_or("ord() requires a non-empty string")?;
+                    Ok(Value::Number(c as u32 as f64))
+                },
+            ),
+            
+            // chr(n) - One-character string for a Unicode scalar value
+            NativeFn::new(
+                "chr",
+                Some(1),
+                |_vm, args| {
+                    let Value::Number(n) = args[0] else {
+                        return Err(format!("chr() requires a number, got {}", args[0].type_name()));
+                    };
+                    let c = (n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64)
+                        .then(|| char::from_u32(n as u32))
+                        .flatten()
+                        .ok_or_else(|| format!("chr() requires a Unicode scalar value, got {}", n))?;
+                    Ok(Value::String(c.to_string().into()))
                 },
             ),
             
@@ -169,7 +531,78 @@ impl VM {
             NativeFn::new(
                 "type",
                 Some(1),
-                |_vm, args| Ok(Value::String(args[0].type_name().to_string())),
+                |_vm, args| Ok(Value::String(args[0].type_name().into())),
+            ),
+            
+            // id(val) - Identity of a heap object (array, map, instance, function, class)
+            NativeFn::new(
+                "id",
+                Some(1),
+                |vm, args| {
+                    let id = args[0].handle().and_then(|handle| vm.heap.object_id(handle)).ok_or_else(|| format!(
+                        "id() requires an array, map, instance, function or class, got {}",
+                        args[0].type_name()
+                    ))?;
+                    Ok(Value::Number(id as f64))
+                },
+            ),
+            
+            // inspect(val): type, rendering and kind-specific details
+            NativeFn::new(
+                "inspect",
+                Some(1),
+                |vm, args| Ok(Value::String(crate::inspect::describe(&vm.heap, &args[0]).into())),
+            ),
+            
+            // disasm(f) - Bytecode listing of a function defined in SkyHetu
+            NativeFn::new(
+                "disasm",
+                Some(1),
+                |vm, args| {
+                    let f = script_function(vm, "disasm", &args[0])?;
+                    Ok(Value::String(f.chunk.disassemble(&format!("fn {}", f.name), &vm.heap).into()))
+                },
+            ),
+            
+            // arity(f) - Number of parameters, or nil for a native taking any number
+            NativeFn::new(
+                "arity",
+                Some(1),
+                |vm, args| match &args[0] {
+                    Value::NativeFunction(native) => Ok(native.arity.map_or(Value::Nil, |n| Value::Number(n as f64))),
+                    other => Ok(Value::Number(script_function(vm, "arity", other)?.params.len() as f64)),
+                },
+            ),
+            
+            // fn_name(f) - Name a function was declared with
+            NativeFn::new(
+                "fn_name",
+                Some(1),
+                |vm, args| match &args[0] {
+                    Value::NativeFunction(native) => Ok(Value::String(Rc::clone(&native.name))),
+                    other => Ok(Value::String(script_function(vm, "fn_name", other)?.name.as_str().into())),
+                },
+            ),
+            
+            // heap_dump() - Table of live objects by type and allocation category
+            NativeFn::new(
+                "heap_dump",
+                Some(0),
+                |vm, _args| Ok(Value::String(crate::gc::format_dump(&vm.heap.dump()).into())),
+            ),
+            
+            // gc_stats() - Map of heap size, live objects and collections so far
+            NativeFn::new(
+                "gc_stats",
+                Some(0),
+                |vm, _args| {
+                    let stats = HashMap::from([
+                        ("bytes".to_string(), Value::Number(vm.heap.bytes_allocated() as f64)),
+                        ("objects".to_string(), Value::Number(vm.heap.object_count() as f64)),
+                        ("collections".to_string(), Value::Number(vm.heap.collections() as f64)),
+                    ]);
+                    Ok(Value::Map(vm.heap.alloc_map(stats)))
+                },
             ),
             
             // range(n) or range(start, end)
@@ -195,10 +628,11 @@ impl VM {
                         _ => return Err("range() takes 1 or 2 arguments".to_string()),
                     };
                     
+                    vm.reserve_heap((end.saturating_sub(start).max(0) as usize).saturating_mul(std::mem::size_of::<Value>()))?;
                     let values: Vec<Value> = (start..end)
                         .map(|i| Value::Number(i as f64))
                         .collect();
-                    Ok(Value::Array(vm.heap.alloc_array(values)))
+                    Ok(Value::Array(vm.heap.alloc_array_as(values, AllocCategory::RangeResult)))
                 },
             ),
             
@@ -206,7 +640,7 @@ impl VM {
             NativeFn::new(
                 "assert",
                 None,
-                |_vm, args| {
+                |vm, args| {
                     if args.is_empty() {
                         return Err("assert() requires at least one argument".to_string());
                     }
@@ -214,151 +648,1251 @@ impl VM {
                         let msg = args.get(1)
                             .map(|v| format!("{}", v))
                             .unwrap_or_else(|| "assertion failed".to_string());
-                        return Err(msg);
+                        return assertion_failed(vm, msg);
                     }
                     Ok(Value::Nil)
                 },
             ),
             
-            // === Math functions ===
+            // assert_eq(actual, expected, msg?) - Arrays and maps compare by contents
+            NativeFn::new(
+                "assert_eq",
+                None,
+                |vm, args| {
+                    if !(2..=3).contains(&args.len()) {
+                        return Err(format!("assert_eq() takes 2 or 3 arguments, got {}", args.len()));
+                    }
+                    if vm.deep_equal(&args[0], &args[1]) {
+                        return Ok(Value::Nil);
+                    }
+                    let detail = format!("{} != {}", assert_operand(vm, &args[0]), assert_operand(vm, &args[1]));
+                    assertion_failed(vm, assert_message("assert_eq", args.get(2), detail))
+                },
+            ),
             
-            // abs(n)
+            // assert_ne(actual, unexpected, msg?)
             NativeFn::new(
-                "abs",
-                Some(1),
-                |_vm, args| {
-                    match &args[0] {
-                        Value::Number(n) => Ok(Value::Number(n.abs())),
-                        _ => Err("abs() requires a number".to_string()),
+                "assert_ne",
+                None,
+                |vm, args| {
+                    if !(2..=3).contains(&args.len()) {
+                        return Err(format!("assert_ne() takes 2 or 3 arguments, got {}", args.len()));
                     }
+                    if !vm.deep_equal(&args[0], &args[1]) {
+                        return Ok(Value::Nil);
+                    }
+                    let detail = format!("both are {}", assert_operand(vm, &args[0]));
+                    assertion_failed(vm, assert_message("assert_ne", args.get(2), detail))
                 },
             ),
             
-            // min(a, b)
+            // === String search ===
+            
+            // find(s, sub) - char index of the first occurrence, or -1
             NativeFn::new(
-                "min",
+                "find",
                 Some(2),
                 |_vm, args| {
-                    match (&args[0], &args[1]) {
-                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.min(*b))),
-                        _ => Err("min() requires two numbers".to_string()),
-                    }
+                    let (s, sub) = string_pair("find", args)?;
+                    Ok(Value::Number(char_index(s, s.find(sub))))
                 },
             ),
             
-            // max(a, b)
+            // rfind(s, sub) - char index of the last occurrence, or -1
             NativeFn::new(
-                "max",
+                "rfind",
                 Some(2),
                 |_vm, args| {
-                    match (&args[0], &args[1]) {
-                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.max(*b))),
-                        _ => Err("max() requires two numbers".to_string()),
-                    }
+                    let (s, sub) = string_pair("rfind", args)?;
+                    Ok(Value::Number(char_index(s, s.rfind(sub))))
                 },
             ),
             
-            // floor(n)
+            // count(s, sub) - number of non-overlapping occurrences
             NativeFn::new(
-                "floor",
-                Some(1),
+                "count",
+                Some(2),
                 |_vm, args| {
-                    match &args[0] {
-                        Value::Number(n) => Ok(Value::Number(n.floor())),
-                        _ => Err("floor() requires a number".to_string()),
+                    let (s, sub) = string_pair("count", args)?;
+                    if sub.is_empty() {
+                        return Err("count() requires a non-empty substring".to_string());
                     }
+                    Ok(Value::Number(s.matches(sub).count() as f64))
                 },
             ),
             
-            // ceil(n)
+            // matches(s, pattern) - glob match of the whole string
             NativeFn::new(
-                "ceil",
-                Some(1),
+                "matches",
+                Some(2),
                 |_vm, args| {
-                    match &args[0] {
-                        Value::Number(n) => Ok(Value::Number(n.ceil())),
-                        _ => Err("ceil() requires a number".to_string()),
-                    }
+                    let (s, pattern) = string_pair("matches", args)?;
+                    Ok(Value::Bool(crate::glob::is_match(s, pattern)?))
                 },
             ),
             
-            // round(n)
+            // capture(s, pattern) - text matched by each `*`, or nil
             NativeFn::new(
-                "round",
-                Some(1),
-                |_vm, args| {
-                    match &args[0] {
-                        Value::Number(n) => Ok(Value::Number(n.round())),
-                        _ => Err("round() requires a number".to_string()),
+                "capture",
+                Some(2),
+                |vm, args| {
+                    let (s, pattern) = string_pair("capture", args)?;
+                    match crate::glob::captures(s, pattern)? {
+                        Some(parts) => {
+                            let values = parts.into_iter().map(|part| Value::String(part.into())).collect();
+                            Ok(Value::Array(vm.heap.alloc_array(values)))
+                        }
+                        None => Ok(Value::Nil),
                     }
                 },
             ),
             
-            // === Enhanced Causality Functions ===
+            // === String utilities ===
             
-            // causal_graph(var_name, format?) - Export causality as DOT or JSON
+            // split(s, sep) - Pieces between separators; an empty sep splits into characters
             NativeFn::new(
-                "causal_graph",
-                None,
+                "split",
+                Some(2),
                 |vm, args| {
-                    if args.is_empty() || args.len() > 2 {
-                        return Err("causal_graph() takes 1 or 2 arguments".to_string());
-                    }
-                    let var_name = match &args[0] {
-                        Value::String(s) => s.clone(),
-                        _ => return Err("causal_graph() requires variable name as string".to_string()),
-                    };
-                    let format = if args.len() > 1 {
-                        match &args[1] {
-                            Value::String(s) => s.as_str(),
-                            _ => return Err("causal_graph() format must be string".to_string()),
-                        }
+                    let (s, sep) = string_pair("split", args)?;
+                    let parts: Vec<Value> = if sep.is_empty() {
+                        s.chars().map(|c| Value::String(c.to_string().into())).collect()
                     } else {
-                        "dot"
+                        s.split(sep).map(|part| Value::String(part.into())).collect()
                     };
-                    
-                    match format {
-                        "dot" => Ok(Value::String(vm.causality.to_dot(&var_name))),
-                        "json" => Ok(Value::String(vm.causality.to_json(&var_name))),
-                        _ => Err(format!("Unknown format '{}'. Use 'dot' or 'json'", format)),
-                    }
+                    Ok(Value::Array(vm.heap.alloc_array(parts)))
                 },
             ),
             
-            // transitions(var_name) - Get number of state transitions
+            // join(arr, sep) - Elements converted as by str(), separated by sep
             NativeFn::new(
-                "transitions",
+                "join",
+                Some(2),
+                |vm, args| {
+                    let handle = array_handle("join", &args[0])?;
+                    let Value::String(sep) = &args[1] else {
+                        return Err(format!("join() requires a string separator, got {}", args[1].type_name()));
+                    };
+                    let items = vm.heap.get_array(handle).ok_or("Array not found (GC error?)")?;
+                    let parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                    Ok(Value::String(parts.join(sep).into()))
+                },
+            ),
+            
+            // buffer() - Empty string buffer, for building text piece by piece
+            NativeFn::new(
+                "buffer",
+                Some(0),
+                |vm, _args| Ok(Value::Buffer(vm.heap.alloc_buffer(String::new()))),
+            ),
+            
+            // buf_push(b, val) - Append val, converted as by str(), in place; returns b
+            NativeFn::new(
+                "buf_push",
+                Some(2),
+                |vm, args| {
+                    let Value::Buffer(handle) = &args[0] else {
+                        return Err(format!("buf_push() requires a buffer, got {}", args[0].type_name()));
+                    };
+                    let piece = args[1].to_string();
+                    let text = vm.heap.get_buffer_mut(*handle).ok_or("Buffer not found (GC error?)")?;
+                    text.push_str(&piece);
+                    vm.heap.grow(piece.len());
+                    Ok(args[0].clone())
+                },
+            ),
+            
+            // buf_str(b) - The text built so far, as a string
+            NativeFn::new(
+                "buf_str",
+                Some(1),
+                |vm, args| {
+                    let Value::Buffer(handle) = &args[0] else {
+                        return Err(format!("buf_str() requires a buffer, got {}", args[0].type_name()));
+                    };
+                    let text = vm.heap.get_buffer(*handle).ok_or("Buffer not found (GC error?)")?;
+                    Ok(Value::String(text.as_str().into()))
+                },
+            ),
+            
+            // trim(s) - Without leading and trailing whitespace
+            NativeFn::new(
+                "trim",
+                Some(1),
+                |_vm, args| Ok(Value::String(string_arg("trim", &args[0])?.trim().into())),
+            ),
+            
+            // upper(s)
+            NativeFn::new(
+                "upper",
+                Some(1),
+                |_vm, args| Ok(Value::String(string_arg("upper", &args[0])?.to_uppercase().into())),
+            ),
+            
+            // lower(s)
+            NativeFn::new(
+                "lower",
+                Some(1),
+                |_vm, args| Ok(Value::String(string_arg("lower", &args[0])?.to_lowercase().into())),
+            ),
+            
+            // contains(s, sub)
+            NativeFn::new(
+                "contains",
+                Some(2),
+                |_vm, args| {
+                    let (s, sub) = string_pair("contains", args)?;
+                    Ok(Value::Bool(s.contains(sub)))
+                },
+            ),
+            
+            // index_of(s, sub) - char index of the first occurrence, or -1 (same as find)
+            NativeFn::new(
+                "index_of",
+                Some(2),
+                |_vm, args| {
+                    let (s, sub) = string_pair("index_of", args)?;
+                    Ok(Value::Number(char_index(s, s.find(sub))))
+                },
+            ),
+            
+            // replace(s, from, to) - Every occurrence of from replaced with to
+            NativeFn::new(
+                "replace",
+                Some(3),
+                |_vm, args| {
+                    let (s, from) = string_pair("replace", args)?;
+                    let to = string_arg("replace", &args[2])?;
+                    if from.is_empty() {
+                        return Err("replace() requires a non-empty string to replace".to_string());
+                    }
+                    Ok(Value::String(s.replace(from, to).into()))
+                },
+            ),
+            
+            // === Math functions ===
+            
+            // abs(n)
+            NativeFn::new(
+                "abs",
+                Some(1),
+                |_vm, args| {
+                    match &args[0] {
+                        Value::Number(n) => Ok(Value::Number(n.abs())),
+                        _ => Err("abs() requires a number".to_string()),
+                    }
+                },
+            ),
+            
+            // min(a, b)
+            NativeFn::new(
+                "min",
+                Some(2),
+                |_vm, args| {
+                    match (&args[0], &args[1]) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.min(*b))),
+                        _ => Err("min() requires two numbers".to_string()),
+                    }
+                },
+            ),
+            
+            // max(a, b)
+            NativeFn::new(
+                "max",
+                Some(2),
+                |_vm, args| {
+                    match (&args[0], &args[1]) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.max(*b))),
+                        _ => Err("max() requires two numbers".to_string()),
+                    }
+                },
+            ),
+            
+            // floor(n)
+            NativeFn::new(
+                "floor",
+                Some(1),
+                |_vm, args| {
+                    match &args[0] {
+                        Value::Number(n) => Ok(Value::Number(n.floor())),
+                        _ => Err("floor() requires a number".to_string()),
+                    }
+                },
+            ),
+            
+            // ceil(n)
+            NativeFn::new(
+                "ceil",
+                Some(1),
+                |_vm, args| {
+                    match &args[0] {
+                        Value::Number(n) => Ok(Value::Number(n.ceil())),
+                        _ => Err("ceil() requires a number".to_string()),
+                    }
+                },
+            ),
+            
+            // round(n)
+            NativeFn::new(
+                "round",
+                Some(1),
+                |_vm, args| {
+                    match &args[0] {
+                        Value::Number(n) => Ok(Value::Number(n.round())),
+                        _ => Err("round() requires a number".to_string()),
+                    }
+                },
+            ),
+            
+            // === Enhanced Causality Functions ===
+            
+            // causal_graph(var_name, format?) - Export causality as DOT or JSON
+            NativeFn::new(
+                "causal_graph",
+                None,
+                |vm, args| {
+                    if args.is_empty() {
+                        return Ok(Value::String(vm.causality.to_dot_all().into()));
+                    }
+                    if args.len() > 2 {
+                        return Err("causal_graph() takes at most 2 arguments".to_string());
+                    }
+                    let var_name = match &args[0] {
+                        Value::String(s) => s.clone(),
+                        _ => return Err("causal_graph() requires variable name as string".to_string()),
+                    };
+                    let format = if args.len() > 1 {
+                        match &args[1] {
+                            Value::String(s) => &**s,
+                            _ => return Err("causal_graph() format must be string".to_string()),
+                        }
+                    } else {
+                        "dot"
+                    };
+                    
+                    match format {
+                        "dot" => Ok(Value::String(vm.causality.to_dot(&var_name).into())),
+                        "json" => Ok(Value::String(vm.causality.to_json(&var_name).into())),
+                        _ => Err(format!("Unknown format '{}'. Use 'dot' or 'json'", format)),
+                    }
+                },
+            ),
+            
+            // transitions(var_name) - Get number of state transitions
+            NativeFn::new(
+                "transitions",
                 Some(1),
                 |vm, args| {
-                    let var_name = match &args[0] {
-                        Value::String(s) => s.clone(),
-                        _ => return Err("transitions() requires variable name as string".to_string()),
-                    };
-                    Ok(Value::Number(vm.causality.transition_count(&var_name) as f64))
+                    let var_name = match &args[0] {
+                        Value::String(s) => s.clone(),
+                        _ => return Err("transitions() requires variable name as string".to_string()),
+                    };
+                    Ok(Value::Number(vm.causality.transition_count(&var_name) as f64))
+                },
+            ),
+            
+            // why(var_name) - Causality chain of a state variable named at runtime;
+            // the compiler turns why(x) into a direct lookup
+            NativeFn::new(
+                "why",
+                Some(1),
+                |vm, args| {
+                    let Value::String(var_name) = &args[0] else {
+                        return Err(format!("why() requires variable name as string, got {}", args[0].type_name()));
+                    };
+                    Ok(Value::String(vm.causality.why_with_heap(var_name, &vm.heap).into()))
+                },
+            ),
+            
+            // history(var_name) - One string per recorded mutation of a state variable
+            NativeFn::new(
+                "history",
+                Some(1),
+                |vm, args| {
+                    let Value::String(var_name) = &args[0] else {
+                        return Err(format!("history() requires variable name as string, got {}", args[0].type_name()));
+                    };
+                    let entries = vm.causality.history_lines(var_name, &vm.heap)
+                        .into_iter()
+                        .map(|line| Value::String(line.into()))
+                        .collect();
+                    Ok(Value::Array(vm.heap.alloc_array_as(entries, AllocCategory::CausalitySnapshot)))
+                },
+            ),
+            
+            // watch(var_name, f) - Call f(old, new) after each transition of a state
+            // variable; the compiler turns watch(x, f) into watch with x's log name
+            NativeFn::new(
+                "watch",
+                Some(2),
+                |vm, args| {
+                    let Value::String(var_name) = &args[0] else {
+                        return Err(format!("watch() requires variable name as string, got {}", args[0].type_name()));
+                    };
+                    if !matches!(args[1], Value::Closure(_) | Value::NativeFunction(_) | Value::BoundMethod(_)) {
+                        return Err(format!("watch() requires a function to call, got {}", args[1].type_name()));
+                    }
+                    vm.watchers.entry(var_name.to_string()).or_default().push(args[1].clone());
+                    Ok(Value::Nil)
+                },
+            ),
+            
+            // unwatch(var_name) - Remove a state variable's watchers; true if it had any
+            NativeFn::new(
+                "unwatch",
+                Some(1),
+                |vm, args| {
+                    let Value::String(var_name) = &args[0] else {
+                        return Err(format!("unwatch() requires variable name as string, got {}", args[0].type_name()));
+                    };
+                    Ok(Value::Bool(vm.watchers.remove(&**var_name).is_some()))
+                },
+            ),
+            
+            // why_field(instance, field) - Causality chain of an instance field
+            // whose name is only known at runtime
+            NativeFn::new(
+                "why_field",
+                Some(2),
+                |vm, args| {
+                    let Value::Instance(handle) = args[0] else {
+                        return Err(format!("why_field() requires an instance, got {}", args[0].type_name()));
+                    };
+                    let Value::String(field) = &args[1] else {
+                        return Err("why_field() requires field name as string".to_string());
+                    };
+                    let key = vm.field_key(handle, field);
+                    Ok(Value::String(vm.causality.why_with_heap(&key, &vm.heap).into()))
+                },
+            ),
+            
+            // snapshot() - Get current logical time
+            NativeFn::new(
+                "snapshot",
+                Some(0),
+                |vm, _args| {
+                    Ok(Value::Number(vm.causality.current_time() as f64))
+                },
+            ),
+            
+            // at(name, t) - Value a state variable had at logical time t
+            NativeFn::new(
+                "at",
+                Some(2),
+                |vm, args| {
+                    let (name, timestamp) = history_args("at", args)?;
+                    Ok(vm.state_value_at("at", name, timestamp)?.unwrap_or(Value::Nil))
+                },
+            ),
+            
+            // diff(t1, t2) - "name: before -> after" for each variable changed in between
+            NativeFn::new(
+                "diff",
+                Some(2),
+                |vm, args| {
+                    let (Some(from), Some(to)) = (timestamp_arg(&args[0]), timestamp_arg(&args[1])) else {
+                        return Err("diff() requires non-negative whole timestamps".to_string());
+                    };
+                    if from > to {
+                        return Err(format!("diff() requires the first time to come first, got {} and {}", from, to));
+                    }
+                    let lines = vm.causality.diff_lines(from, to, &vm.heap)
+                        .into_iter()
+                        .map(|line| Value::String(line.into()))
+                        .collect();
+                    Ok(Value::Array(vm.heap.alloc_array_as(lines, AllocCategory::CausalitySnapshot)))
+                },
+            ),
+            
+            // rewind(name, t) - Reset a global state variable to its value at time t
+            NativeFn::new(
+                "rewind",
+                Some(2),
+                |vm, args| {
+                    let (name, timestamp) = history_args("rewind", args)?;
+                    let current = match vm.globals.get(name) {
+                        Some(binding) if binding.kind == BindingKind::State => binding.value.clone(),
+                        _ => return Err(format!("rewind() requires a global state variable, '{}' is not one", name)),
+                    };
+                    let value = vm.state_value_at("rewind", name, timestamp)?
+                        .ok_or_else(|| format!("rewind() has no history for '{}'", name))?;
+                    if let Some(binding) = vm.globals.get_mut(name) {
+                        binding.value = value.clone();
+                    }
+                    let location = format!("line {}, rewound to t={}", vm.current_line(), timestamp);
+                    let (old_value, new_value) = (vm.logged_copy(&current), vm.logged_copy(&value));
+                    vm.causality.record_mutation(name, old_value, new_value, Some(location));
+                    vm.check_causality_stream().map_err(|e| e.to_string())?;
+                    vm.notify_watchers(name, current, value.clone()).map_err(|e| e.kind.to_string())?;
+                    Ok(value)
+                },
+            ),
+            
+            // push(arr, val) - Append to an array, returns the new length
+            NativeFn::new(
+                "push",
+                Some(2),
+                |vm, args| {
+                    let Value::Array(handle) = &args[0] else {
+                        return Err(format!("push() requires an array, got {}", args[0].type_name()));
+                    };
+                    let arr = vm.heap.resize_array(*handle).ok_or("Array not found (GC error?)")?;
+                    arr.push(args[1].clone());
+                    let len = arr.len();
+                    vm.heap.grow(std::mem::size_of::<Value>());
+                    Ok(Value::Number(len as f64))
+                },
+            ),
+            
+            // pop(arr) - Remove and return the last element, or nil if empty
+            NativeFn::new(
+                "pop",
+                Some(1),
+                |vm, args| {
+                    let Value::Array(handle) = &args[0] else {
+                        return Err(format!("pop() requires an array, got {}", args[0].type_name()));
+                    };
+                    let arr = vm.heap.resize_array(*handle).ok_or("Array not found (GC error?)")?;
+                    Ok(arr.pop().unwrap_or(Value::Nil))
+                },
+            ),
+            
+            // array_of(n, v) - n-element array; every slot holds v itself, so a
+            // container or instance is shared. A function is called per slot instead.
+            NativeFn::new(
+                "array_of",
+                Some(2),
+                |vm, args| {
+                    let len = match args[0] {
+                        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n,
+                        _ => return Err(format!("array_of() requires a non-negative whole length, got {}", args[0])),
+                    };
+                    if len > MAX_ARRAY_OF_LEN as f64 {
+                        return Err(format!("array_of() length {} exceeds the maximum of {}", len, MAX_ARRAY_OF_LEN));
+                    }
+                    let len = len as usize;
+                    vm.reserve_heap(len.saturating_mul(std::mem::size_of::<Value>()))?;
+                    let init = &args[1];
+                    if !matches!(init, Value::Closure(_) | Value::Function(_)) {
+                        return Ok(Value::Array(vm.heap.alloc_array(vec![init.clone(); len])));
+                    }
+                    // Root the array on the stack while the initializer runs; the
+                    // native call's cleanup pops it
+                    let handle = vm.heap.alloc_array(Vec::with_capacity(len));
+                    vm.push(Value::Array(handle));
+                    for _ in 0..len {
+                        let item = vm.call_value_reentrant(init.clone(), Vec::new())
+                            .map_err(|e| e.kind.to_string())?;
+                        vm.heap.get_array_mut(handle).ok_or("Array not found (GC error?)")?.push(item);
+                    }
+                    Ok(Value::Array(handle))
+                },
+            ),
+            
+            // fill(arr, v) - Overwrite every element with v, returns arr
+            NativeFn::new(
+                "fill",
+                Some(2),
+                |vm, args| {
+                    let handle = array_handle("fill", &args[0])?;
+                    let arr = vm.heap.get_array_mut(handle).ok_or("Array not found (GC error?)")?;
+                    arr.fill(args[1].clone());
+                    Ok(args[0].clone())
+                },
+            ),
+            
+            // sort(arr, cmp?) - New array sorted ascending, or by cmp(a, b) < 0 meaning a first
+            NativeFn::new(
+                "sort",
+                None,
+                |vm, args| {
+                    let sorted = sorted_items(vm, "sort", args)?;
+                    Ok(Value::Array(vm.heap.alloc_array(sorted)))
+                },
+            ),
+            
+            // sort_in_place(arr, cmp?) - Sort arr itself, returns arr
+            NativeFn::new(
+                "sort_in_place",
+                None,
+                |vm, args| {
+                    let sorted = sorted_items(vm, "sort_in_place", args)?;
+                    let Value::Array(handle) = &args[0] else {
+                        unreachable!("sorted_items checks for an array");
+                    };
+                    let arr = vm.heap.get_array_mut(*handle).ok_or("Array not found (GC error?)")?;
+                    *arr = sorted;
+                    Ok(args[0].clone())
+                },
+            ),
+            
+            // reverse(arr) - New array with the elements in reverse order
+            NativeFn::new(
+                "reverse",
+                Some(1),
+                |vm, args| {
+                    let handle = array_handle("reverse", &args[0])?;
+                    let mut items = vm.heap.get_array(handle).ok_or("Array not found (GC error?)")?.clone();
+                    items.reverse();
+                    Ok(Value::Array(vm.heap.alloc_array(items)))
+                },
+            ),
+            
+            // deep_equal(a, b) - Like ==, but arrays and maps compare by contents
+            NativeFn::new(
+                "deep_equal",
+                Some(2),
+                |vm, args| Ok(Value::Bool(vm.deep_equal(&args[0], &args[1]))),
+            ),
+            
+            // zip(a, b) - Array of [a[i], b[i]] pairs, as long as the shorter input
+            NativeFn::new(
+                "zip",
+                Some(2),
+                |vm, args| {
+                    let left = array_handle("zip", &args[0])?;
+                    let right = array_handle("zip", &args[1])?;
+                    let left = vm.heap.get_array(left).ok_or("Array not found (GC error?)")?.clone();
+                    let right = vm.heap.get_array(right).ok_or("Array not found (GC error?)")?.clone();
+                    let pairs = left.into_iter().zip(right).map(|(a, b)| vec![a, b]).collect();
+                    Ok(Value::Array(alloc_pairs(vm, pairs)))
+                },
+            ),
+            
+            // enumerate(arr) - Array of [index, element] pairs, counting from 0
+            NativeFn::new(
+                "enumerate",
+                Some(1),
+                |vm, args| {
+                    let handle = array_handle("enumerate", &args[0])?;
+                    let items = vm.heap.get_array(handle).ok_or("Array not found (GC error?)")?.clone();
+                    let pairs = items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, item)| vec![Value::Number(i as f64), item])
+                        .collect();
+                    Ok(Value::Array(alloc_pairs(vm, pairs)))
+                },
+            ),
+            
+            // map() - New empty map; map(arr, f) - New array of f applied to each element
+            NativeFn::new(
+                "map",
+                None,
+                |vm, args| {
+                    match args {
+                        [] => Ok(Value::Map(vm.heap.alloc_map(HashMap::new()))),
+                        [array, f] => {
+                            let source = array_handle("map", array)?;
+                            let handle = vm.heap.alloc_array(Vec::new());
+                            // Rooted on the stack while callbacks run; the call's cleanup pops it
+                            vm.push(Value::Array(handle));
+                            // Elements the callback appends are not visited, so it can't loop forever
+                            for index in 0..array_len(vm, source) {
+                                let Some(item) = array_element(vm, source, index) else { break };
+                                let mapped = vm.call_value_reentrant(f.clone(), vec![item])
+                                    .map_err(|e| e.kind.to_string())?;
+                                vm.heap.get_array_mut(handle).ok_or("Array not found (GC error?)")?.push(mapped);
+                            }
+                            Ok(Value::Array(handle))
+                        }
+                        _ => Err("map() takes 0 or 2 arguments".to_string()),
+                    }
+                },
+            ),
+            
+            // filter(arr, f) - New array of the elements for which f returns a truthy value
+            NativeFn::new(
+                "filter",
+                Some(2),
+                |vm, args| {
+                    let source = array_handle("filter", &args[0])?;
+                    let handle = vm.heap.alloc_array(Vec::new());
+                    vm.push(Value::Array(handle));
+                    for index in 0..array_len(vm, source) {
+                        let Some(item) = array_element(vm, source, index) else { break };
+                        let keep = vm.call_value_reentrant(args[1].clone(), vec![item.clone()])
+                            .map_err(|e| e.kind.to_string())?;
+                        if keep.is_truthy() {
+                            vm.heap.get_array_mut(handle).ok_or("Array not found (GC error?)")?.push(item);
+                        }
+                    }
+                    Ok(Value::Array(handle))
+                },
+            ),
+            
+            // reduce(arr, f, init) - Fold the elements left to right with f(acc, element)
+            NativeFn::new(
+                "reduce",
+                Some(3),
+                |vm, args| {
+                    let source = array_handle("reduce", &args[0])?;
+                    let mut acc = args[2].clone();
+                    for index in 0..array_len(vm, source) {
+                        let Some(item) = array_element(vm, source, index) else { break };
+                        acc = vm.call_value_reentrant(args[1].clone(), vec![acc, item])
+                            .map_err(|e| e.kind.to_string())?;
+                    }
+                    Ok(acc)
+                },
+            ),
+            
+            // get(m, k) - Value stored under k, or nil
+            NativeFn::new(
+                "get",
+                Some(2),
+                |vm, args| {
+                    let (handle, key) = map_and_key("get", args)?;
+                    let map = vm.heap.get_map(handle).ok_or("Map not found (GC error?)")?;
+                    Ok(map.get(key).cloned().unwrap_or(Value::Nil))
+                },
+            ),
+            
+            // set(m, k, v) - Store v under k, returns v
+            NativeFn::new(
+                "set",
+                Some(3),
+                |vm, args| {
+                    let (handle, key) = map_and_key("set", args)?;
+                    let map = vm.heap.get_map_mut(handle).ok_or("Map not found (GC error?)")?;
+                    map.insert(key.to_string(), args[2].clone());
+                    Ok(args[2].clone())
+                },
+            ),
+            
+            // has(m, k) - Whether k is present
+            NativeFn::new(
+                "has",
+                Some(2),
+                |vm, args| {
+                    let (handle, key) = map_and_key("has", args)?;
+                    let map = vm.heap.get_map(handle).ok_or("Map not found (GC error?)")?;
+                    Ok(Value::Bool(map.contains_key(key)))
+                },
+            ),
+            
+            // delete(m, k) - Remove k, returns its value or nil
+            NativeFn::new(
+                "delete",
+                Some(2),
+                |vm, args| {
+                    let (handle, key) = map_and_key("delete", args)?;
+                    let map = vm.heap.get_map_mut(handle).ok_or("Map not found (GC error?)")?;
+                    Ok(map.remove(key).unwrap_or(Value::Nil))
+                },
+            ),
+            
+            // keys(m) - Array of the keys, sorted
+            NativeFn::new(
+                "keys",
+                Some(1),
+                |vm, args| {
+                    let Value::Map(handle) = &args[0] else {
+                        return Err(format!("keys() requires a map, got {}", args[0].type_name()));
+                    };
+                    let map = vm.heap.get_map(*handle).ok_or("Map not found (GC error?)")?;
+                    let keys = sorted_keys(map);
+                    Ok(Value::Array(vm.heap.alloc_array(keys)))
+                },
+            ),
+            
+            // clock_ms() - Wall-clock milliseconds since the Unix epoch
+            NativeFn::new(
+                "clock_ms",
+                Some(0),
+                |vm, _args| {
+                    if let Some(fake) = vm.fake_clock_ms.as_mut() {
+                        *fake += DETERMINISTIC_TICK_MS;
+                        return Ok(Value::Number(*fake));
+                    }
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_err(|e| format!("clock_ms(): {}", e))?;
+                    Ok(Value::Number(now.as_millis() as f64))
+                },
+            ),
+            
+            // sleep_ms(ms) - Pause execution
+            NativeFn::new(
+                "sleep_ms",
+                Some(1),
+                |vm, args| {
+                    let ms = match &args[0] {
+                        Value::Number(n) if *n >= 0.0 => *n,
+                        _ => return Err("sleep_ms() requires a non-negative number".to_string()),
+                    };
+                    let duration = std::time::Duration::try_from_secs_f64(ms / 1000.0)
+                        .map_err(|_| format!("sleep_ms() cannot sleep for {} ms", ms))?;
+                    match vm.fake_clock_ms.as_mut() {
+                        Some(fake) => *fake += ms,
+                        None => std::thread::sleep(duration),
+                    }
+                    Ok(Value::Nil)
+                },
+            ),
+            
+            // rand() - Uniform random number in [0, 1)
+            NativeFn::new(
+                "rand",
+                Some(0),
+                |vm, _args| Ok(Value::Number(vm.rng.next_f64())),
+            ),
+            
+            // read_file(path) - The whole file as a string
+            NativeFn::new(
+                "read_file",
+                Some(1),
+                |vm, args| {
+                    let path = io_path(vm, "read_file", &args[0])?;
+                    let text = std::fs::read_to_string(path)
+                        .map_err(|e| format!("read_file() cannot read '{}': {}", path, e))?;
+                    Ok(Value::String(text.into()))
+                },
+            ),
+            
+            // write_file(path, content) - Create or replace a file
+            NativeFn::new(
+                "write_file",
+                Some(2),
+                |vm, args| {
+                    let path = io_path(vm, "write_file", &args[0])?;
+                    let content = string_arg("write_file", &args[1])?;
+                    std::fs::write(path, content)
+                        .map_err(|e| format!("write_file() cannot write '{}': {}", path, e))?;
+                    Ok(Value::Nil)
+                },
+            ),
+            
+            // append_file(path, content) - Add to the end of a file, creating it if needed
+            NativeFn::new(
+                "append_file",
+                Some(2),
+                |vm, args| {
+                    let path = io_path(vm, "append_file", &args[0])?;
+                    let content = string_arg("append_file", &args[1])?;
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .and_then(|mut file| file.write_all(content.as_bytes()))
+                        .map_err(|e| format!("append_file() cannot write '{}': {}", path, e))?;
+                    Ok(Value::Nil)
                 },
             ),
             
-            // snapshot() - Get current logical time
+            // read_line() - Next line of input without its line ending, or nil at the end
             NativeFn::new(
-                "snapshot",
+                "read_line",
                 Some(0),
                 |vm, _args| {
-                    Ok(Value::Number(vm.causality.current_time() as f64))
+                    check_io(vm, "read_line")?;
+                    let mut line = String::new();
+                    let read = vm.input.read_line(&mut line)
+                        .map_err(|e| format!("read_line() cannot read input: {}", e))?;
+                    if read == 0 {
+                        return Ok(Value::Nil);
+                    }
+                    let end = line.trim_end_matches(['\n', '\r']).len();
+                    line.truncate(end);
+                    Ok(Value::String(line.into()))
+                },
+            ),
+            
+            // args() - Command-line arguments given after the script name
+            NativeFn::new(
+                "args",
+                Some(0),
+                |vm, _args| {
+                    let items = vm.args.iter().map(|arg| Value::String(arg.as_str().into())).collect();
+                    Ok(Value::Array(vm.heap.alloc_array(items)))
                 },
             ),
         ];
 
         for native in natives {
-            let name = native.name.clone();
-            self.globals.insert(name, Binding {
-                value: Value::NativeFunction(native),
-                is_state: false,
-            });
+            self.register_native(native);
+        }
+    }
+    
+    /// Register `f` as a native called `name`, taking `arity` arguments (any
+    /// number when `None`). Unlike a plain function, `f` may capture host state.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        f: impl Fn(&mut VM, &[Value]) -> std::result::Result<Value, String> + 'static,
+    ) {
+        self.register_native(NativeFn::new(name, arity, f));
+    }
+    
+    /// Make a host function callable from scripts under its name, replacing
+    /// any global of that name
+    pub fn register_native(&mut self, native: NativeFn) {
+        let name = Rc::clone(&native.name);
+        self.globals.insert(name, Binding {
+            value: Value::NativeFunction(native),
+            kind: BindingKind::Native,
+        });
+    }
+    
+    /// Redirect program output (`print`, REPL notices)
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+    
+    /// Allow `read_file`, `write_file`, `append_file` and `read_line`. Off by
+    /// default, so embedded scripts can't touch the file system or stdin
+    /// unless the host opts in.
+    pub fn enable_io(&mut self, enabled: bool) {
+        self.io_enabled = enabled;
+    }
+    
+    /// Where `read_line()` reads from, stdin by default
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+    
+    /// The arguments `args()` returns
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+    
+    /// Resolve imports in source passed to `eval` against `path` instead of
+    /// the working directory
+    pub fn set_base_path(&mut self, path: impl Into<std::path::PathBuf>) {
+        self.base_path = Some(path.into());
+    }
+    
+    /// Enable REPL behaviour: redefining a global with a different kind prints a notice
+    pub fn set_repl_mode(&mut self, enabled: bool) {
+        self.repl_mode = enabled;
+    }
+    
+    /// Collect garbage before every instruction. Very slow; meant for tests
+    /// that check every live object is reachable from a root.
+    pub fn set_gc_stress(&mut self, enabled: bool) {
+        self.heap.set_stress(enabled);
+    }
+    
+    /// Limit the heap to `max` bytes. A program that needs more, even after a
+    /// collection, fails with `ErrorKind::OutOfMemory`.
+    pub fn set_max_heap(&mut self, max: Option<usize>) {
+        self.heap.set_max_bytes(max);
+    }
+    
+    /// Limit how many more instructions may run, across every later run, or
+    /// lift the limit with `None`. Running out fails with
+    /// `ErrorKind::FuelExhausted`, which stops scripts that never finish.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+    
+    /// Instructions that may still run, if the budget is limited
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+    
+    /// Choose whether failing asserts abort the program or are collected
+    pub fn set_assert_mode(&mut self, mode: AssertMode) {
+        self.assert_mode = mode;
+    }
+    
+    /// Assertion failures collected so far, in the order they happened
+    pub fn assert_failures(&self) -> &[AssertFailure] {
+        &self.assert_failures
+    }
+    
+    /// Take the collected assertion failures, leaving the list empty
+    pub fn take_assert_failures(&mut self) -> Vec<AssertFailure> {
+        std::mem::take(&mut self.assert_failures)
+    }
+    
+    /// Append causality events to `writer` as they are recorded.
+    ///
+    /// Streamed events are evicted from memory once they fall outside the
+    /// retention window, so long runs keep a flat footprint.
+    pub fn stream_causality_to(&mut self, writer: Box<dyn Write>, format: ExportFormat) -> Result<()> {
+        self.causality.stream_to(writer, format).map_err(|e| SkyHetuError::new(
+            ErrorKind::RuntimeError(format!("cannot stream causality log: {}", e)),
+            None,
+        ))
+    }
+    
+    /// Current value of a global, if defined
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).map(|b| b.value.clone())
+    }
+    
+    /// Current value of the local `name` in the innermost call, as of the
+    /// instruction about to run. Only code compiled with debug info knows the
+    /// names of its locals; elsewhere this is `None`.
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        let frame = self.frames.last()?;
+        let slot = frame.chunk.local_slot(frame.ip, name)?;
+        self.stack.get(frame.slot + slot).cloned()
+    }
+    
+    /// Bind a host-provided value to a global, as if declared with `let`
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.into(), Binding { value, kind: BindingKind::Let });
+    }
+    
+    /// Bind a host-provided value to a global `state`, recording it as the
+    /// variable's initial value
+    pub fn set_state(&mut self, name: &str, value: Value) {
+        self.causality.record_init(name, value.clone(), Some("set by host".to_string()));
+        self.globals.insert(name.into(), Binding { value, kind: BindingKind::State });
+    }
+    
+    /// Names and kinds of all currently defined globals, sorted by name
+    pub fn global_kinds(&self) -> Vec<(String, BindingKind)> {
+        let mut kinds: Vec<(String, BindingKind)> = self.globals
+            .iter()
+            .map(|(name, binding)| (name.to_string(), binding.kind))
+            .collect();
+        kinds.sort_by(|a, b| a.0.cmp(&b.0));
+        kinds
+    }
+    
+    /// Every global with its kind and current value, sorted by name
+    pub fn globals_iter(&self) -> impl Iterator<Item = (&str, BindingKind, &Value)> {
+        let mut globals: Vec<(&str, BindingKind, &Value)> = self.globals
+            .iter()
+            .map(|(name, binding)| (&**name, binding.kind, &binding.value))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(b.0));
+        globals.into_iter()
+    }
+    
+    fn write_output(&mut self, text: &str) -> Result<()> {
+        writeln!(self.output, "{}", text).map_err(|e| SkyHetuError::new(
+            ErrorKind::RuntimeError(format!("cannot write output: {}", e)),
+            None,
+        ))
+    }
+    
+    /// Record a state transition with its source line and the state variables
+    /// listed by its causes operand, surfacing any failure of the causality stream
+    fn record_transition(&mut self, name: &str, old_value: Value, new_value: Value, causes: u16) -> Result<()> {
+        let location = format!("line {}", self.current_line());
+        let caused_by = self.current_chunk().causes(causes).to_vec();
+        let (old_value, new_value) = (self.logged_copy(&old_value), self.logged_copy(&new_value));
+        self.causality.record_caused_mutation(name, old_value, new_value, Some(location), caused_by);
+        self.check_causality_stream()
+    }
+    
+    /// Call the watchers of `name` with the value it had and the one it now holds.
+    /// A watcher whose transitions lead back to `name` is an error, not a loop.
+    fn notify_watchers(&mut self, name: &str, old_value: Value, new_value: Value) -> Result<()> {
+        let Some(watchers) = self.watchers.get(name) else {
+            return Ok(());
+        };
+        if self.notifying.iter().any(|n| n == name) {
+            return Err(SkyHetuError::new(
+                ErrorKind::RuntimeError(format!("watcher of '{}' transitioned '{}' again", self.notifying[0], name)),
+                None,
+            ));
+        }
+        let watchers = watchers.clone();
+        self.notifying.push(name.to_string());
+        let result = watchers.into_iter().try_for_each(|watcher| {
+            self.call_value_reentrant(watcher, vec![old_value.clone(), new_value.clone()]).map(|_| ())
+        });
+        self.notifying.pop();
+        result
+    }
+    
+    /// A copy of an array or map for the causality log, so writes to the live
+    /// container don't rewrite its history. Other values are shared as they are.
+    fn logged_copy(&mut self, value: &Value) -> Value {
+        match value {
+            Value::Array(handle) => match self.heap.get_array(*handle) {
+                Some(items) => {
+                    let items = items.to_vec();
+                    Value::Array(self.heap.alloc_array_as(items, AllocCategory::CausalitySnapshot))
+                }
+                None => value.clone(),
+            },
+            Value::Map(handle) => match self.heap.get_map(*handle) {
+                Some(map) => {
+                    let map = map.clone();
+                    Value::Map(self.heap.alloc_map_as(map, AllocCategory::CausalitySnapshot))
+                }
+                None => value.clone(),
+            },
+            _ => value.clone(),
+        }
+    }
+    
+    /// Value of a state variable at logical time `timestamp`: its initial value
+    /// before the declaration, its current value after the last transition.
+    /// An object that is no longer on the heap is an error for native `caller`
+    /// rather than a handle to whatever reuses its slot. Arrays and maps come
+    /// back as fresh copies, so changing them leaves the log as it was.
+    fn state_value_at(&mut self, caller: &str, name: &str, timestamp: usize) -> std::result::Result<Option<Value>, String> {
+        let value = self.causality.value_at(name, timestamp)
+            .or_else(|| self.causality.history(name).first().map(|first| first.new_value.clone()));
+        match value.as_ref().and_then(Value::handle) {
+            Some(handle) if !self.heap.is_live(handle) => Err(format!(
+                "{}() cannot restore '{}' at t={}: its {} was garbage collected",
+                caller, name, timestamp, value.as_ref().map_or("value", Value::type_name)
+            )),
+            _ => Ok(value.map(|value| self.logged_copy(&value))),
+        }
+    }
+    
+    /// Causality log name of an instance field: `Class#id.field`, where `id` is the
+    /// instance's `id()`. Ids are never reused, so a collected instance's history
+    /// can't merge into a later one's.
+    fn field_key(&self, instance: crate::gc::Handle, field: &str) -> String {
+        let class = self.heap.get_instance(instance)
+            .and_then(|i| self.heap.get_class(i.class))
+            .map_or("<instance>", |c| c.name.as_str());
+        let id = self.heap.object_id(instance).unwrap_or(0);
+        format!("{}#{}.{}", class, id, field)
+    }
+    
+    /// Error for a global that doesn't exist, suggesting similarly named ones.
+    /// Module-private globals (`path::name`) are never suggested.
+    fn undefined_variable(&self, name: &str) -> SkyHetuError {
+        let candidates = self.globals.keys().map(|g| &**g).filter(|g| !g.contains("::"));
+        let suggestions = crate::error::suggest(name, candidates);
+        SkyHetuError::new(ErrorKind::UndefinedVariable(name.to_string()), None).with_suggestions(suggestions)
+    }
+    
+    /// Causality log name of a `state` parameter in the current call: `function#call.param`
+    fn param_key(&self, param: &str) -> String {
+        let frame = self.current_frame();
+        let function = self.heap.get_closure(frame.closure)
+            .and_then(|c| self.heap.get_function(c.function))
+            .map_or("<fn>", |f| f.name.as_str());
+        format!("{}#{}.{}", function, frame.call_id, param)
+    }
+    
+    /// Whether `value` matches a parameter annotation: a built-in type name,
+    /// or the name of a class the value is an instance of, directly or by inheritance
+    fn has_type(&self, value: &Value, type_name: &str) -> bool {
+        match type_name {
+            "number" | "string" | "bool" | "array" | "map" => value.type_name() == type_name,
+            "function" => matches!(
+                value,
+                Value::Function(_) | Value::Closure(_) | Value::NativeFunction(_) | Value::BoundMethod(_)
+            ),
+            _ => {
+                let Value::Instance(handle) = value else { return false };
+                let mut class = self.heap.get_instance(*handle).map(|instance| instance.class);
+                while let Some(handle) = class {
+                    let Some(found) = self.heap.get_class(handle) else { break };
+                    if found.name == type_name {
+                        return true;
+                    }
+                    class = found.superclass;
+                }
+                false
+            }
+        }
+    }
+    
+    /// Type of a value as an annotation would name it: instances by their class
+    fn annotated_type_name(&self, value: &Value) -> String {
+        match value {
+            Value::Instance(handle) => self.heap.get_instance(*handle)
+                .and_then(|instance| self.heap.get_class(instance.class))
+                .map_or_else(|| "instance".to_string(), |class| class.name.clone()),
+            _ => value.type_name().to_string(),
+        }
+    }
+    
+    /// Record a state declaration with its initial value and source line
+    fn record_init(&mut self, name: &str, value: Value) -> Result<()> {
+        let location = format!("line {}", self.current_line());
+        let value = self.logged_copy(&value);
+        self.causality.record_init(name, value, Some(location));
+        self.check_causality_stream()
+    }
+    
+    fn check_causality_stream(&mut self) -> Result<()> {
+        match self.causality.take_stream_error() {
+            Some(e) => Err(SkyHetuError::new(
+                ErrorKind::RuntimeError(format!("cannot stream causality log: {}", e)),
+                None,
+            )),
+            None => Ok(()),
+        }
+    }
+    
+    fn define_global(&mut self, name: Rc<str>, value: Value, kind: BindingKind) -> Result<()> {
+        if self.repl_mode {
+            let previous = self.globals.get(&name).map(|b| b.kind);
+            if let Some(old) = previous {
+                if old != kind {
+                    let notice = format!("note: {}", redefinition_message(&name, old, kind));
+                    self.write_output(&notice)?;
+                }
+            }
         }
+        self.globals.insert(name, Binding { value, kind });
+        Ok(())
     }
     
     /// Run bytecode
     pub fn run(&mut self, chunk: Chunk) -> Result<Value> {
+        self.start(chunk);
+        let result = self.execute().map_err(|e| self.locate(e));
+        // Observers see the final state, not the last periodic snapshot
+        self.publish_snapshot();
+        result
+    }
+    
+    /// `run`, calling `hook` before each instruction where execution should
+    /// stop: the first one, then wherever the action the hook last returned
+    /// and the breakpoints say. Callbacks that natives such as `map` call run
+    /// without stopping.
+    pub fn run_with_hook(&mut self, chunk: Chunk, hook: &mut dyn FnMut(&VM, DebugEvent) -> DebugAction) -> Result<Value> {
+        self.start(chunk);
+        let mut stepper = Stepper::new();
+        let result = loop {
+            let frame = self.current_frame();
+            let line = frame.chunk.line(frame.ip);
+            let depth = self.frames.len();
+            if let Some(at_breakpoint) = stepper.check(line, depth, &self.breakpoints) {
+                let opcode = OpCode::from(frame.chunk.code()[frame.ip]);
+                let action = hook(self, DebugEvent { line, opcode, depth, at_breakpoint });
+                stepper.resume(action, line, depth);
+            }
+            match self.step(0) {
+                Ok(Some(value)) => break Ok(value),
+                Ok(None) => {}
+                Err(e) => break Err(self.locate(e)),
+            }
+        };
+        self.publish_snapshot();
+        result
+    }
+    
+    /// Shared handle to the lines `run_with_hook` stops at
+    pub fn breakpoints(&self) -> Breakpoints {
+        self.breakpoints.clone()
+    }
+    
+    /// The value stack, bottom first: locals and temporaries of every active call
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+    
+    /// Depth of the value stack, for checking that code leaves nothing behind
+    pub fn stack_len(&self) -> usize {
+        self.stack.len()
+    }
+    
+    /// Set up a fresh script frame for `chunk`
+    fn start(&mut self, chunk: Chunk) {
+        // A previous run that failed leaves its frames and temporaries behind
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.stack_fault = None;
+        
         let chunk = Rc::new(chunk);
         let function = crate::value::Function::new(
             "<script>".to_string(),
@@ -377,29 +1911,153 @@ impl VM {
             chunk,
             0,
         ));
-        
-        self.execute()
     }
     
-    /// Register compiled function chunks
-    pub fn register_chunks(&mut self, chunks: Vec<Chunk>) {
-        for chunk in chunks {
-            self.function_chunks.push(Rc::new(chunk));
+    /// Compile and run `source` against this VM's globals, as the REPL does for each line.
+    /// Imports are resolved relative to the current directory.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        self.eval_in_place(source, &mut Timings::default())
+            .map_err(|e| e.with_source(source))
+    }
+    
+    /// `eval` without the source excerpt on errors, for `run_in`
+    pub(crate) fn eval_in_place(&mut self, source: &str, timings: &mut Timings) -> Result<Value> {
+        self.eval_with_base(source, self.base_path.clone(), timings)
+    }
+    
+    /// Compile and run a file against this VM's globals, so its definitions stay
+    /// available afterwards. Imports inside it resolve relative to the file.
+    pub fn load_file(&mut self, path: impl AsRef<std::path::Path>) -> Result<Value> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| SkyHetuError::new(
+            ErrorKind::RuntimeError(format!("cannot read file '{}': {}", path.display(), e)),
+            None,
+        ))?;
+        let base = path.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| std::path::PathBuf::from("."));
+        self.eval_with_base(&source, Some(base), &mut Timings::default())
+            .map_err(|e| e.with_source(&source))
+    }
+    
+    /// A compiler for code run against this VM's globals, resolving imports
+    /// against the VM's base path
+    pub(crate) fn session_compiler(&self) -> crate::compiler::Compiler {
+        let mut compiler = self.compiler_for(self.base_path.clone());
+        compiler.set_allow_redefine(true);
+        compiler
+    }
+    
+    /// A compiler for a program run on this VM, resolving imports against
+    /// `base_path` and knowing every global the VM defines, natives included
+    pub fn program_compiler(&self, base_path: std::path::PathBuf) -> crate::compiler::Compiler {
+        let mut compiler = crate::compiler::Compiler::with_base_path(base_path);
+        for (name, kind) in self.global_kinds() {
+            compiler.declare_global(&name, kind);
         }
+        compiler
+    }
+    
+    /// A compiler that knows this VM's constants, so their uses are inlined
+    fn compiler_for(&self, base_path: Option<std::path::PathBuf>) -> crate::compiler::Compiler {
+        let mut compiler = match base_path {
+            Some(base) => crate::compiler::Compiler::with_base_path(base),
+            None => crate::compiler::Compiler::new(),
+        };
+        for (name, kind) in self.global_kinds() {
+            if kind == BindingKind::Const {
+                if let Some(value) = self.get_global(&name) {
+                    compiler.declare_const(&name, value);
+                }
+            }
+        }
+        compiler
+    }
+    
+    fn eval_with_base(&mut self, source: &str, base_path: Option<std::path::PathBuf>, timings: &mut Timings) -> Result<Value> {
+        let start = Instant::now();
+        let tokens = crate::lexer::Lexer::new(source).tokenize()?;
+        timings.lex = start.elapsed();
+        
+        let start = Instant::now();
+        let program = crate::parser::Parser::new(tokens).parse()?;
+        timings.parse = start.elapsed();
+        
+        let start = Instant::now();
+        let mut compiler = self.compiler_for(base_path);
+        let compiled = compiler.compile(&program, &mut self.heap);
+        timings.compile = start.elapsed();
+        timings.modules = compiler.module_timings().to_vec();
+        let chunk = compiled?;
+        
+        let start = Instant::now();
+        let gc_before = self.gc_time;
+        let result = self.run(chunk);
+        timings.execute = start.elapsed();
+        timings.gc = self.gc_time - gc_before;
+        result
     }
     
     pub fn collect_garbage(&mut self) {
+        let start = Instant::now();
+        
         // 1. Mark roots
         self.mark_roots();
         
         // 2. Trace references (Blacken)
         self.heap.trace_references();
         
+        // Prune upvalues that weren't marked (no longer reachable). This has to
+        // happen before the sweep, which clears the marks.
+        self.open_upvalues.retain(|&handle| self.heap.is_marked(handle));
+        
         // 3. Sweep
         self.heap.sweep();
         
-        // Prune upvalues that weren't marked (no longer reachable)
-        self.open_upvalues.retain(|&handle| self.heap.is_marked(handle));
+        self.gc_time += start.elapsed();
+    }
+    
+    /// Total time spent in garbage collection pauses
+    pub fn gc_time(&self) -> Duration {
+        self.gc_time
+    }
+    
+    /// The instruction just executed took the heap over its limit with an
+    /// allocation of `size` bytes: collect, and fail if that didn't make room
+    fn enforce_heap_limit(&mut self, size: usize) -> Result<()> {
+        self.collect_garbage();
+        if self.heap.has_room(0) {
+            return Ok(());
+        }
+        Err(self.out_of_memory(size))
+    }
+    
+    fn out_of_memory(&self, size: usize) -> SkyHetuError {
+        let limit = self.heap.max_bytes().unwrap_or(usize::MAX);
+        SkyHetuError::new(ErrorKind::OutOfMemory(size, limit), None)
+    }
+    
+    /// For natives about to allocate `size` bytes at once: collect if they
+    /// wouldn't fit, and refuse if they still don't. Only safe before the
+    /// native has allocated anything, while its arguments are still rooted on
+    /// the stack; the native call reports the refusal as out of memory.
+    fn reserve_heap(&mut self, size: usize) -> std::result::Result<(), String> {
+        if self.heap.has_room(size) {
+            return Ok(());
+        }
+        self.collect_garbage();
+        if self.heap.has_room(size) {
+            return Ok(());
+        }
+        self.heap.refuse(size);
+        Err(self.out_of_memory(size).kind.to_string())
+    }
+    
+    /// Strings live outside the heap, but one longer than the heap limit
+    /// can't be a reasonable result either
+    fn check_string_len(&self, len: usize) -> Result<()> {
+        match self.heap.max_bytes() {
+            Some(max) if len > max => Err(self.out_of_memory(len)),
+            _ => Ok(()),
+        }
     }
     
     fn capture_upvalue(&mut self, location: usize) -> crate::gc::Handle {
@@ -472,12 +2130,25 @@ impl VM {
             }
         }
         
-        // Functions (Chunks)
-        // We need to trace constants in all chunks because functions might be running
-        // or reachable via call frames.
-        for chunk in &self.function_chunks {
-            for constant in &chunk.constants {
-                for child in constant.children() {
+        // Running closures; their functions trace the constants of their chunks.
+        // Open upvalues are not roots: one that no live closure holds is
+        // pruned below and recreated if the slot is captured again.
+        for frame in &self.frames {
+            self.heap.mark(frame.closure);
+        }
+        
+        // Watchers
+        for watcher in self.watchers.values().flatten() {
+            for child in watcher.children() {
+                self.heap.mark(child);
+            }
+        }
+        
+        // Retained causality events, so why(), history() and at() can still
+        // show old values. Eviction digests only hold numbers.
+        for event in self.causality.all_events() {
+            for value in [&event.old_value, &event.new_value] {
+                for child in value.children() {
                     self.heap.mark(child);
                 }
             }
@@ -485,579 +2156,947 @@ impl VM {
     }
     
     fn execute(&mut self) -> Result<Value> {
+        self.execute_until(0)
+    }
+    
+    /// Run until the frame that sits just above `base` frames returns, yielding its result
+    fn execute_until(&mut self, base: usize) -> Result<Value> {
         loop {
-            if self.frames.is_empty() {
-                return Ok(self.stack.pop().unwrap_or(Value::Nil));
+            if let Some(result) = self.step(base)? {
+                return Ok(result);
             }
-            
-            let op = self.read_byte();
-            let opcode = OpCode::from(op);
-
-            // GC Check
-            if self.heap.should_collect() {
-                // println!("-- Triggering GC --"); // Debug
-                self.collect_garbage();
+        }
+    }
+    
+    /// Execute one instruction, or yield the result once the frame above
+    /// `base` has returned
+    #[inline(always)]
+    fn step(&mut self, base: usize) -> Result<Option<Value>> {
+        if self.frames.len() <= base {
+            return Ok(Some(self.stack.pop().unwrap_or(Value::Nil)));
+        }
+        
+        let op = self.read_byte();
+        let opcode = OpCode::from(op);
+        
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(SkyHetuError::new(ErrorKind::FuelExhausted, None));
             }
+            *fuel -= 1;
+        }
 
+        // GC Check
+        if self.heap.should_collect() {
+            // println!("-- Triggering GC --"); // Debug
+            self.collect_garbage();
+        }
+        
+        if self.observation.as_mut().is_some_and(Observation::tick) {
+            self.publish_snapshot();
+        }
+        
+        match opcode {
+            OpCode::Constant => {
+                let idx = self.read_u16();
+                let value = self.current_chunk().constants()[idx as usize].clone();
+                self.push(value);
+            }
             
-            match opcode {
-                OpCode::Constant => {
-                    let idx = self.read_u16();
-                    let value = self.current_chunk().constants[idx as usize].clone();
-                    self.push(value);
-                }
-                
-                OpCode::Nil => self.push(Value::Nil),
-                OpCode::True => self.push(Value::Bool(true)),
-                OpCode::False => self.push(Value::Bool(false)),
-                
-                OpCode::Pop => { self.pop(); }
-                
-                OpCode::Dup => {
-                    let val = self.peek(0).clone();
-                    self.push(val);
-                }
-                
-                OpCode::DefineGlobal => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let value = self.pop();
-                    self.globals.insert(name, Binding { value, is_state: false });
-                }
-                
-                OpCode::DefineState => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let value = self.pop();
-                    self.globals.insert(name, Binding { value, is_state: true });
-                }
-                
-                OpCode::GetGlobal => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let value = self.globals.get(&name)
-                        .ok_or_else(|| SkyHetuError::new(
-                            ErrorKind::UndefinedVariable(name.clone()),
-                            None,
-                        ))?
-                        .value
-                        .clone();
-                    self.push(value);
-                }
-                
-                OpCode::SetGlobal => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let value = self.peek(0).clone();
-                    
-                    if let Some(binding) = self.globals.get_mut(&name) {
-                        if !binding.is_state {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::ImmutableVariable(name),
-                                None,
-                            ));
-                        }
-                        binding.value = value;
-                    } else {
-                        return Err(SkyHetuError::new(
-                            ErrorKind::UndefinedVariable(name),
-                            None,
-                        ));
-                    }
-                }
-                
-                OpCode::Transition => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let new_value = self.pop();
-                    
-                    if let Some(binding) = self.globals.get_mut(&name) {
-                        if !binding.is_state {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::ImmutableVariable(name),
-                                None,
-                            ));
-                        }
-                        
-                        let old_value = binding.value.clone();
-                        
-                        // Record causality
-                        self.causality.record_mutation(
-                            &name,
-                            old_value,
-                            new_value.clone(),
-                            None,
-                        );
-                        
-                        binding.value = new_value;
-                    } else {
-                        return Err(SkyHetuError::new(
-                            ErrorKind::UndefinedVariable(name),
-                            None,
-                        ));
-                    }
-                }
-
-                OpCode::TransitionLocal => {
-                    let slot = self.read_u16() as usize;
-                    let frame_slot = self.current_frame().slot;
-                    let stack_idx = frame_slot + slot;
-                    let new_value = self.pop();
-                    
-                    // We don't have the name of local easily for causality log?
-                    // We can reconstruct it or pass it?
-                    // Passing it would require add_name call in compiler.
-                    // For now, let's use "local_state_{slot}" or similar if needed,
-                    // BUT CausalityLog expects names.
-                    // To do this properly, the compiler should pass the name too.
-                    // Or we just update value and log with generic name.
-                    // Ideally, TransitionLocal should take (slot, name_idx).
-                    // Bytecode: TransitionLocal(slot: u16, name: u16).
-                    
-                    // Let's modify opcode usage to include name index.
-                    let name_idx = self.read_u16();
-                    let name = self.get_name(name_idx);
-                    
-                    let old_value = self.stack[stack_idx].clone();
-                    
-                    self.causality.record_mutation(
-                        &name,
-                        old_value,
-                        new_value.clone(),
-                        None, 
-                    );
-                    
-                    self.stack[stack_idx] = new_value;
-                }
-                
-                OpCode::GetLocal => {
-                    let slot = self.read_u16() as usize;
-                    let frame_slot = self.current_frame().slot;
-                    let value = self.stack[frame_slot + slot].clone();
-                    self.push(value);
-                }
-                
-                OpCode::SetLocal => {
-                    let slot = self.read_u16() as usize;
-                    let frame_slot = self.current_frame().slot;
-                    let value = self.peek(0).clone();
-                    self.stack[frame_slot + slot] = value;
-                }
-                
-                // Arithmetic
-                OpCode::Add => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    
-                    match (&a, &b) {
-                        (Value::Number(x), Value::Number(y)) => {
-                            self.push(Value::Number(x + y));
-                        }
-                        (Value::String(s1), Value::String(s2)) => {
-                            self.push(Value::String(format!("{}{}", s1, s2)));
-                        }
-                        (Value::String(s), Value::Number(n)) => {
-                             self.push(Value::String(format!("{}{}", s, n)));
-                        }
-                        (Value::Number(n), Value::String(s)) => {
-                             self.push(Value::String(format!("{}{}", n, s)));
-                        }
-                        _ => {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::TypeMismatch("numbers or strings".to_string(), format!("{} and {}", a.type_name(), b.type_name())),
-                                None,
-                            ));
-                        }
-                    }
-                }
+            OpCode::Nil => self.push(Value::Nil),
+            OpCode::True => self.push(Value::Bool(true)),
+            OpCode::False => self.push(Value::Bool(false)),
+            
+            OpCode::Pop => { self.pop(); }
+            
+            OpCode::Dup => {
+                let val = self.peek(0).clone();
+                self.push(val);
+            }
+            
+            OpCode::DefineGlobal => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.pop();
+                let kind = BindingKind::of_definition(&value);
+                self.define_global(name, value, kind)?;
+            }
+            
+            OpCode::DefineState => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.pop();
+                self.record_init(&name, value.clone())?;
+                self.define_global(name, value, BindingKind::State)?;
+            }
+            
+            OpCode::DefineConst => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.pop();
+                self.define_global(name, value, BindingKind::Const)?;
+            }
+            
+            OpCode::InitLocalState => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.peek(0).clone();
+                self.record_init(&name, value)?;
+            }
+            
+            OpCode::GetGlobal => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = match self.globals.get(&name) {
+                    Some(binding) => binding.value.clone(),
+                    None => return Err(self.undefined_variable(&name)),
+                };
+                self.push(value);
+            }
+            
+            OpCode::SetGlobal => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.peek(0).clone();
                 
-                OpCode::Subtract => self.binary_op(|a, b| a - b, "-")?,
-                OpCode::Multiply => self.binary_op(|a, b| a * b, "*")?,
-                OpCode::Divide => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    match (&a, &b) {
-                        (Value::Number(x), Value::Number(y)) => {
-                            if *y == 0.0 {
-                                return Err(SkyHetuError::new(ErrorKind::DivisionByZero, None));
-                            }
-                            self.push(Value::Number(x / y));
-                        }
-                        _ => {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::TypeMismatch("numbers".to_string(), format!("{} and {}", a.type_name(), b.type_name())),
-                                None,
-                            ));
-                        }
+                if let Some(binding) = self.globals.get_mut(&name) {
+                    if !binding.is_state() {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::ImmutableVariable(name.to_string()),
+                            None,
+                        ));
                     }
+                    binding.value = value;
+                } else {
+                    return Err(self.undefined_variable(&name));
                 }
-                OpCode::Modulo => self.binary_op(|a, b| a % b, "%")?,
+            }
+            
+            OpCode::Transition => {
+                let idx = self.read_u16();
+                let causes = self.read_u16();
+                let name = self.get_name(idx);
+                let new_value = self.pop();
                 
-                OpCode::Negate => {
-                    let val = self.pop();
-                    match val {
-                        Value::Number(n) => self.push(Value::Number(-n)),
-                        _ => {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::TypeMismatch("number".to_string(), val.type_name().to_string()),
-                                None,
-                            ));
-                        }
+                if let Some(binding) = self.globals.get(&name) {
+                    if !binding.is_state() {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::ImmutableVariable(name.to_string()),
+                            None,
+                        ));
+                    }
+                    
+                    let old_value = binding.value.clone();
+                    
+                    // Record causality
+                    self.record_transition(&name, old_value.clone(), new_value.clone(), causes)?;
+                    
+                    if let Some(binding) = self.globals.get_mut(&name) {
+                        binding.value = new_value.clone();
                     }
+                    self.notify_watchers(&name, old_value, new_value)?;
+                } else {
+                    return Err(self.undefined_variable(&name));
                 }
+            }
+
+            OpCode::TransitionLocal => {
+                let slot = self.read_u16() as usize;
+                let stack_idx = self.local_index(slot)?;
+                let new_value = self.pop();
                 
-                // Comparison
-                OpCode::Equal => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(Value::Bool(a == b));
-                }
+                // We don't have the name of local easily for causality log?
+                // We can reconstruct it or pass it?
+                // Passing it would require add_name call in compiler.
+                // For now, let's use "local_state_{slot}" or similar if needed,
+                // BUT CausalityLog expects names.
+                // To do this properly, the compiler should pass the name too.
+                // Or we just update value and log with generic name.
+                // Ideally, TransitionLocal should take (slot, name_idx).
+                // Bytecode: TransitionLocal(slot: u16, name: u16).
                 
-                OpCode::NotEqual => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(Value::Bool(a != b));
-                }
+                // Let's modify opcode usage to include name index.
+                let name_idx = self.read_u16();
+                let causes = self.read_u16();
+                let name = self.get_name(name_idx);
                 
-                OpCode::Less => self.comparison_op(|a, b| a < b)?,
-                OpCode::LessEqual => self.comparison_op(|a, b| a <= b)?,
-                OpCode::Greater => self.comparison_op(|a, b| a > b)?,
-                OpCode::GreaterEqual => self.comparison_op(|a, b| a >= b)?,
+                let old_value = self.stack[stack_idx].clone();
                 
-                OpCode::Not => {
-                    let val = self.pop();
-                    self.push(Value::Bool(!val.is_truthy()));
-                }
+                self.record_transition(&name, old_value.clone(), new_value.clone(), causes)?;
                 
-                // Control flow
-                OpCode::Jump => {
-                    let offset = self.read_u16() as usize;
-                    let current_ip = self.current_frame().ip;
-                    self.current_frame_mut().ip = current_ip + offset;
+                self.stack[stack_idx] = new_value.clone();
+                self.notify_watchers(&name, old_value, new_value)?;
+            }
+            
+            OpCode::InitParamState => {
+                let slot = self.read_u16() as usize;
+                let name_idx = self.read_u16();
+                let key = self.param_key(&self.get_name(name_idx));
+                let value = self.stack[self.local_index(slot)?].clone();
+                self.record_init(&key, value)?;
+            }
+            
+            OpCode::CheckType => {
+                let slot = self.read_u16() as usize;
+                let type_idx = self.read_u16();
+                let expected = self.get_name(type_idx);
+                let value = &self.stack[self.local_index(slot)?];
+                if !self.has_type(value, &expected) {
+                    let got = self.annotated_type_name(value);
+                    let frame = self.current_frame();
+                    let function = self.heap.get_closure(frame.closure)
+                        .and_then(|c| self.heap.get_function(c.function));
+                    let param = function.and_then(|f| f.params.get(slot.checked_sub(1)?)).map_or("?", |p| p.as_str());
+                    let owner = match function.map(|f| f.name.as_str()) {
+                        Some(name) if !name.starts_with('<') => format!("'{}'", name),
+                        _ => "a lambda".to_string(),
+                    };
+                    return Err(SkyHetuError::new(
+                        ErrorKind::TypeMismatch(format!("{} for parameter '{}' of {}", expected, param, owner), got),
+                        None,
+                    ));
                 }
+            }
+            
+            OpCode::TransitionParam => {
+                let slot = self.read_u16() as usize;
+                let name_idx = self.read_u16();
+                let causes = self.read_u16();
+                let key = self.param_key(&self.get_name(name_idx));
+                let stack_idx = self.local_index(slot)?;
+                let new_value = self.pop();
+                let old_value = std::mem::replace(&mut self.stack[stack_idx], new_value.clone());
+                self.record_transition(&key, old_value.clone(), new_value.clone(), causes)?;
+                self.notify_watchers(&key, old_value, new_value)?;
+            }
+            
+            OpCode::WhyParam => {
+                let idx = self.read_u16();
+                let key = self.param_key(&self.get_name(idx));
+                let result = self.causality.why_with_heap(&key, &self.heap);
+                self.push(Value::String(result.into()));
+            }
+            
+            OpCode::GetLocal => {
+                let slot = self.read_u16() as usize;
+                let value = self.stack[self.local_index(slot)?].clone();
+                self.push(value);
+            }
+            
+            OpCode::SetLocal => {
+                let slot = self.read_u16() as usize;
+                let stack_idx = self.local_index(slot)?;
+                let value = self.peek(0).clone();
+                self.stack[stack_idx] = value;
+            }
+            
+            // Arithmetic
+            OpCode::Add => {
+                let b = self.pop();
+                let a = self.pop();
                 
-                OpCode::JumpIfFalse => {
-                    let offset = self.read_u16() as usize;
-                    if !self.peek(0).is_truthy() {
-                        let current_ip = self.current_frame().ip;
-                        self.current_frame_mut().ip = current_ip + offset;
+                match (&a, &b) {
+                    (Value::Number(x), Value::Number(y)) => {
+                        self.push(Value::Number(x + y));
+                    }
+                    (Value::String(s1), Value::String(s2)) => {
+                        self.check_string_len(s1.len() + s2.len())?;
+                        self.push(Value::String(format!("{}{}", s1, s2).into()));
+                    }
+                    (Value::String(s), Value::Number(n)) => {
+                        let formatted = format_number(*n);
+                        self.check_string_len(s.len() + formatted.len())?;
+                        self.push(Value::String(format!("{}{}", s, formatted).into()));
                     }
+                    (Value::Number(n), Value::String(s)) => {
+                        let formatted = format_number(*n);
+                        self.check_string_len(s.len() + formatted.len())?;
+                        self.push(Value::String(format!("{}{}", formatted, s).into()));
+                    }
+                    _ => return Err(operand_mismatch("numbers or strings", "+", &a, &b)),
                 }
-                
-                OpCode::JumpIfTrue => {
-                    let offset = self.read_u16() as usize;
-                    if self.peek(0).is_truthy() {
-                        let current_ip = self.current_frame().ip;
-                        self.current_frame_mut().ip = current_ip + offset;
+            }
+            
+            OpCode::Subtract => self.binary_op(|a, b| a - b, "-")?,
+            OpCode::Multiply => self.binary_op(|a, b| a * b, "*")?,
+            OpCode::Divide => self.division_op(|a, b| a / b, "/")?,
+            OpCode::Modulo => self.division_op(|a, b| a % b, "%")?,
+            
+            OpCode::Negate => {
+                let val = self.pop();
+                match val {
+                    Value::Number(n) => self.push(Value::Number(-n)),
+                    _ => {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::TypeMismatch("number".to_string(), val.type_name().to_string()),
+                            None,
+                        ));
                     }
                 }
-                
-                OpCode::Loop => {
-                    let offset = self.read_u16() as usize;
+            }
+            
+            // Comparison
+            OpCode::Equal => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(Value::Bool(self.values_equal(&a, &b)));
+            }
+            
+            OpCode::NotEqual => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(Value::Bool(!self.values_equal(&a, &b)));
+            }
+            
+            OpCode::Less => self.comparison_op(Ordering::is_lt, "<")?,
+            OpCode::LessEqual => self.comparison_op(Ordering::is_le, "<=")?,
+            OpCode::Greater => self.comparison_op(Ordering::is_gt, ">")?,
+            OpCode::GreaterEqual => self.comparison_op(Ordering::is_ge, ">=")?,
+            
+            OpCode::Not => {
+                let val = self.pop();
+                self.push(Value::Bool(!val.is_truthy()));
+            }
+            
+            // Control flow
+            OpCode::Jump => {
+                let offset = self.read_u16() as usize;
+                let current_ip = self.current_frame().ip;
+                self.current_frame_mut().ip = current_ip + offset;
+            }
+            
+            OpCode::JumpIfFalse => {
+                let offset = self.read_u16() as usize;
+                if !self.peek(0).is_truthy() {
                     let current_ip = self.current_frame().ip;
-                    self.current_frame_mut().ip = current_ip - offset;
+                    self.current_frame_mut().ip = current_ip + offset;
                 }
-                
-                // Functions
-                OpCode::Call => {
-                    let arg_count = self.read_byte() as usize;
-                    let callee = self.peek(arg_count).clone();
-                    self.call_value(callee, arg_count)?;
+            }
+            
+            OpCode::JumpIfTrue => {
+                let offset = self.read_u16() as usize;
+                if self.peek(0).is_truthy() {
+                    let current_ip = self.current_frame().ip;
+                    self.current_frame_mut().ip = current_ip + offset;
                 }
+            }
+            
+            OpCode::Loop => {
+                let offset = self.read_u16() as usize;
+                let current_ip = self.current_frame().ip;
+                self.current_frame_mut().ip = current_ip - offset;
+            }
+            
+            // Functions
+            OpCode::Call => {
+                let arg_count = self.read_byte() as usize;
+                let callee = self.peek(arg_count).clone();
+                self.call_value(callee, arg_count)?;
+            }
+            
+            OpCode::TailCall => {
+                let arg_count = self.read_byte() as usize;
+                let callee = self.peek(arg_count).clone();
+                self.tail_call(callee, arg_count)?;
+            }
+            
+            OpCode::CallSpread => {
+                let arg_count = self.read_byte() as usize;
+                let spread: Vec<bool> = (0..arg_count).map(|_| self.read_byte() != 0).collect();
+                let arg_count = self.expand_spread_args(&spread)?;
+                let callee = self.peek(arg_count).clone();
+                self.call_value(callee, arg_count)?;
+            }
+            
+            OpCode::Return => {
+                let result = self.pop();
+                let frame = self.frames.pop().unwrap();
                 
-                OpCode::Return => {
-                    let result = self.pop();
-                    let frame = self.frames.pop().unwrap();
-                    
-                    // Close upvalues for the frame being popped
-                    self.close_upvalues(frame.slot);
-                    
-                    if self.frames.is_empty() {
-                        self.push(result);
-                        return Ok(self.pop());
-                    }
-                    
-                    // Pop arguments and function
+                // Close upvalues for the frame being popped
+                self.close_upvalues(frame.slot);
+                
+                if self.frames.len() <= base {
                     self.stack.truncate(frame.slot);
-                    self.push(result);
+                    return Ok(Some(result));
                 }
                 
-                OpCode::Closure => {
-                    let idx = self.read_u16();
-                    let func_const = self.current_chunk().constants[idx as usize].clone();
+                // Pop arguments and function
+                self.stack.truncate(frame.slot);
+                self.push(result);
+            }
+            
+            OpCode::Closure => {
+                let idx = self.read_u16();
+                let func_const = self.current_chunk().constants()[idx as usize].clone();
+                
+                if let Value::Function(func_handle) = func_const {
+                    let func = self.heap.get_function(func_handle).unwrap(); // Should exist
+                    let upvalue_count = func.upvalue_count;
                     
-                    if let Value::Function(func_handle) = func_const {
-                        let func = self.heap.get_function(func_handle).unwrap(); // Should exist
-                        let upvalue_count = func.upvalue_count;
-                        
-                        let mut upvalues = Vec::with_capacity(upvalue_count);
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+                    
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() != 0;
+                        let index = self.read_byte() as usize;
                         
-                        for _ in 0..upvalue_count {
-                            let is_local = self.read_byte() != 0;
-                            let index = self.read_byte() as usize;
-                            
-                            if is_local {
-                                let frame_slot = self.current_frame().slot;
-                                let location = frame_slot + index;
-                                let upvalue = self.capture_upvalue(location);
-                                upvalues.push(upvalue);
-                            } else {
-                                // Capture from enclosing closure
-                                let current_closure_handle = self.current_frame().closure;
-                                let current_closure = self.heap.get_closure(current_closure_handle).expect("Closure missing");
-                                let upvalue = current_closure.upvalues[index];
-                                upvalues.push(upvalue);
-                            }
+                        if is_local {
+                            let location = self.local_index(index)?;
+                            let upvalue = self.capture_upvalue(location);
+                            upvalues.push(upvalue);
+                        } else {
+                            // Capture from enclosing closure
+                            let current_closure_handle = self.current_frame().closure;
+                            let current_closure = self.heap.get_closure(current_closure_handle).expect("Closure missing");
+                            let upvalue = current_closure.upvalues[index];
+                            upvalues.push(upvalue);
                         }
-                        
-                        let closure_handle = self.heap.alloc_closure(func_handle, upvalues);
-                        self.push(Value::Closure(closure_handle));
-                        
-                    } else {
-                        return Err(SkyHetuError::new(ErrorKind::RuntimeError("Closure operand must be a function".to_string()), None));
                     }
+                    
+                    let closure_handle = self.heap.alloc_closure(func_handle, upvalues);
+                    self.push(Value::Closure(closure_handle));
+                    
+                } else {
+                    return Err(SkyHetuError::new(ErrorKind::RuntimeError("Closure operand must be a function".to_string()), None));
                 }
-                
-                // Built-ins
-                OpCode::Print => {
-                    let count = self.read_byte() as usize;
-                    let mut output = Vec::new();
-                    for _ in 0..count {
-                        output.push(format!("{}", self.pop()));
-                    }
-                    output.reverse();
-                    println!("{}", output.join(" "));
-                    self.push(Value::Nil);
+            }
+            
+            // Built-ins
+            OpCode::Print => {
+                let count = self.read_u16() as usize;
+                let mut output = Vec::new();
+                for _ in 0..count {
+                    let value = self.pop();
+                    output.push(value.display(&self.heap));
                 }
-                
-                OpCode::Why => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let result = self.causality.why(&name);
-                    self.push(Value::String(result));
+                output.reverse();
+                self.write_output(&output.join(" "))?;
+                self.push(Value::Nil);
+            }
+            
+            OpCode::Why => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let result = self.causality.why_with_heap(&name, &self.heap);
+                self.push(Value::String(result.into()));
+            }
+            
+            OpCode::Time => {
+                let time = self.causality.current_time() as f64;
+                self.push(Value::Number(time));
+            }
+            
+            OpCode::Array => {
+                let count = self.read_u16() as usize;
+                let mut elements = Vec::new();
+                for _ in 0..count {
+                    elements.push(self.pop());
                 }
+                elements.reverse();
+                let handle = self.heap.alloc_array(elements);
+                self.push(Value::Array(handle));
+            }
+            
+            OpCode::Index => {
+                let index = self.pop();
+                let array = self.pop();
                 
-                OpCode::Time => {
-                    let time = self.causality.current_time() as f64;
-                    self.push(Value::Number(time));
+                match (&array, &index) {
+                    (Value::Array(handle), _) => {
+                        let items = self.heap.get_array(*handle).map_or(&[][..], |items| &items[..]);
+                        let val = element_index(&index, items.len())?
+                            .map_or(Value::Nil, |idx| items[idx].clone());
+                        self.push(val);
+                    }
+                    (Value::String(s), _) => {
+                        let val = element_index(&index, s.chars().count())?
+                            .and_then(|idx| s.chars().nth(idx))
+                            .map_or(Value::Nil, |c| Value::String(c.to_string().into()));
+                        self.push(val);
+                    }
+                    (Value::Map(handle), _) => {
+                        let key = map_key(&index)?;
+                        let val = self.heap.get_map(*handle)
+                            .and_then(|map| map.get(key).cloned())
+                            .unwrap_or(Value::Nil);
+                        self.push(val);
+                    }
+                    _ => {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::TypeMismatch("array, map or string".to_string(), array.type_name().to_string()),
+                            None,
+                        ));
+                    }
                 }
-                
-                OpCode::Array => {
-                    let count = self.read_byte() as usize;
-                    let mut elements = Vec::new();
-                    for _ in 0..count {
-                        elements.push(self.pop());
-                    }
-                    elements.reverse();
-                    let handle = self.heap.alloc_array(elements);
-                    self.push(Value::Array(handle));
+            }
+            
+            OpCode::IndexSet => {
+                self.set_index()?;
+            }
+            
+            OpCode::IndexSetState => {
+                let name_idx = self.read_u16();
+                let name = self.get_name(name_idx);
+                // The log keeps copies of the whole container, as for transitions
+                let container = self.peek(2).clone();
+                let old_value = self.logged_copy(&container);
+                let element = self.set_index()?;
+                let new_value = self.logged_copy(&container);
+                let location = format!("line {}, {}", self.current_line(), element);
+                self.causality.record_mutation(&name, old_value, new_value, Some(location));
+                self.check_causality_stream()?;
+            }
+            
+            OpCode::CallState => {
+                let arg_count = self.read_byte() as usize;
+                let name_idx = self.read_u16();
+                let name = self.get_name(name_idx);
+                let callee = self.peek(arg_count).clone();
+                // Only the push and pop natives are recorded; they run no
+                // code, so the copy taken before the call survives it
+                let native = match &callee {
+                    Value::NativeFunction(native) if matches!(&*native.name, "push" | "pop") => Some(native.name.clone()),
+                    _ => None,
+                };
+                if let Some(native) = native {
+                    let container = self.peek(arg_count.saturating_sub(1)).clone();
+                    let old_value = self.logged_copy(&container);
+                    self.call_value(callee, arg_count)?;
+                    let new_value = self.logged_copy(&container);
+                    let location = format!("line {}, {}()", self.current_line(), native);
+                    self.causality.record_mutation(&name, old_value, new_value, Some(location));
+                    self.check_causality_stream()?;
+                } else {
+                    self.call_value(callee, arg_count)?;
+                }
+            }
+            
+            OpCode::Break | OpCode::Continue => {
+                // The compiler emits jumps instead, and loading a file rejects these
+                return Err(SkyHetuError::new(
+                    ErrorKind::RuntimeError(format!("{:?} cannot be executed: the bytecode is malformed", opcode)),
+                    None,
+                ));
+            }
+            
+            OpCode::IterStart => {
+                let iterable = self.pop();
+                let walked = match iterable {
+                    Value::Array(_) | Value::Number(_) => iterable,
+                    Value::String(s) => {
+                        let chars = s.chars().map(|c| Value::String(c.to_string().into())).collect();
+                        Value::Array(self.heap.alloc_array(chars))
+                    }
+                    // Keys are taken up front, so the loop body may add or remove entries
+                    Value::Map(handle) => {
+                        let keys = self.heap.get_map(handle).map(sorted_keys).unwrap_or_default();
+                        Value::Array(self.heap.alloc_array(keys))
+                    }
+                    other => {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::TypeMismatch("array, string, map or number to iterate".to_string(), other.type_name().to_string()),
+                            None,
+                        ));
+                    }
+                };
+                self.push(walked);
+            }
+            
+            OpCode::IterLen => {
+                let slot = self.read_u16() as usize;
+                let count = match &self.stack[self.local_index(slot)?] {
+                    Value::Array(handle) => self.heap.get_array(*handle).map_or(0, |items| items.len()) as f64,
+                    // Counts like range(): 0 up to the number, rounded toward zero
+                    Value::Number(n) => n.trunc().max(0.0),
+                    _ => 0.0,
+                };
+                self.push(Value::Number(count));
+            }
+            
+            OpCode::IterGet => {
+                let iterable = self.read_u16() as usize;
+                let iterable = self.local_index(iterable)?;
+                let index = self.read_u16() as usize;
+                let index = self.local_index(index)?;
+                // The compiler only ever stores numbers in the index slot
+                let index = if let Value::Number(n) = self.stack[index] { n } else { 0.0 };
+                let element = match &self.stack[iterable] {
+                    Value::Array(handle) => array_element(self, *handle, index as usize).unwrap_or(Value::Nil),
+                    _ => Value::Number(index),
+                };
+                self.push(element);
+            }
+            
+            OpCode::IterVersion => {
+                let version = match *self.peek(0) {
+                    Value::Array(handle) => self.heap.array_version(handle).unwrap_or(0),
+                    _ => 0,
+                };
+                self.push(Value::Number(version as f64));
+            }
+            
+            OpCode::CheckIteration => {
+                let iterable = self.read_u16() as usize;
+                let iterable = self.local_index(iterable)?;
+                let version = self.read_u16() as usize;
+                let version = self.local_index(version)?;
+                if let Value::Array(handle) = &self.stack[iterable] {
+                    let current = self.heap.array_version(*handle).unwrap_or(0);
+                    if Value::Number(current as f64) != self.stack[version] {
+                        return Err(SkyHetuError::new(
+                            ErrorKind::RuntimeError("array modified during iteration: elements were added or removed inside the for loop".to_string()),
+                            None,
+                        ));
+                    }
                 }
+            }
+            
+            OpCode::Halt => {
+                return Ok(Some(self.stack.pop().unwrap_or(Value::Nil)));
+            }
+            
+            OpCode::GetUpvalue => {
+                let idx = self.read_u16();
+                let closure_handle = self.current_frame().closure;
+                let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
+                let upvalue_handle = closure.upvalues[idx as usize];
                 
-                OpCode::Index => {
-                    let index = self.pop();
-                    let array = self.pop();
-                    
-                    match (&array, &index) {
-                        (Value::Array(handle), Value::Number(i)) => {
-                            if let Some(arr) = self.heap.get_array(*handle) {
-                                let idx = *i as usize;
-                                let val = arr.get(idx).cloned().unwrap_or(Value::Nil);
-                                self.push(val);
-                            } else {
-                                // Array not found
-                                self.push(Value::Nil);
-                            }
-                        }
-                        (Value::String(s), Value::Number(i)) => {
-                            let idx = *i as usize;
-                            let val = s.chars().nth(idx)
-                                .map(|c| Value::String(c.to_string()))
-                                .unwrap_or(Value::Nil);
-                            self.push(val);
+                let value = if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
+                    match &*upvalue.location.borrow() {
+                        crate::gc::UpvalueState::Open(slot) => self.stack[*slot].clone(),
+                        crate::gc::UpvalueState::Closed(val) => val.clone(),
+                    }
+                } else {
+                    Value::Nil
+                };
+                self.push(value);
+            }
+
+            OpCode::SetUpvalue => {
+                let idx = self.read_u16();
+                let closure_handle = self.current_frame().closure;
+                let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
+                let upvalue_handle = closure.upvalues[idx as usize];
+                let value = self.peek(0).clone();
+                
+                if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
+                    let mut location = upvalue.location.borrow_mut();
+                    match *location {
+                        crate::gc::UpvalueState::Open(slot) => {
+                            self.stack[slot] = value;
                         }
-                        _ => {
-                            return Err(SkyHetuError::new(
-                                ErrorKind::TypeMismatch("array or string".to_string(), array.type_name().to_string()),
-                                None,
-                            ));
+                        crate::gc::UpvalueState::Closed(ref mut val) => {
+                            *val = value;
                         }
                     }
                 }
+            }
+            
+            OpCode::TransitionUpvalue => {
+                let slot = self.read_u16() as usize;
+                let name_idx = self.read_u16();
+                let causes = self.read_u16();
+                let name = self.get_name(name_idx);
+                let new_value = self.pop();
                 
-                OpCode::Break | OpCode::Continue => {
-                    // These should be compiled to jumps
-                    unreachable!("Break/Continue should be compiled to jumps");
-                }
+                let closure_handle = self.current_frame().closure;
+                let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
+                let upvalue_handle = closure.upvalues[slot];
                 
-                OpCode::Halt => {
-                    return Ok(self.stack.pop().unwrap_or(Value::Nil));
+                // The compiler rejects these; chunks built elsewhere may not have
+                let captures_state = self.heap.get_function(closure.function)
+                    .and_then(|f| f.state_upvalues.get(slot).copied());
+                if captures_state == Some(false) {
+                    return Err(SkyHetuError::new(ErrorKind::ImmutableVariable(name.to_string()), None));
                 }
                 
-                OpCode::GetUpvalue => {
-                    let idx = self.read_u16();
-                    let closure_handle = self.current_frame().closure;
-                    let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
-                    let upvalue_handle = closure.upvalues[idx as usize];
-                    
-                    let value = if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
-                        match &*upvalue.location.borrow() {
-                            crate::gc::UpvalueState::Open(slot) => self.stack[*slot].clone(),
-                            crate::gc::UpvalueState::Closed(val) => val.clone(),
-                        }
-                    } else {
-                        Value::Nil
-                    };
-                    self.push(value);
-                }
-
-                OpCode::SetUpvalue => {
-                    let idx = self.read_u16();
-                    let closure_handle = self.current_frame().closure;
-                    let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
-                    let upvalue_handle = closure.upvalues[idx as usize];
-                    let value = self.peek(0).clone();
-                    
-                    if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
-                        let mut location = upvalue.location.borrow_mut();
-                        match *location {
-                            crate::gc::UpvalueState::Open(slot) => {
-                                self.stack[slot] = value;
-                            }
-                            crate::gc::UpvalueState::Closed(ref mut val) => {
-                                *val = value;
-                            }
-                        }
+                let old_value = if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
+                     match &*upvalue.location.borrow() {
+                        crate::gc::UpvalueState::Open(s) => self.stack[*s].clone(),
+                        crate::gc::UpvalueState::Closed(val) => val.clone(),
                     }
-                }
+                } else { Value::Nil };
                 
-                OpCode::TransitionUpvalue => {
-                    let slot = self.read_u16() as usize;
-                    let name_idx = self.read_u16();
-                    let name = self.get_name(name_idx);
-                    let new_value = self.pop();
-                    
-                    let closure_handle = self.current_frame().closure;
-                    let closure = self.heap.get_closure(closure_handle).expect("Closure missing");
-                    let upvalue_handle = closure.upvalues[slot];
-                    
-                    let old_value = if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
-                         match &*upvalue.location.borrow() {
-                            crate::gc::UpvalueState::Open(s) => self.stack[*s].clone(),
-                            crate::gc::UpvalueState::Closed(val) => val.clone(),
+                self.record_transition(&name, old_value.clone(), new_value.clone(), causes)?;
+                
+                if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
+                    let mut location = upvalue.location.borrow_mut();
+                    match *location {
+                        crate::gc::UpvalueState::Open(s) => {
+                            self.stack[s] = new_value.clone();
                         }
-                    } else { Value::Nil };
-                    
-                    self.causality.record_mutation(
-                        &name,
-                        old_value,
-                        new_value.clone(),
-                        None,
-                    );
-                    
-                    if let Some(upvalue) = self.heap.get_upvalue(upvalue_handle) {
-                        let mut location = upvalue.location.borrow_mut();
-                        match *location {
-                            crate::gc::UpvalueState::Open(s) => {
-                                self.stack[s] = new_value;
-                            }
-                            crate::gc::UpvalueState::Closed(ref mut val) => {
-                                *val = new_value;
-                            }
+                        crate::gc::UpvalueState::Closed(ref mut val) => {
+                            *val = new_value.clone();
                         }
                     }
                 }
+                self.notify_watchers(&name, old_value, new_value)?;
+            }
+            
+            OpCode::CloseUpvalue => {
+                self.close_upvalues(self.stack.len() - 1);
+                self.pop();
+            }
+            
+            // --- Classes & Instances ---
+            
+            OpCode::Class => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let handle = self.heap.alloc_class(name.to_string());
+                self.push(Value::Class(handle));
+            }
+            
+            OpCode::Method => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let method_val = self.peek(0).clone();
+                let class_val = self.peek(1).clone();
                 
-                OpCode::CloseUpvalue => {
-                    self.close_upvalues(self.stack.len() - 1);
-                    self.pop();
-                }
-                
-                // --- Classes & Instances ---
-                
-                OpCode::Class => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let handle = self.heap.alloc_class(name);
-                    self.push(Value::Class(handle));
-                }
-                
-                OpCode::Method => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let method_val = self.peek(0).clone();
-                    let class_val = self.peek(1).clone();
-                    
-                    if let Value::Class(class_handle) = class_val {
-                        if let Value::Closure(method_handle) = method_val {
-                            if let Some(class) = self.heap.get_class_mut(class_handle) {
-                                class.methods.insert(name, method_handle);
-                            }
-                        } else {
-                             return Err(SkyHetuError::new(ErrorKind::RuntimeError("Method must be a closure".to_string()), None));
+                if let Value::Class(class_handle) = class_val {
+                    if let Value::Closure(method_handle) = method_val {
+                        if let Some(class) = self.heap.get_class_mut(class_handle) {
+                            class.methods.insert(name.to_string(), method_handle);
                         }
                     } else {
-                        return Err(SkyHetuError::new(ErrorKind::RuntimeError("Cannot define method on non-class".to_string()), None));
+                         return Err(SkyHetuError::new(ErrorKind::RuntimeError("Method must be a closure".to_string()), None));
+                    }
+                } else {
+                    return Err(SkyHetuError::new(ErrorKind::RuntimeError("Cannot define method on non-class".to_string()), None));
+                }
+                self.pop(); // Pop method closure
+            }
+            
+            OpCode::Inherit => {
+                let Value::Class(class_handle) = self.pop() else {
+                    return Err(SkyHetuError::new(ErrorKind::RuntimeError("Cannot inherit into a non-class".to_string()), None));
+                };
+                let parent = match self.peek(0) {
+                    Value::Class(handle) => *handle,
+                    other => {
+                        let message = format!("Superclass must be a class, got {}", other.type_name());
+                        return Err(SkyHetuError::new(ErrorKind::RuntimeError(message), None));
                     }
-                    self.pop(); // Pop method closure
+                };
+                // Copy the parent's methods down; the subclass's own methods,
+                // defined next, replace the ones they override
+                let methods = self.heap.get_class(parent).map(|c| c.methods.clone()).unwrap_or_default();
+                if let Some(class) = self.heap.get_class_mut(class_handle) {
+                    class.methods = methods;
+                    class.superclass = Some(parent);
                 }
+            }
+            
+            OpCode::GetSuper => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let Value::Class(parent) = self.pop() else {
+                    return Err(SkyHetuError::new(ErrorKind::RuntimeError("'super' does not refer to a class".to_string()), None));
+                };
+                let receiver = self.pop();
+                let method = self.heap.get_class(parent).and_then(|c| c.methods.get(&*name).copied());
+                let Some(method) = method else {
+                    return Err(SkyHetuError::new(ErrorKind::UndefinedProperty(name.to_string()), None));
+                };
+                let bound = self.heap.alloc_bound_method(receiver, method);
+                self.push(Value::BoundMethod(bound));
+            }
+            
+            OpCode::GetProperty => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let receiver = self.peek(0).clone();
                 
-                OpCode::GetProperty => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let receiver = self.peek(0).clone();
+                if let Value::Instance(handle) = receiver {
+                    // 1. Try Fields
+                    let field_val = {
+                         let instance = self.heap.get_instance(handle).unwrap();
+                         instance.fields.borrow().get(&*name).cloned()
+                    };
                     
-                    if let Value::Instance(handle) = receiver {
-                        // 1. Try Fields
-                        let field_val = {
-                             let instance = self.heap.get_instance(handle).unwrap();
-                             instance.fields.borrow().get(&name).cloned()
+                    if let Some(val) = field_val {
+                        self.pop(); // Instance
+                        self.push(val);
+                    } else {
+                        // 2. Try Methods
+                        let method_handle = {
+                            let instance = self.heap.get_instance(handle).unwrap();
+                            let class_handle = instance.class;
+                            let class = self.heap.get_class(class_handle).unwrap();
+                            class.methods.get(&*name).cloned()
                         };
                         
-                        if let Some(val) = field_val {
+                        if let Some(handle) = method_handle {
+                            let bound = self.heap.alloc_bound_method(receiver, handle);
                             self.pop(); // Instance
-                            self.push(val);
+                            self.push(Value::BoundMethod(bound));
                         } else {
-                            // 2. Try Methods
-                            let method_handle = {
-                                let instance = self.heap.get_instance(handle).unwrap();
-                                let class_handle = instance.class;
-                                let class = self.heap.get_class(class_handle).unwrap();
-                                class.methods.get(&name).cloned()
-                            };
-                            
-                            if let Some(handle) = method_handle {
-                                let bound = self.heap.alloc_bound_method(receiver, handle);
-                                self.pop(); // Instance
-                                self.push(Value::BoundMethod(bound));
-                            } else {
-                                return Err(SkyHetuError::new(ErrorKind::UndefinedProperty(name), None));
-                            }
+                            return Err(SkyHetuError::new(ErrorKind::UndefinedProperty(name.to_string()), None));
                         }
-                    } else {
-                         return Err(SkyHetuError::new(ErrorKind::RuntimeError("Only instances have properties.".to_string()), None));
                     }
+                } else {
+                     return Err(SkyHetuError::new(ErrorKind::RuntimeError("Only instances have properties.".to_string()), None));
+                }
+            }
+            
+            OpCode::TransitionProperty => {
+                let idx = self.read_u16();
+                let causes = self.read_u16();
+                let name = self.get_name(idx);
+                let new_value = self.pop();
+                let receiver = self.pop();
+                
+                let Value::Instance(handle) = receiver else {
+                    return Err(SkyHetuError::new(ErrorKind::RuntimeError("Only instances have properties.".to_string()), None));
+                };
+                let old_value = {
+                    let instance = self.heap.get_instance(handle).unwrap();
+                    instance.fields.borrow_mut().insert(name.to_string(), new_value.clone())
+                };
+                let key = self.field_key(handle, &name);
+                let old_value = old_value.unwrap_or(Value::Nil);
+                self.record_transition(&key, old_value.clone(), new_value.clone(), causes)?;
+                self.notify_watchers(&key, old_value, new_value)?;
+            }
+            
+            OpCode::WhyProperty => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let receiver = self.pop();
+                
+                let Value::Instance(handle) = receiver else {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::TypeMismatch("instance".to_string(), receiver.type_name().to_string()),
+                        None,
+                    ));
+                };
+                let key = self.field_key(handle, &name);
+                let result = self.causality.why_with_heap(&key, &self.heap);
+                self.push(Value::String(result.into()));
+            }
+            
+            OpCode::SetProperty => {
+                let idx = self.read_u16();
+                let name = self.get_name(idx);
+                let value = self.pop();
+                let receiver = self.peek(0).clone();
+                
+                if let Value::Instance(handle) = receiver {
+                    {
+                        let instance = self.heap.get_instance(handle).unwrap();
+                        instance.fields.borrow_mut().insert(name.to_string(), value.clone());
+                    } // Drop instance borrow
+                    
+                    self.pop(); // Pop Instance
+                    self.push(value); // Push Value (result)
+                } else {
+                     return Err(SkyHetuError::new(ErrorKind::RuntimeError("Only instances have properties.".to_string()), None));
+                }
+            }
+        }
+        
+        if let Some(kind) = self.stack_fault.take() {
+            return Err(SkyHetuError::new(kind, None));
+        }
+        if let Some(size) = self.heap.take_limit_hit() {
+            self.enforce_heap_limit(size)?;
+        }
+        Ok(None)
+    }
+    
+    /// Pop value, index and container, store the value and push it back as the
+    /// expression's result. Returns a description of the element written
+    /// (`index 2`, `key "k"`).
+    fn set_index(&mut self) -> Result<String> {
+        let value = self.pop();
+        let index = self.pop();
+        let container = self.pop();
+        
+        let handle = match container {
+            Value::Array(handle) => handle,
+            Value::Map(handle) => {
+                let key = map_key(&index)?.to_string();
+                let map = self.heap.get_map_mut(handle).ok_or_else(|| SkyHetuError::new(
+                    ErrorKind::RuntimeError("Map not found (GC error?)".to_string()),
+                    None,
+                ))?;
+                map.insert(key.clone(), value.clone());
+                self.push(value);
+                return Ok(format!("key {:?}", key));
+            }
+            _ => {
+                return Err(SkyHetuError::new(
+                    ErrorKind::TypeMismatch("array or map".to_string(), container.type_name().to_string()),
+                    None,
+                ));
+            }
+        };
+        let arr = self.heap.get_array_mut(handle).ok_or_else(|| SkyHetuError::new(
+            ErrorKind::RuntimeError("Array not found (GC error?)".to_string()),
+            None,
+        ))?;
+        let Some(idx) = element_index(&index, arr.len())? else {
+            return Err(SkyHetuError::new(
+                ErrorKind::RuntimeError(format!("index {} out of bounds for array of length {}", index, arr.len())),
+                None,
+            ));
+        };
+        arr[idx] = value.clone();
+        
+        self.push(value);
+        Ok(format!("index {}", idx))
+    }
+    
+    /// Replace the spread-flagged arguments on top of the stack with their
+    /// elements, returning the argument count after expansion
+    fn expand_spread_args(&mut self, spread: &[bool]) -> Result<usize> {
+        let Some(args_start) = self.stack.len().checked_sub(spread.len()) else {
+            return Err(SkyHetuError::new(ErrorKind::StackUnderflow, None));
+        };
+        let args = self.stack.split_off(args_start);
+        let mut expanded = Vec::with_capacity(args.len());
+        
+        for (position, (arg, is_spread)) in args.into_iter().zip(spread).enumerate() {
+            if !*is_spread {
+                expanded.push(arg);
+                continue;
+            }
+            match arg {
+                Value::Array(handle) => {
+                    let elements = self.heap.get_array(handle).ok_or_else(|| SkyHetuError::new(
+                        ErrorKind::RuntimeError("Array not found (GC error?)".to_string()),
+                        None,
+                    ))?;
+                    expanded.extend(elements.iter().cloned());
                 }
-                
-                OpCode::SetProperty => {
-                    let idx = self.read_u16();
-                    let name = self.get_name(idx);
-                    let value = self.pop();
-                    let receiver = self.peek(0).clone();
-                    
-                    if let Value::Instance(handle) = receiver {
-                        {
-                            let instance = self.heap.get_instance(handle).unwrap();
-                            instance.fields.borrow_mut().insert(name, value.clone());
-                        } // Drop instance borrow
-                        
-                        self.pop(); // Pop Instance
-                        self.push(value); // Push Value (result)
-                    } else {
-                         return Err(SkyHetuError::new(ErrorKind::RuntimeError("Only instances have properties.".to_string()), None));
-                    }
+                other => {
+                    return Err(SkyHetuError::new(
+                        ErrorKind::RuntimeError(format!(
+                            "cannot spread argument {}: expected array, got {}",
+                            position + 1,
+                            other.type_name()
+                        )),
+                        None,
+                    ));
                 }
             }
         }
+        
+        if expanded.len() > u8::MAX as usize {
+            return Err(SkyHetuError::new(
+                ErrorKind::RuntimeError(format!(
+                    "too many arguments after spreading: {} (maximum is {})",
+                    expanded.len(),
+                    u8::MAX
+                )),
+                None,
+            ));
+        }
+        
+        let count = expanded.len();
+        for value in expanded {
+            self.push(value);
+        }
+        Ok(count)
     }
     
     /// Call a value
@@ -1079,7 +3118,7 @@ impl VM {
                 if let Some(arity) = native.arity {
                     if arg_count != arity {
                         return Err(SkyHetuError::new(
-                            ErrorKind::WrongArity(arity, arg_count),
+                            ErrorKind::WrongArity(Some(native.name.to_string()), arity, arg_count),
                             None,
                         ));
                     }
@@ -1089,10 +3128,13 @@ impl VM {
                 let args_vec = args.to_vec();
                 
                 // Call native function
-                let result = (native.func)(self, &args_vec).map_err(|msg| SkyHetuError::new(
-                    ErrorKind::RuntimeError(msg),
-                    None,
-                ))?;
+                // Natives report callback errors as text; a callback that ran out of
+                // fuel leaves none, and the program can't go on without it anyway
+                let result = (native.func)(self, &args_vec).map_err(|msg| match self.heap.take_limit_hit() {
+                    Some(size) => self.out_of_memory(size),
+                    None if self.fuel == Some(0) => SkyHetuError::new(ErrorKind::FuelExhausted, None),
+                    None => SkyHetuError::new(ErrorKind::RuntimeError(msg), None),
+                })?;
                 
                 // Pop args + function
                 self.stack.truncate(args_start - 1);
@@ -1119,7 +3161,8 @@ impl VM {
                      // So we just call it directly
                      self.call_function(handle, arg_count)
                  } else if arg_count != 0 {
-                      return Err(SkyHetuError::new(ErrorKind::WrongArity(0, arg_count), None));
+                      let name = self.heap.get_class(handle).map(|class| class.name.clone());
+                      Err(SkyHetuError::new(ErrorKind::WrongArity(name, 0, arg_count), None))
                  } else {
                      // No init, valid if 0 args.
                      let _idx = self.stack.len() - 1; // Class is here
@@ -1137,15 +3180,97 @@ impl VM {
                 self.call_function(bound.method, arg_count)
             }
             _ => Err(SkyHetuError::new(
-                ErrorKind::TypeMismatch("function".to_string(), callee.type_name().to_string()),
+                ErrorKind::NotCallable(callee.type_name().to_string()),
                 None,
             )),
         }
     }
     
+    /// Call `callee` with `args` from inside a native function and run it to
+    /// completion, leaving the caller's frames and stack as they were.
+    ///
+    /// On error the callee's frames are left in place, so the error is
+    /// located inside the callback rather than at the native's call site.
+    pub fn call_value_reentrant(&mut self, callee: Value, args: Vec<Value>) -> Result<Value> {
+        let base = self.frames.len();
+        let slot = self.stack.len();
+        let arg_count = args.len();
+        self.push(callee.clone());
+        for arg in args {
+            self.push(arg);
+        }
+        if let Err(e) = self.call_value(callee, arg_count) {
+            self.stack.truncate(slot);
+            return Err(e);
+        }
+        if self.frames.len() == base {
+            // Natives and init-less classes finish without pushing a frame
+            let result = self.pop();
+            self.stack.truncate(slot);
+            return Ok(result);
+        }
+        self.execute_until(base)
+    }
+    
     /// Call a user-defined function
     /// Call a closure
     fn call_function(&mut self, closure_handle: crate::gc::Handle, arg_count: usize) -> Result<()> {
+        let chunk = self.callee_chunk(closure_handle, arg_count)?;
+        
+        if self.frames.len() >= self.frames_max {
+            return Err(SkyHetuError::new(ErrorKind::StackOverflow, None));
+        }
+        
+        self.call_count += 1;
+        
+        self.frames.push(CallFrame {
+            closure: closure_handle,
+            chunk,
+            ip: 0,
+            slot: self.stack.len() - arg_count - 1,
+            call_id: self.call_count,
+        });
+        
+        Ok(())
+    }
+    
+    /// Call `callee` in place of the running function, whose result would be
+    /// the callee's anyway. Closures and bound methods take over the current
+    /// frame, so recursion in tail position runs in constant frame space;
+    /// anything else is called normally.
+    fn tail_call(&mut self, callee: Value, arg_count: usize) -> Result<()> {
+        let closure_handle = match callee {
+            Value::Closure(handle) => handle,
+            Value::BoundMethod(handle) => {
+                let bound = self.heap.get_bound_method(handle).unwrap().clone();
+                let idx = self.stack.len() - 1 - arg_count;
+                self.stack[idx] = bound.receiver;
+                bound.method
+            }
+            _ => return self.call_value(callee, arg_count),
+        };
+        let chunk = self.callee_chunk(closure_handle, arg_count)?;
+        
+        // Slide the callee and its arguments down over the finished frame
+        let slot = self.current_frame().slot;
+        self.close_upvalues(slot);
+        let callee_slot = self.stack.len() - arg_count - 1;
+        self.stack.drain(slot..callee_slot);
+        
+        self.call_count += 1;
+        *self.current_frame_mut() = CallFrame {
+            closure: closure_handle,
+            chunk,
+            ip: 0,
+            slot,
+            call_id: self.call_count,
+        };
+        
+        Ok(())
+    }
+    
+    /// Chunk of the closure's function, after checking it takes `arg_count` arguments
+    fn callee_chunk(&self, closure_handle: crate::gc::Handle, arg_count: usize) -> Result<Rc<Chunk>> {
         // Get function from closure
         let func_handle = if let Some(closure) = self.heap.get_closure(closure_handle) {
             closure.function
@@ -1157,26 +3282,15 @@ impl VM {
             .ok_or_else(|| SkyHetuError::new(ErrorKind::RuntimeError("Function not found".to_string()), None))?;
             
         if arg_count != func.params.len() {
+            // Lambdas have no name worth showing
+            let name = Some(func.name.clone()).filter(|name| !name.starts_with('<'));
             return Err(SkyHetuError::new(
-                ErrorKind::WrongArity(func.params.len(), arg_count),
+                ErrorKind::WrongArity(name, func.params.len(), arg_count),
                 None,
             ));
         }
         
-        if self.frames.len() >= FRAMES_MAX {
-            return Err(SkyHetuError::new(ErrorKind::StackOverflow, None));
-        }
-        
-        let chunk = func.chunk.clone();
-        
-        self.frames.push(CallFrame {
-            closure: closure_handle,
-            chunk,
-            ip: 0,
-            slot: self.stack.len() - arg_count - 1,
-        });
-        
-        Ok(())
+        Ok(func.chunk.clone())
     }
 
     
@@ -1193,65 +3307,136 @@ impl VM {
                 Ok(())
             }
             (Value::String(s1), Value::String(s2)) if op_name == "+" => {
-                self.push(Value::String(format!("{}{}", s1, s2)));
+                self.push(Value::String(format!("{}{}", s1, s2).into()));
                 Ok(())
             }
             (Value::String(s), Value::Number(n)) if op_name == "*" => {
-                self.push(Value::String(s.repeat(*n as usize)));
+                self.check_string_len(s.len().saturating_mul(*n as usize))?;
+                self.push(Value::String(s.repeat(*n as usize).into()));
                 Ok(())
             }
-            _ => Err(SkyHetuError::new(
-                ErrorKind::TypeMismatch(
-                    "numbers".to_string(),
-                    format!("{} and {}", a.type_name(), b.type_name()),
-                ),
-                None,
-            )),
+            _ => Err(operand_mismatch("numbers", op_name, &a, &b)),
         }
     }
     
-    fn comparison_op<F>(&mut self, op: F) -> Result<()>
-    where
-        F: Fn(f64, f64) -> bool,
-    {
+    /// `/` and `%`, which fail on a zero divisor instead of producing
+    /// infinity or NaN
+    fn division_op(&mut self, op: fn(f64, f64) -> f64, op_name: &str) -> Result<()> {
         let b = self.pop();
         let a = self.pop();
         
         match (&a, &b) {
+            (Value::Number(_), Value::Number(y)) if *y == 0.0 => {
+                Err(SkyHetuError::new(ErrorKind::DivisionByZero, None))
+            }
             (Value::Number(x), Value::Number(y)) => {
-                self.push(Value::Bool(op(*x, *y)));
+                self.push(Value::Number(op(*x, *y)));
                 Ok(())
             }
-            _ => Err(SkyHetuError::new(
-                ErrorKind::TypeMismatch(
-                    "numbers".to_string(),
-                    format!("{} and {}", a.type_name(), b.type_name()),
-                ),
-                None,
-            )),
+            _ => Err(operand_mismatch("numbers", op_name, &a, &b)),
+        }
+    }
+    
+    /// Ordering of two numbers, two strings (by code point, like `sort`) or
+    /// two bools (`false` first). Any ordering involving NaN is false.
+    fn comparison_op(&mut self, op: fn(Ordering) -> bool, op_name: &str) -> Result<()> {
+        let b = self.pop();
+        let a = self.pop();
+        
+        let ordering = match (&a, &b) {
+            (Value::Number(x), Value::Number(y)) => x.partial_cmp(y),
+            (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+            (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+            _ => return Err(operand_mismatch("two numbers, strings or bools", op_name, &a, &b)),
+        };
+        self.push(Value::Bool(ordering.is_some_and(op)));
+        Ok(())
+    }
+    
+    /// `==` as the language defines it: primitives compare by value, heap
+    /// values by identity, native functions by name
+    pub fn values_equal(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::NativeFunction(x), Value::NativeFunction(y)) => x.name == y.name,
+            _ => match (a.handle(), b.handle()) {
+                (Some(x), Some(y)) => x == y && std::mem::discriminant(a) == std::mem::discriminant(b),
+                _ => a == b,
+            },
+        }
+    }
+    
+    /// Equality that looks inside arrays and maps, comparing their elements
+    /// with `deep_equal` recursively; everything else compares as `==`
+    pub fn deep_equal(&self, a: &Value, b: &Value) -> bool {
+        self.deep_equal_in(a, b, &mut Vec::new())
+    }
+    
+    /// `comparing` holds the container pairs being compared further up, so a
+    /// cycle back to one of them counts as equal instead of recursing forever
+    fn deep_equal_in(&self, a: &Value, b: &Value, comparing: &mut Vec<(crate::gc::Handle, crate::gc::Handle)>) -> bool {
+        if self.values_equal(a, b) {
+            return true;
         }
+        let pair = match (a, b) {
+            (Value::Array(x), Value::Array(y)) | (Value::Map(x), Value::Map(y)) => (*x, *y),
+            _ => return false,
+        };
+        if comparing.contains(&pair) {
+            return true;
+        }
+        comparing.push(pair);
+        let equal = match (a, b) {
+            (Value::Array(_), _) => match (self.heap.get_array(pair.0), self.heap.get_array(pair.1)) {
+                (Some(xs), Some(ys)) => {
+                    xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| self.deep_equal_in(x, y, comparing))
+                }
+                _ => false,
+            },
+            _ => match (self.heap.get_map(pair.0), self.heap.get_map(pair.1)) {
+                (Some(xs), Some(ys)) => {
+                    xs.len() == ys.len()
+                        && xs.iter().all(|(key, x)| ys.get(key).is_some_and(|y| self.deep_equal_in(x, y, comparing)))
+                }
+                _ => false,
+            },
+        };
+        comparing.pop();
+        equal
     }
     
     // ==================== Helpers ====================
     
+    /// Push a value. Going over the stack limit doesn't stop the instruction;
+    /// it fails once the instruction finishes.
     fn push(&mut self, value: Value) {
-        if self.stack.len() >= STACK_MAX {
-            panic!("Stack overflow");
+        if self.stack.len() >= self.stack_max {
+            self.stack_fault.get_or_insert(ErrorKind::StackOverflow);
         }
         self.stack.push(value);
     }
     
+    /// Pop a value, or give nil and fail the instruction if the stack is empty
     fn pop(&mut self) -> Value {
-        self.stack.pop().expect("Stack underflow")
+        self.stack.pop().unwrap_or_else(|| {
+            self.stack_fault.get_or_insert(ErrorKind::StackUnderflow);
+            Value::Nil
+        })
     }
     
-    fn peek(&self, distance: usize) -> &Value {
+    /// The value `distance` below the top, or nil, failing the instruction,
+    /// if the stack isn't that deep
+    fn peek(&mut self, distance: usize) -> &Value {
+        const NIL: &Value = &Value::Nil;
+        if distance >= self.stack.len() {
+            self.stack_fault.get_or_insert(ErrorKind::StackUnderflow);
+            return NIL;
+        }
         &self.stack[self.stack.len() - 1 - distance]
     }
     
     fn read_byte(&mut self) -> u8 {
         let frame = self.frames.last_mut().unwrap();
-        let byte = frame.chunk.code[frame.ip];
+        let byte = frame.chunk.code()[frame.ip];
         frame.ip += 1;
         byte
     }
@@ -1263,10 +3448,82 @@ impl VM {
         value
     }
     
+    /// Source line of the instruction being executed
+    fn current_line(&self) -> usize {
+        self.frames.last()
+            .map(|frame| frame.chunk.line(frame.ip.saturating_sub(1)))
+            .unwrap_or(0)
+    }
+    
+    /// Give an error without a location the line of the instruction that
+    /// raised it, and the call stack when it was raised inside a function.
+    /// An instruction that ran off the stack failed because of that, whatever
+    /// it went on to complain about.
+    fn locate(&mut self, mut error: SkyHetuError) -> SkyHetuError {
+        if let Some(kind) = self.stack_fault.take() {
+            error.kind = kind;
+        }
+        if error.span.is_none() && !self.frames.is_empty() {
+            // Column 0: the VM only knows the line
+            error.span = Some(Span::new(0, 0, self.current_line(), 0));
+            let frame = self.current_frame();
+            if let Some(path) = frame.chunk.file_at(frame.ip.saturating_sub(1)) {
+                error = error.with_file(path.to_path_buf());
+            }
+        }
+        if error.stack_trace().is_empty() && self.frames.len() > 1 {
+            error = error.with_stack_trace(self.stack_trace());
+        }
+        if error.conditions().is_empty() {
+            if let Some(frame) = self.frames.last() {
+                // Innermost conditions are the most telling
+                let conditions = frame.chunk.guards_at(frame.ip.saturating_sub(1));
+                let skip = conditions.len().saturating_sub(MAX_ERROR_CONDITIONS);
+                error = error.with_conditions(conditions[skip..].to_vec());
+            }
+        }
+        error
+    }
+    
+    /// The active calls, innermost first: `in <fn foo> (line 12)`, or
+    /// `(lib.skyh line 12)` for code imported from another file
+    pub fn stack_trace(&self) -> Vec<String> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let name = self.heap.get_closure(frame.closure)
+                    .and_then(|c| self.heap.get_function(c.function))
+                    .map_or("?", |f| f.name.as_str());
+                let offset = frame.ip.saturating_sub(1);
+                let line = match frame.chunk.file_at(offset) {
+                    Some(path) => format!("{} line {}", path.display(), frame.chunk.line(offset)),
+                    None => format!("line {}", frame.chunk.line(offset)),
+                };
+                if name == "<script>" {
+                    format!("in <script> ({})", line)
+                } else {
+                    format!("in <fn {}> ({})", name, line)
+                }
+            })
+            .collect()
+    }
+    
     fn current_chunk(&self) -> &Chunk {
         &self.frames.last().unwrap().chunk
     }
     
+    /// Stack index of local `slot` in the current call, or an error for a
+    /// slot past the top of the stack, which only malformed bytecode names
+    fn local_index(&self, slot: usize) -> Result<usize> {
+        let index = self.current_frame().slot + slot;
+        if index < self.stack.len() {
+            Ok(index)
+        } else {
+            Err(SkyHetuError::new(ErrorKind::InvalidSlot(slot), None))
+        }
+    }
+    
     fn current_frame(&self) -> &CallFrame {
         self.frames.last().unwrap()
     }
@@ -1275,12 +3532,13 @@ impl VM {
         self.frames.last_mut().unwrap()
     }
     
-    fn get_name(&self, idx: u16) -> String {
-        self.current_chunk().names[idx as usize].clone()
+    /// Name `idx` of the current chunk; cloning it only bumps a reference count
+    fn get_name(&self, idx: u16) -> Rc<str> {
+        Rc::clone(&self.current_chunk().names()[idx as usize])
     }
     
     pub fn why(&self, variable: &str) -> String {
-        self.causality.why(variable)
+        self.causality.why_with_heap(variable, &self.heap)
     }
 }
 
@@ -1290,6 +3548,277 @@ impl Default for VM {
     }
 }
 
+/// Fail an assertion: an error, or under `AssertMode::Collect` a recorded
+/// failure that lets the program carry on
+fn assertion_failed(vm: &mut VM, message: String) -> std::result::Result<Value, String> {
+    if vm.assert_mode == AssertMode::Collect {
+        let failure = AssertFailure {
+            message,
+            line: vm.current_line(),
+            time: vm.causality.current_time(),
+        };
+        vm.assert_failures.push(failure);
+        return Ok(Value::Nil);
+    }
+    Err(message)
+}
+
+/// A value as an assertion failure shows it: contents of containers, strings quoted
+fn assert_operand(vm: &VM, value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", &**s),
+        _ => crate::inspect::render(&vm.heap, value),
+    }
+}
+
+/// `name failed: detail`, or the user's message followed by the detail
+fn assert_message(name: &str, message: Option<&Value>, detail: String) -> String {
+    match message {
+        Some(message) => format!("{} ({})", message, detail),
+        None => format!("{} failed: {}", name, detail),
+    }
+}
+
+/// Position of an index operand among `len` elements, or `None` past either
+/// end. Negative indices count back from the end; anything but a whole
+/// number is a type error.
+fn element_index(index: &Value, len: usize) -> Result<Option<usize>> {
+    let position = match index {
+        Value::Number(n) if n.fract() == 0.0 => *n,
+        _ => {
+            let got = match index {
+                Value::Number(n) => format_number(*n),
+                other => other.type_name().to_string(),
+            };
+            return Err(SkyHetuError::new(ErrorKind::TypeMismatch("a whole number index".to_string(), got), None));
+        }
+    };
+    let position = if position < 0.0 { position + len as f64 } else { position };
+    Ok((position >= 0.0 && position < len as f64).then_some(position as usize))
+}
+
+/// The range of elements `slice()` takes from a sequence of `len`: negative
+/// bounds count back from the end and both are clamped to the sequence
+fn slice_range(len: usize, start: &Value, end: Option<&Value>) -> std::result::Result<std::ops::Range<usize>, String> {
+    let bound = |value: &Value| match value {
+        Value::Number(n) if n.fract() == 0.0 => {
+            let n = if *n < 0.0 { n + len as f64 } else { *n };
+            Ok(n.clamp(0.0, len as f64) as usize)
+        }
+        Value::Number(n) => Err(format!("slice() bounds must be whole numbers, got {}", format_number(*n))),
+        other => Err(format!("slice() bounds must be whole numbers, got {}", other.type_name())),
+    };
+    let start = bound(start)?;
+    let end = end.map_or(Ok(len), bound)?;
+    Ok(start..end.max(start))
+}
+
+/// Map keys are strings
+fn map_key(key: &Value) -> Result<&str> {
+    match key {
+        Value::String(s) => Ok(s),
+        _ => Err(SkyHetuError::new(
+            ErrorKind::RuntimeError(format!("map keys must be strings, got {}", key.type_name())),
+            None,
+        )),
+    }
+}
+
+/// The map and key arguments of a map native
+fn map_and_key<'a>(name: &str, args: &'a [Value]) -> std::result::Result<(crate::gc::Handle, &'a str), String> {
+    match (&args[0], &args[1]) {
+        (Value::Map(handle), Value::String(key)) => Ok((*handle, key)),
+        (Value::Map(_), other) => Err(format!("{}() requires a string key, got {}", name, other.type_name())),
+        (other, _) => Err(format!("{}() requires a map, got {}", name, other.type_name())),
+    }
+}
+
+/// The array argument of an array native
+fn array_handle(name: &str, value: &Value) -> std::result::Result<crate::gc::Handle, String> {
+    match value {
+        Value::Array(handle) => Ok(*handle),
+        other => Err(format!("{}() requires an array, got {}", name, other.type_name())),
+    }
+}
+
+/// Allocate an array holding each of `pairs` as a two-element array.
+/// The heap only collects between instructions, so the pairs already
+/// allocated stay alive until the outer array takes them over.
+fn alloc_pairs(vm: &mut VM, pairs: Vec<Vec<Value>>) -> crate::gc::Handle {
+    let items = pairs.into_iter().map(|pair| Value::Array(vm.heap.alloc_array(pair))).collect();
+    vm.heap.alloc_array(items)
+}
+
+/// The keys of a map as strings, in sorted order
+fn sorted_keys(map: &HashMap<String, Value>) -> Vec<Value> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    keys.into_iter().map(|k| Value::String(k.as_str().into())).collect()
+}
+
+/// Length of an array when a higher-order native starts walking it
+fn array_len(vm: &VM, handle: crate::gc::Handle) -> usize {
+    vm.heap.get_array(handle).map_or(0, |items| items.len())
+}
+
+/// Element `index` of an array, read afresh so callbacks that shrink it are seen
+fn array_element(vm: &VM, handle: crate::gc::Handle, index: usize) -> Option<Value> {
+    vm.heap.get_array(handle)?.get(index).cloned()
+}
+
+/// The elements of the array argument of `sort()`/`sort_in_place()`, sorted
+/// with the optional comparator argument or the natural order
+fn sorted_items(vm: &mut VM, name: &str, args: &[Value]) -> std::result::Result<Vec<Value>, String> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(format!("{}() takes 1 or 2 arguments", name));
+    }
+    let handle = array_handle(name, &args[0])?;
+    let items = vm.heap.get_array(handle).ok_or("Array not found (GC error?)")?.clone();
+    let Some(cmp) = args.get(1) else {
+        return merge_sort(items, &mut |a, b| natural_order(name, a, b));
+    };
+    // The comparator may resize the array, so keep the elements rooted in a copy
+    let copy = vm.heap.alloc_array(items.clone());
+    vm.push(Value::Array(copy));
+    merge_sort(items, &mut |a, b| {
+        let result = vm.call_value_reentrant(cmp.clone(), vec![a.clone(), b.clone()])
+            .map_err(|e| e.kind.to_string())?;
+        match result {
+            Value::Number(n) if n < 0.0 => Ok(Ordering::Less),
+            Value::Number(n) if n > 0.0 => Ok(Ordering::Greater),
+            Value::Number(_) => Ok(Ordering::Equal),
+            other => Err(format!("{}() comparator must return a number, got {}", name, other.type_name())),
+        }
+    })
+}
+
+/// Ascending numbers or lexicographic strings; anything else cannot be sorted without a comparator
+fn natural_order(name: &str, a: &Value, b: &Value) -> std::result::Result<Ordering, String> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.partial_cmp(y)
+            .ok_or_else(|| format!("{}() cannot order NaN", name)),
+        (Value::String(x), Value::String(y)) => Ok(x.cmp(y)),
+        (Value::Number(_), Value::String(_)) | (Value::String(_), Value::Number(_)) => Err(format!(
+            "{}() cannot compare {} and {}; pass a comparator to sort mixed arrays",
+            name, a.type_name(), b.type_name()
+        )),
+        _ => {
+            let other = if matches!(a, Value::Number(_) | Value::String(_)) { b } else { a };
+            Err(format!(
+                "{}() can only order numbers and strings, got {}; pass a comparator",
+                name, other.type_name()
+            ))
+        }
+    }
+}
+
+/// Stable merge sort with a fallible comparator. Unlike `slice::sort_by`, an
+/// inconsistent user comparator cannot make it panic.
+fn merge_sort<F>(mut items: Vec<Value>, cmp: &mut F) -> std::result::Result<Vec<Value>, String>
+where
+    F: FnMut(&Value, &Value) -> std::result::Result<Ordering, String>,
+{
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, cmp)?;
+    let right = merge_sort(right, cmp)?;
+    
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Take from the right only when strictly smaller, so equal elements keep their order
+        if cmp(b, a)? == Ordering::Less {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Character index of a byte offset found by a search, or -1 when nothing was found
+fn char_index(s: &str, byte: Option<usize>) -> f64 {
+    match byte {
+        Some(byte) => s[..byte].chars().count() as f64,
+        None => -1.0,
+    }
+}
+
+/// Type error for operands `a` and `b` of the binary operator `op`
+fn operand_mismatch(expected: &str, op: &str, a: &Value, b: &Value) -> SkyHetuError {
+    SkyHetuError::new(
+        ErrorKind::TypeMismatch(
+            format!("{} for '{}'", expected, op),
+            format!("{} and {}", a.type_name(), b.type_name()),
+        ),
+        None,
+    )
+}
+
+/// A string argument of a string native
+fn string_arg<'a>(name: &str, value: &'a Value) -> std::result::Result<&'a str, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(format!("{}() requires a string, got {}", name, other.type_name())),
+    }
+}
+
+/// The function behind a function, closure or bound method argument of `name`
+fn script_function<'a>(vm: &'a VM, name: &str, value: &Value) -> std::result::Result<&'a crate::value::Function, String> {
+    match value {
+        Value::NativeFunction(_) => Err(format!("{}() requires a function defined in SkyHetu, got native function", name)),
+        other => crate::inspect::function(&vm.heap, other)
+            .ok_or_else(|| format!("{}() requires a function, got {}", name, other.type_name())),
+    }
+}
+
+/// Fail unless the VM allows file and input access
+fn check_io(vm: &VM, name: &str) -> std::result::Result<(), String> {
+    if vm.io_enabled {
+        Ok(())
+    } else {
+        Err(format!("{}() is not available: file and input access is disabled", name))
+    }
+}
+
+/// The path argument of a file native, once file access is known to be allowed
+fn io_path<'a>(vm: &VM, name: &str, value: &'a Value) -> std::result::Result<&'a str, String> {
+    check_io(vm, name)?;
+    string_arg(name, value)
+}
+
+/// Unpack the two string arguments of a string native
+fn string_pair<'a>(name: &str, args: &'a [Value]) -> std::result::Result<(&'a str, &'a str), String> {
+    match (&args[0], &args[1]) {
+        (Value::String(a), Value::String(b)) => Ok((a, b)),
+        _ => Err(format!("{}() requires two strings", name)),
+    }
+}
+
+/// The `(name, timestamp)` arguments shared by `at()` and `rewind()`
+fn history_args<'a>(native: &str, args: &'a [Value]) -> std::result::Result<(&'a str, usize), String> {
+    let Value::String(name) = &args[0] else {
+        return Err(format!("{}() requires variable name as string", native));
+    };
+    match timestamp_arg(&args[1]) {
+        Some(timestamp) => Ok((name, timestamp)),
+        None => Err(format!("{}() requires a non-negative whole timestamp", native)),
+    }
+}
+
+/// A logical time passed to a native: a non-negative whole number
+fn timestamp_arg(value: &Value) -> Option<usize> {
+    match *value {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1305,7 +3834,7 @@ mod tests {
         
         let mut vm = VM::new();
         let mut compiler = Compiler::new();
-        let (chunk, _) = compiler.compile(&program, &mut vm.heap).unwrap();
+        let chunk = compiler.compile(&program, &mut vm.heap).unwrap();
         
         vm.run(chunk).unwrap()
     }
@@ -1385,13 +3914,192 @@ mod tests {
         
         let mut vm = VM::new();
         let mut compiler = crate::compiler::Compiler::new();
-        let (chunk, _) = compiler.compile(&program, &mut vm.heap).unwrap();
+        let chunk = compiler.compile(&program, &mut vm.heap).unwrap();
         
         let result = vm.run(chunk).unwrap();
+        assert_eq!(result, Value::Number(15.0));
         
-        // Check that causality was recorded
+        // Check that causality was recorded: the declaration plus two transitions
         let history = vm.causality.history("x");
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].kind, crate::causality::EventKind::Init);
+        assert_eq!(history[0].new_value, Value::Number(0.0));
+        assert_eq!(vm.causality.transition_count("x"), 2);
+    }
+    
+    #[test]
+    fn test_vm_string_search() {
+        assert_eq!(run_vm(r#"find("héllo wörld", "w")"#), Value::Number(6.0));
+        assert_eq!(run_vm(r#"find("abc", "z")"#), Value::Number(-1.0));
+        assert_eq!(run_vm(r#"rfind("abcabc", "b")"#), Value::Number(4.0));
+        assert_eq!(run_vm(r#"count("banana", "an")"#), Value::Number(2.0));
+        assert_eq!(run_vm(r#"matches("report.txt", "*.[tc]xt")"#), Value::Bool(true));
+        assert_eq!(run_vm(r#"capture("abc", "x*")"#), Value::Nil);
+        assert_eq!(run_vm(r#"len(capture("k=v", "*=*"))"#), Value::Number(2.0));
+    }
+    
+    #[test]
+    fn test_vm_array_mutation() {
+        assert_eq!(run_vm("let a = range(3)
+a[1] = 10
+a[0] + a[1] + a[2]"), Value::Number(12.0));
+        assert_eq!(run_vm("let a = range(0)
+push(a, 4)
+push(a, 5)
+a[1]"), Value::Number(5.0));
+        assert_eq!(run_vm("let a = range(2)
+push(a, 7)"), Value::Number(3.0));
+        assert_eq!(run_vm("let a = range(2)
+pop(a)
+len(a)"), Value::Number(1.0));
+        assert_eq!(run_vm("let a = range(2)
+pop(a)"), Value::Number(1.0));
+        assert_eq!(run_vm("let a = range(0)
+pop(a)"), Value::Nil);
+        assert_eq!(run_vm("let a = range(2)
+a[0] = 9"), Value::Number(9.0));
+    }
+    
+    #[test]
+    fn test_vm_array_literals() {
+        assert_eq!(run_vm("[10, 20, 30][1]"), Value::Number(20.0));
+        assert_eq!(run_vm("let m = [[1, 2], [3, 4],]\nm[1][0]"), Value::Number(3.0));
+        assert_eq!(run_vm("len([])"), Value::Number(0.0));
+        assert_eq!(run_vm("let a = [\"x\", 1 + 1, nil]\nlen(a) + a[1]"), Value::Number(5.0));
+        assert_eq!(run_vm("[1, 2][5]"), Value::Nil);
+    }
+    
+    #[test]
+    fn test_vm_wide_counts() {
+        // Counts above 255 must not wrap and leave stray values on the stack
+        let elements: Vec<String> = (0..300).map(|i| i.to_string()).collect();
+        let source = format!("let a = [{}]\nlen(a) * 1000 + a[299]", elements.join(", "));
+        assert_eq!(run_vm(&source), Value::Number(300299.0));
+        
+        let source = format!("let before = 7\nprint({})\nbefore * 6", elements.join(", "));
+        assert_eq!(run_vm(&source), Value::Number(42.0));
+        
+        let too_many = format!("[{}]", vec!["0"; 65536].join(", "));
+        let err = crate::run(&too_many).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::TooManyOperands(_, 65536, 65535)), "{:?}", err.kind);
+        assert!(err.to_string().contains("too many array elements: 65536 (maximum is 65535)"), "{}", err);
+    }
+    
+    #[test]
+    fn test_vm_nested_indexing() {
+        let source = "let grid = [[1, 2, 3], [4, 5, 6]]\nstate total = 0\nstate i = 0\nwhile i < 2 {\n  total -> total + grid[i][i + 1]\n  i -> i + 1\n}\ntotal";
+        assert_eq!(run_vm(source), Value::Number(8.0));
+        assert_eq!(run_vm("fn row() { return [7, 8] }\nrow()[1]"), Value::Number(8.0));
+        assert_eq!(run_vm("\"abc\"[1]"), Value::String("b".into()));
+        assert_eq!(run_vm("[\"héllo\"][0][1]"), Value::String("é".into()));
+        assert_eq!(run_vm("\"abc\"[3]"), Value::Nil);
+        assert_eq!(run_vm("[1, 2][-1]"), Value::Number(2.0));
+        
+        let err = crate::run("[1, 2][0.5]").unwrap_err().to_string();
+        assert!(err.contains("expected a whole number index, got 0.5"), "{}", err);
+    }
+    
+    #[test]
+    fn test_vm_index_set_out_of_bounds_errors() {
+        for (source, expected) in [
+            ("let a = range(3)
+a[3] = 1", "index 3 out of bounds for array of length 3"),
+            ("let a = range(3)
+a[-4] = 1", "index -4 out of bounds for array of length 3"),
+            ("let a = range(3)
+a[0.5] = 1", "expected a whole number index, got 0.5"),
+            ("let s = \"abc\"\ns[0] = \"x\"", "expected array or map, got string"),
+        ] {
+            let err = crate::run(source).unwrap_err().to_string();
+            assert!(err.contains(expected), "{}: {}", source, err);
+        }
+    }
+    
+    #[test]
+    fn test_vm_state_array_element_is_tracked() {
+        let source = "state a = range(3)\na[2] = 7\nlet b = a\nb[0] = 1";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut vm = VM::new();
+        let chunk = Compiler::new().compile(&program, &mut vm.heap).unwrap();
+        vm.run(chunk).unwrap();
+        
+        // Writes through the state variable are recorded; writes through an alias are not,
+        let history = vm.causality.history("a");
+        // against copies of the whole array
+        let contents = |value: &Value| match value {
+            Value::Array(handle) => vm.heap.get_array(*handle).unwrap().to_vec(),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        let numbers = |ns: &[f64]| ns.iter().map(|&n| Value::Number(n)).collect::<Vec<_>>();
         assert_eq!(history.len(), 2);
+        assert_eq!(contents(&history[1].old_value), numbers(&[0.0, 1.0, 2.0]));
+        assert_eq!(contents(&history[1].new_value), numbers(&[0.0, 1.0, 7.0]));
+        assert_eq!(history[1].location.as_deref(), Some("line 2, index 2"));
+        assert!(vm.causality.history("b").is_empty());
+    }
+    
+    #[test]
+    fn test_vm_why_reports_transition_lines() {
+        let source = "state x = 0
+x -> x + 10
+fn bump() {
+  state local = 1
+  local -> local * 2
+  fn inner() {
+    local -> local + 1
+  }
+  inner()
+  return local
+}
+bump()
+why(x)";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut vm = VM::new();
+        let chunk = Compiler::new().compile(&program, &mut vm.heap).unwrap();
+        let why = vm.run(chunk).unwrap();
+        
+        assert_eq!(
+            why,
+            Value::String("Causality chain for 'x':\n  1. [t=1] initialized to 0 (line 1)\n  2. [t=2] 0 -> 10 (line 2)\n".into())
+        );
+        let lines: Vec<_> = vm.causality.history("bump.local").iter().map(|e| e.location.clone().unwrap()).collect();
+        assert_eq!(lines, ["line 4", "line 5", "line 7"]);
+    }
+    
+    #[test]
+    fn test_vm_records_transition_causes() {
+        let source = "state x = 1
+state y = 0
+x -> 5
+y -> x * 2
+x -> x + y
+fn outer() {
+  state total = 0
+  fn add() {
+    total -> total + y
+  }
+  add()
+}
+outer()
+why(y)";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut vm = VM::new();
+        let chunk = Compiler::new().compile(&program, &mut vm.heap).unwrap();
+        let why = vm.run(chunk).unwrap();
+        
+        assert_eq!(
+            why,
+            Value::String("Causality chain for 'y':\n  1. [t=2] initialized to 0 (line 2)\n  2. [t=4] 0 -> 10 (line 4; caused by x)\n".into())
+        );
+        let causes = |name: &str| -> Vec<Vec<String>> {
+            vm.causality.history(name).iter().map(|e| e.caused_by.clone()).collect()
+        };
+        // Reading the variable being transitioned is not a cause
+        assert_eq!(causes("x"), [vec![], vec![], vec!["y".to_string()]]);
+        assert_eq!(causes("outer.total"), [vec![], vec!["y".to_string()]]);
+        assert!(vm.causality.to_dot("y").contains("style=dashed"));
     }
 }
-
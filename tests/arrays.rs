@@ -0,0 +1,187 @@
+//! Integration tests for building and filling arrays
+
+mod common;
+
+use common::{eval, eval_err};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+const COUNTER: &str = "class Counter {
+  init() {
+    this.n = 0
+  }
+}
+";
+
+#[test]
+fn test_array_of_and_fill() {
+    assert_eq!(eval("let a = array_of(4, 7)\nlen(a) * 100 + a[0] + a[3]"), Value::Number(414.0));
+    assert_eq!(eval("len(array_of(0, nil))"), Value::Number(0.0));
+    assert_eq!(eval("let a = range(3)\nfill(a, \"x\")\na[0] + a[1] + a[2]"), Value::String("xxx".into()));
+    assert_eq!(eval("len(fill(array_of(2, 1), 0))"), Value::Number(2.0));
+}
+
+#[test]
+fn test_array_of_shares_one_value() {
+    let source = format!("{}let cs = array_of(3, Counter())\ncs[0].n = 5\ncs[1].n + cs[2].n", COUNTER);
+    assert_eq!(eval(&source), Value::Number(10.0));
+
+    let source = format!("{}let cs = fill(array_of(2, nil), Counter())\ncs[1].n = 2\ncs[0].n", COUNTER);
+    assert_eq!(eval(&source), Value::Number(2.0));
+}
+
+#[test]
+fn test_array_of_calls_a_function_per_slot() {
+    let source = format!("{}let cs = array_of(3, fn() => Counter())\ncs[0].n = 5\ncs[0].n + cs[1].n + cs[2].n", COUNTER);
+    assert_eq!(eval(&source), Value::Number(5.0));
+
+    let source = "state next = 0\nlet a = array_of(3, fn() {\n  next -> next + 1\n  return next * 10\n})\na[0] + a[1] + a[2]";
+    assert_eq!(eval(source), Value::Number(60.0));
+
+    let err = eval_err("array_of(2, fn() => 1 + nil)");
+    assert!(err.contains("numbers"), "{}", err);
+}
+
+#[test]
+fn test_array_of_rejects_bad_lengths() {
+    let err = eval_err("array_of(100000000, 0)");
+    assert!(err.contains("array_of() length 100000000 exceeds the maximum of 10000000"), "{}", err);
+    let err = eval_err("array_of(-1, 0)");
+    assert!(err.contains("non-negative whole length"), "{}", err);
+    let err = eval_err("array_of(1.5, 0)");
+    assert!(err.contains("non-negative whole length"), "{}", err);
+    let err = eval_err("fill(1, 0)");
+    assert!(err.contains("fill() requires an array, got number"), "{}", err);
+}
+
+#[test]
+fn test_map_filter_reduce() {
+    assert_eq!(eval("let d = map(range(4), fn(x) => x * 2)\nd[0] + d[1] * 10 + d[3] * 100"), Value::Number(620.0));
+    assert_eq!(eval("let e = filter(range(10), fn(x) => x % 2 == 0)\nlen(e) * 100 + e[4]"), Value::Number(508.0));
+    assert_eq!(eval("reduce(range(5), fn(acc, x) => acc + x, 100)"), Value::Number(110.0));
+    assert_eq!(eval("reduce([], fn(acc, x) => acc + x, \"empty\")"), Value::String("empty".into()));
+    // The source array is left alone
+    assert_eq!(eval("let a = [1, 2]\nmap(a, fn(x) => x + 1)\na[0]"), Value::Number(1.0));
+}
+
+#[test]
+fn test_callbacks_that_resize_the_array() {
+    // Appended elements are skipped rather than walked forever
+    assert_eq!(eval("let a = [1, 2]\nlen(map(a, fn(x) => push(a, x)))"), Value::Number(2.0));
+    assert_eq!(eval("let a = [1, 2]\nlen(filter(a, fn(x) => push(a, x)))"), Value::Number(2.0));
+    assert_eq!(eval("let a = [1, 2]\nreduce(a, fn(acc, x) { push(a, x)\n return acc + x }, 0)"), Value::Number(3.0));
+    // Removed ones end the walk early
+    assert_eq!(eval("let a = [1, 2, 3]\nlen(map(a, fn(x) => pop(a)))"), Value::Number(2.0));
+}
+
+#[test]
+fn test_higher_order_natives_accept_any_callable() {
+    assert_eq!(eval("let a = map([-1, 2, -3], abs)\na[0] + a[1] + a[2]"), Value::Number(6.0));
+    assert_eq!(eval("fn big(x) { return x > 1 }\nlen(filter([1, 2, 3], big))"), Value::Number(2.0));
+    let source = format!("{}len(map(range(3), fn(i) => Counter()))", COUNTER);
+    assert_eq!(eval(&source), Value::Number(3.0));
+    // map() with no arguments still creates a map
+    assert_eq!(eval("type(map())"), Value::String("map".into()));
+}
+
+#[test]
+fn test_higher_order_native_errors() {
+    let err = eval_err("map(1, abs)");
+    assert!(err.contains("map() requires an array, got number"), "{}", err);
+    let err = eval_err("map([1])");
+    assert!(err.contains("map() takes 0 or 2 arguments"), "{}", err);
+    let err = eval_err("reduce([1], fn(x) => x, 0)");
+    assert!(err.contains("expected 1 arguments, got 2"), "{}", err);
+    let err = eval_err("let xs = [1, 2]\nmap(xs, fn(x) {\n  return x + nil\n})");
+    assert!(err.contains("line 3"), "{}", err);
+}
+
+/// Elements of an array result, joined with commas
+fn joined(source: &str) -> String {
+    let source = format!("{}\nreduce(result, fn(acc, x) => acc + str(x) + \",\", \"\")", source);
+    match eval(&source) {
+        Value::String(s) => s.to_string(),
+        other => panic!("expected a string, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_sort_numbers_and_strings() {
+    assert_eq!(joined("let result = sort([3, -1, 2.5, 0])"), "-1,0,2.5,3,");
+    assert_eq!(joined("let result = sort([\"pear\", \"apple\", \"Zebra\", \"app\"])"), "Zebra,app,apple,pear,");
+    assert_eq!(joined("let result = reverse([1, 2, 3])"), "3,2,1,");
+    // sort and reverse leave the argument alone; sort_in_place does not
+    assert_eq!(joined("let result = [2, 1]\nsort(result)\nreverse(result)"), "2,1,");
+    assert_eq!(joined("let result = [2, 1]\nsort_in_place(result)"), "1,2,");
+}
+
+#[test]
+fn test_sort_with_comparator_is_stable() {
+    assert_eq!(joined("let result = sort([1, 3, 2], fn(a, b) => b - a)"), "3,2,1,");
+    let source = "let words = [\"bb\", \"a\", \"cc\", \"d\", \"ee\"]
+let result = sort(words, fn(a, b) => len(a) - len(b))";
+    assert_eq!(joined(source), "a,d,bb,cc,ee,");
+}
+
+#[test]
+fn test_sort_errors() {
+    let err = eval_err("sort([1, \"a\"])");
+    assert!(err.contains("sort() cannot compare string and number"), "{}", err);
+    let err = eval_err("sort([[1], [2]])");
+    assert!(err.contains("sort() can only order numbers and strings, got array"), "{}", err);
+    let err = eval_err("sort([1, 2], fn(a, b) => a < b)");
+    assert!(err.contains("sort() comparator must return a number, got bool"), "{}", err);
+    let err = eval_err("sort_in_place(nil)");
+    assert!(err.contains("sort_in_place() requires an array, got nil"), "{}", err);
+}
+
+#[test]
+fn test_zip_truncates_to_shorter() {
+    assert_eq!(eval("let z = zip([1, 2, 3], [\"a\", \"b\"])\nlen(z)"), Value::Number(2.0));
+    assert_eq!(eval("let z = zip([1, 2, 3], [\"a\", \"b\"])\nstr(z[1][0]) + z[1][1]"), Value::String("2b".into()));
+    assert_eq!(eval("len(zip([], [1, 2]))"), Value::Number(0.0));
+    assert!(eval_err("zip([1], 2)").contains("zip() requires an array"));
+}
+
+#[test]
+fn test_enumerate_pairs_indexes_with_elements() {
+    assert_eq!(eval("len(enumerate([]))"), Value::Number(0.0));
+    let source = "state total = 0
+for pair in enumerate([10, 20, 30]) {
+  total -> total + pair[0] * pair[1]
+}
+total";
+    assert_eq!(eval(source), Value::Number(80.0));
+}
+
+#[test]
+fn test_zip_survives_gc_stress() {
+    let mut vm = VM::new();
+    vm.set_gc_stress(true);
+    let source = "let z = zip(range(10000), range(10000, 20000))
+let last = z[9999]
+len(z) + z[0][1] + z[5000][0] + last[0] + last[1]";
+    assert_eq!(vm.eval(source).unwrap(), Value::Number(10000.0 + 10000.0 + 5000.0 + 9999.0 + 19999.0));
+}
+
+#[test]
+fn test_equality_of_heap_values() {
+    assert_eq!(eval("let a = [1, 2]\nlet b = a\na == b and !(a != a)"), Value::Bool(true));
+    assert_eq!(eval("[1, 2] == [1, 2]"), Value::Bool(false));
+    assert_eq!(eval("let m = map()\nm == m and map() != map()"), Value::Bool(true));
+    assert_eq!(eval("len == len and len != str"), Value::Bool(true));
+    assert_eq!(eval(&format!("{}let c = Counter()\nc == c and c != Counter()", COUNTER)), Value::Bool(true));
+}
+
+#[test]
+fn test_deep_equal() {
+    assert_eq!(eval("deep_equal([1, [2, \"x\"], nil], [1, [2, \"x\"], nil])"), Value::Bool(true));
+    assert_eq!(eval("deep_equal([1, [2]], [1, [3]])"), Value::Bool(false));
+    assert_eq!(eval("deep_equal([1], [1, 2])"), Value::Bool(false));
+    assert_eq!(eval("let a = map()\nset(a, \"k\", [1])\nlet b = map()\nset(b, \"k\", [1])\ndeep_equal(a, b)"), Value::Bool(true));
+    assert_eq!(eval("deep_equal(map(), [])"), Value::Bool(false));
+    // Instances are compared by identity even when nested
+    assert_eq!(eval(&format!("{}deep_equal([Counter()], [Counter()])", COUNTER)), Value::Bool(false));
+    // Self-referencing arrays terminate
+    assert_eq!(eval("let a = [1]\npush(a, a)\nlet b = [1]\npush(b, b)\ndeep_equal(a, b)"), Value::Bool(true));
+}
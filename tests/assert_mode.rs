@@ -0,0 +1,48 @@
+//! Integration tests for collecting assertion failures
+
+use skyhetu::vm::{AssertMode, VM};
+use skyhetu::Value;
+
+const SCRIPT: &str = "state seen = 0
+assert(1 == 1, \"first\")
+seen -> seen + 1
+assert(1 == 2, \"second\")
+seen -> seen + 1
+assert(true, \"third\")
+seen -> seen + 1
+seen";
+
+#[test]
+fn test_collect_mode_records_failure_and_continues() {
+    let mut vm = VM::new();
+    vm.set_assert_mode(AssertMode::Collect);
+    let result = vm.eval(SCRIPT).expect("collect mode should not abort");
+
+    assert_eq!(result, Value::Number(3.0));
+    let failures = vm.assert_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].message, "second");
+    assert_eq!(failures[0].line, 4);
+    // Declaration of seen at t=1, first transition at t=2
+    assert_eq!(failures[0].time, 2);
+}
+
+#[test]
+fn test_abort_mode_is_default() {
+    let mut vm = VM::new();
+    let err = vm.eval(SCRIPT).unwrap_err();
+    assert!(err.to_string().contains("second"));
+    assert!(vm.assert_failures().is_empty());
+}
+
+#[test]
+fn test_collected_failures_inside_functions() {
+    let mut vm = VM::new();
+    vm.set_assert_mode(AssertMode::Collect);
+    vm.eval("fn check(n) {\n  assert(n > 0)\n}\ncheck(1)\ncheck(-1)\ncheck(-2)").unwrap();
+
+    let failures = vm.take_assert_failures();
+    assert_eq!(failures.len(), 2);
+    assert!(failures.iter().all(|f| f.line == 2 && f.message == "assertion failed"));
+    assert!(vm.assert_failures().is_empty());
+}
@@ -0,0 +1,54 @@
+//! Integration tests for string buffers
+
+mod common;
+
+use common::{eval, string};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+#[test]
+fn test_buffer_builds_text_in_place() {
+    let source = "let b = buffer()
+buf_push(b, \"x = \")
+buf_push(buf_push(b, 1.5), \", \")
+buf_push(b, [1, 2])
+buf_push(b, nil)
+buf_str(b)";
+    assert_eq!(eval(source), string("x = 1.5, <array>nil"));
+    assert_eq!(eval("let b = buffer()\nbuf_push(b, \"héllo\")\nlen(b)"), Value::Number(5.0));
+    assert_eq!(eval("type(buffer())"), string("buffer"));
+    assert_eq!(eval("let b = buffer()\nprint(b)\nbuf_str(b)"), string(""));
+}
+
+#[test]
+fn test_buffer_keeps_growing_after_buf_str() {
+    let source = "let b = buffer()
+buf_push(b, \"ab\")
+let first = buf_str(b)
+buf_push(b, \"cd\")
+first + \"|\" + buf_str(b)";
+    assert_eq!(eval(source), string("ab|abcd"));
+}
+
+#[test]
+fn test_many_appends_survive_collection() {
+    let mut vm = VM::new();
+    vm.heap.set_gc_threshold(4096);
+    let source = "let b = buffer()
+for i in range(50000) { buf_push(b, i % 10) }
+let s = buf_str(b)
+[len(s), slice(s, 0, 12)]";
+    let Value::Array(handle) = vm.eval(source).unwrap() else { panic!("expected an array") };
+    let results = vm.heap.get_array(handle).unwrap().clone();
+    assert_eq!(results, [Value::Number(50000.0), string("012345678901")]);
+    assert!(vm.heap.collections() > 0);
+    assert!(vm.heap.bytes_allocated() >= 50000);
+}
+
+#[test]
+fn test_buffer_argument_errors() {
+    let err = VM::new().eval("buf_push(\"s\", 1)").unwrap_err();
+    assert!(err.to_string().contains("buf_push() requires a buffer, got string"), "{}", err);
+    let err = VM::new().eval("buf_str([])").unwrap_err();
+    assert!(err.to_string().contains("buf_str() requires a buffer, got array"), "{}", err);
+}
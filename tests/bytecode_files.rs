@@ -0,0 +1,229 @@
+//! Integration tests for compiling programs to `.skyc` bytes and running them
+
+mod common;
+
+use common::compile_with;
+use skyhetu::bytecode::{OpCode, BYTECODE_MAGIC, BYTECODE_VERSION};
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::{Chunk, Compiler, ErrorKind, Heap, Value};
+
+const PROGRAM: &str = "state total = 0
+fn make_adder(n) {
+  return fn(x) => x + n
+}
+class Counter {
+  init() {
+    this.n = 0
+  }
+  bump(by) {
+    this.n = this.n + by
+    return this.n
+  }
+}
+let add = make_adder(10)
+let c = Counter()
+for i in range(3) {
+  total -> total + add(i)
+  c.bump(i)
+}
+print(\"total\", total, -1.5, nil, true)
+c.n * 1000 + total";
+
+fn build(source: &str, debug_info: bool) -> Vec<u8> {
+    let mut heap = Heap::new();
+    let mut compiler = Compiler::new();
+    compiler.set_debug_info(debug_info);
+    let chunk = compile_with(&mut compiler, source, &mut heap).expect("compile failed");
+    chunk.serialize(&heap).expect("serialize failed")
+}
+
+/// Load `bytes` on a fresh VM and run them, returning the result and printed output
+fn run_bytes(bytes: &[u8]) -> (skyhetu::Result<Value>, String) {
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    let result = Chunk::deserialize(bytes, &mut vm.heap).and_then(|chunk| vm.run(chunk));
+    (result, output.contents())
+}
+
+#[test]
+fn test_round_trip_matches_direct_run() {
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    let direct = vm.eval(PROGRAM).unwrap();
+
+    let bytes = build(PROGRAM, false);
+    assert_eq!(&bytes[..4], BYTECODE_MAGIC);
+    let (loaded, printed) = run_bytes(&bytes);
+    assert_eq!(loaded.unwrap(), direct);
+    assert_eq!(direct, Value::Number(3033.0));
+    assert_eq!(printed, output.contents());
+}
+
+#[test]
+fn test_errors_keep_lines_and_debug_info() {
+    let source = "let x = 1\nif x > 0 {\n  print(x + nil)\n}";
+    let (result, _) = run_bytes(&build(source, true));
+    let err = result.unwrap_err();
+    assert_eq!(err.span.map(|s| s.line), Some(3));
+    assert_eq!(err.conditions(), ["if x > 0"]);
+}
+
+#[test]
+fn test_rejects_other_versions_and_damaged_files() {
+    let mut bytes = build("1 + 2", false);
+    bytes[4] = BYTECODE_VERSION + 1;
+    let err = run_bytes(&bytes).0.unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::InvalidBytecode(_)));
+    assert!(err.to_string().contains("rebuild it from source"), "{}", err);
+
+    let bytes = build(PROGRAM, false);
+    for len in [0, 3, 5, bytes.len() / 2, bytes.len() - 1] {
+        let err = run_bytes(&bytes[..len]).0.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidBytecode(_)), "length {}: {}", len, err);
+    }
+
+    // An unknown opcode is caught at load time, not executed
+    let mut bytes = build("nil", false);
+    let code_start = BYTECODE_MAGIC.len() + 2;
+    bytes[code_start] = 0xff;
+    let err = run_bytes(&bytes).0.unwrap_err();
+    assert!(err.to_string().contains("unknown opcode 255"), "{}", err);
+}
+
+#[test]
+fn test_rejects_operands_out_of_range() {
+    // Patch the last `op` in the code of `source` with `patch` and load it
+    let load_patched = |source: &str, op: OpCode, patch: &dyn Fn(&mut [u8])| {
+        let mut bytes = build(source, false);
+        let code_start = BYTECODE_MAGIC.len() + 2;
+        let code_len = bytes[code_start - 1] as usize;
+        let code = &mut bytes[code_start..code_start + code_len];
+        let at = code.iter().rposition(|&b| b == op as u8).expect("opcode not found");
+        patch(&mut code[at..]);
+        let err = run_bytes(&bytes).0.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidBytecode(_)), "{}", err);
+        err.to_string()
+    };
+
+    let err = load_patched("let x = 1\nx", OpCode::GetGlobal, &|code| code[1..3].copy_from_slice(&[0xff, 0xff]));
+    assert!(err.contains("name index at offset"), "{}", err);
+    let err = load_patched("for i in [1, 2] { print(i) }", OpCode::Loop, &|code| code[1..3].copy_from_slice(&[0xff, 0xff]));
+    assert!(err.contains("does not land on an instruction"), "{}", err);
+    let err = load_patched("for i in [1, 2] { print(i) }", OpCode::Loop, &|code| code[2] -= 1);
+    assert!(err.contains("does not land on an instruction"), "{}", err);
+    let err = load_patched("nil", OpCode::Return, &|code| code[0] = OpCode::Pop as u8);
+    assert!(err.contains("runs past its end"), "{}", err);
+}
+
+/// Serialize a top-level chunk holding `code`, which may name "x" and
+/// causes 0, followed by `Halt`
+fn craft(code: &[(OpCode, &[u16])]) -> Vec<u8> {
+    craft_chunk(code).serialize(&Heap::new()).expect("serialize failed")
+}
+
+/// The chunk `craft` serializes
+fn craft_chunk(code: &[(OpCode, &[u16])]) -> Chunk {
+    let mut chunk = Chunk::new();
+    chunk.add_name("x".to_string());
+    chunk.add_causes(Vec::new());
+    for (op, operands) in code {
+        chunk.write(*op, 1);
+        for &operand in *operands {
+            chunk.write_u16(operand, 1);
+        }
+    }
+    chunk.write(OpCode::Halt, 1);
+    chunk
+}
+
+#[test]
+fn test_rejects_break_and_continue() {
+    for op in [OpCode::Break, OpCode::Continue] {
+        let err = run_bytes(&craft(&[(op, &[])])).0.unwrap_err();
+        assert_eq!(err.to_string(), format!("Error: invalid bytecode file: {:?} at offset 0 is not executable", op));
+    }
+}
+
+#[test]
+fn test_local_slots_outside_the_frame_fail_without_panicking() {
+    let slot_ops: [(OpCode, &[u16]); 9] = [
+        (OpCode::GetLocal, &[0xffff]),
+        (OpCode::SetLocal, &[0xffff]),
+        (OpCode::IterLen, &[0xffff]),
+        (OpCode::IterGet, &[0, 0xffff]),
+        (OpCode::CheckIteration, &[0xffff, 0]),
+        (OpCode::InitParamState, &[0xffff, 0]),
+        (OpCode::CheckType, &[0xffff, 0]),
+        (OpCode::TransitionLocal, &[0xffff, 0, 0]),
+        (OpCode::TransitionParam, &[0xffff, 0, 0]),
+    ];
+    for (op, operands) in slot_ops {
+        let code = [(OpCode::Nil, &[][..]), (op, operands)];
+        let err = run_bytes(&craft(&code)).0.unwrap_err();
+        assert_eq!(err.to_string(), "Error: invalid bytecode file: local slot at offset 1 is out of range", "{:?}", op);
+
+        // Chunks handed straight to the VM aren't checked, and fail when run
+        let err = VM::new().run(craft_chunk(&code)).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidSlot(0xffff)), "{:?}: {}", op, err);
+        assert_eq!(err.to_string(), "[line 1] Error: local slot 65535 is outside the current call: the bytecode is malformed");
+    }
+}
+
+#[test]
+fn test_rejects_code_that_pops_more_than_the_stack_holds() {
+    // The script's own closure is the only value on the stack
+    let err = run_bytes(&craft(&[(OpCode::Add, &[])])).0.unwrap_err();
+    assert_eq!(err.to_string(), "Error: invalid bytecode file: instruction at offset 0 pops more than the stack holds");
+    let err = run_bytes(&craft(&[(OpCode::Array, &[2])])).0.unwrap_err();
+    assert!(err.to_string().contains("pops more than the stack holds"), "{}", err);
+
+    // Both ways past an `if` must leave the stack as deep
+    let err = run_bytes(&craft(&[(OpCode::True, &[]), (OpCode::JumpIfFalse, &[1]), (OpCode::Nil, &[])])).0.unwrap_err();
+    assert_eq!(err.to_string(), "Error: invalid bytecode file: stack depth differs between paths to offset 5");
+}
+
+#[test]
+fn test_damaged_files_never_panic() {
+    let bytes = build(PROGRAM, false);
+    let load = |bytes: &[u8]| {
+        let mut vm = VM::new();
+        vm.set_output(Box::new(CaptureBuffer::new()));
+        vm.set_fuel(Some(10_000));
+        vm.set_max_heap(Some(1 << 20));
+        Chunk::deserialize(bytes, &mut vm.heap).and_then(|chunk| vm.run(chunk))
+    };
+    for len in 0..bytes.len() {
+        assert!(load(&bytes[..len]).is_err(), "length {}", len);
+    }
+    let mut damaged = bytes.clone();
+    for at in 0..bytes.len() {
+        for bit in 0..8 {
+            damaged[at] ^= 1 << bit;
+            let _ = load(&damaged);
+            damaged[at] = bytes[at];
+        }
+    }
+}
+
+/// A program whose top level holds a function holding a function, `depth` deep
+fn nested_functions(depth: usize) -> Vec<u8> {
+    let mut heap = Heap::new();
+    let mut chunk = Chunk::new();
+    chunk.write(OpCode::Return, 1);
+    for _ in 0..depth {
+        let function = skyhetu::value::Function::new("f".to_string(), Vec::new(), std::rc::Rc::new(chunk), 0);
+        chunk = Chunk::new();
+        chunk.add_constant(Value::Function(heap.alloc_function(function)));
+        chunk.write(OpCode::Return, 1);
+    }
+    chunk.serialize(&heap).expect("serialize failed")
+}
+
+#[test]
+fn test_rejects_deeply_nested_functions() {
+    assert!(Chunk::deserialize(&nested_functions(64), &mut Heap::new()).is_ok());
+    let err = run_bytes(&nested_functions(65)).0.unwrap_err();
+    assert_eq!(err.to_string(), "Error: invalid bytecode file: functions nested too deeply");
+}
@@ -0,0 +1,91 @@
+//! Integration tests for the whole-program causality graph
+
+use std::collections::HashSet;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+const SCENARIO: &str = "state price = 10
+state qty = 2
+state total = 0
+total -> price * qty
+price -> 12
+total -> price * qty
+state label = \"none\"
+label -> \"say \\\"hi\\\"\"";
+
+/// Node ids declared and edges (from, to, dashed) of a DOT graph, after
+/// checking that it is one digraph with balanced braces whose edges only
+/// join declared nodes
+fn parse_dot(dot: &str) -> (HashSet<String>, Vec<(String, String, bool)>) {
+    assert!(dot.starts_with("digraph causality {\n"), "{}", dot);
+    assert!(dot.ends_with("}\n"), "{}", dot);
+
+    // Braces outside quoted labels must balance and never close early
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in dot.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => {
+                depth -= 1;
+                assert!(depth >= 0, "{}", dot);
+            }
+            _ => {}
+        }
+    }
+    assert_eq!((depth, in_string), (0, false), "{}", dot);
+
+    let mut nodes = HashSet::new();
+    let mut edges = Vec::new();
+    for line in dot.lines().map(str::trim) {
+        if let Some((from, rest)) = line.split_once(" -> ") {
+            let to = rest.split([' ', ';']).next().unwrap();
+            edges.push((from.to_string(), to.to_string(), rest.contains("style=dashed")));
+        } else if let Some((id, _)) = line.split_once(" [label=") {
+            assert!(nodes.insert(id.to_string()), "node {} declared twice", id);
+        }
+    }
+    for (from, to, _) in &edges {
+        assert!(nodes.contains(from) && nodes.contains(to), "edge {} -> {} joins undeclared nodes", from, to);
+    }
+    (nodes, edges)
+}
+
+#[test]
+fn test_graph_of_every_variable() {
+    let mut vm = VM::new();
+    vm.eval(SCENARIO).unwrap();
+    let dot = vm.causality.to_dot_all();
+    let (nodes, edges) = parse_dot(&dot);
+
+    assert_eq!(nodes.len(), 8);
+    let solid = edges.iter().filter(|(_, _, dashed)| !dashed).count();
+    let dashed = edges.iter().filter(|(_, _, dashed)| *dashed).count();
+    assert_eq!((solid, dashed), (4, 4));
+
+    // One cluster per variable, in order of first change
+    assert_eq!(dot.matches("subgraph cluster_").count(), 4);
+    let labels: Vec<&str> = dot.lines().filter_map(|l| l.trim().strip_prefix("label=")).collect();
+    assert_eq!(labels, ["\"price\";", "\"qty\";", "\"total\";", "\"label\";"]);
+
+    assert!(dot.contains("[label=\"total: 0→20 @4\"]"), "{}", dot);
+    assert!(dot.contains("[label=\"label: none→say \\\"hi\\\" @8\"]"), "{}", dot);
+    // The second total comes from the new price and the unchanged qty
+    assert!(dot.contains("  e4 -> e5 [style=dashed];\n  e1 -> e5 [style=dashed];\n"), "{}", dot);
+}
+
+#[test]
+fn test_causal_graph_without_arguments() {
+    let mut vm = VM::new();
+    let Value::String(dot) = vm.eval(&format!("{}\ncausal_graph()", SCENARIO)).unwrap() else {
+        panic!("causal_graph() returns a string");
+    };
+    assert_eq!(&*dot, vm.causality.to_dot_all());
+
+    let (nodes, edges) = parse_dot(&VM::new().causality.to_dot_all());
+    assert!(nodes.is_empty() && edges.is_empty());
+}
@@ -0,0 +1,141 @@
+//! Integration tests for streaming the causality log out of the VM
+
+use skyhetu::causality::{CausalityLog, ExportFormat, DEFAULT_STREAM_RETENTION};
+use skyhetu::vm::{CaptureBuffer, VM};
+
+#[test]
+fn test_stream_matches_export() {
+    let mut vm = VM::new();
+    let sink = CaptureBuffer::new();
+    vm.stream_causality_to(Box::new(sink.clone()), ExportFormat::Jsonl).unwrap();
+    vm.eval("state a = 1\nstate b = \"x\"\na -> a + 1\nb -> b + \"y\"\na -> nil").expect("execution failed");
+
+    let mut exported = Vec::new();
+    vm.causality.export_to(&mut exported, ExportFormat::Jsonl).unwrap();
+    assert_eq!(sink.contents(), String::from_utf8(exported).unwrap());
+    // Two declarations and three transitions
+    assert_eq!(sink.contents().lines().count(), 5);
+}
+
+#[test]
+fn test_large_run_streams_with_bounded_memory() {
+    let mut vm = VM::new();
+    let sink = CaptureBuffer::new();
+    vm.stream_causality_to(Box::new(sink.clone()), ExportFormat::Binary).unwrap();
+    vm.eval("state i = 0\nwhile i < 100000 { i -> i + 1 }").expect("execution failed");
+
+    assert_eq!(vm.causality.event_count(), DEFAULT_STREAM_RETENTION);
+    assert_eq!(vm.causality.history("i").last().unwrap().new_value, skyhetu::Value::Number(100000.0));
+
+    let bytes = sink.bytes();
+    let loaded = CausalityLog::load_binary(&mut bytes.as_slice()).unwrap();
+    // The declaration of i plus 100k transitions
+    assert_eq!(loaded.event_count(), 100_001);
+    assert_eq!(loaded.transition_count("i"), 100_000);
+    let events: Vec<_> = loaded.all_events().collect();
+    assert_eq!(events[0].id, 0);
+    assert_eq!(events[100_000].new_value, skyhetu::Value::Number(100000.0));
+}
+
+#[test]
+fn test_stream_respects_explicit_retention() {
+    let mut vm = VM::new();
+    vm.causality.set_retention(Some(10));
+    vm.stream_causality_to(Box::new(CaptureBuffer::new()), ExportFormat::Binary).unwrap();
+    vm.eval("state i = 0\nwhile i < 50 { i -> i + 1 }").expect("execution failed");
+    assert_eq!(vm.causality.event_count(), 10);
+}
+
+#[test]
+fn test_evicted_events_are_streamed_and_digested() {
+    let mut vm = VM::new();
+    vm.causality.set_retention(Some(10));
+    let sink = CaptureBuffer::new();
+    vm.stream_causality_to(Box::new(sink.clone()), ExportFormat::Jsonl).unwrap();
+    vm.eval("state i = 0\nwhile i < 100 { i -> i + 1 }").expect("execution failed");
+
+    // Every event reaches the stream even though only 10 stay in memory
+    assert_eq!(sink.contents().lines().count(), 101);
+    assert_eq!(vm.causality.event_count(), 10);
+
+    // The declaration and the first 90 transitions were evicted
+    let digest = vm.causality.digest("i").unwrap();
+    assert_eq!(digest.count, 91);
+    assert_eq!((digest.min, digest.max), (Some(0.0), Some(90.0)));
+    assert!(vm.causality.why("i").contains("91 earlier events evicted"));
+}
+
+#[test]
+fn test_json_export_round_trips() {
+    let mut vm = VM::new();
+    vm.eval("state count = 0
+state label = \"a \\\"quoted\\\"\\nline\"
+state flag = false
+state total = 1.5
+while count < 4 {
+  count -> count + 1
+  if count % 2 == 0 { label -> label + str(count) }
+  total -> total * count
+}
+flag -> !flag
+label -> nil
+total -> total + count").expect("execution failed");
+
+    let text = vm.causality.export_all_json();
+    let loaded = CausalityLog::import_json(&text).expect("import failed");
+    let log = &vm.causality;
+
+    assert_eq!(loaded.current_time(), log.current_time());
+    assert_eq!(loaded.all_events().count(), log.all_events().count());
+    for (a, b) in log.all_events().zip(loaded.all_events()) {
+        assert_eq!((a.id, a.kind, a.timestamp), (b.id, b.kind, b.timestamp));
+        assert_eq!((&a.variable, &a.location, &a.caused_by), (&b.variable, &b.location, &b.caused_by));
+        assert_eq!((&a.old_value, &a.new_value), (&b.old_value, &b.new_value));
+    }
+    for name in ["count", "label", "flag", "total"] {
+        assert_eq!(loaded.why(name), log.why(name));
+        assert_eq!(loaded.history(name).len(), log.history(name).len());
+        for t in 0..=log.current_time() + 1 {
+            assert_eq!(loaded.value_at(name, t), log.value_at(name, t), "{} at t={}", name, t);
+        }
+    }
+
+    // Exporting the loaded log gives back the same document
+    assert_eq!(loaded.export_all_json(), text);
+}
+
+#[test]
+fn test_json_import_rejects_other_documents() {
+    assert!(CausalityLog::import_json("{\"events\":[]}").is_err());
+    assert!(CausalityLog::import_json("{\"version\":99,\"events\":[]}").is_err());
+    assert!(CausalityLog::import_json("{\"version\":1,\"events\":[{\"id\":0}]}").is_err());
+    assert!(CausalityLog::import_json("{\"version\":1,\"events\":[]} x").is_err());
+    let empty = CausalityLog::import_json("{\"version\":1,\"clock\":0,\"events\":[]}").unwrap();
+    assert_eq!(empty.all_events().count(), 0);
+}
+
+#[test]
+fn test_json_import_rejects_out_of_range_numbers() {
+    let event = |id: &str, timestamp: &str| {
+        format!(
+            "{{\"version\":1,\"events\":[{{\"id\":{},\"kind\":\"init\",\"timestamp\":{},\"variable\":\"x\",\"old\":null,\"new\":1,\"location\":null}}]}}",
+            id, timestamp
+        )
+    };
+    assert!(CausalityLog::import_json(&event("9007199254740991", "1")).is_ok());
+    for (id, timestamp, field) in [("1e300", "1", "id"), ("9007199254740992", "1", "id"), ("0", "1e20", "timestamp")] {
+        let err = CausalityLog::import_json(&event(id, timestamp)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), format!("event '{}' is not a whole number from 0 to 9007199254740991", field));
+    }
+    let err = CausalityLog::import_json("{\"version\":1,\"clock\":1e300,\"events\":[]}").unwrap_err();
+    assert_eq!(err.to_string(), "causality log clock is out of range");
+}
+
+#[test]
+fn test_json_import_rejects_deep_nesting() {
+    let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    let err = CausalityLog::import_json(&deep).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "invalid JSON at byte 64: arrays and objects nested too deeply");
+}
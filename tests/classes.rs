@@ -1,31 +1,15 @@
 //! Integration tests for classes and instances
 
-use skyhetu::{Lexer, Parser};
+mod common;
+
+use common::{compile_warnings, eval, eval_err, string};
+use skyhetu::ErrorKind;
 use skyhetu::compiler::Compiler;
 use skyhetu::vm::VM;
 
-fn run(source: &str) -> Result<skyhetu::Value, String> {
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize().map_err(|e| e.to_string())?;
-    
-    let mut parser = Parser::new(tokens);
-    let program = parser.parse().map_err(|e| e.to_string())?;
-    
-    let mut vm = VM::new();
-    let mut compiler = Compiler::new();
-    let (chunk, chunks) = compiler.compile(&program, &mut vm.heap).map_err(|e| e.to_string())?;
-    
-    vm.register_chunks(chunks);
-    vm.run(chunk).map_err(|e| e.to_string())
-}
-
-fn run_ok(source: &str) -> skyhetu::Value {
-    run(source).expect("execution failed")
-}
-
 #[test]
 fn test_class_instantiation() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Box {
             init(value) {
                 this.value = value
@@ -40,7 +24,7 @@ fn test_class_instantiation() {
 
 #[test]
 fn test_class_method() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Counter {
             init() {
                 this.count = 0
@@ -61,7 +45,7 @@ fn test_class_method() {
 
 #[test]
 fn test_property_set() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Point {
             init(x, y) {
                 this.x = x
@@ -78,7 +62,7 @@ fn test_property_set() {
 
 #[test]
 fn test_this_binding() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Person {
             init(name) {
                 this.name = name
@@ -92,7 +76,7 @@ fn test_this_binding() {
     "#);
     
     if let skyhetu::Value::String(s) = result {
-        assert_eq!(s, "Alice");
+        assert_eq!(&*s, "Alice");
     } else {
         panic!("Expected string, got {:?}", result);
     }
@@ -101,7 +85,7 @@ fn test_this_binding() {
 #[test]
 fn test_init_returns_instance() {
     // Verify that init implicitly returns this
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Wrapper {
             init(v) {
                 this.v = v
@@ -116,7 +100,7 @@ fn test_init_returns_instance() {
 
 #[test]
 fn test_multiple_instances() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Counter {
             init(start) {
                 this.val = start
@@ -138,7 +122,7 @@ fn test_multiple_instances() {
 
 #[test]
 fn test_class_no_init() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Empty {}
         let e = Empty()
         e.x = 42
@@ -150,7 +134,7 @@ fn test_class_no_init() {
 
 #[test]
 fn test_method_chaining() {
-    let result = run_ok(r#"
+    let result = eval(r#"
         class Builder {
             init() {
                 this.val = 0
@@ -169,3 +153,212 @@ fn test_method_chaining() {
     
     assert!(matches!(result, skyhetu::Value::Number(n) if n == 6.0));
 }
+
+#[test]
+fn test_why_this_field_inside_method() {
+    let result = eval(r#"
+        class Counter {
+            init() {
+                this.count = 0
+            }
+            bump_three() {
+                this.count -> this.count + 1
+                this.count -> this.count + 1
+                this.count -> this.count + 1
+                return why(this.count)
+            }
+        }
+        let other = Counter()
+        other.count -> 100
+        Counter().bump_three()
+    "#);
+    
+    let skyhetu::Value::String(chain) = result else {
+        panic!("why() returns a string");
+    };
+    let entries: Vec<&str> = chain.lines().skip(1).collect();
+    assert_eq!(entries.len(), 3, "{}", chain);
+    assert!(entries[0].ends_with("0 -> 1 (line 7)"), "{}", chain);
+    assert!(entries[2].ends_with("2 -> 3 (line 9)"), "{}", chain);
+    assert!(!chain.contains("100"), "sibling instance leaked into the chain: {}", chain);
+}
+
+#[test]
+fn test_why_field_from_outside() {
+    let result = eval(r#"
+        class Light {
+            init() {
+                this.on = false
+            }
+        }
+        let l = Light()
+        l.on -> true
+        why(l.on)
+    "#);
+    let skyhetu::Value::String(chain) = result else {
+        panic!("why() returns a string");
+    };
+    assert!(chain.contains("1. [t=1] false -> true"), "{}", chain);
+    
+    let err = eval_err("let n = 1\nwhy(n.field)");
+    assert!(err.contains("expected instance, got number"), "{}", err);
+}
+
+#[test]
+fn test_field_history_per_instance() {
+    let mut vm = VM::new();
+    vm.eval("class Counter {
+  init() { this.count = 0 }
+  increment() { this.count -> this.count + 1 }
+}
+let a = Counter()
+let b = Counter()
+a.increment()
+a.increment()
+a.increment()
+b.increment()").unwrap();
+
+    let mut key = |name: &str| match vm.eval(&format!("id({})", name)).unwrap() {
+        skyhetu::Value::Number(id) => format!("Counter#{}.count", id),
+        other => panic!("expected an id, got {:?}", other),
+    };
+    let (a, b) = (key("a"), key("b"));
+    assert_eq!(vm.causality.history(&a).len(), 3);
+    assert_eq!(vm.causality.history(&b).len(), 1);
+
+    let skyhetu::Value::String(chain) = vm.eval("why_field(a, \"count\")").unwrap() else {
+        panic!("why_field() returns a string");
+    };
+    assert_eq!(chain.lines().count(), 4, "{}", chain);
+    assert!(chain.contains("2 -> 3 (line 3)"), "{}", chain);
+    assert_eq!(vm.eval("why_field(b, \"count\") == why(b.count)").unwrap(), skyhetu::Value::Bool(true));
+
+    let err = vm.eval("why_field(1, \"count\")").unwrap_err().to_string();
+    assert!(err.contains("why_field() requires an instance, got number"), "{}", err);
+}
+
+#[test]
+fn test_duplicate_method_is_an_error() {
+    let err = eval_err("class C {\n  inc() { return 1 }\n\n  inc(n) { return n }\n}");
+    assert!(err.contains("[line 4:3]"), "{}", err);
+    assert!(err.contains("method 'inc' already defined at line 2"), "{}", err);
+    let err = skyhetu::run("class C {\n  inc() { return 1 }\n  inc() { return 2 }\n}").unwrap_err();
+    assert!(matches!(&err.kind, ErrorKind::DuplicateMethod(name, 2) if name == "inc"), "{:?}", err.kind);
+}
+
+#[test]
+fn test_second_init_is_an_error() {
+    let err = eval_err("class C {\n  init(a) { this.a = a }\n  init() { this.a = 0 }\n}");
+    assert!(err.contains("method 'init' already defined at line 2"), "{}", err);
+    
+    // A single init with parameters is fine
+    assert!(compile_warnings(&mut Compiler::new(), "class C {\n  init(a, b) { this.a = a }\n}").is_empty());
+}
+
+#[test]
+fn test_field_hiding_method_warns() {
+    let warnings: Vec<String> = compile_warnings(&mut Compiler::new(), "class Counter {
+  init() {
+    this.count = 0
+    if true {
+      this.reset -> nil
+    }
+  }
+  count() { return 1 }
+  reset() { this.count = 0 }
+}").iter().map(ToString::to_string).collect();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings[0].contains("field 'count' set in init hides the method defined at line 8"), "{:?}", warnings);
+    assert!(warnings[1].starts_with("[line 5:"), "{:?}", warnings);
+    assert!(warnings[1].contains("field 'reset'"), "{:?}", warnings);
+}
+
+const ANIMALS: &str = "class Animal {
+  init(name) {
+    this.name = name
+  }
+  speak() { return this.name + \" makes a sound\" }
+  describe() { return \"I am \" + this.name + \": \" + this.speak() }
+}
+class Dog : Animal {
+  init(name, breed) {
+    super.init(name)
+    this.breed = breed
+  }
+  speak() { return this.name + \" barks\" }
+}
+class Puppy : Dog {
+  speak() { return super.speak() + \" softly\" }
+}
+";
+
+fn animals(expr: &str) -> skyhetu::Value {
+    eval(&format!("{}{}", ANIMALS, expr))
+}
+
+#[test]
+fn test_subclass_overrides_and_inherits_methods() {
+    assert_eq!(animals("Dog(\"Rex\", \"lab\").speak()"), string("Rex barks"));
+    // An inherited method calls the override through `this`
+    assert_eq!(animals("Dog(\"Rex\", \"lab\").describe()"), string("I am Rex: Rex barks"));
+    assert_eq!(animals("Animal(\"Cat\").describe()"), string("I am Cat: Cat makes a sound"));
+    // Methods come from the nearest class that defines them
+    assert_eq!(animals("Puppy(\"Bit\", \"pug\").describe()"), string("I am Bit: Bit barks softly"));
+}
+
+#[test]
+fn test_super_init_runs_the_parent_initializer() {
+    assert_eq!(animals("let d = Dog(\"Rex\", \"lab\")\nd.name + \"/\" + d.breed"), string("Rex/lab"));
+    // Puppy has no init of its own, so Dog's is inherited
+    assert_eq!(animals("Puppy(\"Bit\", \"pug\").breed"), string("pug"));
+}
+
+#[test]
+fn test_super_resolves_against_the_defining_class() {
+    // `super` in Dog means Animal even when `this` is a Puppy, and closures keep it
+    let source = "class Loud : Dog {
+  speak() {
+    let inner = fn() => super.speak()
+    return inner() + \"!\"
+  }
+}
+Loud(\"Max\", \"mix\").speak()";
+    assert_eq!(animals(source), string("Max barks!"));
+}
+
+#[test]
+fn test_inheritance_errors() {
+    let err = eval_err("class A : A {}");
+    assert!(err.contains("cannot inherit from itself"), "{}", err);
+    let err = eval_err("let x = 1\nclass A : x {}");
+    assert!(err.contains("Superclass must be a class, got number"), "{}", err);
+    let err = eval_err("class A {\n  f() { return super.f() }\n}");
+    assert!(err.contains("'super' can only be used in methods of a class with a superclass"), "{}", err);
+    let err = eval_err("class A {}\nclass B : A {\n  f() { return super.missing() }\n}\nB().f()");
+    assert!(err.contains("undefined property 'missing'"), "{}", err);
+
+    // The first and third are caught by the compiler
+    let err = skyhetu::run("class A : A {}").unwrap_err();
+    assert!(matches!(&err.kind, ErrorKind::SelfInheritance(name) if name == "A"), "{:?}", err.kind);
+    let err = skyhetu::run("class A {\n  f() { return super.f() }\n}").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::SuperOutsideSubclass), "{:?}", err.kind);
+}
+
+#[test]
+fn test_superclass_survives_collection() {
+    let mut vm = VM::new();
+    // Once `make` returns, the parent is only reachable as the subclass's superclass
+    vm.eval("fn make() {
+  class Base { hi() { return \"base\" } }
+  class Child : Base {}
+  return Child
+}
+let Made = make()").unwrap();
+    vm.eval("let junk = map(range(2000), fn(i) => [i])").unwrap();
+    vm.collect_garbage();
+
+    let Some(skyhetu::Value::Class(child)) = vm.get_global("Made") else { panic!("expected a class") };
+    let parent = vm.heap.get_class(child).and_then(|c| c.superclass).expect("superclass recorded");
+    assert_eq!(vm.heap.get_class(parent).map(|c| c.name.as_str()), Some("Base"));
+    assert_eq!(vm.eval("Made().hi()").unwrap(), string("base"));
+}
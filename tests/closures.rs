@@ -18,7 +18,7 @@ fn test_basic_closure() {
     "#;
     let result = run(source).expect("Execution failed");
     match result {
-        Value::String(s) => assert_eq!(s, "global captured"),
+        Value::String(s) => assert_eq!(&*s, "global captured"),
         _ => panic!("Expected string, got {:?}", result),
     }
 }
@@ -46,7 +46,7 @@ fn test_counter_state() {
     "#;
     let result = run(source).expect("Execution failed");
     match result {
-        Value::String(s) => assert_eq!(s, "1,2,1"), // Numbers are floats formatted as X.X usually
+        Value::String(s) => assert_eq!(&*s, "1,2,1"),
         _ => panic!("Expected string, got {:?}", result),
     }
 }
@@ -72,7 +72,90 @@ fn test_close_upvalue() {
     "#;
     let result = run(source).expect("Execution failed");
     match result {
-        Value::String(s) => assert_eq!(s, "first"),
+        Value::String(s) => assert_eq!(&*s, "first"),
         _ => panic!("Expected string, got {:?}", result),
     }
 }
+
+#[test]
+fn test_lambda_expression_body() {
+    let source = "let add = fn(a, b) => a + b\nadd(2, 3)";
+    assert_eq!(run(source).unwrap(), Value::Number(5.0));
+}
+
+#[test]
+fn test_lambda_captures_local() {
+    let source = r#"
+        fn make_adder(n) {
+            let offset = n * 10
+            return fn(x) => x + offset
+        }
+        let add20 = make_adder(2)
+        add20(1)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Number(21.0));
+}
+
+#[test]
+fn test_lambda_block_body_and_higher_order() {
+    let source = r#"
+        fn apply_twice(f, x) {
+            return f(f(x))
+        }
+        fn counter() {
+            state count = 0
+            return fn() {
+                count -> count + 1
+                return count
+            }
+        }
+        let next = counter()
+        next()
+        let quiet = fn() { let unused = 1 }
+        [apply_twice(fn(v) => v * 3, 2), next(), quiet()]
+    "#;
+    let mut vm = skyhetu::VM::new();
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[18, 2, nil]");
+}
+
+#[test]
+fn test_transition_of_captured_let_is_rejected() {
+    let source = "fn make() {
+  let i = 0
+  fn bump() {
+    i -> i + 1
+  }
+  return bump
+}";
+    let err = run(source).unwrap_err();
+    assert!(matches!(err.kind, skyhetu::ErrorKind::ImmutableVariable(ref name) if name == "i"), "{}", err);
+    assert_eq!(err.span.unwrap().line, 4);
+
+    // Captured through an intermediate function, and by a lambda
+    let nested = "fn outer() {
+  let total = 0
+  fn middle() {
+    return fn() { total -> total + 1 }
+  }
+  return middle
+}";
+    let err = run(nested).unwrap_err();
+    assert!(matches!(err.kind, skyhetu::ErrorKind::ImmutableVariable(_)), "{}", err);
+}
+
+#[test]
+fn test_captured_state_can_transition_through_nested_closures() {
+    let source = "fn outer() {
+  state total = 0
+  fn middle() {
+    return fn(n) { total -> total + n }
+  }
+  let add = middle()
+  add(2)
+  add(3)
+  return total
+}
+outer()";
+    assert_eq!(run(source).unwrap(), Value::Number(5.0));
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by the integration tests.
+//!
+//! A test file includes this with `mod common;`.
+
+// Each test file uses only part of this
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::{Chunk, Heap, Lexer, Parser, Value, Warning};
+
+/// Run `source` on a fresh VM and return its value
+pub fn eval(source: &str) -> Value {
+    VM::new().eval(source).expect("execution failed")
+}
+
+/// Run `source` on a fresh VM and return the error it fails with
+pub fn eval_err(source: &str) -> String {
+    VM::new().eval(source).unwrap_err().to_string()
+}
+
+/// A string value
+pub fn string(s: &str) -> Value {
+    Value::String(s.into())
+}
+
+/// The contents of a string value
+pub fn text(value: Value) -> String {
+    let Value::String(s) = value else { panic!("expected a string, got {:?}", value) };
+    s.to_string()
+}
+
+/// Run `source` on a fresh VM and return its value and everything it printed
+pub fn eval_output(source: &str) -> (Value, String) {
+    let mut vm = VM::new();
+    let captured = CaptureBuffer::new();
+    vm.set_output(Box::new(captured.clone()));
+    let value = vm.eval(source).expect("execution failed");
+    (value, captured.contents())
+}
+
+/// Run `source` on a fresh VM and return everything it printed
+pub fn output(source: &str) -> String {
+    eval_output(source).1
+}
+
+/// Run `source` on a fresh VM and return the VM, to inspect its globals and
+/// causality log
+pub fn run(source: &str) -> VM {
+    let mut vm = VM::new();
+    vm.eval(source).expect("execution failed");
+    vm
+}
+
+/// Compile `source` with `compiler`, allocating its functions in `heap`
+pub fn compile_with(compiler: &mut Compiler, source: &str, heap: &mut Heap) -> skyhetu::Result<Chunk> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    compiler.compile(&program, heap)
+}
+
+/// Compile `source` for `vm`, with or without optimization. Imports resolve
+/// relative to `examples/`.
+pub fn compile(source: &str, optimize: bool, vm: &mut VM) -> Chunk {
+    let mut compiler = Compiler::with_base_path(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples"));
+    compiler.set_optimize(optimize);
+    compile_with(&mut compiler, source, &mut vm.heap).expect("compile failed")
+}
+
+/// The warnings `compiler` reports for `source`, which must compile
+pub fn compile_warnings(compiler: &mut Compiler, source: &str) -> Vec<Warning> {
+    compile_with(compiler, source, &mut Heap::new()).expect("compile failed");
+    compiler.warnings().to_vec()
+}
+
+/// The warning messages a default compiler reports for `source`, with their lines
+pub fn warning_lines(source: &str) -> Vec<(usize, String)> {
+    compile_warnings(&mut Compiler::new(), source)
+        .into_iter()
+        .map(|w| (w.span.expect("warning without a line").line, w.message))
+        .collect()
+}
+
+/// Compile `source` with or without optimization, run it on a deterministic
+/// VM and return everything it printed
+pub fn compiled_output(source: &str, optimize: bool) -> String {
+    let mut vm = VM::deterministic(0);
+    let captured = CaptureBuffer::new();
+    vm.set_output(Box::new(captured.clone()));
+    let chunk = compile(source, optimize, &mut vm);
+    vm.run(chunk).expect("execution failed");
+    captured.contents()
+}
+
+/// A fresh scratch directory for one test
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "skyhetu_{}_{}_{}",
+        env!("CARGO_CRATE_NAME"),
+        test,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,44 @@
+//! Integration tests for `?:` and the values `and` / `or` produce
+
+mod common;
+
+use common::{eval, string};
+use skyhetu::Value;
+
+#[test]
+fn test_logicals_return_an_operand() {
+    assert_eq!(eval("nil or 5"), Value::Number(5.0));
+    assert_eq!(eval("0 and 3"), Value::Number(0.0));
+    assert_eq!(eval("1 and \"yes\""), string("yes"));
+    assert_eq!(eval("\"\" or false"), Value::Bool(false));
+    assert_eq!(eval("let maybe = nil\nmaybe or \"default\""), string("default"));
+
+    // The right side only runs when the left doesn't decide the result
+    assert_eq!(eval("state calls = 0\nfn bump() { calls -> calls + 1\n return calls }\nlet a = 0 and bump()\nlet b = 1 or bump()\ncalls"), Value::Number(0.0));
+}
+
+#[test]
+fn test_ternary_chooses_a_branch() {
+    let pick = |n: i32| eval(&format!("fn size(n) {{ return n > 5 ? \"big\" : \"small\" }}\nsize({})", n));
+    assert_eq!(pick(7), string("big"));
+    assert_eq!(pick(2), string("small"));
+
+    // Only the chosen branch is evaluated
+    assert_eq!(eval("state hits = 0\nfn hit() { hits -> hits + 1\n return hits }\nlet x = len(\"ab\") > 1 ? \"ok\" : hit()\nhits"), Value::Number(0.0));
+}
+
+#[test]
+fn test_nested_ternary_groups_to_the_right() {
+    let classify = "fn classify(n) {
+  return n < 0 ? \"negative\" : n == 0 ? \"zero\" : n < 10 ?
+    \"small\" :
+    \"large\"
+}
+";
+    assert_eq!(eval(&format!("{}classify(-4)", classify)), string("negative"));
+    assert_eq!(eval(&format!("{}classify(0)", classify)), string("zero"));
+    assert_eq!(eval(&format!("{}classify(3)", classify)), string("small"));
+    assert_eq!(eval(&format!("{}classify(30)", classify)), string("large"));
+    assert_eq!(eval("let t = true\n(t ? false : true) ? 1 : 2"), Value::Number(2.0));
+    assert_eq!(eval("nil or 0 ? \"a\" : \"b\""), string("b"));
+}
@@ -0,0 +1,89 @@
+//! Integration tests for conditions whose value is known at compile time
+
+mod common;
+
+use common::{compile, compile_warnings, compiled_output};
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::VM;
+
+/// The warning messages for `source`, with their lines
+fn warnings(source: &str) -> Vec<(usize, String)> {
+    let mut compiler = Compiler::new();
+    compiler.set_optimize(false);
+    compile_warnings(&mut compiler, source).into_iter().map(|w| (w.span.unwrap().line, w.message)).collect()
+}
+
+/// Size of the bytecode for `source`, with or without optimization
+fn code_size(source: &str, optimize: bool) -> usize {
+    compile(source, optimize, &mut VM::new()).code().len()
+}
+
+const FLAGS: &str = "let debug = false
+const LEVEL = 2
+if debug { print(\"debugging\") } else { print(\"quiet\") }
+if LEVEL > 1 and !debug { print(\"verbose\") }
+while debug { print(\"never\") }";
+
+#[test]
+fn test_fixed_conditions_are_reported() {
+    let found = warnings(FLAGS);
+    assert_eq!(found.len(), 3, "{:?}", found);
+    assert_eq!(found[0], (3, "condition 'debug' is always false".to_string()));
+    assert_eq!(found[1].0, 4);
+    assert!(found[1].1.ends_with("is always true"), "{}", found[1].1);
+    assert_eq!(found[2].0, 5);
+}
+
+#[test]
+fn test_shadowed_and_dynamic_bindings_are_not_reported() {
+    let source = "let debug = false
+let computed = len(\"\") > 0
+fn check(debug) {
+  if debug { return 1 }
+  return 0
+}
+fn later() {
+  if computed { return 1 }
+  return 0
+}
+if computed { print(1) }
+if true { print(2) }
+while false { print(3) }";
+    let found = warnings(source);
+    assert!(found.is_empty(), "{:?}", found);
+
+    // A redefinition with a non-literal value, which a REPL entry may make,
+    // forgets the known value
+    let mut compiler = Compiler::new();
+    compiler.set_allow_redefine(true);
+    let found = compile_warnings(&mut compiler, "let debug = false\nlet debug = !len(\"\")\nif debug { print(1) }");
+    assert!(found.is_empty(), "{:?}", found);
+}
+
+#[test]
+fn test_optimize_drops_dead_code() {
+    let plain = code_size(FLAGS, false);
+    let optimized = code_size(FLAGS, true);
+    assert!(optimized < plain, "{} >= {}", optimized, plain);
+    assert_eq!(compiled_output(FLAGS, false), compiled_output(FLAGS, true));
+    assert_eq!(compiled_output(FLAGS, true), "quiet\nverbose\n");
+}
+
+#[test]
+fn test_always_true_loop_keeps_break_and_continue() {
+    let source = "let forever = true
+state i = 0
+state seen = 0
+while forever {
+  i -> i + 1
+  if i % 2 == 0 { continue }
+  if i > 7 { break }
+  seen -> seen + 1
+}
+print(i, seen)";
+    let plain = code_size(source, false);
+    let optimized = code_size(source, true);
+    assert!(optimized < plain, "{} >= {}", optimized, plain);
+    assert_eq!(compiled_output(source, false), "9 4\n");
+    assert_eq!(compiled_output(source, true), "9 4\n");
+}
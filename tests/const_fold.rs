@@ -0,0 +1,78 @@
+//! Integration tests for computing operators on literals at compile time
+
+mod common;
+
+use std::path::Path;
+use common::{compile, compiled_output};
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+#[test]
+fn test_literal_arithmetic_is_one_constant() {
+    let source = "let seconds_per_day = 60 * 60 * 24\nseconds_per_day";
+    let mut vm = VM::new();
+    let plain = compile(source, false, &mut vm);
+    let optimized = compile(source, true, &mut vm);
+    assert_eq!(optimized.constants(), [Value::Number(86400.0)]);
+    assert!(optimized.code().len() < plain.code().len(), "{} >= {}", optimized.code().len(), plain.code().len());
+    assert!(!optimized.disassemble("script", &vm.heap).contains("Multiply"));
+    assert_eq!(vm.run(optimized).unwrap(), Value::Number(86400.0));
+
+    // Strings, comparisons, logic and constants fold too
+    let source = "const BASE = 10
+let greeting = \"sky\" + \"hetu \" + 2
+let checks = [-BASE * 2, BASE >= 10 and !false, \"ab\" < \"b\", 1 == 2 or nil]";
+    let listing = compile(source, true, &mut vm).disassemble("script", &vm.heap);
+    for op in ["Add", "Negate", "Multiply", "GreaterEqual", "Not", "Less", "Equal", "JumpIfFalse", "JumpIfTrue"] {
+        assert!(!listing.contains(op), "{} in\n{}", op, listing);
+    }
+    assert_eq!(compiled_output(&format!("{}\nprint(greeting, checks)", source), true), "skyhetu 2 [-20, true, true, nil]\n");
+}
+
+#[test]
+fn test_failing_expressions_are_left_to_the_vm() {
+    let source = "let ok = 1\nlet ratio = 60 / (2 - 2)\nprint(ratio)";
+    let mut vm = VM::new();
+    let chunk = compile(source, true, &mut vm);
+    let err = vm.run(chunk).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{}", err);
+    assert_eq!(err.span.unwrap().line, 2);
+
+    let mut vm = VM::new();
+    let chunk = compile("let bad = \"a\" - 1", true, &mut vm);
+    let err = vm.run(chunk).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::TypeMismatch(..)), "{}", err);
+
+    // Variables and long repeats are computed at runtime
+    let mut vm = VM::new();
+    let listing = compile("fn f(x) { return x * 2 }\nlet wide = \"-\" * 5000", true, &mut vm).disassemble_all("script", &vm.heap);
+    assert_eq!(listing.matches("Multiply").count(), 2, "{}", listing);
+}
+
+#[test]
+fn test_dead_branches_are_not_compiled() {
+    let source = "if false {\n  print(\"never\")\n} else {\n  print(\"always\")\n}\nif 2 > 3 { print(\"no\") }";
+    let mut vm = VM::new();
+    let plain = compile(source, false, &mut vm);
+    let optimized = compile(source, true, &mut vm);
+    assert!(!optimized.constants().contains(&Value::String("never".into())));
+    assert!(!optimized.disassemble("script", &vm.heap).contains("Jump"));
+    assert!(optimized.code().len() < plain.code().len());
+    assert_eq!(compiled_output(source, true), "always\n");
+}
+
+#[test]
+fn test_examples_behave_the_same_optimized() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut compared = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.with_extension("expected").exists() || path.extension().is_none_or(|e| e != "skyh") {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(compiled_output(&source, true), compiled_output(&source, false), "{}", path.display());
+        compared += 1;
+    }
+    assert!(compared > 5);
+}
@@ -0,0 +1,33 @@
+//! Integration tests for the constant pool: repeats share an entry, and a full pool is an error
+
+mod common;
+
+use common::compile_with;
+use skyhetu::compiler::Compiler;
+use skyhetu::error::ErrorKind;
+use skyhetu::vm::VM;
+use skyhetu::{Heap, Value};
+
+#[test]
+fn test_repeated_literal_is_pooled_once() {
+    let mut vm = VM::new();
+    // Unoptimized, so the sum is not computed at compile time
+    let mut compiler = Compiler::new();
+    compiler.set_optimize(false);
+    let source = format!("let total = {}\ntotal", ["1"; 10].join(" + "));
+    let chunk = compile_with(&mut compiler, &source, &mut vm.heap).expect("compile failed");
+
+    let ones = chunk.constants().iter().filter(|c| **c == Value::Number(1.0)).count();
+    assert_eq!(ones, 1, "{:?}", chunk.constants());
+    assert_eq!(compiler.stats().constants_deduplicated, 9);
+    assert_eq!(vm.run(chunk).expect("execution failed"), Value::Number(10.0));
+}
+
+#[test]
+fn test_too_many_constants_is_an_error() {
+    let source: String = (0..70_000).map(|i| format!("let v{} = {}\n", i, i)).collect();
+    let err = compile_with(&mut Compiler::new(), &source, &mut Heap::new()).expect_err("pool should overflow");
+    assert!(matches!(&err.kind, ErrorKind::TooManyConstants(pool) if pool == "constants"), "{:?}", err.kind);
+    assert_eq!(err.span.map(|s| s.line), Some(65_537));
+    assert_eq!(err.kind.to_string(), "too many constants in one function (maximum is 65536)");
+}
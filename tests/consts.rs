@@ -0,0 +1,118 @@
+//! Integration tests for compile-time `const` declarations
+
+mod common;
+
+use common::compile_with;
+use skyhetu::bytecode::OpCode;
+use skyhetu::{run, Chunk, Compiler, ErrorKind, Heap, SkyHetuError, Value};
+
+fn compile(source: &str) -> Result<Chunk, SkyHetuError> {
+    compile_with(&mut Compiler::new(), source, &mut Heap::new())
+}
+
+/// Opcodes of the chunk in order
+fn opcodes(chunk: &Chunk, heap: &Heap) -> Vec<OpCode> {
+    let mut ops = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        ops.push(OpCode::from(chunk.code()[offset]));
+        offset = chunk.next_instruction(offset, heap);
+    }
+    ops
+}
+
+#[test]
+fn test_const_chain_folds_to_single_constant() {
+    let heap = Heap::new();
+    let chunk = compile("const A = 2\nconst B = A * 10 + 1\nB").unwrap();
+
+    // A, B and the use of B each load one pre-computed constant
+    assert_eq!(
+        opcodes(&chunk, &heap),
+        vec![
+            OpCode::Constant, OpCode::DefineConst,
+            OpCode::Constant, OpCode::DefineConst,
+            OpCode::Constant, OpCode::Return,
+        ]
+    );
+    assert_eq!(chunk.constants().last(), Some(&Value::Number(21.0)));
+    assert_eq!(run("const A = 2\nconst B = A * 10 + 1\nB").unwrap(), Value::Number(21.0));
+}
+
+#[test]
+fn test_const_string_concat() {
+    let source = "const NAME = \"sky\"\nconst GREETING = \"hello \" + NAME + \" \" + (1 + 2)\nGREETING";
+    assert_eq!(run(source).unwrap(), Value::String("hello sky 3".into()));
+}
+
+#[test]
+fn test_non_const_initializer_points_at_offending_expression() {
+    let err = compile("let y = 1\nconst X = 1 + len(\"ab\")").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::NotConstant), "{:?}", err.kind);
+    let span = err.span.expect("error has a span");
+    assert_eq!(span.line, 2);
+    assert!(span.column > 11, "span should point past '1 +': {:?}", span);
+
+    let err = compile("let y = 1\nconst X = y * 2").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::NotConstant), "{:?}", err.kind);
+    assert_eq!(err.span.map(|s| (s.line, s.column)), Some((2, 11)));
+}
+
+#[test]
+fn test_const_type_errors_are_compile_errors() {
+    let err = compile("const X = \"a\" - 1").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::TypeMismatch(..)), "{:?}", err.kind);
+    let err = compile("const X = 1 / 0").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{:?}", err.kind);
+}
+
+#[test]
+fn test_huge_const_string_is_a_compile_error() {
+    for source in ["const X = \"ab\" * 100000000000000000000", "const A = \"x\" * 700000\nconst B = A + A"] {
+        let err = compile(source).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::ConstantTooLong(_)), "{:?}", err.kind);
+        assert_eq!(err.span.map(|s| s.line), Some(source.lines().count()));
+    }
+    assert_eq!(run("const LINE = \"-\" * 2000\nlen(LINE)").unwrap(), Value::Number(2000.0));
+}
+
+#[test]
+fn test_const_cannot_be_changed() {
+    let err = compile("const LIMIT = 10\nLIMIT -> 11").unwrap_err();
+    assert!(matches!(&err.kind, ErrorKind::ConstantReassignment(name) if name == "LIMIT"), "{:?}", err.kind);
+    assert_eq!(err.to_string().lines().next(), Some("[line 2:1] Error: cannot change constant 'LIMIT'"));
+}
+
+#[test]
+fn test_const_only_at_top_level() {
+    let err = compile("fn f() {\n  const X = 1\n}").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::ConstOutsideTopLevel), "{:?}", err.kind);
+    assert_eq!(err.span.unwrap().line, 2);
+}
+
+#[test]
+fn test_local_may_shadow_const() {
+    let source = "const A = 1\nfn f(A) { return A * 2 }\nfn g() {\n  let A = 5\n  return A\n}\nf(10) + g() + A";
+    assert_eq!(run(source).unwrap(), Value::Number(26.0));
+}
+
+#[test]
+fn test_function_declared_before_const_reads_global() {
+    let source = "fn limit() { return LIMIT }\nconst LIMIT = 3\nlimit()";
+    assert_eq!(run(source).unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn test_imported_const_is_inlined() {
+    let dir = std::env::temp_dir().join(format!("skyhetu_consts_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("limits.skyh"), "export const BASE = 40\nexport const MAX = BASE + 2\n").unwrap();
+
+    let mut heap = Heap::new();
+    let mut compiler = Compiler::with_base_path(dir.clone());
+    let chunk = compile_with(&mut compiler, "import { MAX } from \"limits\"\nMAX", &mut heap).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!opcodes(&chunk, &heap).contains(&OpCode::GetGlobal));
+    assert_eq!(chunk.constants().last(), Some(&Value::Number(42.0)));
+}
@@ -0,0 +1,127 @@
+//! Integration tests for the conversion natives
+
+mod common;
+
+use common::{eval, eval_err, string};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+#[test]
+fn test_bool_follows_truthiness() {
+    for (source, expected) in [
+        ("bool(nil)", false),
+        ("bool(0)", false),
+        ("bool(\"\")", false),
+        ("bool(false)", false),
+        ("bool(-1)", true),
+        ("bool(\"0\")", true),
+        ("bool([])", true),
+        ("bool(map())", true),
+    ] {
+        assert_eq!(eval(source), Value::Bool(expected), "{}", source);
+    }
+}
+
+#[test]
+fn test_int_truncates_toward_zero() {
+    assert_eq!(eval("int(2.7)"), Value::Number(2.0));
+    assert_eq!(eval("int(-2.7)"), Value::Number(-2.0));
+    assert_eq!(eval("floor(-2.7)"), Value::Number(-3.0));
+    assert_eq!(eval("int(-0.5) == 0"), Value::Bool(true));
+
+    let err = eval_err("int(num(\"inf\"))");
+    assert!(err.contains("int() requires a finite number, got inf"), "{}", err);
+    let err = eval_err("int(num(\"NaN\"))");
+    assert!(err.contains("int() requires a finite number, got NaN"), "{}", err);
+    let err = eval_err("int(\"3\")");
+    assert!(err.contains("int() requires a number, got string"), "{}", err);
+}
+
+#[test]
+fn test_ord_and_chr_round_trip() {
+    assert_eq!(eval("ord(\"A\")"), Value::Number(65.0));
+    assert_eq!(eval("ord(\"été\")"), Value::Number(233.0));
+    assert_eq!(eval("chr(97)"), Value::String("a".into()));
+    assert_eq!(eval("chr(128512)"), Value::String("\u{1F600}".into()));
+    assert_eq!(eval("ord(chr(128512))"), Value::Number(128512.0));
+    assert_eq!(eval("chr(ord(\"a\") + 2)"), Value::String("c".into()));
+}
+
+#[test]
+fn test_ord_and_chr_reject_invalid_input() {
+    let err = eval_err("ord(\"\")");
+    assert!(err.contains("ord() requires a non-empty string"), "{}", err);
+    for bad in ["55296", "57343", "1114112", "-1", "65.5"] {
+        let err = eval_err(&format!("chr({})", bad));
+        assert!(err.contains(&format!("chr() requires a Unicode scalar value, got {}", bad)), "{}", err);
+    }
+    assert_eq!(eval("chr(1114111) == chr(1114111)"), Value::Bool(true));
+}
+
+#[test]
+fn test_numbers_format_exactly() {
+    for (source, expected) in [
+        ("str(3)", "3"),
+        ("str(1 + 2)", "3"),
+        ("str(-42)", "-42"),
+        ("str(2.5)", "2.5"),
+        ("str(0.1 + 0.2)", "0.30000000000000004"),
+        ("str(1 / 3)", "0.3333333333333333"),
+        ("str(-0.0)", "0"),
+        ("str(123456789012345680000)", "123456789012345680000"),
+        ("str(1000000000000000000000)", "1e21"),
+                ("str(0.0000001)", "0.0000001"),
+        ("str(0.000000025)", "2.5e-8"),
+        ("str(-25 / 1000000000)", "-2.5e-8"),
+        ("str(num(\"NaN\"))", "nan"),
+        ("str(num(\"inf\"))", "inf"),
+        ("str(-num(\"inf\"))", "-inf"),
+        ("\"n=\" + 10000000000000000000000000", "n=1e25"),
+        ("0.5 + \"!\"", "0.5!"),
+        ("join([1, 2.5, 100000000000000000000000], \" \")", "1 2.5 1e23"),
+    ] {
+        assert_eq!(eval(source), string(expected), "{}", source);
+    }
+}
+
+#[test]
+fn test_print_uses_the_same_format() {
+    let mut vm = VM::new();
+    let output = skyhetu::vm::CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    vm.eval("print(10 / 4, 7, [10000000000000000000000, num(\"NaN\")])").unwrap();
+    assert_eq!(output.contents(), "2.5 7 [1e22, nan]\n");
+}
+
+#[test]
+fn test_to_fixed() {
+    assert_eq!(eval("to_fixed(3.14159, 2)"), string("3.14"));
+    assert_eq!(eval("to_fixed(2, 1)"), string("2.0"));
+    assert_eq!(eval("to_fixed(2.5, 0)"), string("2"));
+    assert_eq!(eval("to_fixed(-0.125, 2)"), string("-0.12"));
+    assert_eq!(eval("to_fixed(1000000000000000000000, 1)"), string("1000000000000000000000.0"));
+    assert_eq!(eval("to_fixed(num(\"NaN\"), 2)"), string("nan"));
+    let err = eval_err("to_fixed(1, 1.5)");
+    assert!(err.contains("to_fixed() requires a whole number of digits from 0 to 100"), "{}", err);
+    let err = eval_err("to_fixed(\"1\", 2)");
+    assert!(err.contains("to_fixed() requires a number, got string"), "{}", err);
+}
+
+#[test]
+fn test_parse_int_and_parse_float() {
+    assert_eq!(eval("parse_int(\"42\")"), Value::Number(42.0));
+    assert_eq!(eval("parse_int(\" -7 \")"), Value::Number(-7.0));
+    assert_eq!(eval("parse_int(\"+0012\")"), Value::Number(12.0));
+    for source in ["parse_int(\"4.2\")", "parse_int(\"\")", "parse_int(\"-\")", "parse_int(\"1e3\")", "parse_int(\"12abc\")"] {
+        assert_eq!(eval(source), Value::Nil, "{}", source);
+    }
+
+    assert_eq!(eval("parse_float(\"2.5e3\")"), Value::Number(2500.0));
+    assert_eq!(eval("parse_float(\" .5 \")"), Value::Number(0.5));
+    assert_eq!(eval("parse_float(\"-3\")"), Value::Number(-3.0));
+    for source in ["parse_float(\"inf\")", "parse_float(\"NaN\")", "parse_float(\"1e400\")", "parse_float(\"abc\")", "parse_float(\"\")"] {
+        assert_eq!(eval(source), Value::Nil, "{}", source);
+    }
+    let err = eval_err("parse_int(5)");
+    assert!(err.contains("parse_int() requires a string, got number"), "{}", err);
+}
@@ -0,0 +1,98 @@
+//! Integration tests for removing unreachable bytecode after compilation
+
+mod common;
+
+use common::compile_with;
+use skyhetu::compiler::Compiler;
+use skyhetu::gc::Heap;
+use skyhetu::value::Value;
+use skyhetu::vm::VM;
+
+/// Compile `source` and return the listing of its function named `name`,
+/// that function's length in bytes, and the bytes removed overall
+fn compile_function(source: &str, name: &str) -> (String, usize, usize) {
+    let mut heap = Heap::new();
+    let mut compiler = Compiler::new();
+    let chunk = compile_with(&mut compiler, source, &mut heap).expect("compile failed");
+    let listing = chunk.disassemble_all("script", &heap);
+    let section = listing
+        .split("\n\n")
+        .find(|s| s.starts_with(&format!("== fn {} ==", name)))
+        .unwrap_or_else(|| panic!("no function '{}' in:\n{}", name, listing))
+        .to_string();
+    let bytes = compiler.stats().functions.iter().find(|f| f.name == name).unwrap().bytes;
+    (section, bytes, compiler.stats().dead_code_removed)
+}
+
+const SIGN: &str = "fn sign(n) {
+  if n < 0 {
+    return -1
+  } else {
+    if n == 0 { return 0 }
+    return 1
+  }
+}";
+
+#[test]
+fn test_code_after_every_return_is_removed() {
+    let (listing, bytes, removed) = compile_function(SIGN, "sign");
+    assert!(removed > 0);
+    // One Return per path through the function, and no implicit `return nil`
+    assert_eq!(listing.matches("Return").count(), 3, "{}", listing);
+    assert!(!listing.contains("Nil"), "{}", listing);
+
+    // Statements after the if/else compile to nothing
+    let padded = SIGN.replace("  }\n}", "  }\n  print(\"never\")\n  return 99\n}");
+    let (_, padded_bytes, padded_removed) = compile_function(&padded, "sign");
+    assert_eq!(padded_bytes, bytes);
+    assert!(padded_removed > removed);
+}
+
+#[test]
+fn test_semantics_are_unchanged() {
+    let source = format!("{}
+fn first_even(items) {{
+  for x in items {{
+    if x % 2 == 0 {{
+      return x
+      print(\"never\")
+    }}
+  }}
+  return nil
+}}
+fn count_down(n) {{
+  state i = n
+  while true {{
+    if i == 0 {{ break }}
+    i -> i - 1
+  }}
+  return i
+}}
+[sign(-4), sign(0), sign(9), first_even([1, 3, 6, 8]), first_even([1]), count_down(3)]", SIGN);
+    let mut vm = VM::new();
+    let result = vm.eval(&source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[-1, 0, 1, 6, nil, 0]");
+}
+
+#[test]
+fn test_guards_follow_removed_code() {
+    let source = "fn check(n) {
+  while n > 0 {
+    return 0
+    print(\"never\")
+  }
+  if n < 0 {
+    return n / nil
+  }
+  return 1
+}
+check(-1)";
+    let mut vm = VM::new();
+    let mut compiler = Compiler::new();
+    compiler.set_debug_info(true);
+    let chunk = compile_with(&mut compiler, source, &mut vm.heap).unwrap();
+    let err = vm.run(chunk).unwrap_err();
+    assert_eq!(err.span.unwrap().line, 7);
+    assert_eq!(err.conditions(), ["if n < 0"]);
+    assert_eq!(vm.eval("check(2) + check(0)").unwrap(), Value::Number(1.0));
+}
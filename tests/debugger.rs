@@ -0,0 +1,136 @@
+//! Integration tests for stepping through programs with `VM::run_with_hook`
+
+mod common;
+
+use common::{compile, compile_with};
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::{DebugAction, DebugEvent, Value};
+
+const PROGRAM: &str = "fn square(n) {
+  let r = n * n
+  return r
+}
+let a = square(2)
+let b = square(3)
+a + b";
+
+/// Run `source` answering every stop with `action`, returning the result and
+/// the line and call depth of each stop
+fn stops(vm: &mut VM, source: &str, action: DebugAction) -> (Value, Vec<(usize, usize)>) {
+    let chunk = compile(source, true, vm);
+    let mut seen = Vec::new();
+    let result = vm
+        .run_with_hook(chunk, &mut |_vm, event: DebugEvent| {
+            seen.push((event.line, event.depth));
+            action
+        })
+        .expect("execution failed");
+    (result, seen)
+}
+
+#[test]
+fn test_step_over_visits_each_top_level_line() {
+    let (result, seen) = stops(&mut VM::new(), PROGRAM, DebugAction::StepOver);
+    assert_eq!(result, Value::Number(13.0));
+    assert_eq!(seen, [(1, 1), (5, 1), (6, 1), (7, 1)]);
+}
+
+#[test]
+fn test_pause_stops_at_every_instruction() {
+    let mut vm = VM::new();
+    let chunk = compile(PROGRAM, true, &mut vm);
+    let mut lines = Vec::new();
+    let mut instructions = 0;
+    vm.run_with_hook(chunk, &mut |_vm, event| {
+        instructions += 1;
+        if lines.last() != Some(&(event.line, event.depth)) {
+            lines.push((event.line, event.depth));
+        }
+        DebugAction::Pause
+    })
+    .unwrap();
+    assert!(instructions > lines.len());
+    assert_eq!(lines, [(1, 1), (5, 1), (2, 2), (3, 2), (5, 1), (6, 1), (2, 2), (3, 2), (6, 1), (7, 1)]);
+}
+
+#[test]
+fn test_continue_stops_only_at_breakpoints() {
+    let mut vm = VM::new();
+    vm.breakpoints().add(3);
+    let (result, seen) = stops(&mut vm, PROGRAM, DebugAction::Continue);
+    assert_eq!(result, Value::Number(13.0));
+    assert_eq!(seen, [(1, 1), (3, 2), (3, 2)]);
+}
+
+#[test]
+fn test_hook_can_inspect_the_vm_and_add_breakpoints() {
+    let mut vm = VM::new();
+    let breakpoints = vm.breakpoints();
+    let chunk = compile("state total = 0\nfor i in range(3) {\n  total -> total + i\n}\ntotal", true, &mut vm);
+    let mut totals = Vec::new();
+    let result = vm.run_with_hook(chunk, &mut |vm, event| {
+        if event.line == 1 {
+            breakpoints.add(3);
+        } else {
+            assert!(event.at_breakpoint);
+            totals.push(vm.get_global("total").unwrap());
+        }
+        DebugAction::Continue
+    });
+    assert_eq!(result.unwrap(), Value::Number(3.0));
+    assert_eq!(totals, [Value::Number(0.0), Value::Number(0.0), Value::Number(1.0)]);
+    assert_eq!(breakpoints.lines(), [3]);
+}
+
+#[test]
+fn test_errors_are_located_as_in_run() {
+    let mut vm = VM::new();
+    let chunk = compile("let a = 1\nlet b = a + nil", true, &mut vm);
+    let err = vm.run_with_hook(chunk, &mut |_vm, _event| DebugAction::StepOver).unwrap_err();
+    assert_eq!(err.span.unwrap().line, 2);
+}
+
+#[test]
+fn test_hook_can_read_locals_by_name() {
+    let source = "let r = \"global\"
+fn square(n) {
+  let r = n * n
+  if r > 1 {
+    let r = -1
+    print(r)
+  }
+  return r
+}
+square(3)";
+    let mut vm = VM::new();
+    vm.set_output(Box::new(CaptureBuffer::new()));
+    let mut compiler = Compiler::new();
+    compiler.set_debug_info(true);
+    let chunk = compile_with(&mut compiler, source, &mut vm.heap).unwrap();
+    let mut seen = Vec::new();
+    vm.breakpoints().add(6);
+    vm.breakpoints().add(8);
+    vm.run_with_hook(chunk, &mut |vm, event| {
+        if event.at_breakpoint {
+            seen.push((event.line, vm.get_local("n"), vm.get_local("r")));
+        } else {
+            // Top-level code has no locals; `r` is a global there
+            assert_eq!(vm.get_local("r"), None);
+        }
+        DebugAction::Continue
+    })
+    .unwrap();
+    let number = |n: f64| Some(Value::Number(n));
+    assert_eq!(seen, [(6, number(3.0), number(-1.0)), (8, number(3.0), number(9.0))]);
+
+    // Without debug info the names aren't known
+    let mut vm = VM::new();
+    vm.breakpoints().add(3);
+    let chunk = compile(PROGRAM, true, &mut vm);
+    vm.run_with_hook(chunk, &mut |vm, event| {
+        assert!(!event.at_breakpoint || vm.get_local("n").is_none());
+        DebugAction::Continue
+    })
+    .unwrap();
+}
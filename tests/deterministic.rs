@@ -0,0 +1,77 @@
+//! Integration tests for deterministic mode (seeded rand, fake clock)
+
+mod common;
+
+use common::compile;
+use skyhetu::vm::{CaptureBuffer, VM};
+
+const PROGRAM: &str = "state total = 0
+state i = 0
+let start = clock_ms()
+while i < 5 {
+  total -> total + floor(rand() * 100)
+  sleep_ms(250)
+  print(i, rand(), clock_ms() - start)
+  i -> i + 1
+}
+print(why(total))
+print(clock_ms())";
+
+/// Run `PROGRAM` on `vm` and return everything it printed
+fn captured_output(mut vm: VM) -> Vec<u8> {
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    let chunk = compile(PROGRAM, true, &mut vm);
+    vm.run(chunk).expect("execution failed");
+    output.bytes()
+}
+
+#[test]
+fn test_same_seed_gives_identical_output() {
+    let first = captured_output(VM::deterministic(7));
+    let second = captured_output(VM::deterministic(7));
+    assert_eq!(first, second);
+    assert!(!first.is_empty());
+}
+
+#[test]
+fn test_different_seeds_differ() {
+    assert_ne!(captured_output(VM::deterministic(1)), captured_output(VM::deterministic(2)));
+}
+
+#[test]
+fn test_fake_clock_advances_by_ticks_and_sleeps() {
+    let text = String::from_utf8(captured_output(VM::deterministic(0))).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    // Each iteration sleeps 250ms and reads the clock once (1ms tick)
+    assert!(lines[0].ends_with(" 251"), "{:?}", lines[0]);
+    assert!(lines[4].ends_with(" 1255"), "{:?}", lines[4]);
+    // start = 1, five reads in the loop, then this one
+    assert_eq!(lines.last(), Some(&"1257"));
+}
+
+#[test]
+fn test_rand_stays_in_unit_interval() {
+    let mut vm = VM::deterministic(42);
+    let source = "state n = 0\nstate ok = true\nwhile n < 1000 {\n  let r = rand()\n  if r < 0 or r >= 1 { ok -> false }\n  n -> n + 1\n}\nok";
+    let chunk = compile(source, true, &mut vm);
+    assert_eq!(vm.run(chunk).unwrap(), skyhetu::Value::Bool(true));
+}
+
+#[test]
+fn test_global_kinds_are_sorted() {
+    let names: Vec<String> = VM::new().global_kinds().into_iter().map(|(name, _)| name).collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+}
+
+#[test]
+fn test_sleep_longer_than_a_duration_is_an_error() {
+    // A literal too large for a number reads as infinity
+    let source = format!("sleep_ms({})", "9".repeat(400));
+    for mut vm in [VM::new(), VM::deterministic(0)] {
+        let err = vm.eval(&source).unwrap_err();
+        assert!(err.to_string().contains("sleep_ms() cannot sleep for inf ms"), "{}", err);
+    }
+}
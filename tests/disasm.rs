@@ -0,0 +1,57 @@
+//! Integration tests for disassembly of compiled programs
+
+mod common;
+
+use common::compile_with;
+use skyhetu::compiler::Compiler;
+use skyhetu::gc::Heap;
+
+fn disassemble(source: &str) -> String {
+    let mut heap = Heap::new();
+    let chunk = compile_with(&mut Compiler::new(), source, &mut heap).expect("compile failed");
+    chunk.disassemble_all("script", &heap)
+}
+
+/// The instruction listed at `offset` in the section headed `section`
+fn instruction_at<'a>(listing: &'a str, section: &str, offset: usize) -> &'a str {
+    let header = format!("== {} ==", section);
+    listing
+        .split("\n\n")
+        .find(|s| s.starts_with(&header))
+        .unwrap_or_else(|| panic!("no section '{}' in:\n{}", section, listing))
+        .lines()
+        .find(|line| line.starts_with(&format!("{:04} ", offset)))
+        .unwrap_or_else(|| panic!("no instruction at {} in:\n{}", offset, listing))
+}
+
+#[test]
+fn test_script_instructions_at_offsets() {
+    let listing = disassemble("let x = 1\nprint(x, 2)");
+    assert!(instruction_at(&listing, "script", 0).ends_with("CONSTANT 0000 '1'"), "{}", listing);
+    assert!(instruction_at(&listing, "script", 3).contains("DefineGlobal 0000 'x'"), "{}", listing);
+    assert!(instruction_at(&listing, "script", 6).contains("GetGlobal 0000 'x'"), "{}", listing);
+    assert!(instruction_at(&listing, "script", 12).contains("Print (2)"), "{}", listing);
+    assert!(instruction_at(&listing, "script", 15).ends_with("Return"), "{}", listing);
+}
+
+#[test]
+fn test_function_chunks_are_listed_under_their_names() {
+    let listing = disassemble("fn outer() {\n  fn inner(a) { return a }\n  return inner(1)\n}\nouter()");
+    assert!(instruction_at(&listing, "script", 0).contains("CLOSURE 0000 <fn outer>"), "{}", listing);
+    assert!(instruction_at(&listing, "fn outer", 0).contains("CLOSURE 0000 <fn inner>"), "{}", listing);
+    assert!(instruction_at(&listing, "fn outer", 3).contains("GetLocal 0001"), "{}", listing);
+    assert!(instruction_at(&listing, "fn outer", 9).contains("Call (1)"), "{}", listing);
+    assert!(instruction_at(&listing, "fn inner", 0).contains("GetLocal 0001"), "{}", listing);
+}
+
+#[test]
+fn test_closure_upvalue_pairs_keep_offsets_in_step() {
+    let source = "fn counter() {\n  state n = 0\n  let step = 1\n  fn next() {\n    n -> n + step\n    return n\n  }\n  return next\n}";
+    let listing = disassemble(source);
+    // The closure's two (is_local, index) pairs take four bytes after its operand
+    assert!(instruction_at(&listing, "fn counter", 9).contains("CLOSURE 0002 <fn next>"), "{}", listing);
+    assert!(instruction_at(&listing, "fn counter", 12).contains("|   local 1"), "{}", listing);
+    assert!(instruction_at(&listing, "fn counter", 14).contains("|   local 2"), "{}", listing);
+    assert!(instruction_at(&listing, "fn counter", 16).contains("GetLocal 0003"), "{}", listing);
+    assert!(instruction_at(&listing, "fn counter", 19).ends_with("Return"), "{}", listing);
+}
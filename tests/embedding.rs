@@ -0,0 +1,135 @@
+//! Integration tests for running several scripts against one embedded VM
+
+mod common;
+
+use common::compile_warnings;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use skyhetu::{Engine, NativeFn, Value, VM};
+
+#[test]
+fn test_run_in_shares_globals_heap_and_history() {
+    let mut vm = VM::new();
+    let table: Vec<Value> = (0..10_000).map(|i| Value::Number(i as f64)).collect();
+    let handle = vm.heap.alloc_array(table);
+    vm.set_global("table", Value::Array(handle));
+
+    let setup = "state hits = 0\nfn lookup(i) {\n  hits -> hits + 1\n  return table[i]\n}\nlookup(42)";
+    assert_eq!(skyhetu::run_in(&mut vm, setup).unwrap(), Value::Number(42.0));
+
+    // The second snippet reuses the compiled function, the state and the preloaded data
+    let result = skyhetu::run_in(&mut vm, "lookup(9999) + hits + len(table)").unwrap();
+    assert_eq!(result, Value::Number(9999.0 + 2.0 + 10_000.0));
+    assert_eq!(vm.causality.history("hits").len(), 3);
+}
+
+#[test]
+fn test_run_in_errors_leave_vm_usable() {
+    let mut vm = VM::new();
+    skyhetu::run_in(&mut vm, "let base = 10").unwrap();
+    let err = skyhetu::run_in(&mut vm, "base + nil").unwrap_err();
+    assert!(err.to_string().contains("numbers or strings"), "{}", err);
+    assert_eq!(skyhetu::run_in(&mut vm, "base * 2").unwrap(), Value::Number(20.0));
+}
+
+#[test]
+fn test_run_in_resolves_imports_against_base_path() {
+    let dir: PathBuf = std::env::temp_dir().join(format!("skyhetu_embed_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("math.skyh"), "export fn square(n) { return n * n }\n").unwrap();
+
+    let mut vm = VM::new();
+    vm.set_base_path(&dir);
+    let result = skyhetu::run_in(&mut vm, "import { square } from \"math\"\nsquare(7)").unwrap();
+    assert_eq!(result, Value::Number(49.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_engine_evals_share_state() {
+    let mut engine = Engine::new();
+    engine.eval("state visits = 0\nfn visit() {\n  visits -> visits + 1\n  return visits\n}").unwrap();
+    assert_eq!(engine.eval("visit()").unwrap(), Value::Number(1.0));
+    assert_eq!(engine.eval("visit() + visit()").unwrap(), Value::Number(5.0));
+    assert_eq!(engine.get_global("visits"), Some(Value::Number(3.0)));
+    let why = engine.why("visits");
+    assert!(why.contains("initialized to 0") && why.contains("2 -> 3"), "{}", why);
+
+    // Constants defined by one eval are inlined into later ones
+    engine.eval("const LIMIT = 10").unwrap();
+    engine.compile("LIMIT -> 1").unwrap_err();
+    assert_eq!(engine.eval("LIMIT * 2").unwrap(), Value::Number(20.0));
+}
+
+#[test]
+fn test_engine_host_globals_and_natives() {
+    let mut engine = Engine::new();
+    engine.set_global("rate", Value::Number(2.0), false);
+    engine.set_global("balance", Value::Number(100.0), true);
+    engine.register_native(NativeFn::new("fee", Some(1), |_vm, args| match &args[0] {
+        Value::Number(n) => Ok(Value::Number(n * 0.5)),
+        other => Err(format!("fee() requires a number, got {}", other.type_name())),
+    }));
+
+    assert_eq!(engine.eval("balance -> balance - fee(rate * 10)\nbalance").unwrap(), Value::Number(90.0));
+    let why = engine.why("balance");
+    assert!(why.contains("initialized to 100 (set by host)") && why.contains("100 -> 90"), "{}", why);
+    let err = engine.eval("fee(\"x\")").unwrap_err();
+    assert!(err.to_string().contains("fee() requires a number, got string"), "{}", err);
+    assert!(engine.eval("rate -> 3").is_err());
+}
+
+#[test]
+fn test_engine_reports_warnings_and_source_excerpts() {
+    let mut engine = Engine::new();
+    engine.eval("let debug = false\nif debug { print(1) }").unwrap();
+    assert_eq!(engine.warnings().len(), 1, "{:?}", engine.warnings());
+    let err = engine.eval("let a = 1\na + nil").unwrap_err();
+    assert!(err.to_string().contains("a + nil"), "{}", err);
+    assert!(engine.warnings().is_empty());
+}
+
+#[test]
+fn test_engine_optimization_can_be_turned_off() {
+    let mut engine = Engine::new();
+    let folded = engine.compile("60 * 60 * 24").unwrap();
+    engine.set_optimize(false);
+    let plain = engine.compile("60 * 60 * 24").unwrap();
+    assert_eq!(folded.constants(), [Value::Number(86400.0)]);
+    assert_eq!(plain.constants(), [Value::Number(60.0), Value::Number(24.0)]);
+    assert_eq!(engine.run(plain).unwrap(), Value::Number(86400.0));
+}
+
+#[test]
+fn test_natives_can_capture_host_state() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = VM::new();
+    let sink = Rc::clone(&log);
+    vm.define_native("emit", None, move |vm, args| {
+        let line: Vec<String> = args.iter().map(|arg| arg.display(&vm.heap)).collect();
+        sink.borrow_mut().push(line.join(" "));
+        Ok(Value::Number(sink.borrow().len() as f64))
+    });
+
+    let result = skyhetu::run_in(&mut vm, "for i in range(3) { emit(\"tick\", i) }\nemit([1, 2])").unwrap();
+    assert_eq!(result, Value::Number(4.0));
+    assert_eq!(*log.borrow(), ["tick 0", "tick 1", "tick 2", "[1, 2]"]);
+
+    // The VM holds the closure, and with it a share of the log, until it is dropped
+    assert_eq!(Rc::strong_count(&log), 2);
+    drop(vm);
+    assert_eq!(Rc::strong_count(&log), 1);
+}
+
+#[test]
+fn test_program_compiler_knows_the_vm_globals() {
+    let mut vm = VM::new();
+    vm.set_global("rate", Value::Number(2.0));
+    // Declaring them again warns, as it does in a file run with `skyhetu run`
+    let warnings = compile_warnings(&mut vm.program_compiler(PathBuf::from(".")), "fn len(s) { return 0 }");
+    assert!(warnings[0].to_string().contains("'len' redefined from native fn"), "{:?}", warnings);
+    let warnings = compile_warnings(&mut vm.program_compiler(PathBuf::from(".")), "state rate = 1");
+    assert!(warnings[0].to_string().contains("'rate' redefined from let to state"), "{:?}", warnings);
+}
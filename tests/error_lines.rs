@@ -0,0 +1,192 @@
+//! Integration tests for the source lines attached to runtime errors
+
+mod common;
+
+use common::compile_with;
+use skyhetu::{Compiler, ErrorKind, SkyHetuError, VM};
+
+fn run_err(source: &str) -> SkyHetuError {
+    skyhetu::run(source).unwrap_err()
+}
+
+/// Run `source` compiled with debug info and return its error
+fn run_err_with_debug_info(source: &str) -> SkyHetuError {
+    let mut vm = VM::new();
+    let mut compiler = Compiler::new();
+    compiler.set_debug_info(true);
+    let chunk = compile_with(&mut compiler, source, &mut vm.heap).unwrap();
+    vm.run(chunk).unwrap_err()
+}
+
+const NESTED: &str = "class User {
+  init(active) { this.active = active }
+}
+fn settle(user, balance) {
+  state i = 0
+  while i < 3 {
+    i -> i + 1
+    if user.active {
+      if balance < 0 {
+        return balance / 0
+      } else {
+        return 1
+      }
+    }
+  }
+}
+settle(User(true), -5)";
+
+#[test]
+fn test_error_on_line_seven() {
+    let source = "state i = 0
+state total = 0
+let divisor = 0
+while i < 3 {
+  i -> i + 1
+  total -> total + i
+  total -> total / divisor
+}";
+    let err = run_err(source);
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{}", err);
+    assert_eq!(err.span.map(|s| s.line), Some(7));
+
+    let err = err.with_source(source);
+    let text = err.to_string();
+    assert!(text.starts_with("[line 7] Error: division by zero"), "{}", text);
+    assert!(text.contains("  |   total -> total / divisor"), "{}", text);
+}
+
+/// Line the error raised by `source` points at
+fn error_line(source: &str) -> Option<usize> {
+    run_err(source).span.map(|s| s.line)
+}
+
+#[test]
+fn test_each_error_kind_gets_a_line() {
+    assert!(matches!(run_err("print(missing)").kind, ErrorKind::UndefinedVariable(_)));
+    assert_eq!(error_line("let a = 1\nprint(missing)"), Some(2));
+    assert_eq!(error_line("let a = 1\n\nlet b = a + nil"), Some(3));
+    assert!(matches!(run_err("fn f(x) { return x }\nf(1, 2)").kind, ErrorKind::WrongArity(Some(ref name), 1, 2) if name == "f"));
+    assert_eq!(error_line("fn f(x) { return x }\nf(1, 2)"), Some(2));
+    assert_eq!(error_line("let n = 3\nn()"), Some(2));
+    assert_eq!(error_line("class C {}\nlet c = C()\nc.nothing"), Some(3));
+    assert_eq!(error_line("let fixed = 1\nfn f() {\n  return 1\n}\nfixed2 -> 3"), Some(5));
+}
+
+#[test]
+fn test_error_inside_function_reports_the_function_line() {
+    let source = "fn half(n) {
+  return n / 0
+}
+half(4)";
+    let err = run_err(source);
+    assert_eq!(err.span.map(|s| s.line), Some(2), "{}", err);
+}
+
+#[test]
+fn test_stack_trace_lists_calls_innermost_first() {
+    let source = "fn inner(n) {
+  return n / 0
+}
+fn middle(n) {
+  return inner(n) + 1
+}
+fn outer() {
+  return middle(2) + 1
+}
+outer()";
+    let err = run_err(source);
+    assert_eq!(err.stack_trace(), [
+        "in <fn inner> (line 2)",
+        "in <fn middle> (line 5)",
+        "in <fn outer> (line 8)",
+        "in <script> (line 10)",
+    ]);
+    let text = err.with_source(source).to_string();
+    assert_eq!(text, "[line 2] Error: division by zero
+  |   return n / 0
+  in <fn inner> (line 2)
+  in <fn middle> (line 5)
+  in <fn outer> (line 8)
+  in <script> (line 10)");
+}
+
+#[test]
+fn test_stack_trace_collapses_repeated_calls() {
+    let source = "fn down(n) {
+  return down(n + 1) + 1
+}
+fn start() {
+  return down(0) + 1
+}
+start()";
+    let err = run_err(source);
+    assert_eq!(err.stack_trace().len(), 64);
+    assert_eq!(err.to_string(), "[line 2] Error: stack overflow
+  in <fn down> (line 2) (repeated 62 times)
+  in <fn start> (line 5)
+  in <script> (line 7)");
+}
+
+#[test]
+fn test_top_level_errors_have_no_stack_trace() {
+    let err = run_err("let x = 1\nx / 0");
+    assert!(err.stack_trace().is_empty());
+    assert_eq!(err.to_string(), "[line 2] Error: division by zero");
+}
+
+#[test]
+fn test_debug_info_reports_guarding_conditions() {
+    let err = run_err_with_debug_info(NESTED);
+    assert_eq!(err.conditions(), ["while i < 3", "if user.active", "if balance < 0"]);
+    let text = err.to_string();
+    assert!(
+        text.contains("[line 10] Error: division by zero\n  guarded by: while i < 3 → if user.active → if balance < 0\n  in <fn settle>"),
+        "{}",
+        text
+    );
+
+    // Else branches report the negated condition, and top-level code has none
+    let err = run_err_with_debug_info("let n = 0\nif n > 0 { 1 } else { n / 0 }");
+    assert_eq!(err.conditions(), ["if !(n > 0)"]);
+    assert!(run_err_with_debug_info("1 / 0").conditions().is_empty());
+}
+
+#[test]
+fn test_conditions_are_capped_and_need_debug_info() {
+    let err = run_err_with_debug_info("let a = 1\nif a {\n if a {\n  if a {\n   if a {\n    if a == 1 { a / 0 }\n   }\n  }\n }\n}");
+    assert_eq!(err.conditions(), ["if a", "if a", "if a", "if a == 1"]);
+    assert!(run_err(NESTED).conditions().is_empty());
+    assert!(!run_err(NESTED).to_string().contains("guarded by"));
+}
+
+#[test]
+fn test_wrong_arity_names_the_function() {
+    let err = run_err("let a = 1\nmin(a)");
+    assert!(matches!(err.kind, ErrorKind::WrongArity(Some(ref name), 2, 1) if name == "min"), "{:?}", err.kind);
+    assert!(err.to_string().contains("function 'min' expected 2 arguments, got 1"), "{}", err);
+    assert_eq!(err.span.map(|s| s.line), Some(2));
+
+    // The line is the call site's, not the definition's
+    let source = "fn add(a, b) {
+  return a + b
+}
+let x = add(1, 2)
+let y = add(1, 2, 3)";
+    let err = run_err(source);
+    assert!(err.to_string().contains("function 'add' expected 2 arguments, got 3"), "{}", err);
+    assert_eq!(err.span.map(|s| s.line), Some(5));
+
+    let err = run_err("class Point {}\nPoint(1)");
+    assert!(err.to_string().contains("function 'Point' expected 0 arguments, got 1"), "{}", err);
+    let err = run_err("let f = fn(a) => a\nf()");
+    assert!(matches!(err.kind, ErrorKind::WrongArity(None, 1, 0)), "{:?}", err.kind);
+}
+
+#[test]
+fn test_calling_a_non_function_names_its_type() {
+    let err = run_err("let n = 3\n\nn(1)");
+    assert!(matches!(err.kind, ErrorKind::NotCallable(ref t) if t == "number"), "{:?}", err.kind);
+    assert!(err.to_string().contains("cannot call value of type 'number'"), "{}", err);
+    assert_eq!(err.span.map(|s| s.line), Some(3));
+}
@@ -0,0 +1,156 @@
+//! Specification tests for evaluation order.
+//!
+//! Every call evaluates its callee first, then its arguments left to right,
+//! whatever kind of value is being called. For method calls the receiver is
+//! the callee's first part, so it is evaluated before the arguments. `and`
+//! and `or` skip their right operand when the left one decides the result.
+//!
+//! Each test calls `note(tag)`, which appends `tag` to the `trace` state
+//! variable and returns it; the causality history of `trace` then shows the
+//! order in which the expressions ran.
+
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+const PRELUDE: &str = "state trace = \"\"
+fn note(tag) {
+  trace -> trace + tag
+  return tag
+}
+";
+
+/// Run `source` after the prelude; returns the result and the tag recorded by each transition of `trace`
+fn run_traced(source: &str) -> (Value, Vec<String>) {
+    let source = format!("{}{}", PRELUDE, source);
+    let mut vm = VM::new();
+    let result = vm.eval(&source).expect("execution failed");
+
+    let history = vm.causality.history("trace");
+    let transitions: Vec<_> = history.iter().skip(1).collect();
+    assert!(
+        transitions.windows(2).all(|w| w[0].timestamp < w[1].timestamp),
+        "timestamps must increase along the history"
+    );
+    let tags = transitions
+        .iter()
+        .map(|event| {
+            let (Value::String(old), Value::String(new)) = (&event.old_value, &event.new_value) else {
+                panic!("trace holds strings");
+            };
+            new[old.len()..].to_string()
+        })
+        .collect();
+    (result, tags)
+}
+
+fn tags(expected: &[&str]) -> Vec<String> {
+    expected.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+fn test_closure_arguments_left_to_right() {
+    let (result, order) = run_traced("fn join3(a, b, c) { return a + b + c }\njoin3(note(\"a\"), note(\"b\"), note(\"c\"))");
+    assert_eq!(result, Value::String("abc".into()));
+    assert_eq!(order, tags(&["a", "b", "c"]));
+}
+
+#[test]
+fn test_native_arguments_left_to_right() {
+    let (result, order) = run_traced("slice(note(\"hello\"), len(note(\"x\")), len(note(\"yyy\")))");
+    assert_eq!(result, Value::String("el".into()));
+    assert_eq!(order, tags(&["hello", "x", "yyy"]));
+}
+
+#[test]
+fn test_callee_before_arguments() {
+    let source = "fn pick(tag) {\n  note(tag)\n  fn add(a, b) { return a + b }\n  return add\n}\npick(\"f\")(note(\"1\"), note(\"2\"))";
+    let (result, order) = run_traced(source);
+    assert_eq!(result, Value::String("12".into()));
+    assert_eq!(order, tags(&["f", "1", "2"]));
+}
+
+#[test]
+fn test_constructor_arguments_left_to_right() {
+    let source = "class Pair {\n  init(a, b) {\n    this.both = a + b\n  }\n}\nPair(note(\"l\"), note(\"r\")).both";
+    let (result, order) = run_traced(source);
+    assert_eq!(result, Value::String("lr".into()));
+    assert_eq!(order, tags(&["l", "r"]));
+}
+
+#[test]
+fn test_receiver_before_method_arguments() {
+    let source = "class Joiner {
+  init(sep) {
+    this.sep = sep
+  }
+  join(a, b) {
+    return a + this.sep + b
+  }
+}
+let joiners = [Joiner(\"-\")]
+fn receiver() {
+  note(\"recv\")
+  return joiners[0]
+}
+receiver().join(note(\"a\"), note(\"b\"))";
+    let (result, order) = run_traced(source);
+    assert_eq!(result, Value::String("a-b".into()));
+    assert_eq!(order, tags(&["recv", "a", "b"]));
+}
+
+#[test]
+fn test_bound_method_arguments_left_to_right() {
+    let source = "class Joiner {
+  join(a, b) {
+    return a + b
+  }
+}
+let bound = Joiner().join
+bound(note(\"x\"), note(\"y\"))";
+    let (result, order) = run_traced(source);
+    assert_eq!(result, Value::String("xy".into()));
+    assert_eq!(order, tags(&["x", "y"]));
+}
+
+#[test]
+fn test_spread_arguments_left_to_right() {
+    let source = "fn join3(a, b, c) { return a + b + c }\njoin3(note(\"a\"), ...[note(\"b\"), note(\"c\")])";
+    let (result, order) = run_traced(source);
+    assert_eq!(result, Value::String("abc".into()));
+    assert_eq!(order, tags(&["a", "b", "c"]));
+}
+
+#[test]
+fn test_short_circuit_skips_right_operand() {
+    let (result, order) = run_traced("false and note(\"and\")");
+    assert_eq!(result, Value::Bool(false));
+    assert!(order.is_empty());
+
+    let (result, order) = run_traced("note(\"l\") or note(\"or\")");
+    assert_eq!(result, Value::String("l".into()));
+    assert_eq!(order, tags(&["l"]));
+
+    let (_, order) = run_traced("note(\"l\") and note(\"r\")");
+    assert_eq!(order, tags(&["l", "r"]));
+}
+
+#[test]
+fn test_binary_operands_left_to_right() {
+    let (result, order) = run_traced("note(\"a\") + note(\"b\") + note(\"c\")");
+    assert_eq!(result, Value::String("abc".into()));
+    assert_eq!(order, tags(&["a", "b", "c"]));
+}
+
+#[test]
+fn test_argument_count_limit() {
+    let params: Vec<String> = (0..255).map(|i| format!("p{}", i)).collect();
+    let args = |n: usize| (0..n).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+    let wide = format!("fn wide({}) {{ return p0 + p254 }}\n", params.join(", "));
+    let (result, _) = run_traced(&format!("{}wide({})", wide, args(255)));
+    assert_eq!(result, Value::Number(254.0));
+
+    let source = format!("{}{}wide({})", PRELUDE, wide, args(256));
+    let err = skyhetu::run(&source).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::TooManyOperands(_, 256, 255)), "{:?}", err.kind);
+    assert!(err.to_string().contains("too many arguments: 256 (maximum is 255)"), "{}", err);
+}
@@ -0,0 +1,51 @@
+//! Runs every example that has a `.expected` file next to it and compares its
+//! output, so the examples keep working as documentation.
+//!
+//! Examples run in deterministic mode, once normally and once with the
+//! garbage collector running before every instruction.
+
+use std::path::{Path, PathBuf};
+use skyhetu::vm::{CaptureBuffer, VM};
+
+/// Examples with recorded output, sorted by name
+fn examples_with_expectations() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut found: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("examples directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "skyh") && path.with_extension("expected").exists())
+        .collect();
+    found.sort();
+    found
+}
+
+fn run_example(path: &Path, gc_stress: bool) -> String {
+    let mut vm = VM::deterministic(0);
+    vm.set_gc_stress(gc_stress);
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    if let Err(e) = vm.load_file(path) {
+        panic!("{} failed: {}\noutput so far:\n{}", path.display(), e, output.contents());
+    }
+    output.contents()
+}
+
+fn check_examples(gc_stress: bool) {
+    let examples = examples_with_expectations();
+    assert!(!examples.is_empty(), "no examples with .expected files");
+    for path in examples {
+        let expected = std::fs::read_to_string(path.with_extension("expected")).unwrap();
+        let actual = run_example(&path, gc_stress);
+        assert_eq!(actual, expected, "output of {} changed", path.display());
+    }
+}
+
+#[test]
+fn test_examples_match_expected_output() {
+    check_examples(false);
+}
+
+#[test]
+fn test_examples_survive_gc_stress() {
+    check_examples(true);
+}
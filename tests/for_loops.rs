@@ -0,0 +1,83 @@
+//! Integration tests for `for` loops, including arrays changed while they are iterated
+
+use skyhetu::{run, Value};
+
+#[test]
+fn test_for_iterates_arrays_and_strings() {
+    let source = "state total = 0\nfor x in [1, 2, 3] {\n  total -> total + x\n}\nstate s = \"\"\nfor c in \"abc\" {\n  s -> c + s\n}\n[total, s]";
+    let mut vm = skyhetu::VM::new();
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[6, \"cba\"]");
+}
+
+#[test]
+fn test_appending_during_iteration_is_an_error() {
+    let err = run("let xs = [1, 2, 3]\nfor x in xs {\n  push(xs, x)\n}").unwrap_err().to_string();
+    assert!(err.contains("array modified during iteration"), "{}", err);
+}
+
+#[test]
+fn test_removing_during_iteration_is_an_error() {
+    let source = "state items = [1, 2, 3, 4]\nfor x in items {\n  if x > 2 {\n    pop(items)\n  }\n}";
+    let err = run(source).unwrap_err().to_string();
+    assert!(err.contains("array modified during iteration"), "{}", err);
+
+    // Resizing in the last iteration is caught too, and so is a push undone by a pop
+    assert!(run("let xs = [1]\nfor x in xs { push(xs, 2) }").is_err());
+    assert!(run("let xs = [1, 2]\nfor x in xs {\n  push(xs, 0)\n  pop(xs)\n}").is_err());
+}
+
+#[test]
+fn test_changing_elements_during_iteration_is_allowed() {
+    let source = "let xs = [1, 2, 3]\nstate i = 0\nfor x in xs {\n  xs[i] = x * 10\n  i -> i + 1\n}\npush(xs, 4)\nxs[2] + xs[3]";
+    assert_eq!(run(source).unwrap(), Value::Number(34.0));
+}
+
+#[test]
+fn test_strings_iterate_characters() {
+    let source = "state count = 0\nstate last = \"\"\nfor c in \"naïve ☃\" {\n  count -> count + 1\n  last -> c\n}\n[count, len(\"naïve ☃\"), last]";
+    let mut vm = skyhetu::VM::new();
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[7, 7, \"☃\"]");
+    assert_eq!(run("slice(\"naïve\", 2, 4)").unwrap(), Value::String("ïv".into()));
+}
+
+#[test]
+fn test_numbers_iterate_without_building_an_array() {
+    let source = "let total = [0]\nfor i in 1000000 {\n  total[0] = total[0] + i\n}\n[total[0], gc_stats()[\"bytes\"]]";
+    let mut vm = skyhetu::VM::new();
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    let Value::Array(handle) = result else { panic!("expected an array") };
+    let pair = vm.heap.get_array(handle).unwrap();
+    assert_eq!(pair[0], Value::Number(499999500000.0));
+    let Value::Number(bytes) = pair[1] else { panic!("expected a number") };
+    assert!(bytes < 100_000.0, "{} bytes live after the loop", bytes);
+
+    // Like range(): fractions round toward zero and negative counts run nothing
+    assert_eq!(run("state n = 0\nfor i in 2.9 { n -> n + i + 1 }\nfor i in -3 { n -> 100 }\nn").unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn test_maps_iterate_sorted_keys() {
+    let source = "let m = map()\nset(m, \"b\", 2)\nset(m, \"a\", 1)\nset(m, \"c\", 3)\nstate order = \"\"\nfor k in m {\n  order -> order + k + str(m[k])\n  delete(m, k)\n  set(m, k + k, 0)\n}\n[order, len(m)]";
+    let mut vm = skyhetu::VM::new();
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[\"a1b2c3\", 3]");
+}
+
+#[test]
+fn test_nested_loops_over_mixed_iterables() {
+    let source = "state pairs = \"\"\nfor i in 3 {\n  for c in \"ab\" {\n    for x in [i] {\n      pairs -> pairs + c + str(x)\n    }\n  }\n}\npairs";
+    assert_eq!(run(source).unwrap(), Value::String("a0b0a1b1a2b2".into()));
+
+    // The loop doesn't depend on a global called `len`
+    assert_eq!(run("fn len(x) { return 0 }\nstate n = 0\nfor x in [1, 2] { n -> n + x }\nn").unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn test_iterating_other_values_is_an_error() {
+    for source in ["for x in true {}", "for x in nil {}", "for x in fn() => 1 {}"] {
+        let err = run(source).unwrap_err().to_string();
+        assert!(err.contains("expected array, string, map or number to iterate"), "{}: {}", source, err);
+    }
+}
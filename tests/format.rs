@@ -0,0 +1,75 @@
+//! Tests for the source formatter: golden outputs for the fixtures under
+//! `tests/fmt`, and formatting as a fixed point for them and every example
+
+use std::path::{Path, PathBuf};
+use skyhetu::format::{format_program, format_with_comments};
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::{Lexer, Parser, Program};
+
+fn parse(source: &str) -> Program {
+    Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap()
+}
+
+fn format(source: &str) -> String {
+    format_with_comments(&parse(source), source)
+}
+
+/// `.skyh` files in `dir` under the crate root, sorted by name
+fn sources(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut found: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "skyh"))
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn test_fixtures_match_golden_output() {
+    let fixtures = sources("tests/fmt");
+    assert!(!fixtures.is_empty());
+    for path in fixtures {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let expected = std::fs::read_to_string(path.with_extension("expected")).unwrap();
+        assert_eq!(format(&source), expected, "formatting {} changed", path.display());
+        assert_eq!(format(&expected), expected, "{} is not a fixed point", path.display());
+    }
+}
+
+#[test]
+fn test_formatting_is_a_fixed_point() {
+    for path in sources("tests/fmt").into_iter().chain(sources("examples")) {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let once = format_program(&parse(&source));
+        let twice = format_program(&parse(&once));
+        assert_eq!(once, twice, "{}", path.display());
+        assert_eq!(format(&format(&source)), format(&source), "{}", path.display());
+    }
+}
+
+#[test]
+fn test_formatted_examples_behave_the_same() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    for path in sources("examples") {
+        let Ok(expected) = std::fs::read_to_string(path.with_extension("expected")) else {
+            continue;
+        };
+        let mut vm = VM::deterministic(0);
+        vm.set_base_path(&dir);
+        let output = CaptureBuffer::new();
+        vm.set_output(Box::new(output.clone()));
+        vm.eval(&format(&std::fs::read_to_string(&path).unwrap())).unwrap();
+        assert_eq!(output.contents(), expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn test_comments_need_the_source() {
+    let source = "// greeting\nprint(\"hi\") // says hi\n";
+    assert_eq!(format_program(&parse(source)), "print(\"hi\")\n");
+    assert_eq!(format(source), source);
+    // A `//` inside a string is not a comment
+    assert_eq!(format("let u=\"a//b\"//c"), "let u = \"a//b\" //c\n");
+}
@@ -0,0 +1,54 @@
+//! Integration tests for the instruction budget
+
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+#[test]
+fn test_endless_loop_runs_out_of_fuel() {
+    let mut vm = VM::new();
+    vm.set_fuel(Some(10_000));
+    let err = vm.eval("state n = 0\nwhile true {\n  n -> n + 1\n}").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::FuelExhausted), "{}", err);
+    assert!(err.to_string().contains("out of fuel: the instruction budget ran out"), "{}", err);
+    assert_eq!(vm.remaining_fuel(), Some(0));
+
+    // Callbacks run by natives draw on the same budget
+    vm.set_fuel(Some(10_000));
+    let err = vm.eval("fn spin(x) {\n  while true {}\n}\nmap([1], spin)").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::FuelExhausted), "{}", err);
+}
+
+#[test]
+fn test_finite_program_leaves_fuel() {
+    let mut vm = VM::new();
+    assert_eq!(vm.remaining_fuel(), None);
+    vm.set_fuel(Some(1_000_000));
+    let source = "state total = 0
+for i in range(100) { total -> total + i }
+total";
+    assert_eq!(vm.eval(source).unwrap(), Value::Number(4950.0));
+    let left = vm.remaining_fuel().unwrap();
+    assert!(left < 1_000_000 && left > 990_000, "{}", left);
+
+    // The budget carries over to later runs until it is lifted
+    vm.eval("1 + 1").unwrap();
+    assert!(vm.remaining_fuel().unwrap() < left);
+    vm.set_fuel(None);
+    assert_eq!(vm.eval("1 + 1").unwrap(), Value::Number(2.0));
+    assert_eq!(vm.remaining_fuel(), None);
+}
+
+#[test]
+fn test_exact_budget_is_enough() {
+    let mut vm = VM::new();
+    vm.set_fuel(Some(u64::MAX));
+    vm.eval("let x = [1, 2]\nx[0] + x[1]").unwrap();
+    let used = u64::MAX - vm.remaining_fuel().unwrap();
+
+    vm.set_fuel(Some(used));
+    assert_eq!(vm.eval("let x = [1, 2]\nx[0] + x[1]").unwrap(), Value::Number(3.0));
+    assert_eq!(vm.remaining_fuel(), Some(0));
+    vm.set_fuel(Some(used - 1));
+    let err = vm.eval("let x = [1, 2]\nx[0] + x[1]").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::FuelExhausted), "{}", err);
+}
@@ -0,0 +1,48 @@
+//! Integration tests for `disasm()`, `arity()` and `fn_name()`
+
+mod common;
+
+use common::{eval, text};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+#[test]
+fn test_disasm_lists_a_functions_bytecode() {
+    let listing = text(eval("fn greet(name) { return \"hi \" + name + 42 }\ndisasm(greet)"));
+    assert!(listing.starts_with("== fn greet ==\n"), "{}", listing);
+    assert!(listing.contains("Return"), "{}", listing);
+    assert!(listing.contains("'hi '") && listing.contains("'42'"), "{}", listing);
+
+    // Closures and bound methods list the function behind them
+    let listing = text(eval("fn make(n) { return fn(m) { return n * m } }\ndisasm(make(3))"));
+    assert!(listing.starts_with("== fn <lambda> ==\n") && listing.contains("GetUpvalue"), "{}", listing);
+    let listing = text(eval("class Box { init(v) { this.v = v } get() { return this.v } }\ndisasm(Box(1).get)"));
+    assert!(listing.starts_with("== fn get ==\n") && listing.contains("GetProperty"), "{}", listing);
+}
+
+#[test]
+fn test_arity_and_fn_name() {
+    assert_eq!(eval("fn add(a, b) { return a + b }\narity(add)"), Value::Number(2.0));
+    assert_eq!(eval("arity(len)"), Value::Number(1.0));
+    assert_eq!(eval("arity(slice)"), Value::Nil);
+    assert_eq!(eval("arity(fn() { 1 })"), Value::Number(0.0));
+    assert_eq!(eval("class C { init() {} scale(by, at) { return by } }\narity(C().scale)"), Value::Number(2.0));
+
+    assert_eq!(text(eval("fn add(a, b) { return a + b }\nfn_name(add)")), "add");
+    assert_eq!(text(eval("fn_name(len)")), "len");
+    assert_eq!(text(eval("fn_name(fn(x) { x })")), "<lambda>");
+}
+
+#[test]
+fn test_non_functions_are_errors() {
+    let mut vm = VM::new();
+    for (call, message) in [
+        ("disasm(3)", "disasm() requires a function, got number"),
+        ("arity(\"add\")", "arity() requires a function, got string"),
+        ("fn_name(nil)", "fn_name() requires a function, got nil"),
+        ("disasm(len)", "disasm() requires a function defined in SkyHetu, got native function"),
+    ] {
+        let err = vm.eval(call).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", call, err);
+    }
+}
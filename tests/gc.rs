@@ -0,0 +1,234 @@
+//! Garbage collection across REPL-style sessions: functions compiled by one
+//! `eval` must survive collections triggered while later lines run.
+
+use skyhetu::gc::{AllocCategory, Handle};
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::Value;
+
+#[test]
+fn test_nested_functions_survive_collection_between_lines() {
+    let mut vm = VM::new();
+    vm.eval("fn outer() {\n  fn inner(n) { return n * 2 }\n  return inner(21)\n}").unwrap();
+    vm.eval("class Box {\n  init(v) {\n    this.v = v\n  }\n  get() { return this.v }\n}").unwrap();
+    vm.collect_garbage();
+    assert_eq!(vm.eval("outer()").unwrap(), Value::Number(42.0));
+    assert_eq!(vm.eval("Box(7).get()").unwrap(), Value::Number(7.0));
+}
+
+#[test]
+fn test_session_definitions_survive_allocation_heavy_lines() {
+    let mut vm = VM::new();
+    vm.eval("fn make_adder(n) { return fn(x) => x + n }").unwrap();
+    vm.eval("let add5 = make_adder(5)").unwrap();
+    vm.eval("let LABELS = [\"a\", \"b\"]").unwrap();
+    for round in 0..5 {
+        // Enough garbage to trigger several collections mid-line
+        vm.eval("let junk = map(range(2000), fn(i) => [i, str(i)])").unwrap();
+        vm.collect_garbage();
+        let source = format!("add5({}) + len(LABELS)", round);
+        assert_eq!(vm.eval(&source).unwrap(), Value::Number(round as f64 + 7.0));
+    }
+    // Functions defined late in the session can still call early ones
+    vm.eval("fn twice(f, x) { return f(f(x)) }").unwrap();
+    assert_eq!(vm.eval("twice(add5, 0)").unwrap(), Value::Number(10.0));
+}
+
+#[test]
+fn test_callbacks_survive_collection_inside_natives() {
+    let mut vm = VM::new();
+    let source = "fn wrap(i) {
+  fn pair() { return [i, i * i] }
+  return pair()
+}
+let rows = map(range(3000), wrap)
+rows[2999][1] - rows[0][1]";
+    assert_eq!(vm.eval(source).unwrap(), Value::Number(2999.0 * 2999.0));
+    vm.collect_garbage();
+    assert_eq!(vm.eval("wrap(4)[1]").unwrap(), Value::Number(16.0));
+}
+
+#[test]
+fn test_id_is_stable_across_collection() {
+    let mut vm = VM::new();
+    vm.eval("class Point {}\nlet p = Point()\nlet first = id(p)").unwrap();
+    vm.eval("let junk = map(range(2000), fn(i) => [i])").unwrap();
+    vm.collect_garbage();
+    assert_eq!(vm.eval("id(p) == first").unwrap(), Value::Bool(true));
+    assert_eq!(vm.eval("id(p) == id(Point())").unwrap(), Value::Bool(false));
+}
+
+#[test]
+fn test_id_is_not_reused_with_the_slot() {
+    let mut vm = VM::new();
+    let Value::Array(handle) = vm.eval("[1, 2, 3]").unwrap() else {
+        panic!("expected an array");
+    };
+    let original = vm.heap.object_id(handle).unwrap();
+    vm.collect_garbage();
+    assert_eq!(vm.heap.object_id(handle), None);
+
+    // Allocate until the freed slot is handed out again
+    let reused = (0..100)
+        .map(|_| vm.heap.alloc_array(Vec::new()))
+        .find(|h| h.0 == handle.0)
+        .expect("freed slot was not reused");
+    assert_ne!(vm.heap.object_id(reused), Some(original));
+}
+
+/// Free `[1, 2, 3]` and allocate into its slot again, returning the stale
+/// handle and the new one
+fn reuse_slot(vm: &mut VM) -> (Handle, Handle) {
+    let Value::Array(stale) = vm.eval("[1, 2, 3]").unwrap() else {
+        panic!("expected an array");
+    };
+    vm.collect_garbage();
+    let fresh = (0..100)
+        .map(|_| vm.heap.alloc_string(format!("s{}", vm.heap.object_count())))
+        .find(|h| h.0 == stale.0)
+        .expect("freed slot was not reused");
+    (stale, fresh)
+}
+
+#[test]
+fn test_stale_handle_does_not_resolve_to_the_new_object() {
+    let mut vm = VM::new();
+    let (stale, fresh) = reuse_slot(&mut vm);
+    assert_ne!(stale, fresh);
+    assert!(!vm.heap.is_live(stale));
+    assert!(vm.heap.is_live(fresh));
+    assert_eq!(vm.heap.object_id(stale), None);
+    assert!(vm.heap.get_string(fresh).is_some());
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "stale handle"))]
+fn test_stale_handle_lookup_fails_loudly() {
+    let mut vm = VM::new();
+    let (stale, _) = reuse_slot(&mut vm);
+    // Release builds answer None, debug builds stop here
+    assert_eq!(vm.heap.get_string(stale), None);
+    assert_eq!(vm.heap.get_array(stale), None);
+}
+
+#[test]
+fn test_id_deduplicates_instances() {
+    let source = "class Tag {}
+let a = Tag()
+let b = Tag()
+let seen = map()
+for t in [a, b, a, a, b] {
+  set(seen, str(id(t)), t)
+}
+len(seen)";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(2.0));
+
+    let err = VM::new().eval("id(\"text\")").unwrap_err().to_string();
+    assert!(err.contains("id() requires an array, map, instance, function or class, got string"), "{}", err);
+}
+
+/// Live objects and bytes of one (type, category) group in the heap dump
+fn dump_group(vm: &VM, type_name: &str, category: AllocCategory) -> (usize, usize) {
+    vm.heap.dump().iter()
+        .find(|e| e.type_name == type_name && e.category == category)
+        .map_or((0, 0), |e| (e.count, e.bytes))
+}
+
+#[test]
+fn test_heap_dump_attributes_allocation_sites() {
+    let mut vm = VM::new();
+    vm.eval("let r = range(0, 50)
+let literal = [1, 2, 3]
+let mapped = map(literal, fn(x) => x * 2)
+fn counter() {
+  state n = 0
+  fn next() { n -> n + 1 }
+  return next
+}
+let tick = counter()
+state level = 0
+level -> 1
+let changes = history(\"level\")").unwrap();
+    vm.collect_garbage();
+
+    assert_eq!(dump_group(&vm, "array", AllocCategory::RangeResult).0, 1);
+    assert_eq!(dump_group(&vm, "array", AllocCategory::UserArray).0, 2);
+    assert_eq!(dump_group(&vm, "upvalue", AllocCategory::ClosureUpvalue).0, 1);
+    assert_eq!(dump_group(&vm, "array", AllocCategory::CausalitySnapshot).0, 1);
+
+    // Unreachable range results are freed and leave their bucket
+    let before = dump_group(&vm, "array", AllocCategory::RangeResult);
+    vm.eval("fn total() {\n  let scratch = range(0, 1000)\n  return len(scratch)\n}\ntotal()").unwrap();
+    vm.collect_garbage();
+    assert_eq!(dump_group(&vm, "array", AllocCategory::RangeResult), before);
+
+    let Value::String(table) = vm.eval("heap_dump()").unwrap() else {
+        panic!("heap_dump returns a string");
+    };
+    assert!(table.starts_with("type"), "{}", table);
+    assert!(table.contains("range result"), "{}", table);
+    assert!(table.lines().last().unwrap().starts_with("total"), "{}", table);
+}
+
+#[test]
+fn test_closures_survive_frequent_collection() {
+    let mut vm = VM::new();
+    vm.heap.set_gc_threshold(256);
+    let source = "fn make_counter(start) {
+  state count = start
+  fn increment() {
+    count -> count + 1
+    return count
+  }
+  return increment
+}
+state total = 0
+for i in range(3000) {
+  let counter = make_counter(i)
+  counter()
+  let seen = [counter(), str(i)]
+  total -> total + seen[0] - i
+}
+let keep = make_counter(10)
+for i in range(3000) { keep() }
+[total, keep(), str(keep)]";
+    let Value::Array(handle) = vm.eval(source).unwrap() else { panic!("expected an array") };
+    let results = vm.heap.get_array(handle).unwrap().clone();
+    assert_eq!(results[0], Value::Number(6000.0));
+    assert_eq!(results[1], Value::Number(3011.0));
+    let Value::String(shown) = &results[2] else { panic!("expected a string") };
+    assert!(!shown.contains("(collected)"), "{}", shown);
+    assert!(vm.gc_time() > std::time::Duration::ZERO);
+}
+
+#[test]
+fn test_collection_frees_unreachable_arrays() {
+    let mut vm = VM::new();
+    vm.eval("let kept = [1, 2, 3]\nfn churn() {\n  for i in range(500) { let tmp = [i, [i]] }\n}\nchurn()").unwrap();
+    let before = vm.heap.object_count();
+    let collections = vm.heap.collections();
+    vm.collect_garbage();
+    assert!(vm.heap.object_count() + 1000 <= before, "{} -> {}", before, vm.heap.object_count());
+    assert_eq!(vm.heap.collections(), collections + 1);
+    assert_eq!(vm.eval("kept[2]").unwrap(), Value::Number(3.0));
+
+    let Value::Map(stats) = vm.eval("gc_stats()").unwrap() else { panic!("expected a map") };
+    let stats = vm.heap.get_map(stats).unwrap();
+    assert_eq!(stats["collections"], Value::Number((collections + 1) as f64));
+    assert_eq!(stats["objects"], Value::Number(vm.heap.object_count() as f64 - 1.0));
+    assert!(matches!(stats["bytes"], Value::Number(n) if n > 0.0));
+}
+
+#[test]
+fn test_state_history_survives_collection() {
+    let mut vm = VM::new();
+    let buffer = CaptureBuffer::new();
+    vm.set_output(Box::new(buffer.clone()));
+    vm.eval("state a = [1, 2]\na -> [3]\nfn churn() {\n  for i in range(500) { let tmp = [i, [i]] }\n}\nchurn()").unwrap();
+    vm.collect_garbage();
+    vm.eval("churn()\nprint(why(a))\nprint(history(\"a\"))\nprint(at(\"a\", 1))").unwrap();
+
+    let shown = buffer.contents();
+    assert!(shown.contains("initialized to [1, 2]") && shown.contains("[1, 2] -> [3]"), "{}", shown);
+    assert!(shown.ends_with("\n[1, 2]\n"), "{}", shown);
+    assert!(!shown.contains("(collected)"), "{}", shown);
+}
@@ -0,0 +1,53 @@
+//! Integration tests for the heap size limit
+
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+const LIMIT: usize = 1024 * 1024;
+
+fn limited_vm() -> VM {
+    let mut vm = VM::new();
+    vm.set_max_heap(Some(LIMIT));
+    vm
+}
+
+#[test]
+fn test_unbounded_growth_is_out_of_memory() {
+    let mut vm = limited_vm();
+    let err = vm.eval("let items = []\nwhile true {\n  push(items, [len(items)])\n}").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::OutOfMemory(_, LIMIT)), "{}", err);
+    assert_eq!(err.span.unwrap().line, 3);
+    assert!(vm.heap.bytes_allocated() > LIMIT);
+}
+
+#[test]
+fn test_garbage_is_collected_before_failing() {
+    let mut vm = limited_vm();
+    let source = "state total = 0
+for i in range(200) {
+  let chunk = range(5000)
+  total -> total + len(chunk)
+}
+total";
+    assert_eq!(vm.eval(source).unwrap(), Value::Number(1_000_000.0));
+    assert!(vm.heap.bytes_allocated() <= LIMIT);
+}
+
+#[test]
+fn test_oversized_requests_are_refused_up_front() {
+    let err = limited_vm().eval("let big = range(100000000)").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::OutOfMemory(_, LIMIT)), "{}", err);
+    assert!(err.to_string().contains("would exceed the heap limit of 1048576 bytes"), "{}", err);
+
+    let err = limited_vm().eval("let s = \"ab\" * 10000000").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::OutOfMemory(20_000_000, LIMIT)), "{}", err);
+
+    // Joining a number onto a string is checked like joining two strings
+    let err = limited_vm().eval("let s = \"a\" * 1048576\ns + 1").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::OutOfMemory(1_048_577, LIMIT)), "{}", err);
+    let err = limited_vm().eval("let s = \"a\" * 1048576\n1 + s").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::OutOfMemory(1_048_577, LIMIT)), "{}", err);
+
+    // Without a limit the same program is fine
+    assert_eq!(VM::new().eval("len(\"ab\" * 1000000)").unwrap(), Value::Number(2_000_000.0));
+}
@@ -0,0 +1,50 @@
+//! Integration tests for `if` used as an expression
+
+mod common;
+
+use common::{eval, eval_output};
+use skyhetu::Value;
+
+#[test]
+fn test_if_expression_in_let_binding() {
+    let source = "fn grade(score) {
+  let letter = if score > 90 { \"A\" } else if score > 80 { \"B\" } else { \"C\" }
+  return letter
+}
+print(grade(95), grade(85), grade(10))
+let missing = if len(\"\") > 0 { 1 }
+missing";
+    let (result, printed) = eval_output(source);
+    assert_eq!(printed, "A B C\n");
+    assert_eq!(result, Value::Nil);
+}
+
+#[test]
+fn test_if_expression_as_call_argument() {
+    let source = "fn pair(a, b) { return str(a) + \"/\" + str(b) }
+fn describe(n) {
+  return pair(if n % 2 == 0 { \"even\" } else { \"odd\" }, if n > 0 {
+    n
+  }
+  else {
+    -n
+  })
+}
+print(describe(4), describe(-3))
+let empty = if len(\"x\") > 0 {} else { 1 }
+empty";
+    let (result, printed) = eval_output(source);
+    assert_eq!(printed, "even/4 odd/3\n");
+    assert_eq!(result, Value::Nil);
+}
+
+#[test]
+fn test_if_expression_transition_records_causes() {
+    let source = "state limit = 10
+state level = 0
+level -> if limit > 5 { level + 2 } else { level }
+why(level)";
+    let result = eval(source);
+    let Value::String(why) = result else { panic!("expected why() text") };
+    assert!(why.contains("0 -> 2") && why.contains("caused by limit"), "{}", why);
+}
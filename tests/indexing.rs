@@ -0,0 +1,89 @@
+//! Integration tests for negative indices and slices of arrays and strings
+
+mod common;
+
+use common::{eval, eval_err, string};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+/// Render an array result as text, since arrays compare by identity
+fn render(source: &str) -> String {
+    let mut vm = VM::new();
+    let value = vm.eval(source).expect("execution failed");
+    skyhetu::inspect::render(&vm.heap, &value)
+}
+
+#[test]
+fn test_negative_indices_count_from_the_end() {
+    assert_eq!(eval("let a = [10, 20, 30]\na[-1]"), Value::Number(30.0));
+    assert_eq!(eval("let a = [10, 20, 30]\na[-len(a)]"), Value::Number(10.0));
+    assert_eq!(eval("\"héllo\"[-1]"), string("o"));
+    assert_eq!(eval("\"héllo\"[-4]"), string("é"));
+
+    // Assignments count from the end too
+    assert_eq!(render("let a = [1, 2, 3]\na[-1] = 9\na[-3] = 7\na"), "[7, 2, 9]");
+}
+
+#[test]
+fn test_reads_out_of_range_are_nil() {
+    for source in ["[1, 2, 3][3]", "[1, 2, 3][-4]", "[][0]", "[][-1]", "\"abc\"[3]", "\"abc\"[-4]", "\"\"[0]"] {
+        assert_eq!(eval(source), Value::Nil, "{}", source);
+    }
+}
+
+#[test]
+fn test_writes_out_of_range_are_errors() {
+    let err = eval_err("let a = [1, 2, 3]\na[3] = 0");
+    assert!(err.contains("index 3 out of bounds for array of length 3"), "{}", err);
+    let err = eval_err("let a = [1, 2, 3]\na[-4] = 0");
+    assert!(err.contains("index -4 out of bounds for array of length 3"), "{}", err);
+}
+
+#[test]
+fn test_non_integer_indices_are_type_errors() {
+    for (source, got) in [
+        ("[1, 2][1.5]", "1.5"),
+        ("\"ab\"[0.5]", "0.5"),
+        ("[1, 2][\"0\"]", "string"),
+        ("\"ab\"[nil]", "nil"),
+        ("let a = [1]\na[-0.5] = 2", "-0.5"),
+    ] {
+        let err = eval_err(source);
+        assert!(err.contains(&format!("type mismatch: expected a whole number index, got {}", got)), "{}: {}", source, err);
+    }
+}
+
+#[test]
+fn test_slices_of_arrays() {
+    assert_eq!(render("slice([1, 2, 3, 4], 1)"), "[2, 3, 4]");
+    assert_eq!(render("slice([1, 2, 3, 4], 1, 3)"), "[2, 3]");
+    assert_eq!(render("slice([1, 2, 3, 4], -3, -1)"), "[2, 3]");
+    assert_eq!(render("slice([1, 2, 3, 4], -2)"), "[3, 4]");
+    assert_eq!(render("slice([1, 2, 3, 4], -10, 10)"), "[1, 2, 3, 4]");
+    assert_eq!(render("slice([1, 2, 3, 4], 3, 1)"), "[]");
+
+    // The slice is a copy
+    assert_eq!(render("let a = [1, 2, 3]\nlet b = slice(a, 0)\nb[0] = 9\na"), "[1, 2, 3]");
+}
+
+#[test]
+fn test_slices_of_strings() {
+    assert_eq!(eval("slice(\"héllo\", 1, 3)"), string("él"));
+    assert_eq!(eval("slice(\"héllo\", -3)"), string("llo"));
+    assert_eq!(eval("slice(\"héllo\", -4, -1)"), string("éll"));
+    assert_eq!(eval("slice(\"héllo\", 1, 99)"), string("éllo"));
+    assert_eq!(eval("slice(\"héllo\", 4, 2)"), string(""));
+}
+
+#[test]
+fn test_slice_errors() {
+    for (source, expected) in [
+        ("slice(5, 0)", "slice() requires an array or string, got number"),
+        ("slice([1], 0.5)", "slice() bounds must be whole numbers, got 0.5"),
+        ("slice(\"ab\", 0, \"1\")", "slice() bounds must be whole numbers, got string"),
+        ("slice([1])", "slice() takes 2 or 3 arguments"),
+    ] {
+        let err = eval_err(source);
+        assert!(err.contains(expected), "{}: {}", source, err);
+    }
+}
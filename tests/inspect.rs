@@ -0,0 +1,73 @@
+//! Snapshot tests for `inspect()`, one value of each kind
+
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+const PRELUDE: &str = "class Point {
+  init(x, y) {
+    this.y = y
+    this.x = x
+  }
+  norm() { return this.x * this.x + this.y * this.y }
+}
+fn adder(n) {
+  fn add(m) { return n + m }
+  return add
+}
+";
+
+fn inspect(expr: &str) -> String {
+    let mut vm = VM::new();
+    match vm.eval(&format!("{}inspect({})", PRELUDE, expr)).expect("execution failed") {
+        Value::String(s) => s.to_string(),
+        other => panic!("inspect returned {}", other),
+    }
+}
+
+#[test]
+fn test_inspect_numbers() {
+    assert_eq!(inspect("42"), "type: number\nvalue: 42\nkind: integral");
+    assert_eq!(inspect("1.5"), "type: number\nvalue: 1.5\nkind: fractional");
+    assert_eq!(inspect("num(\"NaN\")").lines().last(), Some("kind: NaN"));
+    assert_eq!(inspect("num(\"-inf\")"), "type: number\nvalue: -inf\nkind: infinite");
+}
+
+#[test]
+fn test_inspect_scalars() {
+    assert_eq!(inspect("\"héllo\""), "type: string\nvalue: \"héllo\"\nlength: 5");
+    assert_eq!(inspect("true"), "type: bool\nvalue: true");
+    assert_eq!(inspect("nil"), "type: nil\nvalue: nil");
+}
+
+#[test]
+fn test_inspect_containers() {
+    assert_eq!(
+        inspect("[1, \"a\", 2, [nil, [[3]]]]"),
+        "type: array\nvalue: [1, \"a\", 2, [nil, [...]]]\nlength: 4\nelements: array x1, number x2, string x1"
+    );
+    assert_eq!(inspect("range(12)").lines().nth(1), Some("value: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, ... 2 more]"));
+
+    let mut vm = VM::new();
+    let Value::String(text) = vm.eval("let m = map()\nm[\"b\"] = [1]\nm[\"a\"] = \"x\"\ninspect(m)").unwrap() else {
+        panic!("inspect returns a string");
+    };
+    assert_eq!(&*text, "type: map\nvalue: {\"a\": \"x\", \"b\": [1]}\nlength: 2");
+}
+
+#[test]
+fn test_inspect_instances_and_classes() {
+    assert_eq!(
+        inspect("Point(3, \"four\")"),
+        "type: instance\nvalue: Point {x: 3, y: \"four\"}\nclass: Point\nfields: 2\n  x = 3\n  y = \"four\""
+    );
+    assert_eq!(inspect("Point"), "type: class\nvalue: <class Point>\nname: Point\nmethods: init, norm");
+    assert_eq!(inspect("Point(1, 2).norm"), "type: method\nvalue: <method>\nname: norm\nreceiver: Point {x: 1, y: 2}");
+}
+
+#[test]
+fn test_inspect_functions() {
+    assert_eq!(inspect("adder(1)"), "type: closure\nvalue: <fn add>\nname: add\nparams: (m)\nupvalues: 1");
+    assert_eq!(inspect("adder"), "type: closure\nvalue: <fn adder>\nname: adder\nparams: (n)\nupvalues: 0");
+    assert_eq!(inspect("len"), "type: native function\nvalue: <native fn len>\nname: len\narity: 1");
+    assert_eq!(inspect("range").lines().last(), Some("arity: variadic"));
+}
@@ -0,0 +1,74 @@
+//! Integration tests for the file, input and argument natives
+
+mod common;
+
+use common::scratch_dir;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+fn io_vm() -> VM {
+    let mut vm = VM::new();
+    vm.enable_io(true);
+    vm
+}
+
+#[test]
+fn test_write_append_and_read_back() {
+    let dir = scratch_dir("roundtrip");
+    let path = dir.join("notes.txt");
+    let mut vm = io_vm();
+    vm.set_global("path", Value::String(path.to_string_lossy().into()));
+    let result = vm.eval("write_file(path, \"first\\n\")
+append_file(path, \"second\")
+read_file(path)").unwrap();
+    assert_eq!(result, Value::String("first\nsecond".into()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond");
+
+    // append_file creates a missing file
+    vm.set_global("other", Value::String(dir.join("new.txt").to_string_lossy().into()));
+    assert_eq!(vm.eval("append_file(other, \"x\")\nread_file(other)").unwrap(), Value::String("x".into()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_os_errors_are_runtime_errors() {
+    let dir = scratch_dir("errors");
+    let mut vm = io_vm();
+    vm.set_global("missing", Value::String(dir.join("missing.txt").to_string_lossy().into()));
+    let err = vm.eval("let text = read_file(missing)").unwrap_err().to_string();
+    assert!(err.contains("read_file() cannot read '") && err.contains("missing.txt'"), "{}", err);
+    assert!(err.contains("os error"), "{}", err);
+
+    vm.set_global("dir", Value::String(dir.to_string_lossy().into()));
+    let err = vm.eval("write_file(dir, \"x\")").unwrap_err().to_string();
+    assert!(err.contains("write_file() cannot write '"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_line_and_args() {
+    let mut vm = io_vm();
+    vm.set_input(Box::new(std::io::Cursor::new("one\r\ntwo\n\nlast")));
+    let result = vm.eval("[read_line(), read_line(), read_line(), read_line(), read_line()]").unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[\"one\", \"two\", \"\", \"last\", nil]");
+
+    assert_eq!(vm.eval("len(args())").unwrap(), Value::Number(0.0));
+    vm.set_args(vec!["in.txt".to_string(), "--fast".to_string()]);
+    assert_eq!(vm.eval("args()[1]").unwrap(), Value::String("--fast".into()));
+}
+
+#[test]
+fn test_io_is_disabled_by_default() {
+    let dir = scratch_dir("sandbox");
+    let path = dir.join("blocked.txt");
+    let mut vm = VM::new();
+    vm.set_global("path", Value::String(path.to_string_lossy().into()));
+    for call in ["read_file(path)", "write_file(path, \"x\")", "append_file(path, \"x\")", "read_line()"] {
+        let err = vm.eval(call).unwrap_err().to_string();
+        assert!(err.contains("file and input access is disabled"), "{}: {}", call, err);
+    }
+    assert!(!path.exists());
+    // args() only reports what the host passed in
+    assert_eq!(vm.eval("len(args())").unwrap(), Value::Number(0.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
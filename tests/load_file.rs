@@ -0,0 +1,70 @@
+//! Integration tests for loading files into a persistent VM
+
+mod common;
+
+use common::scratch_dir;
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::Value;
+
+#[test]
+fn test_loaded_definitions_stay_available() {
+    let dir = scratch_dir("defs");
+    let helpers = dir.join("helpers.skyh");
+    std::fs::write(&helpers, "fn double(n) { return n * 2 }\nstate loads = 1\n").unwrap();
+
+    let mut vm = VM::new();
+    vm.load_file(&helpers).unwrap();
+    assert_eq!(vm.eval("double(21)").unwrap(), Value::Number(42.0));
+    assert_eq!(vm.eval("loads").unwrap(), Value::Number(1.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_reloading_a_changed_file_redefines_globals() {
+    let dir = scratch_dir("reload");
+    let helpers = dir.join("helpers.skyh");
+    std::fs::write(&helpers, "fn greet() { return \"hi\" }\n").unwrap();
+
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    vm.set_repl_mode(true);
+    vm.load_file(&helpers).unwrap();
+    assert_eq!(vm.eval("greet()").unwrap(), Value::String("hi".into()));
+
+    std::fs::write(&helpers, "state greet = \"hello\"\n").unwrap();
+    vm.load_file(&helpers).unwrap();
+    assert_eq!(vm.eval("greet").unwrap(), Value::String("hello".into()));
+    assert!(output.contents().contains("'greet' redefined from fn to state"), "{}", output.contents());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_imports_resolve_relative_to_loaded_file() {
+    let dir = scratch_dir("imports");
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/math.skyh"), "export fn square(n) { return n * n }\n").unwrap();
+    std::fs::write(dir.join("lib/helpers.skyh"), "import { square } from \"math\"\nfn cube(n) { return square(n) * n }\n").unwrap();
+
+    let mut vm = VM::new();
+    vm.load_file(dir.join("lib/helpers.skyh")).unwrap();
+    assert_eq!(vm.eval("cube(3)").unwrap(), Value::Number(27.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_failed_load_reports_error_and_vm_stays_usable() {
+    let dir = scratch_dir("errors");
+    let broken = dir.join("broken.skyh");
+    std::fs::write(&broken, "fn ok() { return 1 }\nlet x = 1 + nil\n").unwrap();
+
+    let mut vm = VM::new();
+    let err = vm.load_file(&broken).unwrap_err();
+    assert!(err.to_string().contains("numbers or strings"), "{}", err);
+    // Definitions before the failure are kept, and later evaluation works
+    assert_eq!(vm.eval("ok() + 1").unwrap(), Value::Number(2.0));
+
+    let missing = vm.load_file(dir.join("missing.skyh")).unwrap_err();
+    assert!(missing.to_string().contains("cannot read file"), "{}", missing);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+//! Integration tests for leaving loops early with `break` and `continue`
+
+use skyhetu::value::NativeFn;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+/// A VM with `depth()`, which reports the stack depth of the running code
+fn vm_with_depth() -> VM {
+    let mut vm = VM::new();
+    vm.register_native(NativeFn::new("depth", Some(0), |vm, _| Ok(Value::Number(vm.stack_len() as f64))));
+    vm
+}
+
+#[test]
+fn test_break_and_continue_keep_the_stack_balanced() {
+    let mut vm = vm_with_depth();
+    let source = "let depths = []
+fn spin(n) {
+  state i = 0
+  while i < n {
+    let a = i
+    i -> i + 1
+    if a % 3 == 0 {
+      let skipped = a * 2
+      continue
+    }
+    while true {
+      let b = a + 1
+      if b > a { break }
+    }
+    for x in [1, 2, 3] {
+      let y = x
+      if y == 1 { continue }
+      if y == 2 { break }
+    }
+  }
+  push(depths, depth())
+  return i
+}
+for round in range(10000) {
+  spin(4)
+}
+depths";
+    let depths = vm.eval(source).unwrap();
+    let Value::Array(handle) = depths else { panic!("expected an array, got {}", depths) };
+    let depths = vm.heap.get_array(handle).unwrap();
+    assert_eq!(depths.len(), 10000);
+    assert!(depths.iter().all(|d| *d == depths[0]), "stack depth drifted: {} then {}", depths[0], depths[9999]);
+}
+
+#[test]
+fn test_locals_after_a_broken_loop_resolve() {
+    let source = "fn f() {
+  while true {
+    let a = 1
+    let b = 2
+    break
+  }
+  let c = 5
+  return c
+}
+f()";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(5.0));
+}
+
+#[test]
+fn test_continue_in_for_advances() {
+    let source = "state total = 0
+for x in range(10) {
+  if x % 2 == 0 { continue }
+  total -> total + x
+}
+total";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(25.0));
+}
+
+#[test]
+fn test_closures_capture_each_iteration_of_a_body_local() {
+    let source = "let found = []
+for i in range(4) {
+  let v = i * 10
+  push(found, fn() => v)
+  if i == 1 { continue }
+  if i == 2 { break }
+}
+found[0]() + found[1]() + found[2]()";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(30.0));
+}
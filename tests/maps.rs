@@ -0,0 +1,74 @@
+//! Integration tests for the map value type
+
+mod common;
+
+use common::{eval, eval_err};
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+#[test]
+fn test_insert_overwrite_and_lookup() {
+    assert_eq!(eval("let m = map()\nset(m, \"a\", 1)\nset(m, \"b\", 2)\nget(m, \"a\") + get(m, \"b\")"), Value::Number(3.0));
+    assert_eq!(eval("let m = map()\nset(m, \"a\", 1)\nset(m, \"a\", 5)\nget(m, \"a\") * 10 + len(m)"), Value::Number(51.0));
+    assert_eq!(eval("let m = map()\nm[\"x\"] = 4\nm[\"x\"] = m[\"x\"] + 1\nm[\"x\"]"), Value::Number(5.0));
+}
+
+#[test]
+fn test_missing_key_is_nil() {
+    assert_eq!(eval("get(map(), \"nope\")"), Value::Nil);
+    assert_eq!(eval("map()[\"nope\"]"), Value::Nil);
+    assert_eq!(eval("delete(map(), \"nope\")"), Value::Nil);
+}
+
+#[test]
+fn test_has_delete_and_keys() {
+    let source = "let m = map()
+set(m, \"b\", 2)
+set(m, \"a\", 1)
+set(m, \"c\", 3)
+let removed = delete(m, \"c\")
+let ks = keys(m)
+[has(m, \"a\"), has(m, \"c\"), removed, len(ks), ks[0], ks[1]]";
+    let mut vm = VM::new();
+    let Value::Array(handle) = vm.eval(source).unwrap() else {
+        panic!("expected an array");
+    };
+    let items = vm.heap.get_array(handle).unwrap().clone();
+    assert_eq!(items, vec![
+        Value::Bool(true),
+        Value::Bool(false),
+        Value::Number(3.0),
+        Value::Number(2.0),
+        Value::String("a".into()),
+        Value::String("b".into()),
+    ]);
+}
+
+#[test]
+fn test_type_errors() {
+    assert!(eval_err("get(range(2), \"a\")").contains("get() requires a map, got array"));
+    assert!(eval_err("set(map(), 1, 2)").contains("set() requires a string key, got number"));
+    assert!(eval_err("map()[1]").contains("map keys must be strings, got number"));
+    assert!(eval_err("let m = map()\nm[2] = 1").contains("map keys must be strings, got number"));
+}
+
+#[test]
+fn test_contents_survive_gc() {
+    let mut vm = VM::new();
+    vm.eval("let m = map()\nm[\"list\"] = [1, 2, 3]\nlet inner = map()\ninner[\"deep\"] = \"kept\"\nm[\"inner\"] = inner").unwrap();
+
+    vm.collect_garbage();
+    // Churn the heap so freed slots would be reused by new objects
+    vm.eval("state i = 0\nwhile i < 200 {\n  let junk = [i, i, i]\n  i -> i + 1\n}").unwrap();
+    vm.collect_garbage();
+
+    assert_eq!(vm.eval("m[\"list\"][2]").unwrap(), Value::Number(3.0));
+    assert_eq!(vm.eval("m[\"inner\"][\"deep\"]").unwrap(), Value::String("kept".into()));
+}
+
+#[test]
+fn test_state_map_writes_are_tracked() {
+    let chain = common::text(eval("state scores = map()\nscores[\"ann\"] = 1\nscores[\"ann\"] = 2\nwhy(scores)"));
+    assert!(chain.contains("2. [t=2] {} -> {\"ann\": 1} (line 2, key \"ann\")"), "{}", chain);
+    assert!(chain.contains("3. [t=3] {\"ann\": 1} -> {\"ann\": 2} (line 3, key \"ann\")"), "{}", chain);
+}
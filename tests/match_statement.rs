@@ -0,0 +1,90 @@
+//! Integration tests for `match`
+
+mod common;
+
+use common::output;
+use skyhetu::{Lexer, Parser};
+
+const DESCRIBE: &str = "fn describe(value) {
+  match value {
+    1 | 2 => { return \"small\" }
+    10 => {
+      let doubled = value * 2
+      return \"ten, doubled \" + str(doubled)
+    }
+    \"foo\" | \"bar\" => { return \"word\" }
+    _ => { return \"something else\" }
+  }
+}
+";
+
+#[test]
+fn test_number_match() {
+    assert_eq!(output(&format!("{}print(describe(1), describe(2))\nprint(describe(10))", DESCRIBE)), "small small\nten, doubled 20\n");
+}
+
+#[test]
+fn test_string_match() {
+    assert_eq!(output(&format!("{}print(describe(\"bar\"), describe(\"foo\"))", DESCRIBE)), "word word\n");
+}
+
+#[test]
+fn test_default_taken() {
+    assert_eq!(output(&format!("{}print(describe(3), describe(\"1\"), describe(nil))", DESCRIBE)), "something else something else something else\n");
+}
+
+#[test]
+fn test_no_default_falls_through_doing_nothing() {
+    let source = "state evens = 0
+state seen = 0
+for i in range(0, 7) {
+  match i % 2 {
+    0 => { evens -> evens + 1 }
+  }
+  seen -> seen + 1
+}
+match \"missing\" {
+  \"present\" => { print(\"unreachable\") }
+}
+print(evens, seen)";
+    assert_eq!(output(source), "4 7\n");
+}
+
+#[test]
+fn test_arms_do_not_fall_through() {
+    let source = "state i = 0
+while i < 10 {
+  i -> i + 1
+  match i {
+    3 => { print(\"three\") }
+    5 => { break }
+    _ => {}
+  }
+}
+print(i)";
+    assert_eq!(output(source), "three\n5\n");
+}
+
+/// The parse error for `source`
+fn parse_err(source: &str) -> skyhetu::SkyHetuError {
+    let tokens = Lexer::new(source).tokenize().expect("lex failed");
+    Parser::new(tokens).parse().expect_err("should not parse")
+}
+
+#[test]
+fn test_default_must_be_last() {
+    let err = parse_err("match 1 {\n  _ => {}\n  1 => {}\n}");
+    assert_eq!(err.to_string(), "[line 3:3] Error: expected '}' after the '_' arm, which must come last in a match, found '1'");
+}
+
+#[test]
+fn test_malformed_arms_say_what_was_expected() {
+    let err = parse_err("match 1 {\n  1 { print(1) }\n}");
+    assert_eq!(err.to_string(), "[line 2:5] Error: expected '=>' after match pattern, found '{'");
+
+    let err = parse_err("match 1 {\n  1 => print(1)\n}");
+    assert_eq!(err.to_string(), "[line 2:8] Error: expected '{' after '=>' in match arm, found 'print'");
+
+    let err = parse_err("match 1\n1 => {}");
+    assert_eq!(err.to_string(), "[line 2:1] Error: expected '{' after match subject, found '1'");
+}
@@ -0,0 +1,142 @@
+//! Integration tests for import visibility
+
+use std::path::{Path, PathBuf};
+use skyhetu::{ErrorKind, Value, VM};
+
+/// A scratch directory holding `lib.skyh`
+fn module_dir(test: &str) -> PathBuf {
+    fixture(test, &[(
+        "lib.skyh",
+        "state calls = 0
+fn helper(n) {
+  calls -> calls + 1
+  return n * 10
+}
+export fn scaled(n) { return helper(n) + 1 }
+export let unit = \"m\"
+",
+    )])
+}
+
+fn run_in_dir(dir: &Path, source: &str) -> skyhetu::Result<Value> {
+    let mut vm = VM::new();
+    vm.set_base_path(dir);
+    skyhetu::run_in(&mut vm, source)
+}
+
+#[test]
+fn test_exported_function_uses_private_helper() {
+    let dir = module_dir("exported");
+    let result = run_in_dir(&dir, "import { scaled } from \"lib\"\nscaled(4)").unwrap();
+    assert_eq!(result, Value::Number(41.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_importing_private_name_fails() {
+    let dir = module_dir("private");
+    let err = run_in_dir(&dir, "import { scaled, helper } from \"lib\"").unwrap_err().to_string();
+    assert!(err.contains("module not found: lib: 'helper' is not exported"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_module_internals_do_not_leak() {
+    let dir = module_dir("leak");
+    let err = run_in_dir(&dir, "import { scaled } from \"lib\"\nhelper(1)").unwrap_err().to_string();
+    assert!(err.contains("helper"), "{}", err);
+    // Exported but not listed is not visible either
+    let err = run_in_dir(&dir, "import { scaled } from \"lib\"\nunit").unwrap_err().to_string();
+    assert!(err.contains("unit"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_module_internals_do_not_overwrite_importer_globals() {
+    let dir = module_dir("collide");
+    let source = "state calls = 100\nfn helper(n) { return \"mine\" }\nimport { scaled } from \"lib\"\nscaled(1)\n[calls, helper(0)]";
+    let mut vm = VM::new();
+    vm.set_base_path(&dir);
+    let result = skyhetu::run_in(&mut vm, source).unwrap();
+    assert_eq!(skyhetu::inspect::render(&vm.heap, &result), "[100, \"mine\"]");
+    assert_eq!(vm.causality.history("lib::calls").len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A scratch directory with the given files
+fn fixture(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("skyhetu_modules_{}_{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, source) in files {
+        std::fs::write(dir.join(name), source).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_diamond_import_compiles_shared_module_once() {
+    let dir = fixture("diamond", &[
+        ("util.skyh", "state counter = 0\nexport fn bump() {\n  counter -> counter + 1\n  return counter\n}\n"),
+        ("left.skyh", "import { bump } from \"util\"\nexport fn from_left() { return bump() }\n"),
+        ("right.skyh", "import { bump } from \"util.skyh\"\nexport fn from_right() { return bump() }\n"),
+    ]);
+    let mut vm = VM::new();
+    vm.set_base_path(&dir);
+    let source = "import { from_left } from \"left\"\nimport { from_right } from \"right\"\nimport { bump } from \"util\"\nfrom_left()\nfrom_right()\nbump()";
+    assert_eq!(skyhetu::run_in(&mut vm, source).unwrap(), Value::Number(3.0));
+
+    // One declaration of the module's state, never reset by the second import
+    let history = vm.causality.history("util::counter");
+    assert_eq!(history.len(), 4);
+    assert_eq!(history.iter().filter(|e| e.old_value == Value::Nil && e.new_value == Value::Number(0.0)).count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_circular_import_is_an_error() {
+    let dir = fixture("cycle", &[
+        ("a.skyh", "import { b } from \"b\"\nexport fn a() { return 1 }\n"),
+        ("b.skyh", "import { a } from \"a\"\nexport fn b() { return 2 }\n"),
+    ]);
+    let err = run_in_dir(&dir, "import { a } from \"a\"\na()").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::CircularImport(_)), "{}", err);
+    assert!(err.to_string().contains("circular import: a.skyh -> b.skyh -> a.skyh"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_error_in_module_names_its_file_and_line() {
+    let dir = fixture("source_map", &[
+        ("math.skyh", "// Helpers\nexport fn ratio(a, b) {\n  let scaled = a * 100\n  return scaled / b\n}\n"),
+    ]);
+    let source = "import { ratio } from \"math\"\nlet x = 1\nprint(ratio(x, 0))";
+    let err = run_in_dir(&dir, source).unwrap_err().with_source(source);
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{}", err);
+    assert_eq!(err.span.unwrap().line, 4);
+    assert_eq!(err.file(), Some(dir.join("math.skyh").as_path()));
+    let message = err.to_string();
+    assert!(message.starts_with(&format!("[{} line 4] Error: division by zero", dir.join("math.skyh").display())), "{}", message);
+    assert!(message.contains("\n  |   return scaled / b"), "{}", message);
+    assert!(message.contains("in <script> (line 3)"), "{}", message);
+
+    // Errors in the program's own code still point into it
+    let err = run_in_dir(&dir, "import { ratio } from \"math\"\nlet y = 1 / 0").unwrap_err();
+    assert_eq!(err.file(), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_duplicate_declaration_in_module_is_an_error() {
+    let dir = fixture("duplicate", &[
+        ("twice.skyh", "export let limit = 1
+fn helper() {}
+export let limit = 2
+"),
+    ]);
+    let err = run_in_dir(&dir, "import { limit } from \"twice\"\nlimit").unwrap_err();
+    assert!(matches!(&err.kind, ErrorKind::DuplicateDefinition(name, Some(1)) if name == "limit"), "{:?}", err.kind);
+    assert_eq!(err.span.unwrap().line, 3);
+    assert_eq!(err.file(), Some(dir.join("twice.skyh").as_path()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+//! Integration tests for observing a running VM from another thread
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use skyhetu::vm::VM;
+use skyhetu::Snapshot;
+
+const LOOP: &str = "state i = 0
+state total = 0
+while i < 20000 {
+  total -> total + i
+  i -> i + 1
+}
+total";
+
+#[test]
+fn test_reader_thread_sees_time_advance() {
+    let mut vm = VM::new();
+    let observer = vm.enable_observation(64);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let reader = {
+        let observer = observer.clone();
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut seen: Vec<Snapshot> = Vec::new();
+            loop {
+                let finished = done.load(Ordering::Acquire);
+                let snapshot = observer.snapshot();
+                if seen.last() != Some(&snapshot) {
+                    seen.push(snapshot);
+                }
+                if finished {
+                    return seen;
+                }
+                thread::yield_now();
+            }
+        })
+    };
+
+    vm.eval(LOOP).expect("execution failed");
+    done.store(true, Ordering::Release);
+    let seen = reader.join().unwrap();
+
+    assert!(seen.windows(2).all(|w| w[0].logical_time <= w[1].logical_time), "logical time went backwards");
+    assert!(seen.windows(2).all(|w| w[0].instructions <= w[1].instructions), "instruction count went backwards");
+
+    // The run publishes a final snapshot, so the reader always ends on it
+    let last = seen.last().unwrap();
+    assert_eq!(last.logical_time, vm.causality.current_time());
+    assert!(last.instructions > 20000);
+    let (_, summary) = last.summaries.iter().find(|(name, _)| name == "i").unwrap();
+    assert_eq!(summary.transition_count, 20000);
+}
+
+#[test]
+fn test_snapshots_follow_the_interval() {
+    let mut vm = VM::new();
+    let observer = vm.enable_observation(1_000_000);
+    assert_eq!(observer.snapshot().logical_time, 0);
+
+    vm.eval("state x = 1\nx -> 2").expect("execution failed");
+    let snapshot = observer.snapshot();
+    assert_eq!(snapshot, vm.snapshot());
+    assert_eq!(snapshot.summaries.len(), 1);
+    assert!(snapshot.heap_objects > 0);
+}
+
+#[test]
+fn test_observation_is_off_by_default() {
+    let mut vm = VM::new();
+    assert!(vm.observer().is_none());
+
+    let observer = vm.enable_observation(10);
+    assert!(vm.observer().is_some());
+    vm.disable_observation();
+    assert!(vm.observer().is_none());
+
+    vm.eval("state x = 1").expect("execution failed");
+    assert_eq!(observer.snapshot().logical_time, 0, "a disabled VM publishes nothing");
+}
@@ -0,0 +1,61 @@
+//! Integration tests for ordering and arithmetic operators on their operand types
+
+mod common;
+
+use common::{eval, eval_err};
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+#[test]
+fn test_strings_are_ordered_by_code_point() {
+    assert_eq!(eval("\"abc\" < \"abd\""), Value::Bool(true));
+    assert_eq!(eval("\"ab\" < \"abc\" and \"\" < \"a\""), Value::Bool(true));
+    assert_eq!(eval("\"Zebra\" < \"apple\""), Value::Bool(true));
+    assert_eq!(eval("\"b\" >= \"b\" and \"b\" <= \"b\" and !(\"b\" > \"b\")"), Value::Bool(true));
+    assert_eq!(eval("\"é\" > \"z\""), Value::Bool(true));
+    // The same order `sort` uses
+    assert_eq!(eval("let words = sort([\"pear\", \"Apple\", \"apple\"])\nwords[0] < words[1] and words[1] < words[2]"), Value::Bool(true));
+}
+
+#[test]
+fn test_bools_are_ordered_false_first() {
+    assert_eq!(eval("false < true"), Value::Bool(true));
+    assert_eq!(eval("true > false and true >= true and !(true < true)"), Value::Bool(true));
+}
+
+#[test]
+fn test_mixed_comparisons_name_the_operator() {
+    let err = eval_err("let n = 1\nn < \"2\"");
+    assert!(err.contains("type mismatch: expected two numbers, strings or bools for '<', got number and string"), "{}", err);
+    let err = eval_err("let b = true\nb >= 1");
+    assert!(err.contains("for '>=', got bool and number"), "{}", err);
+    let err = eval_err("let a = [1]\na > a");
+    assert!(err.contains("for '>', got array and array"), "{}", err);
+}
+
+#[test]
+fn test_arithmetic_errors_name_the_operator() {
+    let err = eval_err("let s = \"a\"\ns - 1");
+    assert!(err.contains("type mismatch: expected numbers for '-', got string and number"), "{}", err);
+    let err = eval_err("let s = \"a\"\ns % 2");
+    assert!(err.contains("expected numbers for '%'"), "{}", err);
+    let err = eval_err("let s = nil\ns + 1");
+    assert!(err.contains("expected numbers or strings for '+', got nil and number"), "{}", err);
+}
+
+#[test]
+fn test_modulo_by_zero_is_an_error() {
+    let err = VM::new().eval("let n = 0\n5 % n").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{}", err);
+    assert_eq!(eval("let n = 3\n-7 % n"), Value::Number(-1.0));
+    assert_eq!(eval("let n = 0.5\n7 % n"), Value::Number(0.0));
+}
+
+#[test]
+fn test_constants_follow_the_same_rules() {
+    assert_eq!(eval("const EARLY = \"a\" < \"b\"\nconst ON = true > false\nEARLY and ON"), Value::Bool(true));
+    let err = VM::new().eval("const X = 5 % 0").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::DivisionByZero), "{}", err);
+    let err = eval_err("const X = 1 < \"a\"");
+    assert!(err.contains("expected two numbers, strings or bools for '<', got number and string"), "{}", err);
+}
@@ -0,0 +1,60 @@
+//! Integration tests for parameter type annotations
+
+mod common;
+
+use common::{eval, eval_err};
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+#[test]
+fn test_annotated_parameter_rejects_wrong_type() {
+    let source = "fn add(a: number, b: number) {
+  return a + b
+}
+add(1, \"2\")";
+    let err = VM::new().eval(source).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::TypeMismatch(..)), "{:?}", err.kind);
+    assert!(
+        err.to_string().contains("type mismatch: expected number for parameter 'b' of 'add', got string"),
+        "{}",
+        err
+    );
+    assert_eq!(eval("fn add(a: number, b: number) { return a + b }\nadd(1, 2)"), Value::Number(3.0));
+
+    let err = eval_err("let f = fn(xs: array, g: function) => map(xs, g)\nf([1], 2)");
+    assert!(err.contains("expected function for parameter 'g' of a lambda, got number"), "{}", err);
+    let err = eval_err("fn flag(state on: bool) { on -> !on }\nflag(1)");
+    assert!(err.contains("expected bool for parameter 'on' of 'flag', got number"), "{}", err);
+}
+
+#[test]
+fn test_unannotated_parameters_are_not_checked() {
+    let source = "fn show(a, b) { return str(a) + str(b) }
+show(\"x\", 1) + show(nil, true)";
+    assert_eq!(eval(source), Value::String("x1niltrue".into()));
+
+    // Only annotated parameters get a check
+    let err = eval_err("fn pick(a, b: string) { return a }\npick(1, 2)");
+    assert!(err.contains("parameter 'b' of 'pick'"), "{}", err);
+    assert_eq!(eval("fn pick(a, b: string) { return a }\npick(1, \"2\")"), Value::Number(1.0));
+}
+
+#[test]
+fn test_class_name_annotation_checks_instances() {
+    let source = "class Animal { init(name) { this.name = name } }
+class Dog : Animal {}
+class Car {}
+class Vet {
+  treat(patient: Animal) { return \"treated \" + patient.name }
+}
+let vet = Vet()
+";
+    let treated = eval(&format!("{}vet.treat(Dog(\"rex\"))", source));
+    assert_eq!(treated, Value::String("treated rex".into()));
+    assert_eq!(eval(&format!("{}vet.treat(Animal(\"tom\"))", source)), Value::String("treated tom".into()));
+
+    let err = eval_err(&format!("{}vet.treat(Car())", source));
+    assert!(err.contains("expected Animal for parameter 'patient' of 'treat', got Car"), "{}", err);
+    let err = eval_err(&format!("{}vet.treat(\"rex\")", source));
+    assert!(err.contains("got string"), "{}", err);
+}
@@ -0,0 +1,67 @@
+//! Integration tests for reporting every syntax error in a file
+
+use skyhetu::ast::Stmt;
+use skyhetu::{ErrorKind, Lexer, Parser};
+
+const SOURCE: &str = include_str!("syntax_errors.skyh");
+
+fn parser(source: &str) -> Parser {
+    Parser::new(Lexer::new(source).tokenize().unwrap())
+}
+
+#[test]
+fn test_every_error_is_reported() {
+    let errors = parser(SOURCE).parse_all().unwrap_err();
+    let places: Vec<(usize, usize)> = errors.iter().map(|e| (e.span.unwrap().line, e.span.unwrap().column)).collect();
+    assert_eq!(places, [(2, 9), (4, 13), (7, 15)], "{:?}", errors);
+    assert_eq!(errors[1].to_string(), "[line 4:13] Error: expected ')' after parameters, found '{'");
+
+    // parse() still stops at the first
+    let err = parser(SOURCE).parse().unwrap_err();
+    assert_eq!(err.span, errors[0].span);
+}
+
+#[test]
+fn test_expected_token_errors_name_what_was_found() {
+    let err = parser("for i of range(3) {}").parse().unwrap_err();
+    assert_eq!(err.to_string(), "[line 1:7] Error: expected 'in' after loop variable, found 'of'");
+
+    let err = parser("let = 1").parse().unwrap_err();
+    assert_eq!(err.to_string(), "[line 1:5] Error: expected variable name, found '='");
+
+    // At the end of input there is nothing to name
+    let err = parser("print(1").parse().unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::UnexpectedEof(_)), "{:?}", err.kind);
+    assert_eq!(err.to_string(), "[line 1:8] Error: unexpected end of input: expected ')' after arguments");
+}
+
+#[test]
+fn test_statements_between_errors_still_parse() {
+    let (program, errors) = parser(SOURCE).parse_recovering();
+    assert_eq!(errors.len(), 3);
+    let names: Vec<&str> = program.statements.iter().filter_map(|stmt| match stmt {
+        Stmt::Let { name, .. } | Stmt::State { name, .. } => Some(name.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(names, ["a", "total"]);
+    assert!(matches!(program.statements.last(), Some(Stmt::Expr { .. })));
+}
+
+#[test]
+fn test_recovery_inside_blocks() {
+    let source = "fn f() {\n  let x = )\n  let y = 2\n  let z = * 3\n  return y\n}\nprint(f())";
+    let (program, errors) = parser(source).parse_recovering();
+    let lines: Vec<usize> = errors.iter().map(|e| e.span.unwrap().line).collect();
+    assert_eq!(lines, [2, 4]);
+    let Some(Stmt::Function { body, .. }) = program.statements.first() else {
+        panic!("expected the function to parse");
+    };
+    assert_eq!(body.len(), 2, "{:?}", body);
+    assert_eq!(program.statements.len(), 2);
+
+    // A block cut short by the end of input is reported once
+    let errors = parser("fn f() {\n  if true {\n    let x = ").parse_all().unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(matches!(errors[0].kind, ErrorKind::UnexpectedEof(_)), "{:?}", errors[0].kind);
+    assert!(parser("let a = 1; let b = 2").parse_all().is_ok());
+}
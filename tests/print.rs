@@ -0,0 +1,34 @@
+//! Integration tests for what `print` and `why()` show for heap values
+
+mod common;
+
+use common::output;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+#[test]
+fn test_nested_containers_print_their_contents() {
+    assert_eq!(output("print([1, 2, [3, 4]], [])"), "[1, 2, [3, 4]] []\n");
+    assert_eq!(output("print([\"a\", nil, true], \"a\")"), "[\"a\", nil, true] a\n");
+    assert_eq!(output("let m = map()\nset(m, \"b\", [1])\nset(m, \"a\", 2)\nprint(m)"), "{\"a\": 2, \"b\": [1]}\n");
+    let point = "class Point {\n  init(x, y) {\n    this.y = y\n    this.x = x\n  }\n}\n";
+    assert_eq!(output(&format!("{}print(Point(1, [2]))", point)), "Point {x: 1, y: [2]}\n");
+}
+
+#[test]
+fn test_self_referencing_containers_terminate() {
+    assert_eq!(output("let a = [1]\npush(a, a)\nprint(a)"), "[1, [...]]\n");
+    // A container shared by two siblings is not a cycle
+    assert_eq!(output("let shared = [0]\nprint([shared, shared])"), "[[0], [0]]\n");
+    let node = "class Node {\n  init() {\n    this.next = this\n  }\n}\n";
+    assert_eq!(output(&format!("{}print(Node())", node)), "Node {next: Node {...}}\n");
+}
+
+#[test]
+fn test_why_shows_array_contents() {
+    let mut vm = VM::new();
+    let result = vm.eval("state items = [1]\nitems -> [1, [2]]\nwhy(items)").unwrap();
+    let Value::String(text) = result else { panic!("expected why() text") };
+    assert!(text.contains("initialized to [1]"), "{}", text);
+    assert!(text.contains("[1] -> [1, [2]]"), "{}", text);
+}
@@ -0,0 +1,99 @@
+//! The public API contract: everything here, the shared helpers in `common`
+//! aside, goes through crate-root re-exports only. If this file stops
+//! compiling, embedders break too.
+
+mod common;
+
+use common::compile;
+use skyhetu::{
+    AssertMode, BindingKind, CaptureBuffer, CausalityLog, Chunk, Compiler, ErrorKind,
+    ExportFormat, Heap, Lexer, MutationEvent, NativeFn, Parser, Program, SkyHetuError, Value,
+    Warning, VM,
+};
+
+#[test]
+fn test_run_convenience() {
+    assert_eq!(skyhetu::run("1 + 2").unwrap(), Value::Number(3.0));
+    assert!(!skyhetu::VERSION.is_empty());
+}
+
+#[test]
+fn test_compile_and_run_on_vm() {
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+
+    let chunk: Chunk = compile("state n = 1\nn -> n + 1\nprint(n)\nn", true, &mut vm);
+    assert!(!chunk.code().is_empty());
+    assert!(chunk.names().iter().any(|n| &**n == "n"));
+    assert!(chunk.line(0) >= 1);
+
+    assert_eq!(vm.run(chunk).unwrap(), Value::Number(2.0));
+    assert_eq!(output.contents(), "2\n");
+    assert!(vm.why("n").contains("1 -> 2"));
+    assert!(vm.global_kinds().contains(&("n".to_string(), BindingKind::State)));
+}
+
+#[test]
+fn test_globals_iter() {
+    let mut vm = VM::new();
+    vm.eval("let b = [1, 2]\nstate a = 1\na -> 2").unwrap();
+    let user: Vec<(&str, BindingKind, &Value)> = vm.globals_iter()
+        .filter(|(_, kind, _)| *kind != BindingKind::Native)
+        .collect();
+    assert_eq!(user.len(), 2);
+    assert_eq!((user[0].0, user[0].1, user[0].2), ("a", BindingKind::State, &Value::Number(2.0)));
+    assert_eq!((user[1].0, user[1].1), ("b", BindingKind::Let));
+    assert_eq!(skyhetu::inspect::render(&vm.heap, user[1].2), "[1, 2]");
+    assert!(vm.globals_iter().any(|(name, kind, _)| name == "len" && kind == BindingKind::Native));
+}
+
+#[test]
+fn test_causality_log() {
+    let mut vm = VM::new();
+    let chunk = compile("state n = 0\nn -> 5", true, &mut vm);
+    vm.run(chunk).unwrap();
+
+    let log: &CausalityLog = &vm.causality;
+    let events: Vec<&MutationEvent> = log.all_events().collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].new_value, Value::Number(5.0));
+
+    let mut bytes = Vec::new();
+    log.export_to(&mut bytes, ExportFormat::Binary).unwrap();
+    assert_eq!(CausalityLog::load_binary(&mut bytes.as_slice()).unwrap().event_count(), 2);
+}
+
+#[test]
+fn test_errors_and_warnings() {
+    let err: SkyHetuError = skyhetu::run("missing").unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::UndefinedVariable(ref name) if name == "missing"));
+
+    let tokens = Lexer::new("let x = 1\nstate x = 2").tokenize().unwrap();
+    let program: Program = Parser::new(tokens).parse().unwrap();
+    let mut compiler = Compiler::new();
+    let err = compiler.compile(&program, &mut Heap::new()).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::DuplicateDefinition(ref name, Some(1)) if name == "x"));
+
+    let mut compiler = Compiler::new();
+    compiler.set_allow_redefine(true);
+    compiler.compile(&program, &mut Heap::new()).unwrap();
+    let warnings: &[Warning] = compiler.warnings();
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn test_assert_mode() {
+    let mut vm = VM::new();
+    vm.set_assert_mode(AssertMode::Collect);
+    let chunk = compile("assert(false, \"nope\")", true, &mut vm);
+    vm.run(chunk).unwrap();
+    assert_eq!(vm.assert_failures()[0].message, "nope");
+}
+
+#[test]
+fn test_native_fn_value() {
+    let native = NativeFn::new("answer", Some(0), |_vm, _args| Ok(Value::Number(42.0)));
+    let value = Value::NativeFunction(native);
+    assert_eq!(value.type_name(), "native function");
+}
@@ -0,0 +1,191 @@
+//! Integration tests for redefining globals, and for declaring a name twice in one scope
+
+mod common;
+
+use common::{compile_warnings, compile_with};
+use skyhetu::{Engine, ErrorKind, Heap, Value};
+use skyhetu::compiler::Compiler;
+use skyhetu::bindings::{redefinition_message, BindingKind};
+use skyhetu::vm::{CaptureBuffer, VM};
+
+/// Declarations of `x` for each kind that can be written in source
+const DECLS: &[(BindingKind, &str)] = &[
+    (BindingKind::Let, "let x = 1"),
+    (BindingKind::State, "state x = 1"),
+    (BindingKind::Function, "fn x() { return 1 }"),
+    (BindingKind::Class, "class x {}"),
+];
+
+/// Compile and run one REPL line against a persistent VM
+fn eval_line(vm: &mut VM, source: &str) {
+    let chunk = compile_with(&mut Compiler::new(), source, &mut vm.heap).expect("compile failed");
+    vm.run(chunk).expect("execution failed");
+}
+
+fn repl_vm() -> (VM, CaptureBuffer) {
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    vm.set_repl_mode(true);
+    (vm, output)
+}
+
+/// Warning messages from compiling `source` as a script, with redefinition
+/// allowed or not
+fn script_warnings(source: &str, allow_redefine: bool) -> Vec<String> {
+    let mut compiler = VM::new().program_compiler(".".into());
+    compiler.set_allow_redefine(allow_redefine);
+    compile_warnings(&mut compiler, source).into_iter().map(|w| w.message).collect()
+}
+
+#[test]
+fn test_repl_notice_for_every_kind_change() {
+    for (old_kind, old_src) in DECLS {
+        for (new_kind, new_src) in DECLS {
+            let (mut vm, output) = repl_vm();
+            eval_line(&mut vm, old_src);
+            eval_line(&mut vm, new_src);
+
+            let printed = output.contents();
+            if old_kind == new_kind {
+                assert_eq!(printed, "", "{} then {}", old_src, new_src);
+            } else {
+                let expected = format!("note: {}\n", redefinition_message("x", *old_kind, *new_kind));
+                assert_eq!(printed, expected, "{} then {}", old_src, new_src);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_repl_let_to_state_is_called_out() {
+    let (mut vm, output) = repl_vm();
+    eval_line(&mut vm, "let x = 1");
+    eval_line(&mut vm, "state x = 2");
+    assert_eq!(
+        output.contents(),
+        "note: 'x' redefined from let to state; transitions of 'x' are tracked from here on\n"
+    );
+
+    output.clear();
+    eval_line(&mut vm, "let x = 3");
+    assert_eq!(
+        output.contents(),
+        "note: 'x' redefined from state to let; its causality history ends here\n"
+    );
+}
+
+#[test]
+fn test_repl_redefining_native() {
+    for (kind, src) in DECLS {
+        let (mut vm, output) = repl_vm();
+        eval_line(&mut vm, &src.replace(" x", " len"));
+        let expected = format!("note: {}\n", redefinition_message("len", BindingKind::Native, *kind));
+        assert_eq!(output.contents(), expected);
+    }
+}
+
+#[test]
+fn test_script_mode_prints_no_notices() {
+    let mut vm = VM::new();
+    let output = CaptureBuffer::new();
+    vm.set_output(Box::new(output.clone()));
+    eval_line(&mut vm, "let x = 1");
+    eval_line(&mut vm, "fn x() { return 2 }");
+    assert_eq!(output.contents(), "");
+}
+
+/// Compile `source` as a script, expecting it to fail
+fn compile_error(source: &str) -> skyhetu::SkyHetuError {
+    compile_with(&mut Compiler::new(), source, &mut Heap::new()).expect_err("compile should fail")
+}
+
+#[test]
+fn test_script_redeclaration_is_an_error() {
+    for (_, old_src) in DECLS {
+        for (_, new_src) in DECLS {
+            let err = compile_error(&format!("{}\n{}", old_src, new_src));
+            assert!(matches!(&err.kind, ErrorKind::DuplicateDefinition(name, Some(1)) if name == "x"), "{:?}", err.kind);
+            assert_eq!(err.span.map(|s| s.line), Some(2));
+            assert_eq!(err.kind.to_string(), "'x' is already defined in this scope (first defined at line 1)");
+        }
+    }
+}
+
+#[test]
+fn test_local_redeclaration_is_an_error() {
+    for (source, line) in [
+        ("fn f() {\n  let a = 1\n  state a = 2\n}", 3),
+        ("fn f(a) {\n  let a = 1\n}", 2),
+        ("if true {\n  fn g() {}\n  class g {}\n}", 3),
+        ("fn f(a, b, a) {}", 1),
+        ("let f = fn(x, x) => x", 1),
+    ] {
+        let err = compile_error(source);
+        assert!(matches!(&err.kind, ErrorKind::DuplicateDefinition(..)), "{}: {:?}", source, err.kind);
+        assert_eq!(err.span.map(|s| s.line), Some(line), "{}", source);
+    }
+    let err = compile_error("fn f() {\n  let a = 1\n  let a = 2\n}");
+    assert_eq!(err.kind.to_string(), "'a' is already defined in this scope (first defined at line 2)");
+}
+
+#[test]
+fn test_shadowing_in_an_inner_scope_is_allowed() {
+    let source = "let x = 1
+fn f(x) {
+  let y = x
+  if y > 0 {
+    let y = y * 10
+    let x = y + 1
+    return x
+  }
+  return y
+}
+f(x)";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(11.0));
+}
+
+#[test]
+fn test_repl_session_may_redeclare() {
+    let mut engine = Engine::new();
+    engine.eval("let x = 1").unwrap();
+    engine.eval("let x = 2").unwrap();
+    assert_eq!(engine.eval("let y = x\nlet y = y + 1\ny").unwrap(), Value::Number(3.0));
+    assert_eq!(engine.get_global("x"), Some(Value::Number(2.0)));
+}
+
+#[test]
+fn test_redefinition_warning_for_every_kind_change() {
+    for (old_kind, old_src) in DECLS {
+        for (new_kind, new_src) in DECLS {
+            let warnings = script_warnings(&format!("{}\n{}", old_src, new_src), true);
+            if old_kind == new_kind {
+                assert!(warnings.is_empty(), "{} then {}: {:?}", old_src, new_src, warnings);
+            } else {
+                let expected = format!(
+                    "{} (previously declared at line 1)",
+                    redefinition_message("x", *old_kind, *new_kind)
+                );
+                assert_eq!(warnings, vec![expected], "{} then {}", old_src, new_src);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_script_warning_for_native() {
+    let warnings = script_warnings("fn len(s) { return 0 }", false);
+    assert_eq!(warnings, vec![
+        "'len' redefined from native fn to fn (previously a built-in)".to_string(),
+    ]);
+}
+
+#[test]
+fn test_redefinition_warning_span_points_at_redefinition() {
+    let mut compiler = Compiler::new();
+    compiler.set_allow_redefine(true);
+    let warnings = compile_warnings(&mut compiler, "state total = 0\nlet y = 2\nlet total = 5");
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].span.map(|s| s.line), Some(3));
+    assert!(warnings[0].message.contains("from state to let"));
+}
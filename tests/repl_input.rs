@@ -0,0 +1,61 @@
+//! Integration tests for how the REPL groups lines into complete entries
+
+use skyhetu::parser::is_incomplete;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+/// Feed `lines` one at a time as the REPL does, returning each complete entry
+fn entries(lines: &[&str]) -> Vec<String> {
+    let mut complete = Vec::new();
+    let mut pending = String::new();
+    for line in lines {
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        pending.push_str(line);
+        if !is_incomplete(&pending) {
+            complete.push(std::mem::take(&mut pending));
+        }
+    }
+    assert!(pending.is_empty(), "unfinished entry left over: {:?}", pending);
+    complete
+}
+
+#[test]
+fn test_multi_line_function_definition() {
+    let lines = ["fn add(a, b) {", "  let sum = a + b", "", "  return sum", "}", "add(2, 3)"];
+    let found = entries(&lines);
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert!(is_incomplete("fn add(a, b) {"));
+    assert!(is_incomplete("fn add(a, b) {\n  let sum = a + b"));
+
+    let mut vm = VM::new();
+    vm.eval(&found[0]).unwrap();
+    assert_eq!(vm.eval(&found[1]).unwrap(), Value::Number(5.0));
+}
+
+#[test]
+fn test_multi_line_while_loop() {
+    let lines = ["state i = 0", "while i < 3 {", "  if i == 1 {", "    print(i)", "  }", "  i -> i + 1", "}", "i"];
+    let found = entries(&lines);
+    assert_eq!(found.len(), 3, "{:?}", found);
+    assert_eq!(found[1].lines().count(), 6);
+
+    let mut vm = VM::new();
+    vm.set_output(Box::new(std::io::sink()));
+    for entry in &found[..2] {
+        vm.eval(entry).unwrap();
+    }
+    assert_eq!(vm.eval(&found[2]).unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn test_complete_or_broken_lines_are_not_continued() {
+    assert!(!is_incomplete("let x = 1"));
+    assert!(!is_incomplete("let x = 1 +"), "no bracket left open");
+    assert!(!is_incomplete("}"));
+    assert!(!is_incomplete("fn f() { let = 1"), "error before the end of the input");
+    assert!(!is_incomplete("print(\"unterminated"));
+    assert!(is_incomplete("print((1 + 2)"));
+    assert!(is_incomplete("let xs = [1,"));
+}
@@ -0,0 +1,36 @@
+//! Integration tests for `;` between statements
+
+mod common;
+
+use common::output;
+use skyhetu::vm::VM;
+use skyhetu::ErrorKind;
+
+#[test]
+fn test_one_line_programs() {
+    assert_eq!(output("state c = 0; c -> c + 1; print(c)"), "1\n");
+    assert_eq!(output("let a = 1; let b = 2;\nprint(a + b);"), "3\n");
+    assert_eq!(output("fn f(x) { if x > 1 { return; } return x }; print(f(1)); print(f(2))"), "1\nnil\n");
+    assert_eq!(output("class P { a() { return 1 }; b() { return 2 } }; let p = P(); print(p.a() + p.b())"), "3\n");
+    assert_eq!(output("match 2 { 1 => { print(\"one\") }; 2 => { print(\"two\") }; }"), "two\n");
+}
+
+#[test]
+fn test_statement_ending_in_a_block_needs_no_separator() {
+    assert_eq!(output("if true { print(1) } print(2)"), "1\n2\n");
+    assert_eq!(output("fn f() { return 3 } print(f())"), "3\n");
+}
+
+#[test]
+fn test_missing_separator_is_an_error() {
+    for (source, token, column) in [
+        ("let a = 1 let b = 2", "let", 11),
+        ("print(1) print(2)", "print", 10),
+        ("let f = fn() { 1 } f()", "f", 20),
+    ] {
+        let err = VM::new().eval(source).unwrap_err();
+        assert!(matches!(&err.kind, ErrorKind::MissingSeparator(t) if t == token), "{}: {:?}", source, err.kind);
+        assert_eq!(err.span.unwrap().column, column, "{}", source);
+        assert!(err.to_string().contains("expected ';' or a newline"), "{}", err);
+    }
+}
@@ -0,0 +1,62 @@
+//! Integration tests for spread arguments: f(...arr)
+
+mod common;
+
+use skyhetu::Value;
+
+const ADD3: &str = "fn add3(a, b, c) { return a * 100 + b * 10 + c }\n";
+
+/// Run `source` after defining `add3`
+fn eval(source: &str) -> Value {
+    common::eval(&format!("{}{}", ADD3, source))
+}
+
+/// The error `source` fails with after defining `add3`
+fn eval_err(source: &str) -> String {
+    common::eval_err(&format!("{}{}", ADD3, source))
+}
+
+#[test]
+fn test_spread_forwards_through_two_layers() {
+    let source = "fn layer2(a, b, c) { return add3(...range(a, a + 1), b, c) }
+fn layer1(args) { return layer2(...args) }
+layer1(range(1, 4))";
+    assert_eq!(eval(source), Value::Number(123.0));
+}
+
+#[test]
+fn test_spread_mixed_with_positional_args() {
+    assert_eq!(eval("add3(1, ...range(2, 4))"), Value::Number(123.0));
+    assert_eq!(eval("add3(...range(1, 2), 2, ...range(3, 4))"), Value::Number(123.0));
+    assert_eq!(eval("max(...range(2, 4))"), Value::Number(3.0));
+}
+
+#[test]
+fn test_spread_empty_array() {
+    assert_eq!(eval("fn seven() { return 7 }\nseven(...range(0))"), Value::Number(7.0));
+    assert_eq!(eval("add3(1, ...range(0), 2, 3)"), Value::Number(123.0));
+}
+
+#[test]
+fn test_spread_non_array_is_an_error() {
+    let err = eval_err("add3(1, ...5, 3)");
+    assert!(err.contains("cannot spread argument 2: expected array, got number"), "{}", err);
+}
+
+#[test]
+fn test_arity_is_checked_after_expansion() {
+    let err = eval_err("add3(...range(4))");
+    assert!(err.contains("expected 3 arguments, got 4"), "{}", err);
+}
+
+#[test]
+fn test_argument_limit_after_expansion() {
+    let err = eval_err("add3(...range(300))");
+    assert!(err.contains("too many arguments after spreading: 300"), "{}", err);
+}
+
+#[test]
+fn test_spread_rejected_in_print() {
+    let err = eval_err("print(...range(3))");
+    assert!(err.contains("unexpected token '...'"), "{}", err);
+}
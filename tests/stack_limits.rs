@@ -0,0 +1,81 @@
+//! Integration tests for the value stack and call depth limits
+
+use skyhetu::bytecode::{Chunk, OpCode};
+use skyhetu::vm::VM;
+use skyhetu::{ErrorKind, Value};
+
+/// An array literal with `n` elements, each of which sits on the stack until the array is built
+fn wide_literal(n: usize) -> String {
+    format!("[{}]", vec!["1"; n].join(", "))
+}
+
+#[test]
+fn test_overflowing_the_stack_is_an_error() {
+    let mut vm = VM::new();
+    let err = vm.eval(&format!("let row = {}", wide_literal(3000))).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackOverflow), "{}", err);
+    assert_eq!(err.span.unwrap().line, 1);
+
+    // The failed run leaves nothing behind that breaks the next one
+    assert_eq!(vm.eval("let a = [1, 2, 3]\na[0] + a[2]").unwrap(), Value::Number(4.0));
+    assert_eq!(vm.eval(&format!("len({})", wide_literal(2000))).unwrap(), Value::Number(2000.0));
+}
+
+#[test]
+fn test_limits_are_configurable() {
+    let mut vm = VM::with_limits(64, 8);
+    let err = vm.eval(&format!("len({})", wide_literal(100))).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackOverflow), "{}", err);
+
+    let deep = "fn depth(n) {
+  if n == 0 { return 0 }
+  return 1 + depth(n - 1)
+}
+depth(N)";
+    assert_eq!(vm.eval(&deep.replace('N', "6")).unwrap(), Value::Number(6.0));
+    let err = vm.eval(&deep.replace('N', "7")).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackOverflow), "{}", err);
+
+    // Larger limits allow more than the defaults
+    let mut vm = VM::with_limits(10_000, 500);
+    assert_eq!(vm.eval(&format!("len({})", wide_literal(5000))).unwrap(), Value::Number(5000.0));
+    assert_eq!(vm.eval(&deep.replace('N', "400")).unwrap(), Value::Number(400.0));
+}
+
+#[test]
+fn test_underflow_from_malformed_bytecode_is_an_error() {
+    let mut chunk = Chunk::new();
+    // The script's own closure is the only value on the stack
+    chunk.write(OpCode::Pop, 1);
+    chunk.write(OpCode::Pop, 2);
+    chunk.write(OpCode::Nil, 3);
+    chunk.write(OpCode::Return, 3);
+    let mut vm = VM::new();
+    let err = vm.run(chunk).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackUnderflow), "{}", err);
+    assert_eq!(err.span.unwrap().line, 2);
+    assert_eq!(vm.eval("1 + 2").unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn test_calls_with_more_arguments_than_the_stack_holds_are_an_error() {
+    let mut chunk = Chunk::new();
+    chunk.write(OpCode::Call, 1);
+    chunk.write_byte(5, 1);
+    chunk.write(OpCode::Return, 1);
+    let mut vm = VM::new();
+    let err = vm.run(chunk).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackUnderflow), "{}", err);
+
+    let mut chunk = Chunk::new();
+    chunk.write(OpCode::Nil, 1);
+    chunk.write(OpCode::CallSpread, 1);
+    chunk.write_byte(3, 1);
+    for _ in 0..3 {
+        chunk.write_byte(1, 1);
+    }
+    chunk.write(OpCode::Return, 1);
+    let err = vm.run(chunk).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::StackUnderflow), "{}", err);
+    assert_eq!(vm.eval("1 + 2").unwrap(), Value::Number(3.0));
+}
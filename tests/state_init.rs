@@ -0,0 +1,98 @@
+//! Integration tests for the causality events recorded by `state` declarations
+
+mod common;
+
+use common::{compile_with, run};
+use skyhetu::causality::EventKind;
+use skyhetu::compiler::Compiler;
+use skyhetu::{Heap, Value};
+
+#[test]
+fn test_declaration_records_init_event() {
+    let vm = run("state x = 5");
+    let history = vm.causality.history("x");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].kind, EventKind::Init);
+    assert_eq!(history[0].old_value, Value::Nil);
+    assert_eq!(history[0].new_value, Value::Number(5.0));
+    assert_eq!(history[0].location.as_deref(), Some("line 1"));
+    assert_eq!(vm.causality.transition_count("x"), 0);
+    assert_eq!(vm.why("x"), "Causality chain for 'x':\n  1. [t=1] initialized to 5 (line 1)\n");
+}
+
+#[test]
+fn test_initializer_side_effects_are_ordered_before_declaration() {
+    let vm = run("state a = 0
+fn bump_and_read() {
+  a -> a + 1
+  return a * 10
+}
+state b = bump_and_read()");
+
+    let events: Vec<_> = vm.causality.all_events().collect();
+    let order: Vec<_> = events.iter().map(|e| (e.variable.as_str(), e.kind, e.timestamp)).collect();
+    assert_eq!(order, vec![
+        ("a", EventKind::Init, 1),
+        ("a", EventKind::Transition, 2),
+        ("b", EventKind::Init, 3),
+    ]);
+    assert_eq!(events[2].new_value, Value::Number(10.0));
+}
+
+#[test]
+fn test_value_at_uses_declaration() {
+    let vm = run("let pad = 0\nstate x = 1\nx -> 2\nx -> 3");
+    let log = &vm.causality;
+    assert_eq!(log.value_at("x", 0), None);
+    assert_eq!(log.value_at("x", 1), Some(Value::Number(1.0)));
+    assert_eq!(log.value_at("x", 2), Some(Value::Number(2.0)));
+    assert_eq!(log.value_at("x", 99), Some(Value::Number(3.0)));
+}
+
+#[test]
+fn test_local_state_declaration_is_recorded() {
+    let vm = run("fn f() {\n  state n = 7\n  n -> n + 1\n  return n\n}\nf()");
+    let history = vm.causality.history("f.n");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].kind, EventKind::Init);
+    assert_eq!(history[0].new_value, Value::Number(7.0));
+    assert_eq!(history[0].location.as_deref(), Some("line 2"));
+    assert_eq!(history[1].new_value, Value::Number(8.0));
+}
+
+#[test]
+fn test_state_parameter_accumulates() {
+    let mut vm = run("fn process(state acc, items) {\n  for x in items { acc -> acc + x }\n  return acc\n}");
+    assert_eq!(vm.eval("process(0, [1, 2, 3, 4])").unwrap(), Value::Number(10.0));
+}
+
+#[test]
+fn test_state_parameter_histories_are_per_call() {
+    let vm = run("fn process(state acc, items) {
+  for x in items { acc -> acc + x }
+  return why(acc)
+}
+state first = process(0, [1, 2])
+state second = process(100, [5])");
+
+    let first = vm.causality.history("process#1.acc");
+    let values: Vec<_> = first.iter().map(|e| e.new_value.clone()).collect();
+    assert_eq!(values, vec![Value::Number(0.0), Value::Number(1.0), Value::Number(3.0)]);
+    assert_eq!(first[0].kind, EventKind::Init);
+
+    let second = vm.causality.history("process#2.acc");
+    let values: Vec<_> = second.iter().map(|e| e.new_value.clone()).collect();
+    assert_eq!(values, vec![Value::Number(100.0), Value::Number(105.0)]);
+
+    let Some(Value::String(chain)) = vm.get_global("second") else {
+        panic!("why returns a string");
+    };
+    assert!(chain.starts_with("Causality chain for 'process#2.acc':"), "{}", chain);
+    assert!(!chain.contains("-> 1 "), "{}", chain);
+}
+
+#[test]
+fn test_transition_on_plain_parameter_is_rejected() {
+    let err = compile_with(&mut Compiler::new(), "fn f(acc) {\n  acc -> acc + 1\n}", &mut Heap::new()).unwrap_err();
+    assert!(matches!(err.kind, skyhetu::ErrorKind::ImmutableVariable(ref name) if name == "acc"), "{}", err);
+}
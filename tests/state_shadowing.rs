@@ -0,0 +1,85 @@
+//! Integration tests for local `state` variables that shadow another state of the same name
+
+mod common;
+
+use common::warning_lines;
+use skyhetu::value::Value;
+use skyhetu::vm::VM;
+
+/// Run `source`, returning the VM, the script's result and the warnings with their lines
+fn run_with_warnings(source: &str) -> (VM, Value, Vec<(usize, String)>) {
+    let warnings = warning_lines(source);
+    let mut vm = VM::new();
+    let result = vm.eval(source).expect("execution failed");
+    (vm, result, warnings)
+}
+
+#[test]
+fn test_local_shadowing_global_is_tracked_separately() {
+    let (vm, result, warnings) = run_with_warnings("state x = 0
+fn f() {
+  state x = 100
+  x -> x + 1
+  x -> x + 1
+  return why(x)
+}
+x -> 5
+let inner = f()
+x -> x + 1
+inner");
+    assert_eq!(warnings, vec![(3, "state 'x' shadows global state 'x'; histories will be tracked separately".to_string())]);
+
+    let outer: Vec<_> = vm.causality.history("x").iter().map(|e| e.new_value.clone()).collect();
+    assert_eq!(outer, [Value::Number(0.0), Value::Number(5.0), Value::Number(6.0)]);
+    let Value::String(inner) = result else { panic!("expected why() text") };
+    assert!(inner.starts_with("Causality chain for 'f.x':"), "{}", inner);
+    assert!(inner.contains("100 -> 101") && inner.contains("101 -> 102"), "{}", inner);
+    assert!(!vm.causality.why("x").contains("101"));
+}
+
+#[test]
+fn test_local_shadowing_local_is_reported() {
+    let (vm, _, warnings) = run_with_warnings("fn outer() {
+  state x = 1
+  fn inner() {
+    state x = 2
+    x -> x * 10
+  }
+  inner()
+  if x > 0 {
+    state x = 3
+    x -> x + 1
+  }
+  x -> x + 1
+}
+outer()");
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert_eq!(warnings[0], (4, "state 'x' shadows state 'x' of enclosing function 'outer'; histories will be tracked separately".to_string()));
+    assert_eq!(warnings[1], (9, "state 'x' shadows state 'x' of an enclosing block; histories will be tracked separately".to_string()));
+
+    let values = |name: &str| -> Vec<Value> {
+        vm.causality.history(name).iter().map(|e| e.new_value.clone()).collect()
+    };
+    assert_eq!(values("outer.x"), [Value::Number(1.0), Value::Number(2.0)]);
+    assert_eq!(values("inner.x"), [Value::Number(2.0), Value::Number(20.0)]);
+    assert_eq!(values("outer.x.2"), [Value::Number(3.0), Value::Number(4.0)]);
+}
+
+#[test]
+fn test_state_shadowing_let_is_not_reported() {
+    let (_, result, warnings) = run_with_warnings("let total = 0
+fn sum(items) {
+  let count = len(items)
+  state total = 0
+  if count > 0 {
+    state count = 0
+    for item in items { count -> count + 1 }
+  }
+  for item in items { total -> total + item }
+  return total
+}
+sum([1, 2, 3])");
+    // Only the general shadowing warning applies, not the one about separate histories
+    assert_eq!(warnings, vec![(6, "'count' shadows the local declared at line 3".to_string())]);
+    assert_eq!(result, Value::Number(6.0));
+}
@@ -0,0 +1,50 @@
+//! Integration tests for the string utility natives
+
+mod common;
+
+use common::{eval, eval_err, string};
+use skyhetu::Value;
+
+#[test]
+fn test_split_and_join() {
+    assert_eq!(eval("join(split(\"a,b,,c\", \",\"), \"|\")"), string("a|b||c"));
+    assert_eq!(eval("len(split(\"no separator\", \";\"))"), Value::Number(1.0));
+    assert_eq!(eval("let cs = split(\"héllo\", \"\")\nlen(cs) * 10 + len(cs[1])"), Value::Number(51.0));
+    assert_eq!(eval("split(\"héllo\", \"\")[1]"), string("é"));
+    assert_eq!(eval("len(split(\"\", \"\"))"), Value::Number(0.0));
+    assert_eq!(eval("join([1, true, nil, \"x\"], \", \")"), string("1, true, nil, x"));
+    assert_eq!(eval("join([], \"-\")"), string(""));
+}
+
+#[test]
+fn test_trim_and_case() {
+    assert_eq!(eval("trim(\"  héllo\\t\\n\")"), string("héllo"));
+    assert_eq!(eval("upper(\"héllo\")"), string("HÉLLO"));
+    assert_eq!(eval("lower(\"HÉLLO World\")"), string("héllo world"));
+    assert_eq!(eval("upper(\"straße\")"), string("STRASSE"));
+}
+
+#[test]
+fn test_contains_index_of_and_replace() {
+    assert_eq!(eval("contains(\"héllo\", \"éll\")"), Value::Bool(true));
+    assert_eq!(eval("contains(\"héllo\", \"x\")"), Value::Bool(false));
+    // Character positions, not bytes
+    assert_eq!(eval("index_of(\"héllo\", \"l\")"), Value::Number(2.0));
+    assert_eq!(eval("index_of(\"héllo\", \"z\")"), Value::Number(-1.0));
+    assert_eq!(eval("replace(\"héllo héllo\", \"é\", \"e\")"), string("hello hello"));
+    assert_eq!(eval("replace(\"aaa\", \"a\", \"\")"), string(""));
+}
+
+#[test]
+fn test_string_native_errors() {
+    let err = eval_err("trim(5)");
+    assert!(err.contains("trim() requires a string, got number"), "{}", err);
+    let err = eval_err("join(\"abc\", \",\")");
+    assert!(err.contains("join() requires an array, got string"), "{}", err);
+    let err = eval_err("join([1], 2)");
+    assert!(err.contains("join() requires a string separator, got number"), "{}", err);
+    let err = eval_err("replace(\"abc\", \"\", \"x\")");
+    assert!(err.contains("replace() requires a non-empty string to replace"), "{}", err);
+    let err = eval_err("split(\"abc\", nil)");
+    assert!(err.contains("split() requires two strings"), "{}", err);
+}
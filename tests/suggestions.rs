@@ -0,0 +1,78 @@
+//! Integration tests for "did you mean" suggestions on undefined variables
+
+mod common;
+
+use common::{compile_warnings, compile_with};
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::VM;
+use skyhetu::SkyHetuError;
+
+fn run_err(source: &str) -> SkyHetuError {
+    skyhetu::run(source).unwrap_err()
+}
+
+/// Compile `source` the way the CLI does, with the VM's globals declared, and return the warnings
+fn warnings(source: &str) -> Vec<String> {
+    let mut compiler = VM::new().program_compiler(".".into());
+    compile_warnings(&mut compiler, source).into_iter().map(|w| w.message).collect()
+}
+
+#[test]
+fn test_one_character_typo_is_suggested() {
+    let err = run_err("state visitors = 0\nvisitors -> visitors + 1\nprint(vistors)");
+    assert_eq!(err.suggestions(), ["visitors", "history"]);
+    assert!(err.to_string().contains("undefined variable 'vistors'. Did you mean 'visitors' or 'history'?"), "{}", err);
+
+    // Every close name is offered, closest first
+    let err = run_err("state counter = 0\nprint(countr)");
+    assert_eq!(err.suggestions(), ["count", "counter"]);
+
+    let err = run_err("state balance = 0\nbalanse -> 5");
+    assert_eq!(err.suggestions(), ["balance"]);
+}
+
+#[test]
+fn test_nothing_close_gives_no_suggestion() {
+    let err = run_err("let total = 1\nprint(zebra)");
+    assert!(err.suggestions().is_empty());
+    assert!(err.to_string().ends_with("undefined variable 'zebra'"), "{}", err);
+}
+
+#[test]
+fn test_compiler_warns_when_globals_are_known() {
+    let found = warnings("fn area(width, height) {\n  return width * heigth\n}\nlet total = 0\nprint(totl)\nprint(len(\"ok\"))");
+    assert_eq!(found, [
+        "undefined variable 'heigth'. Did you mean 'height'?",
+        "undefined variable 'totl'. Did you mean 'total'?",
+    ]);
+
+    // Without the VM's globals the compiler can't tell what will be defined
+    assert!(compile_warnings(&mut Compiler::new(), "print(totl)").is_empty());
+}
+
+#[test]
+fn test_undefined_global_is_reported_once() {
+    // Compile and run the way the CLI does: warnings first, then the runtime
+    // error unless it only repeats one of them
+    let source = "let total = 0\nprint(totl)";
+    let mut vm = VM::new();
+    let mut compiler = vm.program_compiler(".".into());
+    let chunk = compile_with(&mut compiler, source, &mut vm.heap).unwrap();
+    let warnings = compiler.warnings().to_vec();
+    let err = vm.run(chunk).unwrap_err();
+    assert!(warnings.iter().any(|w| err.repeats(w)), "{}", err);
+
+    let mut reported: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    if !warnings.iter().any(|w| err.repeats(w)) {
+        reported.push(err.to_string());
+    }
+    let message = "undefined variable 'totl'. Did you mean 'total'?";
+    assert_eq!(reported.iter().filter(|r| r.contains(message)).count(), 1, "{:?}", reported);
+
+    // A runtime error on another line, or for another name, still stands
+    let err = run_err("let total = 0\nprint(totl)");
+    let elsewhere = skyhetu::Warning::new(warnings[0].message.clone(), Some(skyhetu::token::Span::new(0, 0, 1, 1)));
+    assert!(!err.repeats(&elsewhere));
+    let other = run_err("print(missing)");
+    assert!(!other.repeats(&warnings[0]));
+}
@@ -0,0 +1,109 @@
+//! Integration tests for calls in tail position, which reuse the caller's frame
+
+mod common;
+
+use common::eval;
+use skyhetu::run;
+use skyhetu::value::Value;
+
+#[test]
+fn test_deep_self_recursion() {
+    let source = "fn count_down(n) {
+  if n == 0 { return \"done\" }
+  return count_down(n - 1)
+}
+count_down(100000)";
+    assert_eq!(eval(source), Value::String("done".into()));
+
+    let source = "fn sum(n, acc) {
+  if n == 0 { return acc }
+  return sum(n - 1, acc + n)
+}
+sum(100000, 0)";
+    assert_eq!(eval(source), Value::Number(5000050000.0));
+}
+
+#[test]
+fn test_deep_mutual_recursion() {
+    let source = "fn is_even(n) {
+  if n == 0 { return true }
+  return is_odd(n - 1)
+}
+fn is_odd(n) {
+  if n == 0 { return false }
+  return is_even(n - 1)
+}
+!is_even(100001) and is_odd(100001)";
+    assert_eq!(eval(source), Value::Bool(true));
+}
+
+#[test]
+fn test_method_tail_recursion() {
+    let source = "class Walker {
+  init() { this.steps = 0 }
+  walk(n) {
+    if n == 0 { return this.steps }
+    this.steps = this.steps + 1
+    return this.walk(n - 1)
+  }
+}
+Walker().walk(50000)";
+    assert_eq!(eval(source), Value::Number(50000.0));
+}
+
+#[test]
+fn test_non_tail_recursion_still_overflows() {
+    let source = "fn depth(n) {
+  if n == 0 { return 0 }
+  return 1 + depth(n - 1)
+}
+depth(100)";
+    let err = run(source).unwrap_err();
+    assert!(err.to_string().contains("stack overflow"), "{}", err);
+}
+
+#[test]
+fn test_tail_calls_to_natives_and_classes() {
+    let source = "class Point {
+  init(x) { this.x = x }
+}
+fn size(items) { return len(items) }
+fn make(x) { return Point(x) }
+size([1, 2, 3]) + make(4).x";
+    assert_eq!(eval(source), Value::Number(7.0));
+}
+
+#[test]
+fn test_captured_locals_survive_the_reused_frame() {
+    let source = "fn collect(n, found) {
+  let value = n * 10
+  push(found, fn() => value)
+  if n == 0 { return found }
+  return collect(n - 1, found)
+}
+let getters = collect(3, [])
+getters[0]() + getters[1]() + getters[3]()";
+    assert_eq!(eval(source), Value::Number(30.0 + 20.0 + 0.0));
+}
+
+#[test]
+fn test_wrong_arity_in_tail_position() {
+    let source = "fn one(a) { return a }
+fn caller() { return one(1, 2) }
+caller()";
+    let err = run(source).unwrap_err();
+    assert!(err.to_string().contains("expected 1 arguments, got 2"), "{}", err);
+}
+
+#[test]
+fn test_stack_trace_skips_replaced_frames() {
+    let source = "fn fail(n) {
+  return n / 0
+}
+fn relay(n) {
+  return fail(n)
+}
+relay(1)";
+    let err = run(source).unwrap_err();
+    assert_eq!(err.stack_trace(), ["in <fn fail> (line 2)", "in <script> (line 7)"]);
+}
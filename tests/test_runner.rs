@@ -0,0 +1,107 @@
+//! Integration tests for the `skyhetu test` runner and the assertion natives
+
+mod common;
+
+use common::scratch_dir;
+use skyhetu::testing::{find_test_files, run_tests};
+use skyhetu::vm::VM;
+use skyhetu::{AssertMode, Value};
+
+const MATH_TEST: &str = "import { double } from \"helpers\"
+state runs = 0
+runs -> runs + 1
+
+fn test_double() {
+  assert_eq(double(21), 42)
+  assert_eq(runs, 1, \"each test gets a fresh VM\")
+}
+
+fn test_halve() {
+  print(\"halving\")
+  assert_eq(double(2) / 2, 2)
+  assert_eq([1, double(1)], [1, 3], \"pairs\")
+}
+
+fn not_a_test() {
+  assert(false)
+}
+";
+
+#[test]
+fn test_runner_reports_passes_and_failures() {
+    let dir = scratch_dir("summary");
+    std::fs::write(dir.join("helpers.skyh"), "export fn double(n) { return n * 2 }\n").unwrap();
+    std::fs::write(dir.join("math_test.skyh"), MATH_TEST).unwrap();
+    std::fs::create_dir(dir.join("nested")).unwrap();
+    std::fs::write(dir.join("nested/strings_test.skyh"), "fn test_upper() {\n  assert_ne(upper(\"a\"), \"a\")\n}\n").unwrap();
+
+    let report = run_tests(&dir).unwrap();
+    let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["test_double", "test_halve", "test_upper"]);
+    assert_eq!((report.passed(), report.failed()), (2, 1));
+    assert_eq!(report.summary(), "2 passed, 1 failed");
+
+    let failed: Vec<_> = report.failures().collect();
+    assert_eq!(failed[0].name, "test_halve");
+    assert_eq!(failed[0].file, dir.join("math_test.skyh"));
+    assert_eq!(failed[0].output, "halving\n");
+    let failure = failed[0].failure.as_ref().unwrap();
+    assert_eq!(failure.span.unwrap().line, 13);
+    assert!(failure.to_string().contains("pairs ([1, 2] != [1, 3])"), "{}", failure);
+    assert_eq!(failure.stack_trace(), ["in <fn test_halve> (line 13)", "in <script> (line 10)"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_files_that_fail_to_load_are_failures() {
+    let dir = scratch_dir("broken");
+    std::fs::write(dir.join("broken_test.skyh"), "fn test_nothing() {\n  let = 1\n}\n").unwrap();
+    std::fs::write(dir.join("notes.skyh"), "fn test_ignored() { assert(false) }\n").unwrap();
+
+    assert_eq!(find_test_files(&dir).unwrap(), [dir.join("broken_test.skyh")]);
+    let report = run_tests(&dir).unwrap();
+    assert_eq!(report.summary(), "0 passed, 1 failed");
+    assert_eq!(report.results[0].name, "<file>");
+
+    // A file named directly runs whatever its name
+    let report = run_tests(&dir.join("notes.skyh")).unwrap();
+    assert_eq!(report.summary(), "0 passed, 1 failed");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_tests_run_deterministically() {
+    let dir = scratch_dir("deterministic");
+    let source = "fn test_first() {\n  print(rand(), clock_ms())\n}\nfn test_second() {\n  print(rand(), clock_ms())\n}\n";
+    std::fs::write(dir.join("random_test.skyh"), source).unwrap();
+
+    let report = run_tests(&dir).unwrap();
+    assert_eq!(report.summary(), "2 passed, 0 failed");
+    assert_eq!(report.results[0].output, report.results[1].output);
+    assert!(report.results[0].output.ends_with(" 1\n"), "{:?}", report.results[0].output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_assert_eq_and_assert_ne_messages() {
+    let mut vm = VM::new();
+    assert_eq!(vm.eval("assert_eq([1, [2, 3]], [1, [2, 3]])").unwrap(), Value::Nil);
+    assert_eq!(vm.eval("assert_ne(1, \"1\")").unwrap(), Value::Nil);
+
+    for (source, message) in [
+        ("assert_eq(1 + 1, 3)", "assert_eq failed: 2 != 3"),
+        ("assert_eq(\"a\", \"b\", \"letters\")", "letters (\"a\" != \"b\")"),
+        ("assert_ne([1], [1])", "assert_ne failed: both are [1]"),
+        ("assert_eq(1)", "assert_eq() takes 2 or 3 arguments, got 1"),
+    ] {
+        let err = vm.eval(source).unwrap_err();
+        assert!(err.to_string().contains(message), "{}: {}", source, err);
+        assert_eq!(err.span.unwrap().line, 1);
+    }
+
+    // Collected like assert's failures
+    vm.set_assert_mode(AssertMode::Collect);
+    vm.eval("assert_eq(1, 2)\nassert_ne(3, 3)").unwrap();
+    let lines: Vec<usize> = vm.take_assert_failures().iter().map(|f| f.line).collect();
+    assert_eq!(lines, [1, 2]);
+}
@@ -0,0 +1,103 @@
+//! Integration tests for `at()` and `rewind()`
+
+mod common;
+
+use skyhetu::{run, Value};
+
+const HISTORY: &str = "state x = 0\nx -> 10\nx -> 20\nx -> 30\n";
+
+/// Run `source` after the shared history
+fn eval(source: &str) -> Value {
+    common::eval(&format!("{}{}", HISTORY, source))
+}
+
+#[test]
+fn test_at_returns_value_at_each_time() {
+    for (t, expected) in [(1, 0.0), (2, 10.0), (3, 20.0), (4, 30.0)] {
+        assert_eq!(eval(&format!("at(\"x\", {})", t)), Value::Number(expected), "t={}", t);
+    }
+    // Before the declaration the initial value, after the last transition the current one
+    assert_eq!(eval("at(\"x\", 0)"), Value::Number(0.0));
+    assert_eq!(eval("at(\"x\", 99)"), Value::Number(30.0));
+    assert_eq!(eval("at(\"nope\", 2)"), Value::Nil);
+}
+
+#[test]
+fn test_rewind_restores_and_records() {
+    assert_eq!(eval("rewind(\"x\", 2)\nx"), Value::Number(10.0));
+    assert_eq!(eval("rewind(\"x\", 2)\ntransitions(\"x\")"), Value::Number(4.0));
+
+    let why = eval("rewind(\"x\", 3)\nwhy(x)");
+    let Value::String(why) = why else { panic!("why() returns a string") };
+    assert!(why.contains("[t=5] 30 -> 20 (line 5, rewound to t=3)"), "{}", why);
+
+    // The rewind is itself history: going back to just before it undoes it
+    assert_eq!(eval("rewind(\"x\", 2)\nx -> x + 1\nat(\"x\", 5)"), Value::Number(10.0));
+}
+
+#[test]
+fn test_state_arrays_travel_back_to_their_old_contents() {
+    assert_eq!(common::output("state xs = []\npush(xs, 1)\npush(xs, 2)\nprint(at(\"xs\", 1), xs)"), "[] [1, 2]\n");
+
+    let source = "state arr = [1, 2, 3]
+arr[0] = 9
+arr[1] = 8
+rewind(\"arr\", 1)
+print(arr)
+push(at(\"arr\", 1), 4)
+arr[2] = 7
+print(arr, at(\"arr\", 1))
+print(why(arr))";
+    let printed = common::output(source);
+    let lines: Vec<&str> = printed.lines().collect();
+    assert_eq!(lines[0], "[1, 2, 3]");
+    // Neither the copy at() hands out nor the restored array shares the log's
+    assert_eq!(lines[1], "[1, 2, 7] [1, 2, 3]");
+    assert!(printed.contains("[t=1] initialized to [1, 2, 3]"), "{}", printed);
+    assert!(printed.contains("[9, 8, 3] -> [1, 2, 3] (line 4, rewound to t=1)"), "{}", printed);
+}
+
+#[test]
+fn test_rewind_rejects_non_state_and_bad_times() {
+    let err = run("let y = 1\nrewind(\"y\", 1)").unwrap_err().to_string();
+    assert!(err.contains("'y' is not one"), "{}", err);
+    let err = run(&format!("{}rewind(\"x\", -1)", HISTORY)).unwrap_err().to_string();
+    assert!(err.contains("non-negative whole timestamp"), "{}", err);
+    let err = run("at(1, 2)").unwrap_err().to_string();
+    assert!(err.contains("variable name as string"), "{}", err);
+}
+
+#[test]
+fn test_diff_lists_changes_between_times() {
+    let source = "state y = \"a\"\nstate z = [1]\ny -> \"b\"\nlet changes = diff(2, 7)\nchanges";
+    let mut vm = skyhetu::vm::VM::new();
+    let changes = vm.eval(&format!("{}{}", HISTORY, source)).unwrap();
+    // x changes at t=2..4, y is declared at t=5 and changes at t=7, z is declared at t=6
+    assert_eq!(
+        skyhetu::inspect::render(&vm.heap, &changes),
+        "[\"x: 10 -> 30\", \"y: nil -> b\", \"z: nil -> [1]\"]"
+    );
+    assert_eq!(eval("len(diff(4, 4))"), Value::Number(0.0));
+    assert_eq!(eval("diff(1, 2)[0]"), Value::String("x: 0 -> 10".into()));
+
+    let err = run("diff(3, 1)").unwrap_err().to_string();
+    assert!(err.contains("diff() requires the first time to come first, got 3 and 1"), "{}", err);
+    let err = run("diff(0, 1.5)").unwrap_err().to_string();
+    assert!(err.contains("diff() requires non-negative whole timestamps"), "{}", err);
+}
+
+#[test]
+fn test_rewind_rejects_collected_values() {
+    // A host may log a value the script never held, which then gets collected
+    let mut vm = skyhetu::VM::new();
+    vm.eval("state a = 0").unwrap();
+    let handle = vm.heap.alloc_array(vec![Value::Number(1.0)]);
+    vm.collect_garbage();
+    vm.causality.record_mutation("a", Value::Number(0.0), Value::Array(handle), None);
+
+    for call in ["rewind(\"a\", 2)", "at(\"a\", 2)"] {
+        let err = vm.eval(call).unwrap_err().to_string();
+        assert!(err.contains("'a' at t=2: its array was garbage collected"), "{}: {}", call, err);
+    }
+    assert_eq!(vm.eval("a").unwrap(), Value::Number(0.0));
+}
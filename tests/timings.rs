@@ -0,0 +1,45 @@
+//! Integration tests for per-phase timings
+
+use std::time::{Duration, Instant};
+use skyhetu::{Value, VM};
+
+#[test]
+fn test_phases_are_timed() {
+    let source = "state total = 0\nstate i = 0\nwhile i < 2000 {\n  i -> i + 1\n  total -> total + i\n}\ntotal";
+    let mut vm = VM::new();
+    let start = Instant::now();
+    let (value, timings) = skyhetu::run_timed(&mut vm, source).unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(value, Value::Number(2001000.0));
+    assert!(timings.lex > Duration::ZERO);
+    assert!(timings.parse > Duration::ZERO);
+    assert!(timings.compile > Duration::ZERO);
+    assert!(timings.execute > timings.parse, "{}", timings);
+    assert!(timings.gc <= timings.execute);
+    assert!(timings.total() <= elapsed, "{} vs {:?}", timings, elapsed);
+    assert!(timings.modules.is_empty());
+    assert!(timings.to_string().starts_with("timings: lex "), "{}", timings);
+}
+
+#[test]
+fn test_imported_modules_are_timed_by_path() {
+    let dir = std::env::temp_dir().join(format!("skyhetu_timings_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("shapes.skyh"), "import { square } from \"math\"\nexport fn area(n) { return square(n) }\n").unwrap();
+    std::fs::write(dir.join("math.skyh"), "export fn square(n) { return n * n }\n").unwrap();
+
+    let mut vm = VM::new();
+    vm.set_base_path(&dir);
+    let (value, timings) = skyhetu::run_timed(&mut vm, "import { area } from \"shapes\"\narea(3)").unwrap();
+    assert_eq!(value, Value::Number(9.0));
+
+    let paths: Vec<&str> = timings.modules.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[0].ends_with("shapes.skyh") && paths[1].ends_with("math.skyh"), "{:?}", paths);
+    // A module's time includes the modules it imports, and all of it is compile time
+    assert!(timings.modules[0].1 >= timings.modules[1].1);
+    assert!(timings.modules[0].1 <= timings.compile);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
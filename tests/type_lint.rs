@@ -0,0 +1,51 @@
+//! Integration tests for the warning on `==` / `!=` between operands of different known types
+
+mod common;
+
+use common::warning_lines as warnings;
+
+#[test]
+fn test_mismatched_literals_are_reported() {
+    let found = warnings("let a = \"x\" == 1\nlet b = true != \"yes\"");
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert_eq!(found[0].0, 1);
+    assert!(found[0].1.contains("compares a string with a number and is always false"), "{}", found[0].1);
+    assert_eq!(found[1].0, 2);
+    assert!(found[1].1.contains("compares a bool with a string and is always true"), "{}", found[1].1);
+}
+
+#[test]
+fn test_builtin_results_and_constants_have_known_types() {
+    let found = warnings("let x = 3\nif type(x) == 3 { print(x) }");
+    assert_eq!(found.len(), 1, "{:?}", found);
+    assert!(found[0].1.starts_with("'type(x) == 3'"), "{}", found[0].1);
+
+    let found = warnings("const LIMIT = 10\nlet s = \"10\"\nprint(LIMIT == str(5))\nprint((1 < 2) == \"true\")");
+    assert_eq!(found.len(), 2, "{:?}", found);
+}
+
+#[test]
+fn test_dynamic_operands_are_not_reported() {
+    let found = warnings("fn f(name) {
+  if name == 0 { return 1 }
+  return name != \"x\"
+}
+let v = f(2) == 3
+print(type(v) == \"bool\")
+print(len(\"ab\") + 1 == 3)");
+    assert!(found.is_empty(), "{:?}", found);
+}
+
+#[test]
+fn test_shadowed_builtins_are_not_trusted() {
+    let found = warnings("fn type(x) { return 1 }\nprint(type(2) == 1)");
+    assert!(found.is_empty(), "{:?}", found);
+    let found = warnings("fn f(str) { return str(1) == 1 }");
+    assert!(found.is_empty(), "{:?}", found);
+}
+
+#[test]
+fn test_nil_checks_are_not_reported() {
+    let found = warnings("let x = 1\nprint(x == nil)\nprint(nil != \"a\")\nprint(3 == nil)");
+    assert!(found.is_empty(), "{:?}", found);
+}
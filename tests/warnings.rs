@@ -0,0 +1,131 @@
+//! Integration tests for warnings about unused locals, unreachable code and shadowing
+
+mod common;
+
+use common::{compile_warnings, warning_lines};
+use skyhetu::compiler::Compiler;
+use skyhetu::vm::VM;
+use skyhetu::Value;
+
+/// The warning messages for `source` with their lines, in line order
+fn warnings(source: &str) -> Vec<(usize, String)> {
+    let mut found = warning_lines(source);
+    found.sort();
+    found
+}
+
+const FIXTURE: &str = "let config = 1
+fn tally(items) {
+  let unused = len(items)
+  let _skipped = 0
+  state limit = 10
+  state total = 0
+  for item in items {
+    let total = item
+    if total > limit { return total }
+  }
+  return total
+  total -> total + 1
+}
+fn report(name) {
+  let label = name
+  let show = fn() {
+    let label = \"inner\"
+    return label
+  }
+  state seen = 0
+  seen -> 1
+  return show() + label
+}
+class Box {
+  init(v) {
+    let v2 = v
+    this.v = v2
+  }
+}
+print(tally([1, 2]), report(\"x\"), Box(config).v)";
+
+#[test]
+fn test_fixture_reports_exactly_the_mistakes() {
+    let expected = [
+        (3, "local 'unused' is never read"),
+        (5, "state 'limit' is never transitioned; it could be a let"),
+        (8, "'total' shadows the local declared at line 6"),
+        (12, "unreachable code after return"),
+        (17, "'label' shadows a local of enclosing function 'report'"),
+        (20, "state 'seen' is never read"),
+    ];
+    let expected: Vec<_> = expected.iter().map(|(line, m)| (*line, m.to_string())).collect();
+    assert_eq!(warnings(FIXTURE), expected);
+
+    // Warnings don't stop the program from compiling and running
+    let mut vm = VM::new();
+    vm.eval(FIXTURE).unwrap();
+}
+
+#[test]
+fn test_clean_code_has_no_warnings() {
+    let source = "fn count(items) {
+  state n = 0
+  for item in items {
+    if item { n -> n + 1 }
+  }
+  return n
+}
+fn make() {
+  state hits = 0
+  return fn() {
+    hits -> hits + 1
+    return hits
+  }
+}
+fn grid() {
+  state cells = [0, 0]
+  cells[1] = 5
+  return cells
+}
+count([1, 0]) + make()() + grid()[1]";
+    assert_eq!(warnings(source), []);
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(7.0));
+}
+
+#[test]
+fn test_reads_through_why_and_closures_count() {
+    let source = "fn f() {
+  state x = 1
+  x -> 2
+  return why(x)
+}
+fn g() {
+  let y = 3
+  return fn() => y
+}
+f()
+g()";
+    assert_eq!(warnings(source), []);
+}
+
+#[test]
+fn test_unreachable_code_is_reported_once_per_block() {
+    let source = "fn f() {
+  return 1
+  print(1)
+  print(2)
+}
+fn g(x) {
+  if x {
+    return 2
+    print(3)
+  }
+  return 4
+}";
+    assert_eq!(warnings(source), [(3, "unreachable code after return".to_string()), (9, "unreachable code after return".to_string())]);
+}
+
+#[test]
+fn test_each_compile_starts_with_no_warnings() {
+    let mut compiler = Compiler::new();
+    assert_eq!(compile_warnings(&mut compiler, "fn f() {\n  let unused = 1\n}").len(), 1);
+    let found = compile_warnings(&mut compiler, "let clean = 2");
+    assert!(found.is_empty(), "{:?}", found);
+}
@@ -0,0 +1,87 @@
+//! Integration tests for `watch()` and `unwatch()`
+
+mod common;
+
+use common::output;
+use skyhetu::vm::{CaptureBuffer, VM};
+use skyhetu::{ErrorKind, Value};
+
+#[test]
+fn test_watcher_fires_per_transition() {
+    let source = "state counter = 0
+watch(counter, fn(old, new) { print(\"changed\", old, new, counter) })
+counter -> counter + 1
+counter -> 5
+watch(\"coun\" + \"ter\", fn(old, new) { print(\"again\", new) })
+counter -> 7";
+    assert_eq!(output(source), "changed 0 1 1\nchanged 1 5 5\nchanged 5 7 7\nagain 7\n");
+
+    // A local is watched through the closures that capture it too
+    let source = "fn track() {
+  state n = 1
+  let seen = []
+  watch(n, fn(old, new) { push(seen, old * 10 + new) })
+  let bump = fn() { n -> n + 1 }
+  n -> 2
+  bump()
+  return seen
+}
+print(track())";
+    assert_eq!(output(source), "[12, 23]\n");
+}
+
+#[test]
+fn test_unwatch_stops_watchers() {
+    let source = "state x = 0
+watch(x, fn(old, new) { print(\"first\", new) })
+watch(\"x\", fn(old, new) { print(\"second\", new) })
+x -> 1
+print(unwatch(x))
+x -> 2
+print(unwatch(\"x\"))";
+    assert_eq!(output(source), "first 1\nsecond 1\ntrue\nfalse\n");
+}
+
+#[test]
+fn test_watcher_transitioning_its_variable_is_an_error() {
+    let mut vm = VM::new();
+    let source = "state a = 0
+state b = 0
+watch(a, fn(old, new) { b -> new })
+watch(b, fn(old, new) { a -> new + 1 })
+a -> 1";
+    let err = vm.eval(source).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::RuntimeError(_)), "{:?}", err.kind);
+    assert!(err.to_string().contains("watcher of 'a' transitioned 'a' again"), "{}", err);
+    assert_eq!(err.span.unwrap().line, 4);
+
+    // Other variables may change from a watcher
+    let source = "state total = 0
+state log = 0
+watch(total, fn(old, new) { log -> log + 1 })
+total -> 3
+total -> 4
+log";
+    assert_eq!(VM::new().eval(source).unwrap(), Value::Number(2.0));
+}
+
+#[test]
+fn test_watch_argument_errors() {
+    let mut vm = VM::new();
+    let err = vm.eval("state s = 0\nwatch(s, 3)").unwrap_err().to_string();
+    assert!(err.contains("watch() requires a function to call, got number"), "{}", err);
+    let err = vm.eval("watch(1, fn(a, b) {})").unwrap_err().to_string();
+    assert!(err.contains("watch() requires variable name as string, got number"), "{}", err);
+}
+
+#[test]
+fn test_watchers_survive_collection() {
+    let mut vm = VM::new();
+    vm.set_gc_stress(true);
+    let buffer = CaptureBuffer::new();
+    vm.set_output(Box::new(buffer.clone()));
+    vm.eval("state x = 0\nfn make(tag) { return fn(old, new) { print(tag, new) } }\nwatch(x, make(\"seen\"))").unwrap();
+    vm.collect_garbage();
+    vm.eval("x -> 1\nlet junk = [[1], [2]]\nx -> 2").unwrap();
+    assert_eq!(buffer.contents(), "seen 1\nseen 2\n");
+}
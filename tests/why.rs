@@ -0,0 +1,83 @@
+//! Integration tests for `why()` and `history()` as functions
+
+mod common;
+
+use common::text;
+use skyhetu::vm::VM;
+use skyhetu::{run, ErrorKind, Value};
+
+const HISTORY: &str = "state price = 10\nstate total = 0\ntotal -> price * 3\ntotal -> total + 1\n";
+
+/// Run `source` after the shared history
+fn eval(source: &str) -> Value {
+    common::eval(&format!("{}{}", HISTORY, source))
+}
+
+#[test]
+fn test_bare_name_is_sugar_for_the_string_form() {
+    let chain = text(eval("why(total)"));
+    assert!(chain.starts_with("Causality chain for 'total':"), "{}", chain);
+    assert!(chain.contains("3. [t=4] 30 -> 31 (line 4)"), "{}", chain);
+    assert_eq!(eval("why(total) == why(\"total\")"), Value::Bool(true));
+}
+
+#[test]
+fn test_why_is_a_value() {
+    let chain = text(eval("let ask = why\nask(\"price\")"));
+    assert!(chain.contains("1. [t=1] initialized to 10 (line 1)"), "{}", chain);
+
+    let chain = text(eval("let names = [\"price\", \"total\"]\nmap(names, why)[1]"));
+    assert!(chain.starts_with("Causality chain for 'total':"), "{}", chain);
+
+    let err = run("let ask = why\nask(1)").unwrap_err().to_string();
+    assert!(err.contains("why() requires variable name as string, got number"), "{}", err);
+}
+
+#[test]
+fn test_history_returns_one_string_per_mutation() {
+    assert_eq!(eval("len(history(\"total\"))"), Value::Number(3.0));
+    assert_eq!(text(eval("history(\"total\")[0]")), "[t=2] initialized to 0 (line 2)");
+    assert_eq!(text(eval("history(\"total\")[1]")), "[t=3] 0 -> 30 (line 3; caused by price)");
+    assert_eq!(text(eval("history(\"total\")[-1]")), "[t=4] 30 -> 31 (line 4)");
+    assert_eq!(eval("len(history(\"nope\"))"), Value::Number(0.0));
+
+    let err = run("history(total)").unwrap_err().to_string();
+    assert!(err.contains("undefined variable 'total'"), "{}", err);
+}
+
+#[test]
+fn test_expression_argument_is_a_compile_error() {
+    for source in ["why(1 + 1)", "let points = [1]\nwhy(points[0])", "why(true)"] {
+        let mut vm = VM::new();
+        let err = vm.eval(source).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::WhyOfExpression), "{}: {:?}", source, err.kind);
+        assert!(
+            err.to_string().contains("why() takes a variable, not an expression; pass its name in quotes, as in why(\"total\")"),
+            "{}: {}", source, err
+        );
+    }
+
+    // A string-valued expression names the variable
+    assert_eq!(text(eval("why(\"to\" + \"tal\")")), text(eval("why(total)")));
+}
+
+#[test]
+fn test_user_defined_why_is_an_ordinary_function() {
+    let mut vm = VM::new();
+    assert_eq!(vm.eval("fn why(a) { return a * 2 }\nwhy(1 + 1)").unwrap(), Value::Number(4.0));
+}
+
+#[test]
+fn test_state_array_changes_show_the_contents_before_and_after() {
+    let chain = text(common::eval("state arr = [1, 2, 3]\narr[0] = 9\npush(arr, 4)\npop(arr)\nwhy(arr)"));
+    assert!(chain.contains("1. [t=1] initialized to [1, 2, 3] (line 1)"), "{}", chain);
+    assert!(chain.contains("2. [t=2] [1, 2, 3] -> [9, 2, 3] (line 2, index 0)"), "{}", chain);
+    assert!(chain.contains("3. [t=3] [9, 2, 3] -> [9, 2, 3, 4] (line 3, push())"), "{}", chain);
+    assert!(chain.contains("4. [t=4] [9, 2, 3, 4] -> [9, 2, 3] (line 4, pop())"), "{}", chain);
+
+    // Through an alias or a shadowed push, the array changes without a record
+    let history = eval("state xs = [1]\nlet ys = xs\npush(ys, 2)\nlen(history(\"xs\"))");
+    assert_eq!(history, Value::Number(1.0));
+    let history = eval("fn push(a, v) { return a }\nstate xs = [1]\npush(xs, 2)\nlen(history(\"xs\"))");
+    assert_eq!(history, Value::Number(1.0));
+}